@@ -10,11 +10,14 @@
 use crate::hash::EMPTY_DIGEST;
 use crate::helper_structs::LookupInfo;
 use crate::storage::manager::StorageManager;
-use crate::storage::types::StorageType;
+use crate::storage::types::{
+    split_tree_scoped_key, tree_scoped_key_prefix, DbRecord, StorageType, TreeId,
+};
 use crate::tree_node::{
     new_interior_node, new_leaf_node, new_root_node, node_to_azks_value, node_to_label,
-    NodeHashingMode, NodeKey, TreeNode, TreeNodeType,
+    NodeHashingMode, NodeKey, TreeNode, TreeNodeType, TreeNodeWithPreviousValue,
 };
+use crate::utils::in_span;
 use crate::Configuration;
 use crate::{
     errors::{AkdError, DirectoryError, ParallelismError, TreeNodeError},
@@ -31,8 +34,8 @@ use std::convert::TryFrom;
 use std::marker::Sync;
 use std::ops::Deref;
 
-/// The default azks key
-pub const DEFAULT_AZKS_KEY: u8 = 1u8;
+/// The key of the azks of a storage backend holding a single tree
+pub const DEFAULT_AZKS_KEY: Option<TreeId> = None;
 
 /// The default available parallelism for parallel batch insertions, used when
 /// available parallelism cannot be determined at runtime. Should be > 1
@@ -245,34 +248,42 @@ pub struct Azks {
     pub latest_epoch: u64,
     /// The number of nodes is the total size of this tree
     pub num_nodes: u64,
+    /// The tree this is the azks of, in a storage backend holding several trees
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) tree: Option<TreeId>,
 }
 
 impl SizeOf for Azks {
     fn size_of(&self) -> usize {
-        std::mem::size_of::<u64>() * 2
+        std::mem::size_of::<u64>() * 2 + std::mem::size_of::<Option<TreeId>>()
     }
 }
 
 impl Storable for Azks {
-    type StorageKey = u8;
+    type StorageKey = Option<TreeId>;
 
     fn data_type() -> StorageType {
         StorageType::Azks
     }
 
-    fn get_id(&self) -> u8 {
-        DEFAULT_AZKS_KEY
+    fn get_id(&self) -> Option<TreeId> {
+        self.tree
     }
 
-    fn get_full_binary_key_id(key: &u8) -> Vec<u8> {
-        vec![StorageType::Azks as u8, *key]
+    fn get_full_binary_key_id(key: &Option<TreeId>) -> Vec<u8> {
+        // the byte following the prefix is the key of the azks before trees were identified
+        let mut result = tree_scoped_key_prefix(StorageType::Azks, *key);
+        result.push(1u8);
+        result
     }
 
-    fn key_from_full_binary(bin: &[u8]) -> Result<u8, String> {
-        if bin.is_empty() || bin[0] != StorageType::Azks as u8 {
-            return Err("Not an AZKS key".to_string());
-        }
-        Ok(DEFAULT_AZKS_KEY)
+    fn key_from_full_binary(bin: &[u8]) -> Result<Option<TreeId>, String> {
+        let (tree, _) = split_tree_scoped_key(StorageType::Azks, bin)
+            .map_err(|_| "Not an AZKS key".to_string())?;
+        Ok(tree)
     }
 }
 
@@ -289,12 +300,31 @@ impl Azks {
         let azks = Azks {
             latest_epoch: 0,
             num_nodes: 1,
+            tree: None,
         };
 
         Ok(azks)
     }
 
+    /// The tree this is the azks of, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    /// The azks, of the given tree of a storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self { tree, ..self }
+    }
+
     /// Insert a batch of new leaves.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(leaves = nodes.len(), epoch = self.latest_epoch + 1)
+        )
+    )]
     pub async fn batch_insert_nodes<TC: Configuration, S: Database + 'static>(
         &mut self,
         storage: &StorageManager<S>,
@@ -304,23 +334,79 @@ impl Azks {
         let azks_element_set = AzksElementSet::from(nodes);
 
         // preload the nodes that we will visit during the insertion
-        let (_, time_s) = tic_toc(self.preload_nodes(storage, &azks_element_set)).await;
-        if let Some(time) = time_s {
-            info!("Preload of tree took {} s", time,);
-        }
+        self.preload_nodes_for_insert(storage, &azks_element_set)
+            .await;
 
         // increment the current epoch
         self.increment_epoch();
 
+        self.insert_nodes_in_latest_epoch::<TC, _>(storage, azks_element_set, insert_mode)
+            .await
+    }
+
+    /// Insert a further batch of new leaves in the latest epoch, which must have been begun by
+    /// [Azks::batch_insert_nodes] as part of the same publish. This allows the leaves of an
+    /// epoch to be inserted a batch at a time, resulting in the same tree as inserting them at
+    /// once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(leaves = nodes.len(), epoch = self.latest_epoch)
+        )
+    )]
+    pub(crate) async fn batch_insert_nodes_in_latest_epoch<
+        TC: Configuration,
+        S: Database + 'static,
+    >(
+        &mut self,
+        storage: &StorageManager<S>,
+        nodes: Vec<AzksElement>,
+        insert_mode: InsertMode,
+    ) -> Result<(), AkdError> {
+        let azks_element_set = AzksElementSet::from(nodes);
+        self.preload_nodes_for_insert(storage, &azks_element_set)
+            .await;
+        self.insert_nodes_in_latest_epoch::<TC, _>(storage, azks_element_set, insert_mode)
+            .await
+    }
+
+    async fn preload_nodes_for_insert<S: Database>(
+        &self,
+        storage: &StorageManager<S>,
+        azks_element_set: &AzksElementSet,
+    ) {
+        let (_, time_s) = tic_toc(in_span!(
+            INFO,
+            self.preload_nodes(storage, azks_element_set),
+            "preload_nodes"
+        ))
+        .await;
+        if let Some(time) = time_s {
+            info!("Preload of tree took {} s", time,);
+        }
+    }
+
+    async fn insert_nodes_in_latest_epoch<TC: Configuration, S: Database + 'static>(
+        &mut self,
+        storage: &StorageManager<S>,
+        azks_element_set: AzksElementSet,
+        insert_mode: InsertMode,
+    ) -> Result<(), AkdError> {
         if !azks_element_set.is_empty() {
             // call recursive batch insert on the root
-            let (root_node, is_new, num_inserted) = Self::recursive_batch_insert_nodes::<TC, _>(
-                storage,
-                Some(NodeLabel::root()),
-                azks_element_set,
-                self.latest_epoch,
-                insert_mode,
-                get_parallel_levels(),
+            let (root_node, is_new, num_inserted) = in_span!(
+                INFO,
+                Self::recursive_batch_insert_nodes::<TC, _>(
+                    storage,
+                    Some(NodeLabel::root()),
+                    azks_element_set,
+                    self.latest_epoch,
+                    insert_mode,
+                    get_parallel_levels(),
+                ),
+                "insert_nodes"
             )
             .await?;
             root_node.write_to_storage(storage, is_new).await?;
@@ -334,6 +420,155 @@ impl Azks {
         Ok(())
     }
 
+    /// Builds the tree of a new, empty azks from its leaves as its first epoch, bottom-up rather
+    /// than by insertion. The leaves must be sorted, with no label repeated. Only the rightmost
+    /// path of the tree built so far is held in memory, so the leaves may be streamed. The
+    /// nodes are written directly to storage `batch_size` at a time, the root last, and the
+    /// resulting tree is the same as if the leaves were inserted with
+    /// [Azks::batch_insert_nodes].
+    pub(crate) async fn bootstrap_nodes<TC, S, I>(
+        &mut self,
+        storage: &StorageManager<S>,
+        leaves: I,
+        batch_size: usize,
+    ) -> Result<(), AkdError>
+    where
+        TC: Configuration,
+        S: Database + 'static,
+        I: Iterator<Item = Result<AzksElement, AkdError>> + Send,
+    {
+        if self.latest_epoch != 0 {
+            return Err(AkdError::Directory(DirectoryError::Publish(format!(
+                "Cannot bootstrap the tree at epoch {}",
+                self.latest_epoch
+            ))));
+        }
+        self.increment_epoch();
+        let epoch = self.latest_epoch;
+
+        let mut root =
+            TreeNode::get_from_storage(storage, &NodeKey::new(NodeLabel::root()), epoch).await?;
+        let mut pending = Vec::new();
+        let mut num_inserted = 0;
+
+        // The completed left subtrees of the interior nodes along the rightmost path, whose
+        // right subtrees are still being built, with the length of the prefix they split at.
+        // The split lengths strictly increase up the stack.
+        let mut open: Vec<(u32, TreeNode)> = Vec::new();
+        let mut previous: Option<TreeNode> = None;
+        for leaf in leaves {
+            let leaf = leaf?;
+            num_inserted += 1;
+            let node = new_leaf_node::<TC>(leaf.label, &leaf.value, epoch);
+            if let Some(mut subtree) = previous.take() {
+                if leaf.label <= subtree.label {
+                    return Err(AkdError::Directory(DirectoryError::Publish(
+                        "Cannot bootstrap with leaves which are unsorted or repeated".to_string(),
+                    )));
+                }
+                // the subtrees splitting below the new leaf's split are complete
+                let split = subtree
+                    .label
+                    .get_longest_common_prefix::<TC>(leaf.label)
+                    .get_len();
+                while let Some((open_split, _)) = open.last() {
+                    if *open_split <= split {
+                        break;
+                    }
+                    if let Some((_, left)) = open.pop() {
+                        subtree = Self::bootstrap_parent::<TC>(
+                            left,
+                            subtree,
+                            epoch,
+                            &mut pending,
+                            &mut num_inserted,
+                        )?;
+                    }
+                }
+                open.push((split, subtree));
+                if pending.len() >= batch_size {
+                    storage.batch_set(std::mem::take(&mut pending)).await?;
+                }
+            }
+            previous = Some(node);
+        }
+
+        // complete the rightmost path, whose subtree splitting at the root (if any) is the
+        // root's left child
+        let mut children = [None, None];
+        if let Some(mut subtree) = previous {
+            let mut root_left = None;
+            while let Some((split, left)) = open.pop() {
+                if split == 0 {
+                    root_left = Some(left);
+                } else {
+                    subtree = Self::bootstrap_parent::<TC>(
+                        left,
+                        subtree,
+                        epoch,
+                        &mut pending,
+                        &mut num_inserted,
+                    )?;
+                }
+            }
+            children = match (root_left, root.label.get_prefix_ordering(subtree.label)) {
+                (Some(left), _) => [Some(left), Some(subtree)],
+                (None, PrefixOrdering::WithZero) => [Some(subtree), None],
+                (None, _) => [None, Some(subtree)],
+            };
+        }
+        let [left, right] = children;
+        Self::adopt_bootstrap_children::<TC>(&mut root, left, right, &mut pending)?;
+        storage.batch_set(pending).await?;
+        root.write_to_storage(storage, false).await?;
+
+        self.num_nodes += num_inserted;
+        info!("Bootstrap completed ({} new nodes)", num_inserted);
+        Ok(())
+    }
+
+    /// The interior node adopting two complete, adjacent subtrees
+    fn bootstrap_parent<TC: Configuration>(
+        left: TreeNode,
+        right: TreeNode,
+        epoch: u64,
+        pending: &mut Vec<DbRecord>,
+        num_inserted: &mut u64,
+    ) -> Result<TreeNode, TreeNodeError> {
+        let mut node = new_interior_node::<TC>(
+            left.label.get_longest_common_prefix::<TC>(right.label),
+            epoch,
+        );
+        *num_inserted += 1;
+        Self::adopt_bootstrap_children::<TC>(&mut node, Some(left), Some(right), pending)?;
+        Ok(node)
+    }
+
+    /// Sets the children of a node and computes its hash, queueing the children to be written
+    fn adopt_bootstrap_children<TC: Configuration>(
+        node: &mut TreeNode,
+        mut left: Option<TreeNode>,
+        mut right: Option<TreeNode>,
+        pending: &mut Vec<DbRecord>,
+    ) -> Result<(), TreeNodeError> {
+        for child in [&mut left, &mut right].into_iter().flatten() {
+            node.set_child(child)?;
+        }
+        node.hash = TC::compute_parent_hash_from_children(
+            &node_to_azks_value::<TC>(&left, NodeHashingMode::WithLeafEpoch),
+            &node_to_label::<TC>(&left).value::<TC>(),
+            &node_to_azks_value::<TC>(&right, NodeHashingMode::WithLeafEpoch),
+            &node_to_label::<TC>(&right).value::<TC>(),
+        );
+        pending.extend(
+            [left, right]
+                .into_iter()
+                .flatten()
+                .map(|child| DbRecord::TreeNode(TreeNodeWithPreviousValue::from_tree_node(child))),
+        );
+        Ok(())
+    }
+
     /// Inserts a batch of leaves recursively from a given node label. Note: it
     /// is the caller's responsibility to write the returned node to storage.
     /// This is done so that the caller may set the 'parent' field of a node
@@ -359,7 +594,7 @@ impl Azks {
                 // Case 1: The node label is not None, meaning that there was an
                 // existing node at this level of the tree.
                 let mut existing_node =
-                    TreeNode::get_from_storage(storage, &NodeKey(node_label), epoch).await?;
+                    TreeNode::get_from_storage(storage, &NodeKey::new(node_label), epoch).await?;
 
                 // compute the longest common prefix between all nodes in the
                 // node set and the current node, and check if new nodes
@@ -495,7 +730,9 @@ impl Azks {
         match (node.left_child, node.right_child) {
             (Some(l), _) if l.is_prefix_of(target) => {
                 match storage
-                    .get_from_cache_only::<crate::tree_node::TreeNodeWithPreviousValue>(&NodeKey(l))
+                    .get_from_cache_only::<crate::tree_node::TreeNodeWithPreviousValue>(
+                        &NodeKey::new(l),
+                    )
                     .await
                 {
                     Some(crate::storage::types::DbRecord::TreeNode(tnpv)) => {
@@ -510,7 +747,9 @@ impl Azks {
             }
             (_, Some(r)) if r.is_prefix_of(target) => {
                 match storage
-                    .get_from_cache_only::<crate::tree_node::TreeNodeWithPreviousValue>(&NodeKey(r))
+                    .get_from_cache_only::<crate::tree_node::TreeNodeWithPreviousValue>(
+                        &NodeKey::new(r),
+                    )
                     .await
                 {
                     Some(crate::storage::types::DbRecord::TreeNode(tnpv)) => {
@@ -540,9 +779,12 @@ impl Azks {
         let mut results = HashSet::new();
         let labels = [li.existent_label, li.marker_label, li.non_existent_label];
 
-        let root_node: TreeNode =
-            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), self.latest_epoch)
-                .await?;
+        let root_node: TreeNode = TreeNode::get_from_storage(
+            storage,
+            &NodeKey::new(NodeLabel::root()),
+            self.latest_epoch,
+        )
+        .await?;
 
         for label in labels {
             let mut cnode = root_node.clone();
@@ -586,7 +828,7 @@ impl Azks {
             .build_lookup_maximal_node_set(storage, lookup_info)
             .await?
             .into_iter()
-            .map(NodeKey)
+            .map(NodeKey::new)
             .collect::<Vec<_>>();
         requested_count += nodes.len() as u64;
 
@@ -599,7 +841,7 @@ impl Azks {
         let children = nodes
             .into_iter()
             .flat_map(|node| match (node.left_child, node.right_child) {
-                (Some(l), Some(r)) => vec![NodeKey(l), NodeKey(r)],
+                (Some(l), Some(r)) => vec![NodeKey::new(l), NodeKey::new(r)],
                 _ => vec![],
             })
             .collect::<Vec<_>>();
@@ -650,7 +892,7 @@ impl Azks {
         }
 
         let mut load_count: u64 = 0;
-        let mut current_nodes = vec![NodeKey(NodeLabel::root())];
+        let mut current_nodes = vec![NodeKey::new(NodeLabel::root())];
 
         while !current_nodes.is_empty() {
             let nodes =
@@ -667,7 +909,7 @@ impl Azks {
                 .flat_map(|node| {
                     [Direction::Left, Direction::Right]
                         .iter()
-                        .filter_map(|dir| node.get_child_label(*dir).map(NodeKey))
+                        .filter_map(|dir| node.get_child_label(*dir).map(NodeKey::new))
                         .collect::<Vec<NodeKey>>()
                 })
                 .collect();
@@ -702,9 +944,12 @@ impl Azks {
         let (lcp_node_label, longest_prefix_membership_proof) = self
             .get_lcp_node_label_with_membership_proof::<TC, _>(storage, label)
             .await?;
-        let lcp_node: TreeNode =
-            TreeNode::get_from_storage(storage, &NodeKey(lcp_node_label), self.get_latest_epoch())
-                .await?;
+        let lcp_node: TreeNode = TreeNode::get_from_storage(
+            storage,
+            &NodeKey::new(lcp_node_label),
+            self.get_latest_epoch(),
+        )
+        .await?;
         let longest_prefix = lcp_node.label;
 
         let empty_azks_element = AzksElement {
@@ -724,7 +969,7 @@ impl Azks {
                 Some(child) => {
                     let unwrapped_child: TreeNode = TreeNode::get_from_storage(
                         storage,
-                        &NodeKey(child.label),
+                        &NodeKey::new(child.label),
                         self.get_latest_epoch(),
                     )
                     .await?;
@@ -777,7 +1022,8 @@ impl Azks {
         // between these epochs.
 
         let node =
-            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), latest_epoch).await?;
+            TreeNode::get_from_storage(storage, &NodeKey::new(NodeLabel::root()), latest_epoch)
+                .await?;
 
         for ep in start_epoch..end_epoch {
             let (fallable_load_count, time_s) = tic_toc(self.gather_audit_proof_nodes::<_>(
@@ -857,7 +1103,7 @@ impl Azks {
         let mut children_to_fetch: Vec<NodeKey> = nodes
             .iter()
             .flat_map(|node| Self::determine_retrieval_nodes(node, start_epoch, end_epoch))
-            .map(NodeKey)
+            .map(NodeKey::new)
             .collect();
 
         let mut element_count = 0u64;
@@ -872,7 +1118,7 @@ impl Azks {
             children_to_fetch = got
                 .iter()
                 .flat_map(|node| Self::determine_retrieval_nodes(node, start_epoch, end_epoch))
-                .map(NodeKey)
+                .map(NodeKey::new)
                 .collect();
         }
         Ok(element_count)
@@ -928,7 +1174,7 @@ impl Azks {
                                 let my_storage = storage_clone;
                                 let child_node = TreeNode::get_from_storage(
                                     &my_storage,
-                                    &NodeKey(left_child),
+                                    &NodeKey::new(left_child),
                                     latest_epoch,
                                 )
                                 .await?;
@@ -947,9 +1193,12 @@ impl Azks {
                         Some(tsk)
                     } else {
                         // Enough parallelism already, STOP IT! Don't make me get the belt!
-                        let child_node =
-                            TreeNode::get_from_storage(storage, &NodeKey(left_child), latest_epoch)
-                                .await?;
+                        let child_node = TreeNode::get_from_storage(
+                            storage,
+                            &NodeKey::new(left_child),
+                            latest_epoch,
+                        )
+                        .await?;
                         let (mut inner_unchanged, mut inner_leaf) =
                             Self::get_append_only_proof_helper::<TC, _>(
                                 latest_epoch,
@@ -970,9 +1219,12 @@ impl Azks {
                 #[cfg(not(feature = "parallel_insert"))]
                 {
                     // NO Parallelism, BAD! parallelism. Get your nose out of the garbage!
-                    let child_node =
-                        TreeNode::get_from_storage(storage, &NodeKey(left_child), latest_epoch)
-                            .await?;
+                    let child_node = TreeNode::get_from_storage(
+                        storage,
+                        &NodeKey::new(left_child),
+                        latest_epoch,
+                    )
+                    .await?;
                     let (mut inner_unchanged, mut inner_leaf) =
                         Self::get_append_only_proof_helper::<TC, _>(
                             latest_epoch,
@@ -994,7 +1246,7 @@ impl Azks {
 
             if let Some(right_child) = node.right_child {
                 let child_node =
-                    TreeNode::get_from_storage(storage, &NodeKey(right_child), latest_epoch)
+                    TreeNode::get_from_storage(storage, &NodeKey::new(right_child), latest_epoch)
                         .await?;
                 let (mut inner_unchanged, mut inner_leaf) =
                     Self::get_append_only_proof_helper::<TC, _>(
@@ -1045,9 +1297,12 @@ impl Azks {
                 epoch, self.latest_epoch
             ))));
         }
-        let root_node: TreeNode =
-            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), self.latest_epoch)
-                .await?;
+        let root_node: TreeNode = TreeNode::get_from_storage(
+            storage,
+            &NodeKey::new(NodeLabel::root()),
+            self.latest_epoch,
+        )
+        .await?;
         Ok(TC::compute_root_hash_from_val(&root_node.hash))
     }
 
@@ -1091,7 +1346,8 @@ impl Azks {
 
         // Perform a traversal from the root to the node corresponding to the queried label
         let mut curr_node =
-            TreeNode::get_from_storage(storage, &NodeKey(NodeLabel::root()), latest_epoch).await?;
+            TreeNode::get_from_storage(storage, &NodeKey::new(NodeLabel::root()), latest_epoch)
+                .await?;
 
         let mut prefix_ordering = curr_node.label.get_prefix_ordering(label);
         let mut equal = label == curr_node.label;
@@ -1206,6 +1462,7 @@ mod tests {
                 epoch: 1,
                 label,
                 username: crate::AkdLabel::random(&mut rng),
+                tree: None,
                 value: crate::AkdValue::random(&mut rng),
                 version: 1,
             },
@@ -1667,7 +1924,8 @@ mod tests {
                 .await?;
 
             // Recursively traverse the tree and check that the sibling of each node is correct
-            let root_node = TreeNode::get_from_storage(&db, &NodeKey(NodeLabel::root()), 1).await?;
+            let root_node =
+                TreeNode::get_from_storage(&db, &NodeKey::new(NodeLabel::root()), 1).await?;
             let mut nodes: Vec<TreeNode> = vec![root_node];
             while !nodes.is_empty() {
                 let current_node = nodes.pop().unwrap();
@@ -11,22 +11,43 @@ use crate::append_only_zks::{Azks, InsertMode};
 use crate::ecvrf::{VRFKeyStorage, VRFPublicKey};
 use crate::errors::{AkdError, DirectoryError, StorageError};
 use crate::helper_structs::LookupInfo;
+use crate::helper_structs::{PublishReport, PublishStatus, PublishedLabel, VersionConflict};
+use crate::metrics::{
+    record_duration, PROOF_GENERATION_DURATION_SECONDS, PUBLISH_DURATION_SECONDS,
+    PUBLISH_PHASE_DURATION_SECONDS,
+};
+use crate::publish_policy::{PolicyDecision, PolicyRejection, PublishPolicy};
 use crate::storage::manager::StorageManager;
-use crate::storage::types::{DbRecord, ValueState, ValueStateRetrievalFlag};
+use crate::storage::migration::{check_schema_version, CURRENT_SCHEMA_VERSION};
+use crate::storage::spill::SortedValueStates;
+use crate::storage::types::{
+    DbRecord, PendingEpoch, PendingOperation, SchemaVersion, ValueState, ValueStateRetrievalFlag,
+    DEFAULT_PENDING_EPOCH_KEY, DEFAULT_SCHEMA_VERSION_KEY,
+};
 use crate::storage::Database;
+use crate::utils::in_span;
 use crate::{
-    AkdLabel, AkdValue, AppendOnlyProof, AzksElement, Digest, EpochHash, HistoryProof, LookupProof,
-    NonMembershipProof, UpdateProof,
+    AkdLabel, AkdValue, AppendOnlyProof, AzksElement, Digest, EntryOperation, EpochHash,
+    HistoryProof, LookupProof, MultiEntryValue, NonMembershipProof, UpdateProof,
 };
 
 use crate::VersionFreshness;
 use akd_core::configuration::Configuration;
+use futures::StreamExt;
 use log::{error, info};
 use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
+/// The number of entries read, and tree nodes written, at a time by [Directory::bootstrap]
+const BOOTSTRAP_BATCH_SIZE: usize = 10_000;
+/// The number of value states sorted in memory by [Directory::bootstrap], beyond which they're spilled
+/// to temporary files
+const BOOTSTRAP_RUN_SIZE: usize = 1_000_000;
+
 /// The representation of a auditable key directory
 pub struct Directory<TC, S: Database, V> {
     storage: StorageManager<S>,
@@ -38,6 +59,10 @@ pub struct Directory<TC, S: Database, V> {
     /// (in this case we do utilize the write() lock which can only occur 1
     /// at a time and gates further read() locks being acquired during write()).
     cache_lock: Arc<RwLock<()>>,
+    publish_policy: Option<Arc<dyn PublishPolicy>>,
+    /// The directory [Directory::bootstrap] spills sorted value states to, [None] for the
+    /// temporary directory of the system
+    spill_dir: Option<PathBuf>,
     tc: PhantomData<TC>,
 }
 
@@ -48,6 +73,8 @@ impl<TC, S: Database, V: VRFKeyStorage> Clone for Directory<TC, S, V> {
             storage: self.storage.clone(),
             vrf: self.vrf.clone(),
             cache_lock: self.cache_lock.clone(),
+            publish_policy: self.publish_policy.clone(),
+            spill_dir: self.spill_dir.clone(),
             tc: PhantomData,
         }
     }
@@ -69,28 +96,210 @@ where
             info!("No aZKS was found in storage: {e}. Creating a new aZKS!");
             // generate + store a new azks only if one is not found
             let new_azks = Azks::new::<TC, _>(&storage).await?;
+            storage
+                .set(DbRecord::SchemaVersion(DbRecord::build_schema_version(
+                    CURRENT_SCHEMA_VERSION,
+                )))
+                .await?;
             storage.set(DbRecord::Azks(new_azks)).await?;
         } else {
             // If the value is `Ok`, we drop it since we're not using it below
             // In all other `Err` cases, we propagate the error to the caller
             let _res = azks?;
+            Directory::<TC, S, V>::check_storage_schema_version(&storage).await?;
         }
 
+        // load the pinned upper levels of the tree (if configured)
+        storage.warm_pinned_nodes().await?;
+
         Ok(Directory {
             storage,
             cache_lock: Arc::new(RwLock::new(())),
             vrf,
+            publish_policy: None,
+            spill_dir: None,
             tc: PhantomData,
         })
     }
 
+    /// Sets a policy which validates, and possibly normalizes, each of the updates published
+    /// to the directory, prior to the computation of its VRF label. Updates rejected by the
+    /// policy are left out of the publish, and are returned in the [PublishReport] of
+    /// [Directory::publish_with_report] and [Directory::publish_if_versions], and alongside
+    /// the epoch hash of [Directory::publish_from_iter]. [Directory::publish], which returns
+    /// no report, fails instead. The policy is not applied by [Directory::bootstrap].
+    pub fn with_publish_policy(mut self, policy: Arc<dyn PublishPolicy>) -> Self {
+        self.publish_policy = Some(policy);
+        self
+    }
+
+    /// Sets the directory which [Directory::bootstrap] spills sorted runs of value states to,
+    /// in place of the temporary directory of the system. The runs hold the labels and values
+    /// bootstrapped in plaintext, bypassing any encryption of the storage, so the directory
+    /// should be protected accordingly. They're created with exclusive access for the current
+    /// user, and removed once the bootstrap completes.
+    pub fn with_spill_dir(mut self, spill_dir: impl Into<PathBuf>) -> Self {
+        self.spill_dir = Some(spill_dir.into());
+        self
+    }
+
     /// Updates the directory to include the input label-value pairs.
     ///
     /// Note that the vector of label-value pairs should not contain any entries with duplicate labels. This
     /// condition is explicitly checked, and an error will be returned if this is the case.
+    ///
+    /// If the publish policy of the directory (if any) rejects an update, an error is returned
+    /// and nothing is published. Use [Directory::publish_with_report] to publish the remaining
+    /// updates, and learn which were rejected.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(updates = updates.len(), epoch = tracing::field::Empty)
+        )
+    )]
     pub async fn publish(&self, updates: Vec<(AkdLabel, AkdValue)>) -> Result<EpochHash, AkdError> {
+        let report = self.publish_if_expected(updates, None, true).await?;
+        Ok(report.epoch_hash)
+    }
+
+    /// Updates the directory to include the input label-value pairs, as [Directory::publish]
+    /// does, returning a [PublishReport] of whether each label was inserted, updated, or left
+    /// unchanged as its value was re-published, along with its resulting version and node label.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(updates = updates.len(), epoch = tracing::field::Empty)
+        )
+    )]
+    pub async fn publish_with_report(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+    ) -> Result<PublishReport, AkdError> {
+        self.publish_if_expected(updates, None, false).await
+    }
+
+    /// Updates the directory to include the input label-value pairs, each conditional on the
+    /// label being at the version expected, such as that of an earlier lookup. A version of 0
+    /// denotes a label which is not yet in the directory.
+    ///
+    /// An update whose label is at a different version is rejected with a [VersionConflict],
+    /// rather than failing the whole publish, and the remaining updates are published. The
+    /// versions are checked against the state of the directory the updates are published to.
+    /// As with [Directory::publish], an error is returned if a label is repeated. The conflicts
+    /// are returned in the [PublishReport], along with the outcome for the published labels.
+    ///
+    /// The storage layer offers no conditional write, so the versions are not checked
+    /// atomically with the commit. Instead the epoch of the stored directory is read again just
+    /// before the commit, and the publish is rolled back with an error if another writer has
+    /// published in the meantime. This protects against writers which commit before that check,
+    /// such as another publish within the process, but a writer committing between the check
+    /// and the commit is not detected: as with every publish, the directory must still have a
+    /// single writer.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(updates = updates.len(), epoch = tracing::field::Empty)
+        )
+    )]
+    pub async fn publish_if_versions(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue, u64)>,
+    ) -> Result<PublishReport, AkdError> {
+        let mut expected_versions = HashMap::with_capacity(updates.len());
+        let updates = updates
+            .into_iter()
+            .map(|(akd_label, akd_value, expected_version)| {
+                expected_versions.insert(akd_label.clone(), expected_version);
+                (akd_label, akd_value)
+            })
+            .collect();
+        self.publish_if_expected(updates, Some(expected_versions), false)
+            .await
+    }
+
+    /// Updates the entries of labels holding [MultiEntryValue]s, merging the operations on
+    /// each label into its latest value to publish its next version. A label which is not
+    /// yet in the directory starts with no entries.
+    ///
+    /// The operations are merged into the values read before the publish, and are published
+    /// conditional on the labels still being at the versions read, as with
+    /// [Directory::publish_if_versions]. An update to a label whose value changed in the
+    /// meantime is rejected with a [VersionConflict] in the [PublishReport], and should be
+    /// retried. An error is returned, and nothing published, if a label is repeated, if the
+    /// latest value of a label is not a [MultiEntryValue], or if an operation cannot be
+    /// applied, such as the removal of an entry which does not exist.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(updates = updates.len(), epoch = tracing::field::Empty)
+        )
+    )]
+    pub async fn publish_entry_operations(
+        &self,
+        updates: Vec<(AkdLabel, Vec<EntryOperation>)>,
+    ) -> Result<PublishReport, AkdError> {
+        let current_epoch = self.retrieve_azks().await?.get_latest_epoch();
+        let labels = updates
+            .iter()
+            .map(|(akd_label, _)| akd_label.clone())
+            .collect::<Vec<_>>();
+        let latest_states = self
+            .storage
+            .get_user_states(&labels, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
+            .await?;
+
+        let mut expected_versions = HashMap::with_capacity(updates.len());
+        let mut merged_updates = Vec::with_capacity(updates.len());
+        for (akd_label, operations) in updates {
+            let (version, latest_value) = match latest_states.get(&akd_label) {
+                Some(state) => (state.version, MultiEntryValue::from_value(&state.value)),
+                None => (0, Ok(MultiEntryValue::default())),
+            };
+            let value = latest_value
+                .and_then(|latest_value| latest_value.apply(&operations))
+                .map_err(|err| {
+                    AkdError::Directory(DirectoryError::Publish(format!(
+                        "Cannot apply the entry operations on label {akd_label:?}: {err}"
+                    )))
+                })?;
+            expected_versions.insert(akd_label.clone(), version);
+            merged_updates.push((akd_label, value.to_value()));
+        }
+        self.publish_if_expected(merged_updates, Some(expected_versions), false)
+            .await
+    }
+
+    async fn publish_if_expected(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        mut expected_versions: Option<HashMap<AkdLabel, u64>>,
+        fail_on_rejection: bool,
+    ) -> Result<PublishReport, AkdError> {
         // The guard will be dropped at the end of the publish
         let _guard = self.cache_lock.read().await;
+        let publish_start = Instant::now();
+
+        let mut current_azks = self.retrieve_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        let next_epoch = current_epoch + 1;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", next_epoch);
+        self.get_pending_epoch(current_epoch, None).await?;
+
+        let (updates, rejected) = self
+            .apply_publish_policy(updates, expected_versions.as_mut(), current_epoch)
+            .await?;
+        if fail_on_rejection {
+            policy_rejection_result(&rejected)?;
+        }
 
         // Check for duplicate labels and return an error if any are encountered
         let distinct_set: HashSet<AkdLabel> =
@@ -101,103 +310,31 @@ where
             )));
         }
 
-        let mut update_set = Vec::<AzksElement>::new();
-        let mut user_data_update_set = Vec::<ValueState>::new();
-
-        let mut current_azks = self.retrieve_azks().await?;
-        let current_epoch = current_azks.get_latest_epoch();
-        let next_epoch = current_epoch + 1;
-
-        let mut keys: Vec<AkdLabel> = updates
-            .iter()
-            .map(|(akd_label, _val)| akd_label.clone())
-            .collect();
-
-        // sort the keys, as inserting in primary-key order is more efficient for MySQL
-        keys.sort();
-
-        // we're only using the maximum "version" of the user's state at the last epoch
-        // they were seen in the directory. Therefore we've minimized the call to only
-        // return a hashmap of AkdLabel => u64 and not retrieving the other data which is not
-        // read (i.e. the actual _data_ payload).
-        let all_user_versions_retrieved = self
-            .storage
-            .get_user_state_versions(&keys, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
-            .await?;
-
-        info!(
-            "Retrieved {} previous user versions of {} requested",
-            all_user_versions_retrieved.len(),
-            keys.len()
-        );
-
-        let vrf_computations = updates
-            .iter()
-            .flat_map(
-                |(akd_label, akd_value)| match all_user_versions_retrieved.get(akd_label) {
-                    None => vec![(
-                        akd_label.clone(),
-                        VersionFreshness::Fresh,
-                        1u64,
-                        akd_value.clone(),
-                    )],
-                    Some((latest_version, existing_akd_value)) => {
-                        if existing_akd_value == akd_value {
-                            // Skip this because the user is trying to re-publish the same value
-                            return vec![];
-                        }
-                        vec![
-                            (
-                                akd_label.clone(),
-                                VersionFreshness::Stale,
-                                *latest_version,
-                                akd_value.clone(),
-                            ),
-                            (
-                                akd_label.clone(),
-                                VersionFreshness::Fresh,
-                                *latest_version + 1,
-                                akd_value.clone(),
-                            ),
-                        ]
-                    }
-                },
-            )
-            .collect::<Vec<_>>();
-
-        let vrf_map = self
-            .vrf
-            .get_node_labels::<TC>(&vrf_computations)
-            .await?
-            .into_iter()
-            .collect::<HashMap<_, _>>();
-
         let commitment_key = self.derive_commitment_key().await?;
-
-        for ((akd_label, freshness, version, akd_value), node_label) in vrf_map {
-            let azks_value = match freshness {
-                VersionFreshness::Stale => TC::stale_azks_value(),
-                VersionFreshness::Fresh => {
-                    TC::compute_fresh_azks_value(&commitment_key, &node_label, version, &akd_value)
-                }
-            };
-            update_set.push(AzksElement {
-                label: node_label,
-                value: azks_value,
-            });
-
-            if freshness == VersionFreshness::Fresh {
-                let latest_state =
-                    ValueState::new(akd_label, akd_value, version, node_label, next_epoch);
-                user_data_update_set.push(latest_state);
-            }
-        }
+        let PublishUpdates {
+            update_set,
+            user_data_update_set,
+            labels,
+            conflicts,
+        } = self
+            .compute_publish_updates(
+                updates,
+                expected_versions.as_ref(),
+                current_epoch,
+                &commitment_key,
+            )
+            .await?;
 
         if update_set.is_empty() {
             info!("After filtering for duplicated user information, there is no publish which is necessary (0 updates)");
             // The AZKS has not been updated/mutated at this point, so we can just return the root hash from before
             let root_hash = current_azks.get_root_hash::<TC, _>(&self.storage).await?;
-            return Ok(EpochHash(current_epoch, root_hash));
+            return Ok(PublishReport {
+                epoch_hash: EpochHash(current_epoch, root_hash),
+                labels,
+                conflicts,
+                rejected,
+            });
         }
 
         if !self.storage.begin_transaction() {
@@ -208,9 +345,18 @@ where
         }
         info!("Starting inserting new leaves");
 
-        if let Err(err) = current_azks
-            .batch_insert_nodes::<TC, _>(&self.storage, update_set, InsertMode::Directory)
-            .await
+        let insert_start = Instant::now();
+        if let Err(err) = in_span!(
+            INFO,
+            current_azks.batch_insert_nodes::<TC, _>(
+                &self.storage,
+                update_set,
+                InsertMode::Directory
+            ),
+            "insert",
+            leaves = update_set.len()
+        )
+        .await
         {
             // If we fail to do the batch-leaf insert, we should rollback the transaction so we can try again cleanly.
             // Only fails if transaction is not currently active.
@@ -225,10 +371,26 @@ where
             updates.push(DbRecord::ValueState(update));
         }
         self.storage.batch_set(updates).await?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "insert")],
+            insert_start,
+        );
+
+        // The versions were checked against the epoch read at the start of the publish, so a
+        // conditional publish must not commit over an epoch published since
+        if expected_versions.is_some() {
+            if let Err(err) = self.check_stored_epoch(current_epoch).await {
+                let _ = self.storage.rollback_transaction();
+                return Err(err);
+            }
+        }
 
         // Commit the transaction
         info!("Committing transaction");
-        match self.storage.commit_transaction().await {
+        let commit_start = Instant::now();
+        match in_span!(INFO, self.storage.commit_transaction(), "commit").await {
             Ok(num_records) => {
                 info!("Transaction committed ({} records)", num_records);
             }
@@ -238,12 +400,457 @@ where
                 return Err(AkdError::Storage(err));
             }
         };
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "commit")],
+            commit_start,
+        );
 
         let root_hash = current_azks
             .get_root_hash_safe::<TC, _>(&self.storage, next_epoch)
             .await?;
 
-        Ok(EpochHash(next_epoch, root_hash))
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_DURATION_SECONDS,
+            &[],
+            publish_start,
+        );
+        Ok(PublishReport {
+            epoch_hash: EpochHash(next_epoch, root_hash),
+            labels,
+            conflicts,
+            rejected,
+        })
+    }
+
+    /// Updates the directory to include the label-value pairs read from an iterator, as
+    /// [Directory::publish] does. The updates are read `chunk_size` at a time, and the VRF
+    /// computations and tree insertions for each chunk are completed before the next is read,
+    /// so that the input need not be collected up front. The resulting tree is the same as if
+    /// the updates had been published at once.
+    ///
+    /// The records of each chunk are written directly to storage once its insertions are
+    /// complete, as [Directory::bootstrap] does, rather than in a transaction which would hold
+    /// every record of the epoch in memory until it is committed. Since the [Azks] is written
+    /// last, readers remain at the previous epoch until the publish is complete. A failed
+    /// publish is not rolled back, however: the records it wrote for the incomplete epoch
+    /// remain in storage. A [PendingEpoch] marker is written before the first of them, so
+    /// that nothing else is published to the directory (and it cannot be bootstrapped) until
+    /// the publish is retried with the same updates, resuming the epoch, and completes it.
+    /// The [EpochChangeset](crate::storage::types::EpochChangeset) of the epoch (if recorded)
+    /// is written in parts as the chunks are, covering those of any failed attempts.
+    ///
+    /// A label updated more than once returns an error, as with [Directory::publish]. Repeats
+    /// across chunks are detected from the value states already written for the epoch, so a
+    /// label repeated with the same value is published once, and one repeated with another
+    /// value fails the publish part way through. It should then be retried keeping only the
+    /// first update of the label. An earlier update which left the value of its label
+    /// unchanged writes no value state, so a later update of the label is published.
+    ///
+    /// The updates rejected by the publish policy (if any) are returned with the epoch hash.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(chunk_size = chunk_size, updates = tracing::field::Empty, epoch = tracing::field::Empty)
+        )
+    )]
+    pub async fn publish_from_iter<I>(
+        &self,
+        updates: I,
+        chunk_size: usize,
+    ) -> Result<(EpochHash, Vec<PolicyRejection>), AkdError>
+    where
+        I: IntoIterator<Item = (AkdLabel, AkdValue)>,
+        I::IntoIter: Send,
+    {
+        if chunk_size == 0 {
+            return Err(AkdError::Directory(DirectoryError::Publish(
+                "Cannot publish in chunks of no entries".to_string(),
+            )));
+        }
+
+        // The guard will be dropped at the end of the publish
+        let _guard = self.cache_lock.read().await;
+        let publish_start = Instant::now();
+
+        let mut current_azks = self.retrieve_azks().await?;
+        let current_epoch = current_azks.get_latest_epoch();
+        let next_epoch = current_epoch + 1;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", next_epoch);
+        let commitment_key = self.derive_commitment_key().await?;
+        let mut pending = self
+            .get_pending_epoch(current_epoch, Some(PendingOperation::PublishFromIter))
+            .await?;
+
+        let mut updates = updates.into_iter();
+        let mut num_updates = 0;
+        let mut num_leaves = 0;
+        let mut rejected = Vec::new();
+        loop {
+            let chunk = updates.by_ref().take(chunk_size).collect::<Vec<_>>();
+            if chunk.is_empty() {
+                break;
+            }
+            num_updates += chunk.len();
+            let (chunk, chunk_rejected) = self
+                .apply_publish_policy(chunk, None, current_epoch)
+                .await?;
+            rejected.extend(chunk_rejected);
+
+            // Check for duplicate labels within the chunk, and with the prior chunks through
+            // the value states they wrote for the epoch
+            let distinct_set: HashSet<AkdLabel> =
+                chunk.iter().map(|(label, _)| label.clone()).collect();
+            if distinct_set.len() != chunk.len() {
+                return Err(AkdError::Directory(DirectoryError::Publish(
+                    "Cannot publish with a set of entries that contain duplicate labels"
+                        .to_string(),
+                )));
+            }
+            let labels = distinct_set.into_iter().collect::<Vec<_>>();
+            let written_states = self
+                .storage
+                .get_user_states(&labels, ValueStateRetrievalFlag::SpecificEpoch(next_epoch))
+                .await?;
+            if chunk.iter().any(|(label, value)| {
+                written_states
+                    .get(label)
+                    .is_some_and(|state| state.value != *value)
+            }) {
+                return Err(AkdError::Directory(DirectoryError::Publish(
+                    "Cannot publish with a set of entries that contain duplicate labels"
+                        .to_string(),
+                )));
+            }
+
+            let PublishUpdates {
+                update_set,
+                user_data_update_set,
+                ..
+            } = self
+                .compute_publish_updates(chunk, None, current_epoch, &commitment_key)
+                .await?;
+            if update_set.is_empty() {
+                continue;
+            }
+
+            // The epoch is marked pending before anything is written for it
+            let pending = match &mut pending {
+                Some(pending) => pending,
+                None => {
+                    let marker = DbRecord::build_pending_epoch(
+                        next_epoch,
+                        PendingOperation::PublishFromIter,
+                        1,
+                    );
+                    self.storage
+                        .set(DbRecord::PendingEpoch(marker.clone()))
+                        .await?;
+                    pending.insert(marker)
+                }
+            };
+
+            let insert_start = Instant::now();
+            let leaves = update_set.len();
+            if !self.storage.begin_transaction() {
+                error!("Transaction is already active");
+                return Err(AkdError::Storage(StorageError::Transaction(
+                    "Transaction is already active".to_string(),
+                )));
+            }
+            let written = async {
+                if num_leaves == 0 {
+                    current_azks
+                        .batch_insert_nodes::<TC, _>(
+                            &self.storage,
+                            update_set,
+                            InsertMode::Directory,
+                        )
+                        .await?;
+                } else {
+                    current_azks
+                        .batch_insert_nodes_in_latest_epoch::<TC, _>(
+                            &self.storage,
+                            update_set,
+                            InsertMode::Directory,
+                        )
+                        .await?;
+                }
+                // The value states of the chunk are written along with its tree nodes
+                self.storage
+                    .batch_set(
+                        user_data_update_set
+                            .into_iter()
+                            .map(DbRecord::ValueState)
+                            .collect(),
+                    )
+                    .await?;
+                self.storage.write_pending_transaction(pending).await?;
+                Ok::<_, AkdError>(())
+            }
+            .await;
+            if let Err(err) = written {
+                if self.storage.is_transaction_active() {
+                    let _ = self.storage.rollback_transaction();
+                }
+                return Err(err);
+            }
+            num_leaves += leaves;
+            record_duration(
+                self.storage.metrics_recorder(),
+                PUBLISH_PHASE_DURATION_SECONDS,
+                &[("phase", "insert")],
+                insert_start,
+            );
+            info!("Inserted {num_leaves} leaves from {num_updates} updates");
+        }
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("updates", num_updates);
+
+        let Some(pending) = pending.filter(|_| num_leaves > 0) else {
+            info!("After filtering for duplicated user information, there is no publish which is necessary (0 updates)");
+            // The AZKS has not been updated/mutated at this point, so we can just return the root hash from before
+            let root_hash = current_azks.get_root_hash::<TC, _>(&self.storage).await?;
+            return Ok((EpochHash(current_epoch, root_hash), rejected));
+        };
+
+        // The AZKS is written last, completing the epoch
+        let commit_start = Instant::now();
+        self.storage
+            .complete_pending_epoch(&pending, current_azks.clone())
+            .await?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "commit")],
+            commit_start,
+        );
+
+        // load the pinned upper levels of the new epoch (if configured)
+        self.storage.warm_pinned_nodes().await?;
+
+        let root_hash = current_azks
+            .get_root_hash_safe::<TC, _>(&self.storage, next_epoch)
+            .await?;
+
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_DURATION_SECONDS,
+            &[],
+            publish_start,
+        );
+        Ok((EpochHash(next_epoch, root_hash), rejected))
+    }
+
+    /// Bootstraps a new, empty directory with an initial population of label-value pairs as
+    /// its first epoch. Rather than inserting the entries into the tree as [Directory::publish]
+    /// does, the tree is built bottom-up from their sorted VRF labels, and no previous versions
+    /// are looked up. The resulting tree is the same as if the entries had been published.
+    ///
+    /// The entries are read from the iterator and their VRF labels computed a batch at a
+    /// time. Their value states are sorted by VRF label with an external sort, which spills
+    /// runs of states to temporary files (see [Directory::with_spill_dir]), so that the
+    /// entries need not fit in memory. Once
+    /// all of the entries are read and checked for repeated labels, the tree is built from the
+    /// sorted states, its nodes are written, then the value states, and the [Azks] last.
+    ///
+    /// The records are written directly to storage in batches rather than in a transaction,
+    /// which would hold every record in memory until it is committed. Since the [Azks] is
+    /// written last, the directory remains empty to readers until the bootstrap is complete.
+    /// A [PendingEpoch] marker is written before the first of the records, so that nothing is
+    /// published to the directory after a failed bootstrap until the bootstrap is retried with
+    /// the same entries, overwriting any records it wrote. As with [Directory::publish], an
+    /// error is returned (prior to writing anything) if a label is repeated, or if the publish
+    /// policy of the directory (if any) rejects an entry.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(updates = tracing::field::Empty))
+    )]
+    pub async fn bootstrap<I>(&self, updates: I) -> Result<EpochHash, AkdError>
+    where
+        I: IntoIterator<Item = (AkdLabel, AkdValue)>,
+        I::IntoIter: Send,
+    {
+        self.bootstrap_in_batches(
+            updates.into_iter().map(Ok),
+            BOOTSTRAP_BATCH_SIZE,
+            BOOTSTRAP_RUN_SIZE,
+        )
+        .await
+    }
+
+    /// Bootstraps the directory as [Directory::bootstrap] does, from entries which may fail to
+    /// be read (e.g. those parsed from a file). Since every entry is read before anything is
+    /// written, the first error fails the bootstrap with nothing written.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(updates = tracing::field::Empty))
+    )]
+    pub async fn try_bootstrap<I>(&self, updates: I) -> Result<EpochHash, AkdError>
+    where
+        I: IntoIterator<Item = Result<(AkdLabel, AkdValue), AkdError>>,
+        I::IntoIter: Send,
+    {
+        self.bootstrap_in_batches(updates, BOOTSTRAP_BATCH_SIZE, BOOTSTRAP_RUN_SIZE)
+            .await
+    }
+
+    /// See [Directory::try_bootstrap], reading entries and writing records `batch_size` at a
+    /// time, and sorting runs of `run_size` value states in memory
+    pub(crate) async fn bootstrap_in_batches<I>(
+        &self,
+        updates: I,
+        batch_size: usize,
+        run_size: usize,
+    ) -> Result<EpochHash, AkdError>
+    where
+        I: IntoIterator<Item = Result<(AkdLabel, AkdValue), AkdError>>,
+        I::IntoIter: Send,
+    {
+        // The guard will be dropped at the end of the bootstrap
+        let _guard = self.cache_lock.read().await;
+        let publish_start = Instant::now();
+
+        let mut current_azks = self.retrieve_azks().await?;
+        if current_azks.get_latest_epoch() != 0 {
+            return Err(AkdError::Directory(DirectoryError::Publish(
+                "Cannot bootstrap a directory which has already been published to".to_string(),
+            )));
+        }
+        let pending = self
+            .get_pending_epoch(0, Some(PendingOperation::Bootstrap))
+            .await?;
+        let epoch = 1;
+        let commitment_key = self.derive_commitment_key().await?;
+
+        let mut updates = updates.into_iter();
+        let spill_dir = self.spill_dir.clone().unwrap_or_else(std::env::temp_dir);
+        let mut states = SortedValueStates::new(run_size, spill_dir);
+        let vrf_start = Instant::now();
+        loop {
+            let batch = updates
+                .by_ref()
+                .take(batch_size)
+                .collect::<Result<Vec<_>, _>>()?;
+            if batch.is_empty() {
+                break;
+            }
+            let (batch, rejected) = self.apply_publish_policy(batch, None, 0).await?;
+            policy_rejection_result(&rejected)?;
+            let vrf_computations = batch
+                .into_iter()
+                .map(|(akd_label, akd_value)| (akd_label, VersionFreshness::Fresh, 1, akd_value))
+                .collect::<Vec<_>>();
+            let vrf_results = in_span!(
+                INFO,
+                self.vrf.get_node_labels::<TC>(&vrf_computations),
+                "vrf",
+                computations = vrf_computations.len()
+            )
+            .await?;
+            for ((akd_label, _, version, akd_value), node_label) in vrf_results {
+                states.push(ValueState::new(
+                    akd_label, akd_value, version, node_label, epoch,
+                ))?;
+            }
+        }
+        states.finish()?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "vrf")],
+            vrf_start,
+        );
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("updates", states.len());
+
+        if states.len() == 0 {
+            info!("There is no bootstrap which is necessary (0 updates)");
+            let root_hash = current_azks.get_root_hash::<TC, _>(&self.storage).await?;
+            return Ok(EpochHash(0, root_hash));
+        }
+
+        // Check for duplicate labels, which have the same VRF label, before anything is written
+        let mut previous = None;
+        for state in states.iter()? {
+            let label = state?.label;
+            if previous == Some(label) {
+                return Err(AkdError::Directory(DirectoryError::Publish(
+                    "Cannot bootstrap with a set of entries that contain duplicate labels"
+                        .to_string(),
+                )));
+            }
+            previous = Some(label);
+        }
+
+        // The epoch is marked pending before anything is written for it (unless resumed)
+        if pending.is_none() {
+            let marker = DbRecord::build_pending_epoch(epoch, PendingOperation::Bootstrap, 1);
+            self.storage.set(DbRecord::PendingEpoch(marker)).await?;
+        }
+
+        info!("Building the tree of {} leaves", states.len());
+        let insert_start = Instant::now();
+        let leaves = states.iter()?.map(|state| {
+            let state = state?;
+            Ok(AzksElement {
+                label: state.label,
+                value: TC::compute_fresh_azks_value(
+                    &commitment_key,
+                    &state.label,
+                    state.version,
+                    &state.value,
+                ),
+            })
+        });
+        in_span!(
+            INFO,
+            current_azks.bootstrap_nodes::<TC, _, _>(&self.storage, leaves, batch_size),
+            "insert",
+            leaves = states.len()
+        )
+        .await?;
+
+        // The value states are written once the tree is complete, and the AZKS last
+        let mut user_data_update_set = Vec::with_capacity(batch_size);
+        for state in states.iter()? {
+            user_data_update_set.push(DbRecord::ValueState(state?));
+            if user_data_update_set.len() >= batch_size {
+                self.storage
+                    .batch_set(std::mem::take(&mut user_data_update_set))
+                    .await?;
+            }
+        }
+        self.storage.batch_set(user_data_update_set).await?;
+        drop(states);
+        self.storage
+            .set(DbRecord::Azks(current_azks.clone()))
+            .await?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "insert")],
+            insert_start,
+        );
+
+        // load the pinned upper levels of the new tree (if configured)
+        self.storage.warm_pinned_nodes().await?;
+
+        let root_hash = current_azks
+            .get_root_hash_safe::<TC, _>(&self.storage, epoch)
+            .await?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_DURATION_SECONDS,
+            &[],
+            publish_start,
+        );
+        Ok(EpochHash(epoch, root_hash))
     }
 
     /// Provides proof for correctness of latest version
@@ -252,12 +859,19 @@ where
     ///
     /// Returns [Ok((LookupProof, EpochHash))] upon successful generation for the latest version
     /// of the target label's state. [Err(_)] otherwise
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(epoch = tracing::field::Empty))
+    )]
     pub async fn lookup(&self, akd_label: AkdLabel) -> Result<(LookupProof, EpochHash), AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
+        let start = Instant::now();
 
         let current_azks = self.retrieve_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
         let lookup_info = self.get_lookup_info(akd_label, current_epoch).await?;
 
         let root_hash = EpochHash(
@@ -267,6 +881,12 @@ where
         let proof = self
             .lookup_with_info(&current_azks, lookup_info, false)
             .await?;
+        record_duration(
+            self.storage.metrics_recorder(),
+            PROOF_GENERATION_DURATION_SECONDS,
+            &[("proof", "lookup")],
+            start,
+        );
         Ok((proof, root_hash))
     }
 
@@ -349,15 +969,26 @@ where
 
     // TODO(eoz): Call proof generations async
     /// Allows efficient batch lookups by preloading necessary nodes for the lookups.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(labels = akd_labels.len(), epoch = tracing::field::Empty)
+        )
+    )]
     pub async fn batch_lookup(
         &self,
         akd_labels: &[AkdLabel],
     ) -> Result<(Vec<LookupProof>, EpochHash), AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
+        let start = Instant::now();
 
         let current_azks = self.retrieve_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
 
         // Take a union of the labels we will need proofs of for each lookup.
         let mut lookup_infos = Vec::new();
@@ -387,6 +1018,12 @@ where
             lookup_proofs.push(self.lookup_with_info(&current_azks, info, true).await?);
         }
 
+        record_duration(
+            self.storage.metrics_recorder(),
+            PROOF_GENERATION_DURATION_SECONDS,
+            &[("proof", "batch_lookup")],
+            start,
+        );
         Ok((lookup_proofs, root_hash))
     }
 
@@ -427,6 +1064,8 @@ where
             .get_user_state(&akd_label, ValueStateRetrievalFlag::LeqEpoch(epoch))
             .await
         {
+            // a tampered record must not be reported as a missing user
+            Err(err @ StorageError::IntegrityViolation(_)) => Err(AkdError::Storage(err)),
             Err(_) => {
                 // Need to throw an error
                 match std::str::from_utf8(&akd_label) {
@@ -447,6 +1086,14 @@ where
     /// this function returns all the values ever associated with it,
     /// and the epoch at which each value was first committed to the server state.
     /// It also returns the proof of the latest version being served at all times.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip_all,
+            fields(versions = tracing::field::Empty, epoch = tracing::field::Empty)
+        )
+    )]
     pub async fn key_history(
         &self,
         akd_label: &AkdLabel,
@@ -454,9 +1101,12 @@ where
     ) -> Result<(HistoryProof, EpochHash), AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
+        let start = Instant::now();
 
         let current_azks = self.retrieve_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", current_epoch);
         let mut user_data = self.storage.get_user_data(akd_label).await?.states;
 
         // reverse sort from highest epoch to lowest
@@ -487,6 +1137,8 @@ where
             };
             return Err(AkdError::Storage(StorageError::NotFound(msg)));
         }
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("versions", user_data.len());
 
         #[cfg(feature = "preload_history")]
         {
@@ -568,6 +1220,12 @@ where
             current_azks.get_root_hash::<TC, _>(&self.storage).await?,
         );
 
+        record_duration(
+            self.storage.metrics_recorder(),
+            PROOF_GENERATION_DURATION_SECONDS,
+            &[("proof", "key_history")],
+            start,
+        );
         Ok((
             HistoryProof {
                 update_proofs,
@@ -581,11 +1239,16 @@ where
     }
 
     /// Poll for changes in the epoch number of the AZKS struct
-    /// stored in the storage layer. If an epoch change is detected,
-    /// the object cache (if present) is flushed immediately so
-    /// that new objects are retrieved from the storage layer against
-    /// the "latest" epoch. There is a "special" flow in the storage layer
-    /// to do a storage-layer retrieval which ignores the cache
+    /// stored in the storage layer. If the database supports push-based
+    /// change notification (see [Database::subscribe_epoch_changes]), changes
+    /// are checked for as soon as they're published, and at least every `period`
+    /// otherwise. If an epoch change is detected,
+    /// the records modified since the last seen epoch are evicted from the
+    /// object cache (if present) immediately so that new objects are retrieved
+    /// from the storage layer against the "latest" epoch. If the writer does not
+    /// record epoch changesets, the cache is flushed in its entirety. There is a
+    /// "special" flow in the storage layer to do a storage-layer retrieval which
+    /// ignores the cache
     pub async fn poll_for_azks_changes(
         &self,
         period: tokio::time::Duration,
@@ -594,10 +1257,20 @@ where
         // Retrieve the same AZKS that all the other calls see (i.e. the version that could be cached
         // at this point). We'll compare this via an uncached call when a change is notified
         let mut last = Directory::<TC, S, V>::get_azks_from_storage(&self.storage, false).await?;
+        let mut epoch_changes = self.storage.subscribe_epoch_changes();
 
         loop {
             // loop forever polling for changes
-            tokio::time::sleep(period).await;
+            match &mut epoch_changes {
+                Some(changes) => {
+                    // wait for a change notification, re-checking at least every period
+                    if let Ok(None) = tokio::time::timeout(period, changes.next()).await {
+                        info!("The epoch change feed has closed, falling back to polling");
+                        epoch_changes = None;
+                    }
+                }
+                None => tokio::time::sleep(period).await,
+            }
 
             let latest = Directory::<TC, S, V>::get_azks_from_storage(&self.storage, true).await?;
             if latest.latest_epoch > last.latest_epoch {
@@ -605,8 +1278,10 @@ where
                     // acquire a singleton lock prior to flushing the cache to assert that no
                     // cache accesses are underway (i.e. publish/proof generations/etc)
                     let _guard = self.cache_lock.write().await;
-                    // flush the cache in its entirety
-                    self.storage.flush_cache().await;
+                    // evict the records which were modified since the last seen epoch
+                    self.storage
+                        .invalidate_cache_since(last.latest_epoch, latest.latest_epoch)
+                        .await;
                     // re-fetch the azks to load it into cache so when we release the cache lock
                     // others will see the new AZKS loaded up and ready
                     last =
@@ -631,6 +1306,7 @@ where
 
     /// Returns an [AppendOnlyProof] for the leaves inserted into the underlying tree between
     /// the epochs `audit_start_ep` and `audit_end_ep`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "info", skip(self)))]
     pub async fn audit(
         &self,
         audit_start_ep: u64,
@@ -638,6 +1314,7 @@ where
     ) -> Result<AppendOnlyProof, AkdError> {
         // The guard will be dropped at the end of the proof generation
         let _guard = self.cache_lock.read().await;
+        let start = Instant::now();
 
         let current_azks = self.retrieve_azks().await?;
         let current_epoch = current_azks.get_latest_epoch();
@@ -656,6 +1333,14 @@ where
                 .get_append_only_proof::<TC, _>(&self.storage, audit_start_ep, audit_end_ep)
                 .await;
             self.storage.enable_cache_cleaning();
+            if result.is_ok() {
+                record_duration(
+                    self.storage.metrics_recorder(),
+                    PROOF_GENERATION_DURATION_SECONDS,
+                    &[("proof", "audit")],
+                    start,
+                );
+            }
             result
         }
     }
@@ -665,6 +1350,20 @@ where
         Directory::<TC, S, V>::get_azks_from_storage(&self.storage, false).await
     }
 
+    /// Checks that the directory in storage is still at the given epoch, reading the AZKS
+    /// directly so that neither the cache nor the active transaction masks another writer
+    async fn check_stored_epoch(&self, epoch: u64) -> Result<(), AkdError> {
+        let stored_epoch = Directory::<TC, S, V>::get_azks_from_storage(&self.storage, true)
+            .await?
+            .get_latest_epoch();
+        if stored_epoch != epoch {
+            return Err(AkdError::Directory(DirectoryError::Publish(format!(
+                "The directory was published to at epoch {stored_epoch} while publishing over epoch {epoch}"
+            ))));
+        }
+        Ok(())
+    }
+
     async fn get_azks_from_storage(
         storage: &StorageManager<S>,
         ignore_cache: bool,
@@ -689,6 +1388,46 @@ where
         }
     }
 
+    /// Check that the records held in storage are of a schema version which is supported
+    async fn check_storage_schema_version(storage: &StorageManager<S>) -> Result<(), AkdError> {
+        let stored = match storage
+            .get_direct::<SchemaVersion>(&DEFAULT_SCHEMA_VERSION_KEY)
+            .await
+        {
+            Ok(DbRecord::SchemaVersion(schema)) => Some(schema.version),
+            Ok(_) | Err(StorageError::NotFound(_)) => None,
+            Err(other) => return Err(other.into()),
+        };
+        check_schema_version(stored)?;
+        Ok(())
+    }
+
+    /// Retrieves the [PendingEpoch] marker of an epoch later than the current one, which was
+    /// left by an incomplete publish. The marker is returned if it was left by the given
+    /// operation (which may then resume the epoch); otherwise a pending epoch is an error.
+    async fn get_pending_epoch(
+        &self,
+        current_epoch: u64,
+        operation: Option<PendingOperation>,
+    ) -> Result<Option<PendingEpoch>, AkdError> {
+        let pending = match self
+            .storage
+            .get_direct::<PendingEpoch>(&DEFAULT_PENDING_EPOCH_KEY)
+            .await
+        {
+            Ok(DbRecord::PendingEpoch(pending)) if pending.epoch > current_epoch => pending,
+            Ok(_) | Err(StorageError::NotFound(_)) => return Ok(None),
+            Err(other) => return Err(other.into()),
+        };
+        if operation == Some(pending.operation) {
+            return Ok(Some(pending));
+        }
+        Err(AkdError::Directory(DirectoryError::Publish(format!(
+            "Epoch {} is pending and must be completed by retrying the {:?} which started it with the same updates",
+            pending.epoch, pending.operation
+        ))))
+    }
+
     /// HELPERS ///
 
     /// Use this function to retrieve the [VRFPublicKey] for this AKD.
@@ -765,6 +1504,294 @@ where
         Ok(EpochHash(latest_epoch, root_hash))
     }
 
+    /// Applies the publish policy (if any) to a set of updates to be published in the epoch
+    /// following `current_epoch`, returning those accepted, as normalized, and the rejections.
+    /// The expected versions of normalized labels are moved to the normalized labels.
+    ///
+    /// An update normalized to another label is checked again, given the latest state of the
+    /// normalized label, as that is the label it updates. It's rejected unless the policy then
+    /// accepts it, or normalizes it to the same label.
+    async fn apply_publish_policy(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        mut expected_versions: Option<&mut HashMap<AkdLabel, u64>>,
+        current_epoch: u64,
+    ) -> Result<(Vec<(AkdLabel, AkdValue)>, Vec<PolicyRejection>), AkdError> {
+        let policy = match &self.publish_policy {
+            Some(policy) => policy,
+            None => return Ok((updates, vec![])),
+        };
+
+        let labels = updates
+            .iter()
+            .map(|(akd_label, _)| akd_label.clone())
+            .collect::<Vec<_>>();
+        let previous_states = self
+            .storage
+            .get_user_states(&labels, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
+            .await?;
+
+        let mut decisions = Vec::with_capacity(updates.len());
+        let mut normalized_labels = Vec::new();
+        for (akd_label, akd_value) in updates {
+            let decision = policy.check(&akd_label, &akd_value, previous_states.get(&akd_label));
+            if let PolicyDecision::Normalize(normalized_label, _) = &decision {
+                if normalized_label != &akd_label {
+                    normalized_labels.push(normalized_label.clone());
+                }
+            }
+            decisions.push((akd_label, akd_value, decision));
+        }
+        let normalized_states = if normalized_labels.is_empty() {
+            HashMap::new()
+        } else {
+            self.storage
+                .get_user_states(
+                    &normalized_labels,
+                    ValueStateRetrievalFlag::LeqEpoch(current_epoch),
+                )
+                .await?
+        };
+
+        let mut accepted = Vec::with_capacity(decisions.len());
+        let mut rejected = Vec::new();
+        for (akd_label, akd_value, decision) in decisions {
+            let decision = match decision {
+                PolicyDecision::Normalize(normalized_label, normalized_value)
+                    if normalized_label != akd_label =>
+                {
+                    match policy.check(
+                        &normalized_label,
+                        &normalized_value,
+                        normalized_states.get(&normalized_label),
+                    ) {
+                        PolicyDecision::Accept => {
+                            PolicyDecision::Normalize(normalized_label, normalized_value)
+                        }
+                        PolicyDecision::Normalize(label, _) if label != normalized_label => {
+                            PolicyDecision::Reject(format!(
+                                "The label was normalized again, from {normalized_label:?} to {label:?}"
+                            ))
+                        }
+                        decision => decision,
+                    }
+                }
+                decision => decision,
+            };
+            match decision {
+                PolicyDecision::Accept => accepted.push((akd_label, akd_value)),
+                PolicyDecision::Reject(reason) => rejected.push(PolicyRejection {
+                    label: akd_label,
+                    reason,
+                }),
+                PolicyDecision::Normalize(normalized_label, normalized_value) => {
+                    if let Some(expected_versions) = expected_versions.as_mut() {
+                        if let Some(expected_version) = expected_versions.remove(&akd_label) {
+                            expected_versions.insert(normalized_label.clone(), expected_version);
+                        }
+                    }
+                    accepted.push((normalized_label, normalized_value));
+                }
+            }
+        }
+        if !rejected.is_empty() {
+            info!("Rejected {} updates by the publish policy", rejected.len());
+        }
+
+        Ok((accepted, rejected))
+    }
+
+    /// Computes the leaves to insert in the tree and the new value states for a set of updates
+    /// to be published in the epoch following `current_epoch`. Updates which re-publish the
+    /// latest value of a label are skipped, and those whose label is not at the version
+    /// expected (if any) are rejected. The outcome for each label is returned alongside.
+    async fn compute_publish_updates(
+        &self,
+        updates: Vec<(AkdLabel, AkdValue)>,
+        expected_versions: Option<&HashMap<AkdLabel, u64>>,
+        current_epoch: u64,
+        commitment_key: &Digest,
+    ) -> Result<PublishUpdates, AkdError> {
+        let next_epoch = current_epoch + 1;
+        let mut update_set = Vec::<AzksElement>::new();
+        let mut user_data_update_set = Vec::<ValueState>::new();
+
+        let mut keys: Vec<AkdLabel> = updates
+            .iter()
+            .map(|(akd_label, _val)| akd_label.clone())
+            .collect();
+
+        // sort the keys, as inserting in primary-key order is more efficient for MySQL
+        keys.sort();
+
+        // we're only using the maximum "version" of the user's state at the last epoch
+        // they were seen in the directory. Therefore we've minimized the call to only
+        // return a hashmap of AkdLabel => u64 and not retrieving the other data which is not
+        // read (i.e. the actual _data_ payload).
+        let all_user_versions_retrieved = self
+            .storage
+            .get_user_state_versions(&keys, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
+            .await?;
+
+        info!(
+            "Retrieved {} previous user versions of {} requested",
+            all_user_versions_retrieved.len(),
+            keys.len()
+        );
+
+        let mut conflicts = Vec::new();
+        let updates = match expected_versions {
+            None => updates,
+            Some(expected_versions) => updates
+                .into_iter()
+                .filter(|(akd_label, _)| {
+                    let current_version = all_user_versions_retrieved
+                        .get(akd_label)
+                        .map_or(0, |(version, _)| *version);
+                    match expected_versions.get(akd_label) {
+                        Some(&expected_version) if expected_version != current_version => {
+                            conflicts.push(VersionConflict {
+                                label: akd_label.clone(),
+                                expected_version,
+                                current_version,
+                            });
+                            false
+                        }
+                        _ => true,
+                    }
+                })
+                .collect(),
+        };
+        if !conflicts.is_empty() {
+            info!(
+                "Rejected {} updates with version conflicts",
+                conflicts.len()
+            );
+        }
+
+        let mut unchanged = Vec::new();
+        let vrf_computations = updates
+            .iter()
+            .flat_map(
+                |(akd_label, akd_value)| match all_user_versions_retrieved.get(akd_label) {
+                    None => vec![(
+                        akd_label.clone(),
+                        VersionFreshness::Fresh,
+                        1u64,
+                        akd_value.clone(),
+                    )],
+                    Some((latest_version, existing_akd_value)) => {
+                        if existing_akd_value == akd_value {
+                            // Skip this because the user is trying to re-publish the same value
+                            unchanged.push(akd_label.clone());
+                            return vec![];
+                        }
+                        vec![
+                            (
+                                akd_label.clone(),
+                                VersionFreshness::Stale,
+                                *latest_version,
+                                akd_value.clone(),
+                            ),
+                            (
+                                akd_label.clone(),
+                                VersionFreshness::Fresh,
+                                *latest_version + 1,
+                                akd_value.clone(),
+                            ),
+                        ]
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let vrf_start = Instant::now();
+        let vrf_map = in_span!(
+            INFO,
+            self.vrf.get_node_labels::<TC>(&vrf_computations),
+            "vrf",
+            computations = vrf_computations.len()
+        )
+        .await?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        record_duration(
+            self.storage.metrics_recorder(),
+            PUBLISH_PHASE_DURATION_SECONDS,
+            &[("phase", "vrf")],
+            vrf_start,
+        );
+
+        let mut published_labels = HashMap::new();
+        // the node labels of unchanged labels are those of their stored states, rather than
+        // being computed again
+        if !unchanged.is_empty() {
+            let unchanged_states = self
+                .storage
+                .get_user_states(&unchanged, ValueStateRetrievalFlag::LeqEpoch(current_epoch))
+                .await?;
+            for (akd_label, state) in unchanged_states {
+                published_labels.insert(
+                    akd_label.clone(),
+                    PublishedLabel {
+                        label: akd_label,
+                        status: PublishStatus::Unchanged,
+                        version: state.version,
+                        node_label: state.label,
+                    },
+                );
+            }
+        }
+
+        for ((akd_label, freshness, version, akd_value), node_label) in vrf_map {
+            if freshness == VersionFreshness::Fresh {
+                let status = if version == 1 {
+                    PublishStatus::Inserted
+                } else {
+                    PublishStatus::Updated
+                };
+                published_labels.insert(
+                    akd_label.clone(),
+                    PublishedLabel {
+                        label: akd_label.clone(),
+                        status,
+                        version,
+                        node_label,
+                    },
+                );
+            }
+
+            let azks_value = match freshness {
+                VersionFreshness::Stale => TC::stale_azks_value(),
+                VersionFreshness::Fresh => {
+                    TC::compute_fresh_azks_value(commitment_key, &node_label, version, &akd_value)
+                }
+            };
+            update_set.push(AzksElement {
+                label: node_label,
+                value: azks_value,
+            });
+
+            if freshness == VersionFreshness::Fresh {
+                let latest_state =
+                    ValueState::new(akd_label, akd_value, version, node_label, next_epoch);
+                user_data_update_set.push(latest_state);
+            }
+        }
+
+        let labels = updates
+            .iter()
+            .filter_map(|(akd_label, _)| published_labels.remove(akd_label))
+            .collect();
+
+        Ok(PublishUpdates {
+            update_set,
+            user_data_update_set,
+            labels,
+            conflicts,
+        })
+    }
+
     // We simply hash the VRF private key to derive the commitment key
     async fn derive_commitment_key(&self) -> Result<Digest, AkdError> {
         let raw_key = self.vrf.retrieve().await?;
@@ -773,6 +1800,14 @@ where
     }
 }
 
+/// The changes to the directory computed for a set of updates to be published
+struct PublishUpdates {
+    update_set: Vec<AzksElement>,
+    user_data_update_set: Vec<ValueState>,
+    labels: Vec<PublishedLabel>,
+    conflicts: Vec<VersionConflict>,
+}
+
 /// A thin newtype which offers read-only interactivity with a [Directory].
 #[derive(Clone)]
 pub struct ReadOnlyDirectory<TC, S, V>(Directory<TC, S, V>)
@@ -801,11 +1836,17 @@ where
                 ),
             )));
         }
+        Directory::<TC, S, V>::check_storage_schema_version(&storage).await?;
+
+        // load the pinned upper levels of the tree (if configured)
+        storage.warm_pinned_nodes().await?;
 
         Ok(Self(Directory {
             storage,
             cache_lock: Arc::new(RwLock::new(())),
             vrf,
+            publish_policy: None,
+            spill_dir: None,
             tc: PhantomData,
         }))
     }
@@ -884,6 +1925,19 @@ impl Default for HistoryParams {
 
 /// Helpers
 
+/// Fails a publish which must not proceed with any updates rejected by the publish policy
+fn policy_rejection_result(rejected: &[PolicyRejection]) -> Result<(), AkdError> {
+    match rejected.first() {
+        Some(rejection) => Err(AkdError::Directory(DirectoryError::Publish(format!(
+            "{} updates were rejected by the publish policy, including the update to label {:?}: {}",
+            rejected.len(),
+            rejection.label,
+            rejection.reason
+        )))),
+        None => Ok(()),
+    }
+}
+
 pub(crate) fn get_marker_version(version: u64) -> u64 {
     (64 - version.leading_zeros() - 1).into()
 }
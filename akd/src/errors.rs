@@ -230,6 +230,9 @@ pub enum DirectoryError {
     ReadOnlyDirectory(String),
     /// Publish
     Publish(String),
+    /// A publish of updates queued by a [crate::publish_queue::PublishQueue] failed, with the
+    /// error shared by each of the updates it included
+    QueuedPublish(std::sync::Arc<AkdError>),
 }
 
 impl std::error::Error for DirectoryError {}
@@ -249,6 +252,9 @@ impl fmt::Display for DirectoryError {
             Self::Publish(inner_message) => {
                 write!(f, "Directory publish error: {inner_message}")
             }
+            Self::QueuedPublish(err) => {
+                write!(f, "Queued publish error: {err}")
+            }
         }
     }
 }
@@ -271,10 +277,30 @@ pub enum StorageError {
     Connection(String),
     /// Some other storage-layer error occurred
     Other(String),
+    /// A stored record failed its integrity check, meaning it was modified outside of the
+    /// directory
+    IntegrityViolation(String),
 }
 
 impl std::error::Error for StorageError {}
 
+impl StorageError {
+    /// Whether the error is transient, such that retrying the operation may succeed.
+    ///
+    /// Only [StorageError::Connection] errors are retryable. Storage layers should report
+    /// failures of the connection to (or temporary unavailability of) the backend as such,
+    /// and failures which would recur on a retry as [StorageError::Other].
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StorageError::Connection(_) => true,
+            StorageError::NotFound(_)
+            | StorageError::Transaction(_)
+            | StorageError::Other(_)
+            | StorageError::IntegrityViolation(_) => false,
+        }
+    }
+}
+
 impl fmt::Display for StorageError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -290,6 +316,9 @@ impl fmt::Display for StorageError {
             StorageError::Other(inner) => {
                 write!(f, "Other storage error: {inner}")
             }
+            StorageError::IntegrityViolation(inner) => {
+                write!(f, "Storage integrity violation: {inner}")
+            }
         }
     }
 }
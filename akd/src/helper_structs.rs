@@ -8,8 +8,9 @@
 //! Helper structs that are used for various data structures,
 //! to make it easier to pass arguments around.
 
+use crate::publish_policy::PolicyRejection;
 use crate::Digest;
-use crate::{storage::types::ValueState, NodeLabel};
+use crate::{storage::types::ValueState, AkdLabel, NodeLabel};
 
 /// Root hash of the tree and its associated epoch
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -26,6 +27,58 @@ impl EpochHash {
     }
 }
 
+/// The rejection of a conditional update, as its label was not at the version expected.
+/// A version of 0 denotes a label which is not in the directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict {
+    /// The label of the rejected update
+    pub label: AkdLabel,
+    /// The version the update expected the label to be at
+    pub expected_version: u64,
+    /// The version the label was at
+    pub current_version: u64,
+}
+
+/// The outcome of a publish for a label
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishStatus {
+    /// The label was not in the directory, and was added at version 1
+    Inserted,
+    /// The label was in the directory, and its version was incremented
+    Updated,
+    /// The label was already at the value published, so was left unchanged
+    Unchanged,
+}
+
+/// The outcome of a publish for a label, along with its version following the publish
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedLabel {
+    /// The label published
+    pub label: AkdLabel,
+    /// Whether the label was inserted, updated, or left unchanged
+    pub status: PublishStatus,
+    /// The version of the label following the publish
+    pub version: u64,
+    /// The node label of this version of the label in the tree
+    pub node_label: NodeLabel,
+}
+
+/// The result of a publish, detailing the outcome for each of the labels published
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishReport {
+    /// The epoch and root hash of the directory following the publish. If no label was
+    /// inserted or updated, no new epoch is published, and this is the latest epoch.
+    pub epoch_hash: EpochHash,
+    /// The outcome for each of the labels published, in the order the updates were provided.
+    /// Labels normalized by the publish policy of the directory are listed as normalized.
+    pub labels: Vec<PublishedLabel>,
+    /// The conditional updates which were rejected, as their labels were not at the version
+    /// expected
+    pub conflicts: Vec<VersionConflict>,
+    /// The updates which were rejected by the publish policy of the directory
+    pub rejected: Vec<PolicyRejection>,
+}
+
 #[derive(Clone, Debug)]
 /// Info needed for a lookup of a user for an epoch
 pub struct LookupInfo {
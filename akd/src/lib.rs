@@ -462,6 +462,7 @@
 //! in the event you wish to directly serialize the structures to transmit between library <-> storage layer or library <-> clients. If you're
 //! also utilizing VRFs (see (2.) below) it will additionally enable the _serde_ feature in the ed25519-dalek crate.
 //! - `runtime_metrics`: Collects metrics on the accesses to the storage layer
+//! - `tracing`: Instruments directory operations, publish phases, and storage calls with [`tracing`](https://docs.rs/tracing) spans
 //! - `public_tests`: Will expose some internal sanity testing functionality, which is often helpful so you don't have to write all your own
 //! unit test cases when implementing a storage layer yourself. This helps guarantee the sanity of a given storage implementation. Should be
 //! used only in unit testing scenarios by altering your Cargo.toml as such:
@@ -485,6 +486,9 @@ pub mod client;
 pub mod directory;
 pub mod errors;
 pub mod helper_structs;
+pub mod metrics;
+pub mod publish_policy;
+pub mod publish_queue;
 pub mod storage;
 pub mod tree_node;
 
@@ -502,7 +506,9 @@ mod utils;
 pub use append_only_zks::Azks;
 pub use client::HistoryVerificationParams;
 pub use directory::{Directory, HistoryParams};
-pub use helper_structs::EpochHash;
+pub use helper_structs::{
+    EpochHash, PublishReport, PublishStatus, PublishedLabel, VersionConflict,
+};
 
 // ========== Constants and type aliases ========== //
 #[cfg(any(test, feature = "public_tests"))]
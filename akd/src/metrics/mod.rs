@@ -0,0 +1,97 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Structured metrics of the storage and directory operations.
+//!
+//! Metrics are emitted to a [MetricsRecorder], which is provided to the
+//! [crate::storage::StorageManager] with [crate::storage::StorageManager::with_metrics_recorder].
+//! The recorder follows the model of the `metrics` crate facade: named counters, gauges and
+//! histograms, each qualified by a set of labels, so an adapter forwarding to that facade (or
+//! to any other metrics system) is a thin wrapper. The [prometheus::PrometheusRecorder]
+//! aggregates the metrics in memory and renders them in the Prometheus text exposition format.
+//!
+//! The metrics emitted are:
+//!
+//! | Name | Type | Labels |
+//! |------|------|--------|
+//! | [STORAGE_OPERATIONS_TOTAL] | counter | `operation`, `storage_type` |
+//! | [STORAGE_OPERATION_DURATION_SECONDS] | histogram | `operation`, `storage_type` |
+//! | [STORAGE_RECORDS_WRITTEN_TOTAL] | counter | `storage_type` |
+//! | [CACHE_REQUESTS_TOTAL] | counter | `result` (`hit` or `miss`), `storage_type` |
+//! | [CACHE_HIT_RATIO] | gauge | |
+//! | [PUBLISH_DURATION_SECONDS] | histogram | |
+//! | [PUBLISH_PHASE_DURATION_SECONDS] | histogram | `phase` (`vrf`, `insert` or `commit`) |
+//! | [PROOF_GENERATION_DURATION_SECONDS] | histogram | `proof` (`lookup`, `batch_lookup`, `key_history` or `audit`) |
+//!
+//! A `storage_type` of `mixed` denotes a batch write of records of several types.
+
+use crate::storage::types::StorageType;
+use std::time::Instant;
+
+pub mod prometheus;
+
+#[cfg(test)]
+mod tests;
+
+/// The number of calls made to the database, by operation and type of record
+pub const STORAGE_OPERATIONS_TOTAL: &str = "akd_storage_operations_total";
+/// The latency of calls made to the database, by operation and type of record
+pub const STORAGE_OPERATION_DURATION_SECONDS: &str = "akd_storage_operation_duration_seconds";
+/// The number of records written to the database, by type of record
+pub const STORAGE_RECORDS_WRITTEN_TOTAL: &str = "akd_storage_records_written_total";
+/// The number of lookups of records in the cache (including pinned nodes), by result and type
+/// of record
+pub const CACHE_REQUESTS_TOTAL: &str = "akd_cache_requests_total";
+/// The ratio of cache lookups which were hits, over the lifetime of the storage manager
+pub const CACHE_HIT_RATIO: &str = "akd_cache_hit_ratio";
+/// The duration of successful publishes
+pub const PUBLISH_DURATION_SECONDS: &str = "akd_publish_duration_seconds";
+/// The duration of the phases of successful publishes
+pub const PUBLISH_PHASE_DURATION_SECONDS: &str = "akd_publish_phase_duration_seconds";
+/// The latency of successful proof generations, by type of proof
+pub const PROOF_GENERATION_DURATION_SECONDS: &str = "akd_proof_generation_duration_seconds";
+
+/// The labels qualifying a metric, as (name, value) pairs
+pub type Labels<'a> = &'a [(&'static str, &'a str)];
+
+/// A recorder of structured metrics. Implementations must be cheap to call, as metrics are
+/// recorded on every storage operation.
+pub trait MetricsRecorder: Send + Sync {
+    /// Increment the counter `name` by `value`
+    fn increment_counter(&self, name: &'static str, labels: Labels<'_>, value: u64);
+
+    /// Set the gauge `name` to `value`
+    fn set_gauge(&self, name: &'static str, labels: Labels<'_>, value: f64);
+
+    /// Record an observation of `value` in the histogram `name`
+    fn record_histogram(&self, name: &'static str, labels: Labels<'_>, value: f64);
+}
+
+/// The value of the `storage_type` label for records of the given type
+pub fn storage_type_label(storage_type: StorageType) -> &'static str {
+    match storage_type {
+        StorageType::Azks => "azks",
+        StorageType::TreeNode => "tree_node",
+        StorageType::ValueState => "value_state",
+        StorageType::EpochChangeset => "epoch_changeset",
+        StorageType::SchemaVersion => "schema_version",
+        StorageType::RecordMac => "record_mac",
+        StorageType::PendingEpoch => "pending_epoch",
+    }
+}
+
+/// Record the time elapsed since `start`, in seconds, in the histogram `name`
+pub(crate) fn record_duration(
+    recorder: Option<&dyn MetricsRecorder>,
+    name: &'static str,
+    labels: Labels<'_>,
+    start: Instant,
+) {
+    if let Some(recorder) = recorder {
+        recorder.record_histogram(name, labels, start.elapsed().as_secs_f64());
+    }
+}
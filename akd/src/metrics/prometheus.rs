@@ -0,0 +1,220 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A [MetricsRecorder] rendering the metrics in the Prometheus text exposition format.
+
+use super::{Labels, MetricsRecorder};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// The default upper bounds of the histogram buckets, in seconds
+pub const DEFAULT_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+type LabelSet = Vec<(&'static str, String)>;
+
+enum Series {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        bucket_counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+/// A [MetricsRecorder] which aggregates the metrics in memory, to be served (i.e. on a
+/// `/metrics` endpoint) in the Prometheus text exposition format by
+/// [PrometheusRecorder::render]
+pub struct PrometheusRecorder {
+    buckets: Vec<f64>,
+    metrics: Mutex<BTreeMap<&'static str, BTreeMap<LabelSet, Series>>>,
+}
+
+impl Default for PrometheusRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrometheusRecorder {
+    /// Create a recorder with the [DEFAULT_BUCKETS] for histograms
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+
+    /// Create a recorder with the provided upper bounds of the histogram buckets
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+        buckets.sort_by(|a, b| a.total_cmp(b));
+        Self {
+            buckets,
+            metrics: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn update(
+        &self,
+        name: &'static str,
+        labels: Labels<'_>,
+        new: impl FnOnce() -> Series,
+        f: impl FnOnce(&mut Series),
+    ) {
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect::<LabelSet>();
+        let mut metrics = self.metrics.lock().unwrap();
+        f(metrics
+            .entry(name)
+            .or_default()
+            .entry(labels)
+            .or_insert_with(new));
+    }
+
+    /// Render the current values of all the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in metrics.iter() {
+            let kind = match family.values().next() {
+                Some(Series::Counter(_)) => "counter",
+                Some(Series::Gauge(_)) => "gauge",
+                Some(Series::Histogram { .. }) => "histogram",
+                None => continue,
+            };
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, series) in family.iter() {
+                match series {
+                    Series::Counter(value) => {
+                        let _ = writeln!(out, "{name}{} {value}", format_labels(labels, None));
+                    }
+                    Series::Gauge(value) => {
+                        let _ = writeln!(
+                            out,
+                            "{name}{} {}",
+                            format_labels(labels, None),
+                            format_value(*value)
+                        );
+                    }
+                    Series::Histogram {
+                        bucket_counts,
+                        sum,
+                        count,
+                    } => {
+                        let mut cumulative = 0;
+                        for (bound, bucket_count) in self.buckets.iter().zip(bucket_counts.iter()) {
+                            cumulative += bucket_count;
+                            let _ = writeln!(
+                                out,
+                                "{name}_bucket{} {cumulative}",
+                                format_labels(labels, Some(&format_value(*bound)))
+                            );
+                        }
+                        let _ = writeln!(
+                            out,
+                            "{name}_bucket{} {count}",
+                            format_labels(labels, Some("+Inf"))
+                        );
+                        let _ = writeln!(
+                            out,
+                            "{name}_sum{} {}",
+                            format_labels(labels, None),
+                            format_value(*sum)
+                        );
+                        let _ =
+                            writeln!(out, "{name}_count{} {count}", format_labels(labels, None));
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn increment_counter(&self, name: &'static str, labels: Labels<'_>, value: u64) {
+        self.update(
+            name,
+            labels,
+            || Series::Counter(0),
+            |series| {
+                if let Series::Counter(counter) = series {
+                    *counter += value;
+                }
+            },
+        );
+    }
+
+    fn set_gauge(&self, name: &'static str, labels: Labels<'_>, value: f64) {
+        self.update(
+            name,
+            labels,
+            || Series::Gauge(value),
+            |series| *series = Series::Gauge(value),
+        );
+    }
+
+    fn record_histogram(&self, name: &'static str, labels: Labels<'_>, value: f64) {
+        let num_buckets = self.buckets.len();
+        let bucket = self.buckets.iter().position(|bound| value <= *bound);
+        self.update(
+            name,
+            labels,
+            || Series::Histogram {
+                bucket_counts: vec![0; num_buckets],
+                sum: 0.0,
+                count: 0,
+            },
+            |series| {
+                if let Series::Histogram {
+                    bucket_counts,
+                    sum,
+                    count,
+                } = series
+                {
+                    if let Some(bucket) = bucket {
+                        bucket_counts[bucket] += 1;
+                    }
+                    *sum += value;
+                    *count += 1;
+                }
+            },
+        );
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_labels(labels: &LabelSet, le: Option<&str>) -> String {
+    if labels.is_empty() && le.is_none() {
+        return String::new();
+    }
+    let escaped = labels
+        .iter()
+        .map(|(name, value)| (*name, escape(value)))
+        .chain(le.map(|le| ("le", le.to_string())))
+        .map(|(name, value)| format!("{name}=\"{value}\""))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", escaped.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,72 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Metrics export tests
+
+use super::prometheus::PrometheusRecorder;
+use super::*;
+
+#[test]
+fn test_prometheus_counters_and_gauges() {
+    let recorder = PrometheusRecorder::new();
+    let labels = [("operation", "get"), ("storage_type", "azks")];
+    recorder.increment_counter(STORAGE_OPERATIONS_TOTAL, &labels, 1);
+    recorder.increment_counter(STORAGE_OPERATIONS_TOTAL, &labels, 2);
+    recorder.increment_counter(
+        STORAGE_OPERATIONS_TOTAL,
+        &[("operation", "set"), ("storage_type", "azks")],
+        1,
+    );
+    recorder.set_gauge(CACHE_HIT_RATIO, &[], 0.25);
+    recorder.set_gauge(CACHE_HIT_RATIO, &[], 0.5);
+
+    assert_eq!(
+        "# TYPE akd_cache_hit_ratio gauge
+akd_cache_hit_ratio 0.5
+# TYPE akd_storage_operations_total counter
+akd_storage_operations_total{operation=\"get\",storage_type=\"azks\"} 3
+akd_storage_operations_total{operation=\"set\",storage_type=\"azks\"} 1
+",
+        recorder.render()
+    );
+}
+
+#[test]
+fn test_prometheus_histograms() {
+    let recorder = PrometheusRecorder::with_buckets(vec![1.0, 0.1]);
+    for value in [0.05, 0.5, 0.5, 2.0] {
+        recorder.record_histogram(PUBLISH_DURATION_SECONDS, &[], value);
+    }
+    recorder.record_histogram(
+        PUBLISH_PHASE_DURATION_SECONDS,
+        &[("phase", "a \"quoted\"\\phase")],
+        0.1,
+    );
+
+    assert_eq!(
+        "# TYPE akd_publish_duration_seconds histogram
+akd_publish_duration_seconds_bucket{le=\"0.1\"} 1
+akd_publish_duration_seconds_bucket{le=\"1\"} 3
+akd_publish_duration_seconds_bucket{le=\"+Inf\"} 4
+akd_publish_duration_seconds_sum 3.05
+akd_publish_duration_seconds_count 4
+# TYPE akd_publish_phase_duration_seconds histogram
+akd_publish_phase_duration_seconds_bucket{phase=\"a \\\"quoted\\\"\\\\phase\",le=\"0.1\"} 1
+akd_publish_phase_duration_seconds_bucket{phase=\"a \\\"quoted\\\"\\\\phase\",le=\"1\"} 1
+akd_publish_phase_duration_seconds_bucket{phase=\"a \\\"quoted\\\"\\\\phase\",le=\"+Inf\"} 1
+akd_publish_phase_duration_seconds_sum{phase=\"a \\\"quoted\\\"\\\\phase\"} 0.1
+akd_publish_phase_duration_seconds_count{phase=\"a \\\"quoted\\\"\\\\phase\"} 1
+",
+        recorder.render()
+    );
+}
+
+#[test]
+fn test_storage_type_labels() {
+    assert_eq!("tree_node", storage_type_label(StorageType::TreeNode));
+    assert_eq!("value_state", storage_type_label(StorageType::ValueState));
+}
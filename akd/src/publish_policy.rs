@@ -0,0 +1,175 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Validation and authorization of the updates published to a directory.
+//!
+//! A [PublishPolicy] set with [Directory::with_publish_policy](crate::Directory::with_publish_policy)
+//! is consulted for each label-value pair published, prior to the computation of its VRF
+//! label, and may accept, reject, or normalize it. Rejected updates are left out of the
+//! publish, rather than failing it, and are returned in the
+//! [PublishReport](crate::PublishReport) of the publish (or alongside the epoch hash of
+//! [Directory::publish_from_iter](crate::Directory::publish_from_iter)). Only
+//! [Directory::publish](crate::Directory::publish), which returns no report, fails when an
+//! update is rejected. The latest states of the labels are read in a single batch for each set
+//! of updates checked.
+//!
+//! An update normalized to another label is checked again with the normalized label and
+//! value, given the latest state of the normalized label, so that the decision on an update
+//! rests on the state of the label it updates. A policy must therefore accept the labels it
+//! normalizes to, or normalize them to themselves; an update normalized again to yet another
+//! label is rejected.
+
+use crate::client::continuity::{verify_key_continuity, VerifyingKey};
+use crate::storage::types::ValueState;
+use crate::{AkdLabel, AkdValue, Namespace};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The decision of a [PublishPolicy] on an update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    /// Publish the update as provided
+    Accept,
+    /// Leave the update out of the publish, for the reason provided
+    Reject(String),
+    /// Publish the provided label-value pair in place of the update
+    Normalize(AkdLabel, AkdValue),
+}
+
+/// Decides whether an update may be published, and in what form
+pub trait PublishPolicy: Send + Sync {
+    /// Decide on the update of the label to the value, given the latest state of the label
+    /// as provided, or [None] if the label is not in the directory. An update normalized to
+    /// another label is decided on again, as described in the [module documentation](self).
+    fn check(
+        &self,
+        label: &AkdLabel,
+        value: &AkdValue,
+        previous: Option<&ValueState>,
+    ) -> PolicyDecision;
+}
+
+/// Rejects the updates whose values are longer than a maximum number of bytes
+#[derive(Debug, Clone, Copy)]
+pub struct MaxValueSize(pub usize);
+
+impl PublishPolicy for MaxValueSize {
+    fn check(
+        &self,
+        _label: &AkdLabel,
+        value: &AkdValue,
+        _previous: Option<&ValueState>,
+    ) -> PolicyDecision {
+        if value.len() > self.0 {
+            PolicyDecision::Reject(format!(
+                "The value of {} bytes exceeds the maximum of {} bytes",
+                value.len(),
+                self.0
+            ))
+        } else {
+            PolicyDecision::Accept
+        }
+    }
+}
+
+/// Accepts only the updates which preserve the key continuity of their labels, in that
+/// each value is a [ContinuityValue](crate::client::ContinuityValue) signed by the key of the previous value of the label,
+/// or by the recovery key (if any). Clients check the signature chain of a label with
+/// [key_continuity_verify](crate::client::key_continuity_verify).
+#[derive(Debug, Clone, Default)]
+pub struct KeyContinuityPolicy {
+    recovery_key: Option<VerifyingKey>,
+}
+
+impl KeyContinuityPolicy {
+    /// Creates a policy accepting updates signed by the recovery key, if provided, in place
+    /// of the key of the previous value
+    pub fn new(recovery_key: Option<VerifyingKey>) -> Self {
+        Self { recovery_key }
+    }
+}
+
+impl PublishPolicy for KeyContinuityPolicy {
+    fn check(
+        &self,
+        label: &AkdLabel,
+        value: &AkdValue,
+        previous: Option<&ValueState>,
+    ) -> PolicyDecision {
+        match verify_key_continuity(
+            label,
+            previous.map(|state| &state.value),
+            value,
+            self.recovery_key.as_ref(),
+        ) {
+            Ok(_) => PolicyDecision::Accept,
+            Err(err) => PolicyDecision::Reject(err.to_string()),
+        }
+    }
+}
+
+/// Applies a policy of its own to each of the namespaces of a directory, and rejects the
+/// updates to labels outside of them. The labels published are namespaced with
+/// [Namespace::label], and each policy decides on the label within its namespace, so that a
+/// label normalized by a policy stays in its namespace.
+#[derive(Clone, Default)]
+pub struct NamespacedPolicy {
+    namespaces: HashMap<Namespace, Option<Arc<dyn PublishPolicy>>>,
+}
+
+impl NamespacedPolicy {
+    /// Creates a policy with no namespaces, which rejects every update
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a namespace, whose updates are decided on by the policy provided, or accepted
+    /// if there is none
+    pub fn with_namespace(
+        mut self,
+        namespace: Namespace,
+        policy: Option<Arc<dyn PublishPolicy>>,
+    ) -> Self {
+        self.namespaces.insert(namespace, policy);
+        self
+    }
+}
+
+impl PublishPolicy for NamespacedPolicy {
+    fn check(
+        &self,
+        label: &AkdLabel,
+        value: &AkdValue,
+        previous: Option<&ValueState>,
+    ) -> PolicyDecision {
+        let (namespace, namespace_label) = match Namespace::split(label) {
+            Some(split) => split,
+            None => return PolicyDecision::Reject("The label is not namespaced".to_string()),
+        };
+        let policy = match self.namespaces.get(&namespace) {
+            Some(Some(policy)) => policy,
+            Some(None) => return PolicyDecision::Accept,
+            None => return PolicyDecision::Reject("The namespace is unknown".to_string()),
+        };
+        match policy.check(&namespace_label, value, previous) {
+            PolicyDecision::Normalize(normalized_label, normalized_value) => {
+                PolicyDecision::Normalize(namespace.label(&normalized_label), normalized_value)
+            }
+            decision => decision,
+        }
+    }
+}
+
+/// The rejection of an update by a [PublishPolicy]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyRejection {
+    /// The label of the rejected update, as provided
+    pub label: AkdLabel,
+    /// The reason given by the policy
+    pub reason: String,
+}
@@ -0,0 +1,327 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A queue which batches individual updates into directory publishes.
+//!
+//! A [PublishQueue] accepts updates at any time with [PublishQueue::submit], and publishes
+//! them in the background. Updates to the same label which are pending in the same epoch
+//! are merged according to a [MergePolicy], as [Directory::publish] does not accept
+//! duplicate labels. An epoch is cut once [PublishQueueConfig::max_batch_size] distinct
+//! labels are pending, or [PublishQueueConfig::max_delay] after the first pending update
+//! was received, whichever comes first. Updates received while a publish is underway are
+//! published in the following epoch.
+//!
+//! At most [PublishQueueConfig::max_queued_updates] submitted updates are buffered ahead of
+//! the queue, beyond which [PublishQueue::submit] waits for the queue to catch up. This
+//! bounds the memory of updates which are submitted faster than they can be published.
+//!
+//! Each submitted update resolves to the [EpochHash] of the publish which included it, or to
+//! a [DirectoryError::QueuedPublish] sharing the error of a failed publish. An update
+//! rejected by the publish policy of the directory resolves to a [DirectoryError::Publish]
+//! with the reason for the rejection, as do the other updates to its label merged into the
+//! same epoch. Note that an update which does not change the value of its label results in
+//! no new epoch, and so may resolve to an earlier epoch.
+
+use crate::ecvrf::VRFKeyStorage;
+use crate::errors::{AkdError, DirectoryError};
+use crate::publish_policy::PolicyRejection;
+use crate::storage::Database;
+use crate::{AkdLabel, AkdValue, Directory, EpochHash};
+
+use akd_core::configuration::Configuration;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+#[cfg(test)]
+mod tests;
+
+/// Resolves the value of a label when it is updated more than once in the same epoch
+pub trait MergePolicy: Send + Sync + 'static {
+    /// Merge an incoming update to the label with the value already pending for it,
+    /// returning the value to publish
+    fn merge(&self, label: &AkdLabel, pending: AkdValue, incoming: AkdValue) -> AkdValue;
+}
+
+/// The most recently submitted update to a label is published
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriteWins;
+
+impl MergePolicy for LastWriteWins {
+    fn merge(&self, _label: &AkdLabel, _pending: AkdValue, incoming: AkdValue) -> AkdValue {
+        incoming
+    }
+}
+
+/// The triggers on which a [PublishQueue] cuts an epoch
+#[derive(Debug, Clone)]
+pub struct PublishQueueConfig {
+    /// The number of distinct labels pending, at which they're published
+    pub max_batch_size: usize,
+    /// The longest an update waits before it's published, aside from the duration of any
+    /// publish already underway
+    pub max_delay: Duration,
+    /// The number of submitted updates buffered ahead of the queue, beyond which submitting
+    /// an update waits for capacity
+    pub max_queued_updates: usize,
+}
+
+impl Default for PublishQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 10_000,
+            max_delay: Duration::from_secs(1),
+            max_queued_updates: 100_000,
+        }
+    }
+}
+
+type Waiter = oneshot::Sender<Result<EpochHash, QueueError>>;
+type FlushWaiter = oneshot::Sender<Result<Option<EpochHash>, Arc<AkdError>>>;
+
+enum Message {
+    Update(AkdLabel, AkdValue, Waiter),
+    Flush(FlushWaiter),
+}
+
+/// Batches individual updates into publishes of a [Directory]. See the [module
+/// documentation](self) for details.
+pub struct PublishQueue {
+    sender: mpsc::Sender<Message>,
+    worker: JoinHandle<()>,
+}
+
+impl PublishQueue {
+    /// Starts publishing to the directory, merging updates with [LastWriteWins]. Must be
+    /// called within a tokio runtime.
+    pub fn new<TC, S, V>(directory: Directory<TC, S, V>, config: PublishQueueConfig) -> Self
+    where
+        TC: Configuration,
+        S: Database + 'static,
+        V: VRFKeyStorage + 'static,
+    {
+        Self::new_with_policy(directory, config, LastWriteWins)
+    }
+
+    /// Starts publishing to the directory, merging updates with the provided policy. Must
+    /// be called within a tokio runtime.
+    pub fn new_with_policy<TC, S, V, P>(
+        directory: Directory<TC, S, V>,
+        config: PublishQueueConfig,
+        policy: P,
+    ) -> Self
+    where
+        TC: Configuration,
+        S: Database + 'static,
+        V: VRFKeyStorage + 'static,
+        P: MergePolicy,
+    {
+        let (sender, receiver) = mpsc::channel(config.max_queued_updates.max(1));
+        let worker = tokio::spawn(run(directory, config, policy, receiver));
+        Self { sender, worker }
+    }
+
+    /// Submits an update to be published, waiting for capacity if
+    /// [PublishQueueConfig::max_queued_updates] updates are already queued. Once the update
+    /// is queued, returns a future which resolves to the [EpochHash] of the publish which
+    /// included it.
+    pub async fn submit(
+        &self,
+        label: AkdLabel,
+        value: AkdValue,
+    ) -> Result<impl Future<Output = Result<EpochHash, AkdError>> + Send + 'static, AkdError> {
+        let (waiter, result) = oneshot::channel();
+        self.sender
+            .send(Message::Update(label, value, waiter))
+            .await
+            .map_err(|_| closed())?;
+        Ok(async move {
+            result
+                .await
+                .map_err(|_| closed())?
+                .map_err(|err| match err {
+                    QueueError::Failed(err) => failed(err),
+                    QueueError::Rejected(rejection) => rejected(rejection),
+                })
+        })
+    }
+
+    /// Publishes any pending updates now rather than waiting for a trigger, returning the
+    /// [EpochHash] of the publish, or [None] if no updates were pending
+    pub async fn flush(&self) -> Result<Option<EpochHash>, AkdError> {
+        let (waiter, result) = oneshot::channel();
+        self.sender
+            .send(Message::Flush(waiter))
+            .await
+            .map_err(|_| closed())?;
+        result.await.map_err(|_| closed())?.map_err(failed)
+    }
+
+    /// Stops accepting updates, and waits for those pending to be published
+    pub async fn close(self) -> Result<(), AkdError> {
+        drop(self.sender);
+        self.worker.await.map_err(|join_err| {
+            AkdError::Directory(DirectoryError::Publish(format!(
+                "The publish queue failed with error {join_err}"
+            )))
+        })
+    }
+}
+
+fn closed() -> AkdError {
+    AkdError::Directory(DirectoryError::Publish(
+        "The publish queue has been closed".to_string(),
+    ))
+}
+
+fn failed(err: Arc<AkdError>) -> AkdError {
+    AkdError::Directory(DirectoryError::QueuedPublish(err))
+}
+
+fn rejected(rejection: PolicyRejection) -> AkdError {
+    AkdError::Directory(DirectoryError::Publish(format!(
+        "The update to label {:?} was rejected by the publish policy: {}",
+        rejection.label, rejection.reason
+    )))
+}
+
+/// The error an update resolves to, prior to being returned by its future
+#[derive(Clone)]
+enum QueueError {
+    /// The publish which included the update failed
+    Failed(Arc<AkdError>),
+    /// The update of the label was rejected by the publish policy
+    Rejected(PolicyRejection),
+}
+
+/// The updates to be published in the next epoch
+#[derive(Default)]
+struct PendingEpoch {
+    updates: Vec<(AkdLabel, AkdValue)>,
+    positions: HashMap<AkdLabel, usize>,
+    waiters: Vec<(AkdLabel, Waiter)>,
+    flushes: Vec<FlushWaiter>,
+}
+
+impl PendingEpoch {
+    fn add(&mut self, policy: &impl MergePolicy, label: AkdLabel, value: AkdValue, waiter: Waiter) {
+        match self.positions.get(&label) {
+            Some(&position) => {
+                let (_, pending) = &mut self.updates[position];
+                let previous = std::mem::replace(pending, AkdValue(vec![]));
+                *pending = policy.merge(&label, previous, value);
+            }
+            None => {
+                self.positions.insert(label.clone(), self.updates.len());
+                self.updates.push((label.clone(), value));
+            }
+        }
+        self.waiters.push((label, waiter));
+    }
+
+    async fn publish<TC, S, V>(self, directory: &Directory<TC, S, V>)
+    where
+        TC: Configuration,
+        S: Database + 'static,
+        V: VRFKeyStorage,
+    {
+        if self.updates.is_empty() {
+            for flush in self.flushes {
+                let _ = flush.send(Ok(None));
+            }
+            return;
+        }
+
+        debug!(
+            "Publishing {} labels merged from {} updates",
+            self.updates.len(),
+            self.waiters.len()
+        );
+        let (result, rejections) = match directory.publish_with_report(self.updates).await {
+            Ok(report) => {
+                info!(
+                    "Published {} queued updates in epoch {}, of which {} labels were rejected",
+                    self.waiters.len(),
+                    report.epoch_hash.epoch(),
+                    report.rejected.len()
+                );
+                let rejections = report
+                    .rejected
+                    .into_iter()
+                    .map(|rejection| (rejection.label.clone(), rejection))
+                    .collect::<HashMap<_, _>>();
+                (Ok(report.epoch_hash), rejections)
+            }
+            Err(err) => (Err(Arc::new(err)), HashMap::new()),
+        };
+
+        // the receivers of dropped futures are no longer interested in the result
+        for (label, waiter) in self.waiters {
+            let _ = match rejections.get(&label) {
+                Some(rejection) => waiter.send(Err(QueueError::Rejected(rejection.clone()))),
+                None => waiter.send(result.clone().map_err(QueueError::Failed)),
+            };
+        }
+        for flush in self.flushes {
+            let _ = flush.send(result.clone().map(Some));
+        }
+    }
+}
+
+async fn run<TC, S, V, P>(
+    directory: Directory<TC, S, V>,
+    config: PublishQueueConfig,
+    policy: P,
+    mut receiver: mpsc::Receiver<Message>,
+) where
+    TC: Configuration,
+    S: Database + 'static,
+    V: VRFKeyStorage,
+    P: MergePolicy,
+{
+    let mut open = true;
+    while open {
+        let mut pending = PendingEpoch::default();
+
+        // the delay trigger starts with the first update of the epoch
+        match receiver.recv().await {
+            Some(Message::Update(label, value, waiter)) => {
+                pending.add(&policy, label, value, waiter)
+            }
+            Some(Message::Flush(flush)) => {
+                let _ = flush.send(Ok(None));
+                continue;
+            }
+            None => break,
+        }
+        let deadline = Instant::now() + config.max_delay;
+
+        while pending.updates.len() < config.max_batch_size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(Message::Update(label, value, waiter))) => {
+                    pending.add(&policy, label, value, waiter)
+                }
+                Ok(Some(Message::Flush(flush))) => {
+                    pending.flushes.push(flush);
+                    break;
+                }
+                Ok(None) => {
+                    open = false;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+
+        pending.publish(&directory).await;
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Publish queue tests
+
+use super::*;
+use crate::ecvrf::HardCodedAkdVRF;
+use crate::errors::StorageError;
+use crate::publish_policy::MaxValueSize;
+use crate::storage::faulty::{FaultConfig, FaultTarget, FaultyDatabase};
+use crate::storage::manager::StorageManager;
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::test_config;
+
+type InMemoryDirectory<TC> = Directory<TC, AsyncInMemoryDatabase, HardCodedAkdVRF>;
+
+async fn new_directory<TC: Configuration>() -> Result<InMemoryDirectory<TC>, AkdError> {
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    Directory::<TC, _, _>::new(storage, HardCodedAkdVRF {}).await
+}
+
+/// Never triggers, so that epochs are only cut by flushes
+fn manual_config() -> PublishQueueConfig {
+    PublishQueueConfig {
+        max_batch_size: usize::MAX,
+        max_delay: Duration::from_secs(3600),
+        ..Default::default()
+    }
+}
+
+async fn lookup_value<TC: Configuration>(
+    akd: &InMemoryDirectory<TC>,
+    label: &str,
+) -> Result<AkdValue, AkdError> {
+    Ok(akd.lookup(AkdLabel::from(label)).await?.0.value)
+}
+
+/// Concatenates the values of a label updated more than once in an epoch
+struct Concatenate;
+
+impl MergePolicy for Concatenate {
+    fn merge(&self, _label: &AkdLabel, pending: AkdValue, incoming: AkdValue) -> AkdValue {
+        AkdValue([pending.0, incoming.0].concat())
+    }
+}
+
+test_config!(test_publish_queue_merges_updates);
+async fn test_publish_queue_merges_updates<TC: Configuration>() -> Result<(), AkdError> {
+    let akd = new_directory::<TC>().await?;
+    let queue = PublishQueue::new(akd.clone(), manual_config());
+
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("bob"), AkdValue::from("2"))
+        .await?;
+    let third = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("3"))
+        .await?;
+    let epoch_hash = queue.flush().await?.expect("updates were pending");
+    assert_eq!(1, epoch_hash.epoch());
+    assert_eq!(epoch_hash, first.await?);
+    assert_eq!(epoch_hash, second.await?);
+    assert_eq!(epoch_hash, third.await?);
+    assert_eq!(epoch_hash, akd.get_epoch_hash().await?);
+    assert_eq!(AkdValue::from("3"), lookup_value(&akd, "alice").await?);
+
+    // nothing is pending
+    assert_eq!(None, queue.flush().await?);
+
+    // with a custom policy
+    let queue = PublishQueue::new_with_policy(akd.clone(), manual_config(), Concatenate);
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("4"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("5"))
+        .await?;
+    queue.flush().await?;
+    assert_eq!(2, first.await?.epoch());
+    assert_eq!(2, second.await?.epoch());
+    assert_eq!(AkdValue::from("45"), lookup_value(&akd, "alice").await?);
+    Ok(())
+}
+
+test_config!(test_publish_queue_triggers);
+async fn test_publish_queue_triggers<TC: Configuration>() -> Result<(), AkdError> {
+    let akd = new_directory::<TC>().await?;
+
+    // the size trigger counts distinct labels
+    let queue = PublishQueue::new(
+        akd.clone(),
+        PublishQueueConfig {
+            max_batch_size: 2,
+            ..manual_config()
+        },
+    );
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("2"))
+        .await?;
+    let third = queue
+        .submit(AkdLabel::from("bob"), AkdValue::from("3"))
+        .await?;
+    let fourth = queue
+        .submit(AkdLabel::from("carol"), AkdValue::from("4"))
+        .await?;
+    let patience = Duration::from_secs(60);
+    let result = tokio::time::timeout(patience, futures::future::join3(first, second, third))
+        .await
+        .expect("the size trigger fired");
+    assert_eq!(1, result.0?.epoch());
+    assert_eq!(1, result.1?.epoch());
+    assert_eq!(1, result.2?.epoch());
+    // the remainder waits for a trigger
+    assert_eq!(
+        2,
+        queue.flush().await?.map(|hash| hash.epoch()).unwrap_or(0)
+    );
+    assert_eq!(2, fourth.await?.epoch());
+
+    // the delay trigger starts with the first pending update
+    let queue = PublishQueue::new(
+        akd.clone(),
+        PublishQueueConfig {
+            max_batch_size: usize::MAX,
+            max_delay: Duration::from_millis(10),
+            ..Default::default()
+        },
+    );
+    let update = queue
+        .submit(AkdLabel::from("dave"), AkdValue::from("5"))
+        .await?;
+    let epoch_hash = tokio::time::timeout(patience, update)
+        .await
+        .expect("the delay trigger fired")?;
+    assert_eq!(3, epoch_hash.epoch());
+    assert_eq!(AkdValue::from("5"), lookup_value(&akd, "dave").await?);
+    Ok(())
+}
+
+test_config!(test_publish_queue_close);
+async fn test_publish_queue_close<TC: Configuration>() -> Result<(), AkdError> {
+    let akd = new_directory::<TC>().await?;
+    let queue = PublishQueue::new(akd.clone(), manual_config());
+
+    // pending updates are published on close, including those no longer waited on
+    let update = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    drop(
+        queue
+            .submit(AkdLabel::from("bob"), AkdValue::from("2"))
+            .await?,
+    );
+    queue.close().await?;
+    assert_eq!(1, update.await?.epoch());
+    assert_eq!(AkdValue::from("2"), lookup_value(&akd, "bob").await?);
+    Ok(())
+}
+
+test_config!(test_publish_queue_failures);
+async fn test_publish_queue_failures<TC: Configuration>() -> Result<(), AkdError> {
+    let faulty = FaultyDatabase::new(AsyncInMemoryDatabase::new(), 3, FaultConfig::default());
+    let storage = StorageManager::new_no_cache(faulty.clone());
+    let akd = Directory::<TC, _, _>::new(storage, HardCodedAkdVRF {}).await?;
+    let queue = PublishQueue::new(akd.clone(), manual_config());
+
+    // every update of the failed publish is notified
+    faulty.set_config(FaultConfig::errors(FaultTarget::Writes, 1.0));
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("bob"), AkdValue::from("2"))
+        .await?;
+    let err = match queue.flush().await {
+        Err(AkdError::Directory(DirectoryError::QueuedPublish(err))) => err,
+        other => panic!("Expected the queued publish to fail, got {other:?}"),
+    };
+    assert!(matches!(
+        *err,
+        AkdError::Storage(StorageError::Connection(_))
+    ));
+    for update in [first, second] {
+        assert!(matches!(
+            update.await,
+            Err(AkdError::Directory(DirectoryError::QueuedPublish(shared))) if Arc::ptr_eq(&shared, &err)
+        ));
+    }
+
+    // and the queue carries on
+    faulty.disable();
+    let update = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("3"))
+        .await?;
+    queue.flush().await?;
+    assert_eq!(1, update.await?.epoch());
+    Ok(())
+}
+
+test_config!(test_publish_queue_rejections);
+async fn test_publish_queue_rejections<TC: Configuration>() -> Result<(), AkdError> {
+    let akd = new_directory::<TC>()
+        .await?
+        .with_publish_policy(Arc::new(MaxValueSize(2)));
+    let queue = PublishQueue::new_with_policy(akd.clone(), manual_config(), Concatenate);
+
+    // the updates merged into a rejected value are all rejected, and the others are published
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("bob"), AkdValue::from("2"))
+        .await?;
+    let third = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("34"))
+        .await?;
+    let epoch_hash = queue.flush().await?.expect("updates were pending");
+    assert_eq!(1, epoch_hash.epoch());
+    for update in [first, third] {
+        assert!(matches!(
+            update.await,
+            Err(AkdError::Directory(DirectoryError::Publish(_)))
+        ));
+    }
+    assert_eq!(epoch_hash, second.await?);
+    assert_eq!(AkdValue::from("2"), lookup_value(&akd, "bob").await?);
+    assert!(akd.lookup(AkdLabel::from("alice")).await.is_err());
+    Ok(())
+}
+
+test_config!(test_publish_queue_backpressure);
+async fn test_publish_queue_backpressure<TC: Configuration>() -> Result<(), AkdError> {
+    let faulty = FaultyDatabase::new(AsyncInMemoryDatabase::new(), 3, FaultConfig::default());
+    let storage = StorageManager::new_no_cache(faulty.clone());
+    let akd = Directory::<TC, _, _>::new(storage, HardCodedAkdVRF {}).await?;
+    let queue = PublishQueue::new(
+        akd.clone(),
+        PublishQueueConfig {
+            max_batch_size: 1,
+            max_queued_updates: 1,
+            ..manual_config()
+        },
+    );
+
+    // while the first update is slowly published, a single update can be queued behind it
+    faulty.set_config(FaultConfig {
+        min_latency: Duration::from_millis(50),
+        max_latency: Duration::from_millis(50),
+        ..Default::default()
+    });
+    let first = queue
+        .submit(AkdLabel::from("alice"), AkdValue::from("1"))
+        .await?;
+    let second = queue
+        .submit(AkdLabel::from("bob"), AkdValue::from("2"))
+        .await?;
+    let blocked = tokio::time::timeout(
+        Duration::from_millis(20),
+        queue.submit(AkdLabel::from("carol"), AkdValue::from("3")),
+    )
+    .await;
+    assert!(blocked.is_err());
+
+    faulty.disable();
+    let third = queue
+        .submit(AkdLabel::from("carol"), AkdValue::from("3"))
+        .await?;
+    assert_eq!(1, first.await?.epoch());
+    assert_eq!(2, second.await?.epoch());
+    assert_eq!(3, third.await?.epoch());
+    Ok(())
+}
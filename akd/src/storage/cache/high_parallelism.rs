@@ -8,10 +8,14 @@
 //! This module implements a higher-parallelism, async temporary cache for database
 //! objects
 
-use super::{CachedItem, DEFAULT_CACHE_CLEAN_FREQUENCY_MS, DEFAULT_ITEM_LIFETIME_MS};
+use super::{
+    CacheMetrics, CachedItem, ObjectCache, DEFAULT_CACHE_CLEAN_FREQUENCY_MS,
+    DEFAULT_ITEM_LIFETIME_MS,
+};
 use crate::storage::DbRecord;
 use crate::storage::Storable;
 use akd_core::SizeOf;
+use async_trait::async_trait;
 use dashmap::DashMap;
 #[cfg(not(feature = "runtime_metrics"))]
 use log::debug;
@@ -40,6 +44,10 @@ pub struct TimedCache {
 
     #[cfg(feature = "runtime_metrics")]
     hit_count: Arc<AtomicU64>,
+    #[cfg(feature = "runtime_metrics")]
+    miss_count: Arc<AtomicU64>,
+    #[cfg(feature = "runtime_metrics")]
+    eviction_count: Arc<AtomicU64>,
 }
 
 impl TimedCache {
@@ -94,6 +102,9 @@ impl TimedCache {
 
                 info!("Removed {} expired elements from the cache", num_removed);
                 debug!("Retained cache size is {} bytes", retained_size);
+                #[cfg(feature = "runtime_metrics")]
+                self.eviction_count
+                    .fetch_add(num_removed as u64, Ordering::Relaxed);
 
                 if retained_size > memory_limit_bytes {
                     info!("Retained cache size has exceeded the predefined limit, cleaning old entries");
@@ -117,12 +128,20 @@ impl TimedCache {
                     {
                         self.map.remove(&key);
                     }
+                    #[cfg(feature = "runtime_metrics")]
+                    self.eviction_count
+                        .fetch_add(num_clean as u64, Ordering::Relaxed);
 
                     debug!("END cache memory pressure clean")
                 }
             } else {
                 // memory pressure analysis is disabled, simply utilize timed cache cleaning
+                #[cfg(feature = "runtime_metrics")]
+                let num_items = self.map.len();
                 self.map.retain(|_, v| v.expiration >= now);
+                #[cfg(feature = "runtime_metrics")]
+                self.eviction_count
+                    .fetch_add((num_items - self.map.len()) as u64, Ordering::Relaxed);
             }
 
             // update last clean time
@@ -157,14 +176,22 @@ impl TimedCache {
 
             #[cfg(feature = "runtime_metrics")]
             hit_count: Arc::new(AtomicU64::new(0u64)),
+            #[cfg(feature = "runtime_metrics")]
+            miss_count: Arc::new(AtomicU64::new(0u64)),
+            #[cfg(feature = "runtime_metrics")]
+            eviction_count: Arc::new(AtomicU64::new(0u64)),
         }
     }
 
     /// Perform a hit-test of the cache for a given key. If successful, Some(record) will be returned
     pub async fn hit_test<St: Storable>(&self, key: &St::StorageKey) -> Option<DbRecord> {
-        self.clean().await;
+        self.hit_test_full_key(&St::get_full_binary_key_id(key))
+            .await
+    }
 
-        let full_key = St::get_full_binary_key_id(key);
+    /// Perform a hit-test of the cache for a given full binary key. If successful, Some(record) will be returned
+    pub async fn hit_test_full_key(&self, full_key: &[u8]) -> Option<DbRecord> {
+        self.clean().await;
 
         // special case for AZKS
         if full_key
@@ -183,19 +210,22 @@ impl TimedCache {
             return record;
         }
 
-        if let Some(result) = self.map.get(&full_key) {
-            #[cfg(feature = "runtime_metrics")]
-            self.hit_count.fetch_add(1, Ordering::Relaxed);
-
+        if let Some(result) = self.map.get(full_key) {
             let ignore_clean = !self.can_clean.load(Ordering::Relaxed);
             // if we've disabled cache cleaning, we're in the middle
             // of an in-memory transaction and should ignore expiration
             // of cache items until this flag is disabled again
             if ignore_clean || result.expiration > Instant::now() {
+                #[cfg(feature = "runtime_metrics")]
+                self.hit_count.fetch_add(1, Ordering::Relaxed);
+
                 return Some(result.data.clone());
             }
         }
 
+        #[cfg(feature = "runtime_metrics")]
+        self.miss_count.fetch_add(1, Ordering::Relaxed);
+
         None
     }
 
@@ -243,6 +273,20 @@ impl TimedCache {
         *(self.azks.write().await) = None;
     }
 
+    /// Evict the items with the provided full binary ids from the cache
+    pub async fn evict(&self, full_keys: &[Vec<u8>]) {
+        let azks_key = crate::append_only_zks::Azks::get_full_binary_key_id(
+            &crate::append_only_zks::DEFAULT_AZKS_KEY,
+        );
+        for key in full_keys.iter() {
+            if *key == azks_key {
+                *(self.azks.write().await) = None;
+            } else {
+                self.map.remove(key);
+            }
+        }
+    }
+
     /// Retrieve all of the cached items
     pub async fn get_all(&self) -> Vec<DbRecord> {
         self.clean().await;
@@ -270,3 +314,64 @@ impl TimedCache {
         self.can_clean.store(true, Ordering::Relaxed);
     }
 }
+
+#[async_trait]
+impl ObjectCache for TimedCache {
+    async fn get(&self, full_key: &[u8]) -> Option<DbRecord> {
+        self.hit_test_full_key(full_key).await
+    }
+
+    async fn put(&self, record: &DbRecord) {
+        TimedCache::put(self, record).await
+    }
+
+    async fn batch_put(&self, records: &[DbRecord]) {
+        TimedCache::batch_put(self, records).await
+    }
+
+    async fn flush(&self) {
+        TimedCache::flush(self).await
+    }
+
+    async fn evict(&self, full_keys: &[Vec<u8>]) {
+        TimedCache::evict(self, full_keys).await
+    }
+
+    async fn get_all(&self) -> Vec<DbRecord> {
+        TimedCache::get_all(self).await
+    }
+
+    fn disable_clean(&self) {
+        TimedCache::disable_clean(self)
+    }
+
+    fn enable_clean(&self) {
+        TimedCache::enable_clean(self)
+    }
+
+    fn log_metrics(&self, level: log::Level) {
+        TimedCache::log_metrics(self, level)
+    }
+
+    /// Hit, miss, and eviction counts are only collected with the `runtime_metrics` feature
+    fn metrics(&self) -> CacheMetrics {
+        let size_bytes = self
+            .map
+            .iter()
+            .map(|kv| kv.key().len() + kv.value().size_of())
+            .sum();
+        #[allow(unused_mut)]
+        let mut metrics = CacheMetrics {
+            num_items: self.map.len(),
+            size_bytes,
+            ..Default::default()
+        };
+        #[cfg(feature = "runtime_metrics")]
+        {
+            metrics.hits = self.hit_count.load(Ordering::Relaxed);
+            metrics.misses = self.miss_count.load(Ordering::Relaxed);
+            metrics.evictions = self.eviction_count.load(Ordering::Relaxed);
+        }
+        metrics
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! This module implements a least-recently-used cache for database objects with a hard
+//! memory budget, measured with [SizeOf]. Each [StorageType] may additionally be given
+//! its own quota, so that (for example) a burst of value state reads cannot evict the
+//! frequently accessed upper levels of the tree.
+
+use super::{CacheMetrics, ObjectCache};
+use crate::storage::types::StorageType;
+use crate::storage::{DbRecord, Storable};
+use akd_core::SizeOf;
+use async_trait::async_trait;
+#[cfg(feature = "runtime_metrics")]
+use log::{error, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+struct LruEntry {
+    tick: u64,
+    size: usize,
+    data: DbRecord,
+}
+
+#[derive(Default)]
+struct LruSegment {
+    entries: HashMap<Vec<u8>, LruEntry>,
+    /// Recency ordering of the entries, from least to most recently used
+    order: BTreeMap<u64, Vec<u8>>,
+    size_bytes: usize,
+    quota_bytes: Option<usize>,
+}
+
+impl LruSegment {
+    fn remove(&mut self, key: &[u8]) -> Option<LruEntry> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.size_bytes -= entry.size;
+        Some(entry)
+    }
+
+    fn evict_oldest(&mut self) -> Option<LruEntry> {
+        let (_, key) = self.order.pop_first()?;
+        let entry = self.entries.remove(&key)?;
+        self.size_bytes -= entry.size;
+        Some(entry)
+    }
+
+    fn is_over_quota(&self) -> bool {
+        matches!(self.quota_bytes, Some(quota) if self.size_bytes > quota)
+    }
+}
+
+#[derive(Default)]
+struct LruState {
+    segments: HashMap<StorageType, LruSegment>,
+    tick: u64,
+    size_bytes: usize,
+}
+
+impl LruState {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Evict entries until every segment is within its quota and the total
+    /// size is within the memory limit. Returns the number of evicted items.
+    fn enforce_limits(&mut self, memory_limit_bytes: usize) -> u64 {
+        let mut num_evicted = 0;
+        for segment in self.segments.values_mut() {
+            while segment.is_over_quota() {
+                match segment.evict_oldest() {
+                    Some(entry) => {
+                        self.size_bytes -= entry.size;
+                        num_evicted += 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+        while self.size_bytes > memory_limit_bytes {
+            // take from the largest segment, so that small segments are not starved
+            let largest = self
+                .segments
+                .values_mut()
+                .max_by_key(|segment| segment.size_bytes);
+            match largest.and_then(|segment| segment.evict_oldest()) {
+                Some(entry) => {
+                    self.size_bytes -= entry.size;
+                    num_evicted += 1;
+                }
+                None => break,
+            }
+        }
+        num_evicted
+    }
+}
+
+/// Implements a least-recently-used cache with a hard memory budget. The AZKS record
+/// is held outside of the budget and is never evicted, it must be manually flushed.
+///
+/// Unlike the [super::TimedCache], items do not expire, and the budget is enforced on
+/// every insertion. While cache cleaning is disabled (i.e. during a transaction, so that the
+/// nodes preloaded for a publish remain resident), nothing is evicted and the budget may be
+/// exceeded, until cleaning is re-enabled.
+#[derive(Clone)]
+pub struct LruCache {
+    azks: Arc<RwLock<Option<DbRecord>>>,
+    state: Arc<Mutex<LruState>>,
+    memory_limit_bytes: usize,
+    can_clean: Arc<AtomicBool>,
+
+    hit_count: Arc<AtomicU64>,
+    miss_count: Arc<AtomicU64>,
+    eviction_count: Arc<AtomicU64>,
+}
+
+impl LruCache {
+    /// Create a new LRU cache which will hold at most `memory_limit_bytes` worth of records
+    pub fn new(memory_limit_bytes: usize) -> Self {
+        Self {
+            azks: Arc::new(RwLock::new(None)),
+            state: Arc::new(Mutex::new(LruState::default())),
+            memory_limit_bytes,
+            can_clean: Arc::new(AtomicBool::new(true)),
+            hit_count: Arc::new(AtomicU64::new(0)),
+            miss_count: Arc::new(AtomicU64::new(0)),
+            eviction_count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Limit the records of the given [StorageType] to at most `quota_bytes`. Records of
+    /// this type are also subject to the overall memory limit of the cache.
+    pub fn with_type_quota(self, storage_type: StorageType, quota_bytes: usize) -> Self {
+        self.lock_state()
+            .segments
+            .entry(storage_type)
+            .or_default()
+            .quota_bytes = Some(quota_bytes);
+        self
+    }
+
+    /// Perform a hit-test of the cache for a given key. If successful, Some(record) will be returned
+    pub async fn hit_test<St: Storable>(&self, key: &St::StorageKey) -> Option<DbRecord> {
+        self.get(&St::get_full_binary_key_id(key)).await
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, LruState> {
+        // a poisoned lock means a panic mid-update, the accounting is still consistent
+        // as sizes are updated together with the entries
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_azks_key(full_key: &[u8]) -> bool {
+        full_key
+            == crate::append_only_zks::Azks::get_full_binary_key_id(
+                &crate::append_only_zks::DEFAULT_AZKS_KEY,
+            )
+            .as_slice()
+    }
+
+    fn enforce_limits(&self, state: &mut LruState) {
+        let num_evicted = state.enforce_limits(self.memory_limit_bytes);
+        self.eviction_count
+            .fetch_add(num_evicted, Ordering::Relaxed);
+    }
+
+    fn insert(&self, state: &mut LruState, record: &DbRecord) {
+        if let DbRecord::Azks(_) = record {
+            *self
+                .azks
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(record.clone());
+            return;
+        }
+
+        let key = record.get_full_binary_id();
+        let size = key.len() + record.size_of();
+        let tick = state.next_tick();
+        let segment = state.segments.entry(record.storage_type()).or_default();
+        let previous_size = segment.remove(&key).map_or(0, |entry| entry.size);
+        segment.order.insert(tick, key.clone());
+        segment.entries.insert(
+            key,
+            LruEntry {
+                tick,
+                size,
+                data: record.clone(),
+            },
+        );
+        segment.size_bytes += size;
+        state.size_bytes = state.size_bytes + size - previous_size;
+    }
+}
+
+#[async_trait]
+impl ObjectCache for LruCache {
+    async fn get(&self, full_key: &[u8]) -> Option<DbRecord> {
+        if Self::is_azks_key(full_key) {
+            let record = self
+                .azks
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone();
+            let counter = if record.is_some() {
+                &self.hit_count
+            } else {
+                &self.miss_count
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            return record;
+        }
+
+        let mut state = self.lock_state();
+        let tick = state.next_tick();
+        let found = state.segments.values_mut().find_map(|segment| {
+            let entry = segment.entries.get_mut(full_key)?;
+            // mark the entry as the most recently used
+            let key = segment.order.remove(&entry.tick)?;
+            segment.order.insert(tick, key);
+            entry.tick = tick;
+            Some(entry.data.clone())
+        });
+
+        let counter = if found.is_some() {
+            &self.hit_count
+        } else {
+            &self.miss_count
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    async fn put(&self, record: &DbRecord) {
+        self.batch_put(std::slice::from_ref(record)).await
+    }
+
+    async fn batch_put(&self, records: &[DbRecord]) {
+        let mut state = self.lock_state();
+        for record in records.iter() {
+            self.insert(&mut state, record);
+        }
+        if self.can_clean.load(Ordering::Relaxed) {
+            self.enforce_limits(&mut state);
+        }
+    }
+
+    async fn flush(&self) {
+        let mut state = self.lock_state();
+        for segment in state.segments.values_mut() {
+            segment.entries.clear();
+            segment.order.clear();
+            segment.size_bytes = 0;
+        }
+        state.size_bytes = 0;
+        *self
+            .azks
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    async fn evict(&self, full_keys: &[Vec<u8>]) {
+        let mut state = self.lock_state();
+        for key in full_keys.iter() {
+            if Self::is_azks_key(key) {
+                *self
+                    .azks
+                    .write()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+                continue;
+            }
+            let removed = state
+                .segments
+                .values_mut()
+                .find_map(|segment| segment.remove(key));
+            if let Some(entry) = removed {
+                state.size_bytes -= entry.size;
+            }
+        }
+    }
+
+    async fn get_all(&self) -> Vec<DbRecord> {
+        let mut items = vec![];
+        if let Some(record) = self
+            .azks
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+        {
+            items.push(record);
+        }
+        let state = self.lock_state();
+        for segment in state.segments.values() {
+            items.extend(segment.entries.values().map(|entry| entry.data.clone()));
+        }
+        items
+    }
+
+    fn disable_clean(&self) {
+        self.can_clean.store(false, Ordering::Relaxed);
+    }
+
+    fn enable_clean(&self) {
+        self.can_clean.store(true, Ordering::Relaxed);
+        // evict what was held over the budget while cleaning was disabled
+        let mut state = self.lock_state();
+        self.enforce_limits(&mut state);
+    }
+
+    fn log_metrics(&self, _level: log::Level) {
+        #[cfg(feature = "runtime_metrics")]
+        {
+            let metrics = self.metrics();
+            let msg = format!(
+                "Cache hits: {}, misses: {}, evictions: {}, cached size: {} items ({} bytes)",
+                metrics.hits,
+                metrics.misses,
+                metrics.evictions,
+                metrics.num_items,
+                metrics.size_bytes
+            );
+            match _level {
+                log::Level::Trace => println!("{msg}"),
+                log::Level::Debug => log::debug!("{}", msg),
+                log::Level::Info => info!("{}", msg),
+                log::Level::Warn => warn!("{}", msg),
+                _ => error!("{}", msg),
+            }
+        }
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        let state = self.lock_state();
+        CacheMetrics {
+            hits: self.hit_count.load(Ordering::Relaxed),
+            misses: self.miss_count.load(Ordering::Relaxed),
+            evictions: self.eviction_count.load(Ordering::Relaxed),
+            num_items: state
+                .segments
+                .values()
+                .map(|segment| segment.entries.len())
+                .sum(),
+            size_bytes: state.size_bytes,
+        }
+    }
+}
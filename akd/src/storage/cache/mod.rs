@@ -5,10 +5,14 @@
 // License, Version 2.0 found in the LICENSE-APACHE file in the root directory
 // of this source tree. You may select, at your option, one of the above-listed licenses.
 
-//! This module handles the caching implementation and testing for a time-based cache
-//! which supports memory pressure shedding
+//! This module handles the caching implementation and testing for the object caches
+//! utilized by the [crate::storage::StorageManager]. Two cache policies are provided:
+//! a time-based cache which supports memory pressure shedding ([TimedCache]) and a
+//! least-recently-used cache with a hard memory budget ([LruCache]). Custom policies
+//! can be supplied by implementing the [ObjectCache] trait.
 
 use crate::storage::DbRecord;
+use async_trait::async_trait;
 use std::time::Instant;
 
 #[cfg(test)]
@@ -32,10 +36,66 @@ impl akd_core::SizeOf for CachedItem {
     }
 }
 
+/// Access counters and size information for an [ObjectCache]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// The number of cache hits
+    pub hits: u64,
+    /// The number of cache misses
+    pub misses: u64,
+    /// The number of items evicted from the cache (expiration or memory pressure)
+    pub evictions: u64,
+    /// The number of items currently cached
+    pub num_items: usize,
+    /// The approximate size of the cached items, in bytes
+    pub size_bytes: usize,
+}
+
+/// A cache of [DbRecord]s which sits in front of the data layer. Records are keyed
+/// by their full binary id (see [crate::storage::Storable::get_full_binary_key_id]).
+#[async_trait]
+pub trait ObjectCache: Send + Sync {
+    /// Retrieve a record from the cache by its full binary id, if present
+    async fn get(&self, full_key: &[u8]) -> Option<DbRecord>;
+
+    /// Put an item into the cache
+    async fn put(&self, record: &DbRecord);
+
+    /// Put a batch of items into the cache
+    async fn batch_put(&self, records: &[DbRecord]);
+
+    /// Flush the cache
+    async fn flush(&self);
+
+    /// Evict the records with the provided full binary ids from the cache. By default
+    /// the cache is flushed in its entirety, for policies which cannot evict individual records.
+    async fn evict(&self, full_keys: &[Vec<u8>]) {
+        let _ = full_keys;
+        self.flush().await
+    }
+
+    /// Retrieve all of the cached items
+    async fn get_all(&self) -> Vec<DbRecord>;
+
+    /// Disable cache-cleaning (i.e. during a transaction), if supported by the policy
+    fn disable_clean(&self);
+
+    /// Re-enable cache-cleaning (i.e. when a transaction is over), if supported by the policy
+    fn enable_clean(&self);
+
+    /// Log cache access metrics along with size information
+    fn log_metrics(&self, level: log::Level);
+
+    /// Retrieve the cache access counters and size information
+    fn metrics(&self) -> CacheMetrics;
+}
+
 // -------- sub modules -------- //
 
 pub mod high_parallelism;
+pub mod lru;
 
 // -------- cache exports -------- //
 
 pub use high_parallelism::TimedCache;
+pub use lru::LruCache;
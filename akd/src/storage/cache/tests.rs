@@ -8,6 +8,7 @@
 //! Caching tests
 
 use super::*;
+use akd_core::SizeOf;
 use std::time::Duration;
 
 use crate::storage::types::{ValueState, ValueStateKey};
@@ -31,8 +32,9 @@ async fn test_cache_put_and_expires() {
         },
         value: AkdValue::from("some value"),
         username: AkdLabel::from("user"),
+        tree: None,
     });
-    let key = ValueStateKey(AkdLabel::from("user").0.to_vec(), 1);
+    let key = ValueStateKey::new(AkdLabel::from("user").0.to_vec(), 1);
     cache.put(&value_state).await;
 
     let got = cache.hit_test::<ValueState>(&key).await;
@@ -57,8 +59,9 @@ async fn test_cache_overwrite() {
         },
         value: AkdValue::from("some value"),
         username: AkdLabel::from("user"),
+        tree: None,
     };
-    let key = ValueStateKey(AkdLabel::from("user").0.to_vec(), 1);
+    let key = ValueStateKey::new(AkdLabel::from("user").0.to_vec(), 1);
 
     let value_state_2 = ValueState {
         epoch: 1,
@@ -69,6 +72,7 @@ async fn test_cache_overwrite() {
         },
         value: AkdValue::from("some value"),
         username: AkdLabel::from("user"),
+        tree: None,
     };
     cache.put(&DbRecord::ValueState(value_state)).await;
     cache
@@ -96,8 +100,9 @@ async fn test_cache_memory_pressure() {
         },
         value: AkdValue::from("some value"),
         username: AkdLabel::from("user"),
+        tree: None,
     });
-    let key = ValueStateKey(AkdLabel::from("user").0.to_vec(), 1);
+    let key = ValueStateKey::new(AkdLabel::from("user").0.to_vec(), 1);
     cache.put(&value_state).await;
 
     // we only do an "automated" clean every 50ms in test, which is when memory pressure is evaluated.
@@ -127,6 +132,7 @@ async fn test_many_memory_pressure() {
             },
             value: AkdValue::from("test"),
             username: AkdLabel::from("user"),
+            tree: None,
         })
         .map(DbRecord::ValueState)
         .collect::<Vec<_>>();
@@ -140,3 +146,138 @@ async fn test_many_memory_pressure() {
     let all = cache.get_all().await;
     assert!(all.len() < 99);
 }
+
+fn test_value_state(username: &str, epoch: u64) -> DbRecord {
+    DbRecord::ValueState(ValueState {
+        epoch,
+        version: epoch,
+        label: NodeLabel {
+            label_len: 1,
+            label_val: [0u8; 32],
+        },
+        value: AkdValue::from("some value"),
+        username: AkdLabel::from(username),
+        tree: None,
+    })
+}
+
+#[tokio::test]
+async fn test_lru_cache_evicts_least_recently_used() {
+    let records = (1..=3)
+        .map(|epoch| test_value_state("user", epoch))
+        .collect::<Vec<_>>();
+    let record_size = records[0].get_full_binary_id().len() + records[0].size_of();
+    // room for exactly 2 of the records
+    let cache = LruCache::new(2 * record_size);
+
+    cache.put(&records[0]).await;
+    cache.put(&records[1]).await;
+    // touch the first record so that the second becomes the least recently used
+    let key = ValueStateKey::new(AkdLabel::from("user").0.to_vec(), 1);
+    assert_eq!(
+        Some(records[0].clone()),
+        cache.hit_test::<ValueState>(&key).await
+    );
+
+    cache.put(&records[2]).await;
+    let mut all = cache.get_all().await;
+    all.sort();
+    assert_eq!(vec![records[0].clone(), records[2].clone()], all);
+
+    let key = ValueStateKey::new(AkdLabel::from("user").0.to_vec(), 2);
+    assert_eq!(None, cache.hit_test::<ValueState>(&key).await);
+
+    let metrics = cache.metrics();
+    assert_eq!(1, metrics.hits);
+    assert_eq!(1, metrics.misses);
+    assert_eq!(1, metrics.evictions);
+    assert_eq!(2, metrics.num_items);
+    assert_eq!(2 * record_size, metrics.size_bytes);
+}
+
+#[tokio::test]
+async fn test_lru_cache_disable_clean() {
+    let records = (1..=4)
+        .map(|epoch| test_value_state("user", epoch))
+        .collect::<Vec<_>>();
+    let record_size = records[0].get_full_binary_id().len() + records[0].size_of();
+    let cache = LruCache::new(2 * record_size);
+
+    // while cleaning is disabled (i.e. in a transaction), the budget may be exceeded
+    cache.disable_clean();
+    cache.batch_put(&records).await;
+    assert_eq!(4, cache.get_all().await.len());
+    assert_eq!(0, cache.metrics().evictions);
+
+    // and is enforced again once cleaning is re-enabled
+    cache.enable_clean();
+    let mut all = cache.get_all().await;
+    all.sort();
+    assert_eq!(records[2..].to_vec(), all);
+    assert_eq!(2, cache.metrics().evictions);
+    assert_eq!(2 * record_size, cache.metrics().size_bytes);
+}
+
+#[tokio::test]
+async fn test_lru_cache_type_quota() {
+    let records = (1..=10)
+        .map(|epoch| test_value_state("user", epoch))
+        .collect::<Vec<_>>();
+    let record_size = records[0].get_full_binary_id().len() + records[0].size_of();
+    let cache = LruCache::new(1024 * 1024).with_type_quota(
+        crate::storage::types::StorageType::ValueState,
+        3 * record_size,
+    );
+
+    cache.batch_put(&records).await;
+    // only the 3 most recent value states fit in the quota, even though the overall
+    // budget has plenty of room
+    let mut all = cache.get_all().await;
+    all.sort();
+    assert_eq!(records[7..].to_vec(), all);
+
+    // the AZKS is not subject to the budget
+    let azks = DbRecord::Azks(crate::Azks {
+        latest_epoch: 1,
+        num_nodes: 1,
+        tree: None,
+    });
+    cache.put(&azks).await;
+    assert_eq!(
+        Some(azks),
+        cache
+            .hit_test::<crate::Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await
+    );
+
+    cache.flush().await;
+    assert_eq!(0, cache.get_all().await.len());
+    assert_eq!(0, cache.metrics().size_bytes);
+}
+
+#[tokio::test]
+async fn test_cache_evict() {
+    let records = (1..=3)
+        .map(|epoch| test_value_state("user", epoch))
+        .collect::<Vec<_>>();
+    let azks = DbRecord::Azks(crate::Azks {
+        latest_epoch: 1,
+        num_nodes: 1,
+        tree: None,
+    });
+    let evicted = vec![records[1].get_full_binary_id(), azks.get_full_binary_id()];
+
+    let timed = TimedCache::new(None, None, None);
+    let lru = LruCache::new(1024 * 1024);
+    let caches: [&dyn ObjectCache; 2] = [&timed, &lru];
+    for cache in caches {
+        cache.batch_put(&records).await;
+        cache.put(&azks).await;
+        cache.evict(&evicted).await;
+
+        let mut all = cache.get_all().await;
+        all.sort();
+        assert_eq!(vec![records[0].clone(), records[2].clone()], all);
+        assert_eq!(2, cache.metrics().num_items);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A compact binary encoding of [DbRecord]s, used by the write-ahead log to persist records
+//! and by the integrity layer as the canonical contents of a record.
+//!
+//! All integers are big-endian, and variable-length fields are prefixed with their length. A
+//! record is preceded by its [StorageType] byte, which is flagged and followed by the tree
+//! identifier for records of a tree of a backend holding several trees, like the full binary
+//! keys of the records.
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    tree_scoped_key_prefix, DbRecord, EpochChangeset, PendingEpoch, PendingOperation, RecordMac,
+    SchemaVersion, StorageType, TreeId, ValueState, TREE_SCOPED_KEY_FLAG,
+};
+use crate::tree_node::{TreeNode, TreeNodeType, TreeNodeWithPreviousValue};
+use crate::{AkdLabel, AkdValue, Azks, NodeLabel};
+
+use akd_core::hash::DIGEST_BYTES;
+use akd_core::AzksValue;
+
+// ===== Encoding ===== //
+
+pub(crate) fn encode_record(out: &mut Vec<u8>, record: &DbRecord) {
+    out.extend_from_slice(&tree_scoped_key_prefix(
+        record.storage_type(),
+        record.tree(),
+    ));
+    match record {
+        DbRecord::Azks(azks) => {
+            out.extend_from_slice(&azks.latest_epoch.to_be_bytes());
+            out.extend_from_slice(&azks.num_nodes.to_be_bytes());
+        }
+        DbRecord::TreeNode(node) => {
+            encode_label(out, &node.label);
+            encode_tree_node(out, &node.latest_node);
+            match &node.previous_node {
+                Some(previous) => {
+                    out.push(1);
+                    encode_tree_node(out, previous);
+                }
+                None => out.push(0),
+            }
+        }
+        DbRecord::ValueState(state) => {
+            encode_bytes(out, &state.value.0);
+            out.extend_from_slice(&state.version.to_be_bytes());
+            encode_label(out, &state.label);
+            out.extend_from_slice(&state.epoch.to_be_bytes());
+            encode_bytes(out, &state.username.0);
+        }
+        DbRecord::EpochChangeset(changeset) => {
+            out.extend_from_slice(&changeset.epoch.to_be_bytes());
+            out.extend_from_slice(&changeset.part.to_be_bytes());
+            out.extend_from_slice(&changeset.num_parts.to_be_bytes());
+            out.extend_from_slice(&(changeset.node_labels.len() as u32).to_be_bytes());
+            for label in changeset.node_labels.iter() {
+                encode_label(out, label);
+            }
+        }
+        DbRecord::SchemaVersion(version) => {
+            out.extend_from_slice(&version.version.to_be_bytes());
+        }
+        DbRecord::RecordMac(mac) => {
+            encode_bytes(out, &mac.record_key);
+            out.extend_from_slice(&mac.mac);
+        }
+        DbRecord::PendingEpoch(pending) => {
+            out.extend_from_slice(&pending.epoch.to_be_bytes());
+            out.push(pending.operation as u8);
+            out.extend_from_slice(&pending.next_part.to_be_bytes());
+        }
+    }
+}
+
+fn encode_tree_node(out: &mut Vec<u8>, node: &TreeNode) {
+    encode_label(out, &node.label);
+    out.extend_from_slice(&node.last_epoch.to_be_bytes());
+    out.extend_from_slice(&node.min_descendant_epoch.to_be_bytes());
+    encode_label(out, &node.parent);
+    out.push(node.node_type as u8);
+    for child in [&node.left_child, &node.right_child] {
+        match child {
+            Some(label) => {
+                out.push(1);
+                encode_label(out, label);
+            }
+            None => out.push(0),
+        }
+    }
+    out.extend_from_slice(&node.hash.0);
+}
+
+fn encode_label(out: &mut Vec<u8>, label: &NodeLabel) {
+    out.extend_from_slice(&label.label_len.to_be_bytes());
+    out.extend_from_slice(&label.label_val);
+}
+
+fn encode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+// ===== Decoding ===== //
+
+/// Reads encoded fields from the front of a byte slice
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StorageError> {
+        if self.bytes.len() < len {
+            return Err(StorageError::Other(
+                "Encoded record is truncated".to_string(),
+            ));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn remaining(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], StorageError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StorageError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StorageError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StorageError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn vec(&mut self) -> Result<Vec<u8>, StorageError> {
+        let len = self.u32()?;
+        Ok(self.bytes(len as usize)?.to_vec())
+    }
+
+    fn label(&mut self) -> Result<NodeLabel, StorageError> {
+        let len = self.u32()?;
+        Ok(NodeLabel::new(self.array()?, len))
+    }
+
+    fn optional_label(&mut self) -> Result<Option<NodeLabel>, StorageError> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.label()?)),
+        }
+    }
+
+    fn tree_node(&mut self) -> Result<TreeNode, StorageError> {
+        Ok(TreeNode {
+            label: self.label()?,
+            last_epoch: self.u64()?,
+            min_descendant_epoch: self.u64()?,
+            parent: self.label()?,
+            node_type: TreeNodeType::from_u8(self.u8()?),
+            left_child: self.optional_label()?,
+            right_child: self.optional_label()?,
+            hash: AzksValue(self.array::<DIGEST_BYTES>()?),
+        })
+    }
+
+    pub(crate) fn record(&mut self) -> Result<DbRecord, StorageError> {
+        let storage_type = self.u8()?;
+        // the flagged storage type of a record of one of several trees is followed by the tree
+        let tree = match storage_type & TREE_SCOPED_KEY_FLAG {
+            0 => None,
+            _ => Some(TreeId(self.u64()?)),
+        };
+        let record = match storage_type & !TREE_SCOPED_KEY_FLAG {
+            t if t == StorageType::Azks as u8 => DbRecord::Azks(Azks {
+                latest_epoch: self.u64()?,
+                num_nodes: self.u64()?,
+                tree,
+            }),
+            t if t == StorageType::TreeNode as u8 => {
+                let label = self.label()?;
+                let latest_node = self.tree_node()?;
+                let previous_node = match self.u8()? {
+                    0 => None,
+                    _ => Some(self.tree_node()?),
+                };
+                DbRecord::TreeNode(TreeNodeWithPreviousValue {
+                    label,
+                    tree,
+                    latest_node,
+                    previous_node,
+                })
+            }
+            t if t == StorageType::ValueState as u8 => DbRecord::ValueState(ValueState {
+                value: AkdValue(self.vec()?),
+                version: self.u64()?,
+                label: self.label()?,
+                epoch: self.u64()?,
+                username: AkdLabel(self.vec()?),
+                tree,
+            }),
+            t if t == StorageType::EpochChangeset as u8 => {
+                let epoch = self.u64()?;
+                let part = self.u32()?;
+                let num_parts = self.u32()?;
+                let num_labels = self.u32()?;
+                let node_labels = (0..num_labels)
+                    .map(|_| self.label())
+                    .collect::<Result<Vec<_>, _>>()?;
+                DbRecord::EpochChangeset(EpochChangeset {
+                    epoch,
+                    part,
+                    num_parts,
+                    node_labels,
+                    tree,
+                })
+            }
+            t if t == StorageType::SchemaVersion as u8 => DbRecord::SchemaVersion(SchemaVersion {
+                version: self.u64()?,
+            }),
+            t if t == StorageType::RecordMac as u8 => DbRecord::RecordMac(RecordMac {
+                record_key: self.vec()?,
+                mac: self.array()?,
+            }),
+            t if t == StorageType::PendingEpoch as u8 => {
+                let epoch = self.u64()?;
+                let operation = self.u8()?;
+                let operation = PendingOperation::from_u8(operation).ok_or_else(|| {
+                    StorageError::Other(format!("Unknown pending operation {operation}"))
+                })?;
+                DbRecord::PendingEpoch(PendingEpoch {
+                    epoch,
+                    operation,
+                    next_part: self.u32()?,
+                    tree,
+                })
+            }
+            other => {
+                return Err(StorageError::Other(format!(
+                    "Unknown encoded record type {other}"
+                )))
+            }
+        };
+        Ok(record)
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Encryption at rest of the user data held in [ValueState] records.
+//!
+//! The [EncryptedDatabase] wraps a [Database] and encrypts the `value` and `username` of
+//! every [ValueState] before it is written, decrypting them transparently on read. All other
+//! records are passed through unchanged.
+//!
+//! Records are encrypted with AES-256-GCM-SIV ([RFC 8452](https://www.rfc-editor.org/rfc/rfc8452)).
+//! Usernames form part of the key of the record and must be looked up by their encryption,
+//! so they are encrypted under a fixed nonce. AES-GCM-SIV is resistant to the reuse of
+//! nonces, so this is a deterministic authenticated encryption, which only reveals whether
+//! two usernames are equal. Values are encrypted under a random nonce, which is stored ahead
+//! of their ciphertext, so that equal values are not revealed. Values are bound to the
+//! (encrypted) username and version of their record through the associated data, so a value
+//! cannot be moved between records without detection.
+//!
+//! An encrypted username is 16 bytes (the authentication tag) longer than its plaintext, so
+//! the storage of usernames must allow for labels 16 bytes longer than those published. An
+//! encrypted value is 28 bytes (the nonce and the authentication tag) longer than its
+//! plaintext.
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, KeyData, StorageType, TreeId, ValueState, ValueStateKey, ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue};
+
+use aes_gcm_siv::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+/// The size, in bytes, of an encryption key
+pub const ENCRYPTION_KEY_BYTES: usize = 32;
+
+const USERNAME_DOMAIN: &[u8] = b"username";
+const VALUE_DOMAIN: &[u8] = b"value";
+/// The size, in bytes, of the nonce stored ahead of an encrypted value
+const NONCE_BYTES: usize = 12;
+
+/// Provides the key which records are encrypted under. Implementations which retrieve the
+/// key from an external service (i.e. a key management service) should cache it, as it is
+/// retrieved for every encryption and decryption.
+///
+/// Note that changing the key renders previously encrypted records unreadable.
+#[async_trait]
+pub trait EncryptionKeyProvider: Send + Sync {
+    /// Retrieve the encryption key
+    async fn retrieve(&self) -> Result<[u8; ENCRYPTION_KEY_BYTES], StorageError>;
+}
+
+/// An [EncryptionKeyProvider] which serves a fixed key
+#[derive(Clone)]
+pub struct StaticKeyProvider([u8; ENCRYPTION_KEY_BYTES]);
+
+impl StaticKeyProvider {
+    /// Create a provider which serves the given key
+    pub fn new(key: [u8; ENCRYPTION_KEY_BYTES]) -> Self {
+        Self(key)
+    }
+}
+
+#[async_trait]
+impl EncryptionKeyProvider for StaticKeyProvider {
+    async fn retrieve(&self) -> Result<[u8; ENCRYPTION_KEY_BYTES], StorageError> {
+        Ok(self.0)
+    }
+}
+
+/// The cipher records are encrypted with
+struct RecordCipher(Aes256GcmSiv);
+
+impl RecordCipher {
+    fn new(key: &[u8; ENCRYPTION_KEY_BYTES]) -> Self {
+        Self(Aes256GcmSiv::new(key.into()))
+    }
+
+    /// Encrypt the plaintext under the nonce, producing `ciphertext || tag`
+    fn seal(
+        &self,
+        nonce: &Nonce,
+        associated_data: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        self.0
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| StorageError::Other("Failed to encrypt field".to_string()))
+    }
+
+    /// Authenticate and decrypt the output of [RecordCipher::seal]
+    fn open(
+        &self,
+        nonce: &Nonce,
+        associated_data: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        self.0
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: sealed,
+                    aad: associated_data,
+                },
+            )
+            .map_err(|_| StorageError::Other("Failed to authenticate encrypted field".to_string()))
+    }
+
+    fn encrypt_username(&self, username: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.seal(&Nonce::default(), USERNAME_DOMAIN, username)
+    }
+
+    fn decrypt_username(&self, username: &[u8]) -> Result<Vec<u8>, StorageError> {
+        self.open(&Nonce::default(), USERNAME_DOMAIN, username)
+    }
+
+    /// Encrypt a value under a random nonce, producing `nonce || ciphertext || tag`
+    fn encrypt_value(&self, associated_data: &[u8], value: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let nonce = Aes256GcmSiv::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.append(&mut self.seal(&nonce, associated_data, value)?);
+        Ok(sealed)
+    }
+
+    /// Authenticate and decrypt the output of [RecordCipher::encrypt_value]
+    fn decrypt_value(
+        &self,
+        associated_data: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, StorageError> {
+        if sealed.len() < NONCE_BYTES {
+            return Err(StorageError::Other(
+                "Encrypted value is too short to hold its nonce".to_string(),
+            ));
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_BYTES);
+        self.open(Nonce::from_slice(nonce), associated_data, sealed)
+    }
+
+    fn value_associated_data(encrypted_username: &[u8], version: u64) -> Vec<u8> {
+        let mut data = VALUE_DOMAIN.to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(encrypted_username);
+        data
+    }
+
+    fn encrypt_state(&self, state: ValueState) -> Result<ValueState, StorageError> {
+        let username = self.encrypt_username(&state.username)?;
+        let associated_data = Self::value_associated_data(&username, state.version);
+        Ok(ValueState {
+            value: AkdValue(self.encrypt_value(&associated_data, &state.value)?),
+            username: AkdLabel(username),
+            ..state
+        })
+    }
+
+    fn decrypt_state(&self, state: ValueState) -> Result<ValueState, StorageError> {
+        let associated_data = Self::value_associated_data(&state.username, state.version);
+        Ok(ValueState {
+            value: AkdValue(self.decrypt_value(&associated_data, &state.value)?),
+            username: AkdLabel(self.decrypt_username(&state.username)?),
+            ..state
+        })
+    }
+
+    fn encrypt_record(&self, record: DbRecord) -> Result<DbRecord, StorageError> {
+        match record {
+            DbRecord::ValueState(state) => Ok(DbRecord::ValueState(self.encrypt_state(state)?)),
+            other => Ok(other),
+        }
+    }
+
+    fn decrypt_record(&self, record: DbRecord) -> Result<DbRecord, StorageError> {
+        match record {
+            DbRecord::ValueState(state) => Ok(DbRecord::ValueState(self.decrypt_state(state)?)),
+            other => Ok(other),
+        }
+    }
+
+    fn encrypt_key(&self, key: ValueStateKey) -> Result<ValueStateKey, StorageError> {
+        Ok(ValueStateKey(self.encrypt_username(&key.0)?, key.1, key.2))
+    }
+}
+
+/// A [Database] which encrypts the `value` and `username` of [ValueState] records at rest,
+/// under the key served by an [EncryptionKeyProvider]. See the [module documentation](self).
+pub struct EncryptedDatabase<Db: Database, K: EncryptionKeyProvider> {
+    db: Db,
+    keys: K,
+}
+
+impl<Db: Database, K: EncryptionKeyProvider> EncryptedDatabase<Db, K> {
+    /// Wrap the database, encrypting records under the key served by the provider
+    pub fn new(db: Db, keys: K) -> Self {
+        Self { db, keys }
+    }
+
+    /// Retrieve a reference to the underlying database, which holds the encrypted records
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    async fn cipher(&self) -> Result<RecordCipher, StorageError> {
+        Ok(RecordCipher::new(&self.keys.retrieve().await?))
+    }
+
+    fn value_state_key<St: Storable>(id: &St::StorageKey) -> Result<ValueStateKey, StorageError> {
+        ValueState::key_from_full_binary(&St::get_full_binary_key_id(id))
+            .map_err(StorageError::Other)
+    }
+}
+
+#[async_trait]
+impl<Db: Database, K: EncryptionKeyProvider> Database for EncryptedDatabase<Db, K> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        let cipher = self.cipher().await?;
+        self.db.set(cipher.encrypt_record(record)?).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let cipher = self.cipher().await?;
+        let records = records
+            .into_iter()
+            .map(|record| cipher.encrypt_record(record))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.db.batch_set(records, state).await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        if St::data_type() != StorageType::ValueState {
+            return self.db.get::<St>(id).await;
+        }
+        let cipher = self.cipher().await?;
+        let key = cipher.encrypt_key(Self::value_state_key::<St>(id)?)?;
+        let record = self.db.get::<ValueState>(&key).await?;
+        cipher.decrypt_record(record)
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        if St::data_type() != StorageType::ValueState {
+            return self.db.batch_get::<St>(ids).await;
+        }
+        let cipher = self.cipher().await?;
+        let keys = ids
+            .iter()
+            .map(|id| cipher.encrypt_key(Self::value_state_key::<St>(id)?))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        self.db
+            .batch_get::<ValueState>(&keys)
+            .await?
+            .into_iter()
+            .map(|record| cipher.decrypt_record(record))
+            .collect()
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        let cipher = self.cipher().await?;
+        let encrypted = AkdLabel(cipher.encrypt_username(username)?);
+        let data = self.db.get_user_data(&encrypted, tree).await?;
+        Ok(KeyData {
+            states: data
+                .states
+                .into_iter()
+                .map(|state| cipher.decrypt_state(state))
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        let cipher = self.cipher().await?;
+        let encrypted = AkdLabel(cipher.encrypt_username(username)?);
+        let state = self.db.get_user_state(&encrypted, flag, tree).await?;
+        cipher.decrypt_state(state)
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        let cipher = self.cipher().await?;
+        let encrypted = usernames
+            .iter()
+            .map(|username| Ok(AkdLabel(cipher.encrypt_username(username)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let plaintext_by_encrypted = encrypted
+            .iter()
+            .cloned()
+            .zip(usernames.iter().cloned())
+            .collect::<HashMap<_, _>>();
+
+        let versions = self
+            .db
+            .get_user_state_versions(&encrypted, flag, tree)
+            .await?;
+        let mut results = HashMap::new();
+        for (encrypted_username, (version, value)) in versions.into_iter() {
+            let username = match plaintext_by_encrypted.get(&encrypted_username) {
+                Some(username) => username.clone(),
+                None => AkdLabel(cipher.decrypt_username(&encrypted_username)?),
+            };
+            let associated_data = RecordCipher::value_associated_data(&encrypted_username, version);
+            let value = AkdValue(cipher.decrypt_value(&associated_data, &value)?);
+            results.insert(username, (version, value));
+        }
+        Ok(results)
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        let cipher = self.cipher().await?;
+        let encrypted = usernames
+            .iter()
+            .map(|username| Ok(AkdLabel(cipher.encrypt_username(username)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        self.db
+            .get_user_states(&encrypted, flag, tree)
+            .await?
+            .into_values()
+            .map(|state| {
+                let state = cipher.decrypt_state(state)?;
+                Ok((state.username.clone(), state))
+            })
+            .collect()
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        self.db.computes_record_macs()
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil, K: EncryptionKeyProvider> StorageUtil for EncryptedDatabase<Db, K> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let cipher = self.cipher().await?;
+        self.db
+            .batch_get_type_direct::<St>()
+            .await?
+            .into_iter()
+            .map(|record| cipher.decrypt_record(record))
+            .collect()
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let cipher = self.cipher().await?;
+        self.db
+            .batch_get_all_direct()
+            .await?
+            .into_iter()
+            .map(|record| cipher.decrypt_record(record))
+            .collect()
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let cipher = self.cipher().await?;
+        // the cursor holds the plaintext key of the last record of the previous page, while
+        // the value states are ordered by their encrypted key in the database
+        let after = match after {
+            Some(after) if St::data_type() == StorageType::ValueState => {
+                let key = ValueState::key_from_full_binary(after).map_err(StorageError::Other)?;
+                Some(ValueState::get_full_binary_key_id(
+                    &cipher.encrypt_key(key)?,
+                ))
+            }
+            other => other.map(|after| after.to_vec()),
+        };
+        self.db
+            .batch_get_type_page_direct::<St>(after.as_deref(), limit)
+            .await?
+            .into_iter()
+            .map(|record| cipher.decrypt_record(record))
+            .collect()
+    }
+}
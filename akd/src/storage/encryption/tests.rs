@@ -0,0 +1,244 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Encryption at rest tests
+
+use super::*;
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::tests::{populate_database, user_state};
+use crate::Azks;
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+async fn populated_database() -> Result<
+    (
+        AsyncInMemoryDatabase,
+        EncryptedDatabase<AsyncInMemoryDatabase, StaticKeyProvider>,
+    ),
+    StorageError,
+> {
+    let db = AsyncInMemoryDatabase::new();
+    let encrypted = EncryptedDatabase::new(db.clone(), StaticKeyProvider::new([7u8; 32]));
+    populate_database(&encrypted).await?;
+    Ok((db, encrypted))
+}
+
+#[tokio::test]
+async fn test_encrypted_database_stores_no_plaintext() -> Result<(), StorageError> {
+    let (db, _) = populated_database().await?;
+    let records = db.batch_get_all_direct().await?;
+    assert_eq!(4, records.len());
+    for record in records.into_iter() {
+        if let DbRecord::ValueState(state) = record {
+            assert!(!contains(&state.username, b"alice"));
+            assert!(!contains(&state.username, b"bob"));
+            assert!(!contains(&state.value, b"secret"));
+        }
+    }
+
+    // non-user records are not encrypted
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(2, 3)),
+        db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_database_hides_equal_values() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let encrypted = EncryptedDatabase::new(db.clone(), StaticKeyProvider::new([7u8; 32]));
+    encrypted
+        .batch_set(
+            vec![
+                DbRecord::ValueState(user_state("alice", "same key", 1, 1)),
+                DbRecord::ValueState(user_state("alice", "same key", 2, 2)),
+                DbRecord::ValueState(user_state("bob", "same key", 1, 1)),
+            ],
+            DbSetState::General,
+        )
+        .await?;
+
+    // usernames are encrypted deterministically so that they can be looked up, while equal
+    // values are encrypted under distinct nonces
+    let states = db
+        .batch_get_type_direct::<ValueState>()
+        .await?
+        .into_iter()
+        .filter_map(|record| match record {
+            DbRecord::ValueState(state) => Some(state),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(3, states.len());
+    let usernames = states
+        .iter()
+        .map(|state| state.username.clone())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(2, usernames.len());
+    let values = states
+        .iter()
+        .map(|state| state.value.clone())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(3, values.len());
+    assert!(states
+        .iter()
+        .all(|state| state.value.len() == "same key".len() + 28));
+
+    assert_eq!(
+        user_state("alice", "same key", 2, 2),
+        encrypted
+            .get_user_state(
+                &AkdLabel::from("alice"),
+                ValueStateRetrievalFlag::MaxEpoch,
+                None
+            )
+            .await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_database_reads() -> Result<(), StorageError> {
+    let (_, encrypted) = populated_database().await?;
+    let alice = AkdLabel::from("alice");
+    let bob = AkdLabel::from("bob");
+
+    let record = encrypted
+        .get::<ValueState>(&ValueStateKey::new(alice.to_vec(), 1))
+        .await?;
+    assert_eq!(
+        DbRecord::ValueState(user_state("alice", "secret key 1", 1, 1)),
+        record
+    );
+
+    let records = encrypted
+        .batch_get::<ValueState>(&[
+            ValueStateKey::new(alice.to_vec(), 2),
+            ValueStateKey::new(bob.to_vec(), 2),
+        ])
+        .await?;
+    assert_eq!(2, records.len());
+    assert!(records.contains(&DbRecord::ValueState(user_state(
+        "bob",
+        "secret key 3",
+        1,
+        2
+    ))));
+
+    let data = encrypted.get_user_data(&alice, None).await?;
+    assert_eq!(2, data.states.len());
+    assert!(data.states.iter().all(|state| state.username == alice));
+
+    let state = encrypted
+        .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+        .await?;
+    assert_eq!(user_state("alice", "secret key 2", 2, 2), state);
+
+    let versions = encrypted
+        .get_user_state_versions(
+            &[alice.clone(), bob.clone(), AkdLabel::from("missing")],
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
+        .await?;
+    assert_eq!(2, versions.len());
+    assert_eq!(
+        Some(&(2, AkdValue::from("secret key 2"))),
+        versions.get(&alice)
+    );
+    assert_eq!(
+        Some(&(1, AkdValue::from("secret key 3"))),
+        versions.get(&bob)
+    );
+
+    let states = encrypted
+        .get_user_states(
+            &[alice.clone(), bob.clone()],
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        Some(&user_state("alice", "secret key 2", 2, 2)),
+        states.get(&alice)
+    );
+
+    let all = encrypted.batch_get_type_direct::<ValueState>().await?;
+    assert_eq!(3, all.len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_database_detects_tampering() -> Result<(), StorageError> {
+    let (db, encrypted) = populated_database().await?;
+    let alice = AkdLabel::from("alice");
+
+    // swap the encrypted values of two of alice's records
+    let mut states = db
+        .batch_get_type_direct::<ValueState>()
+        .await?
+        .into_iter()
+        .filter_map(|record| match record {
+            DbRecord::ValueState(state) if state.epoch == 1 || state.version == 2 => Some(state),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(2, states.len());
+    let value = states[0].value.clone();
+    states[0].value = states[1].value.clone();
+    states[1].value = value;
+    db.batch_set(
+        states.into_iter().map(DbRecord::ValueState).collect(),
+        DbSetState::General,
+    )
+    .await?;
+
+    assert!(encrypted
+        .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+        .await
+        .is_err());
+    assert!(encrypted.get_user_data(&alice, None).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_database_wrong_key() -> Result<(), StorageError> {
+    let (db, _) = populated_database().await?;
+    let alice = AkdLabel::from("alice");
+
+    // a different key cannot find the user's records, nor decrypt them
+    let other = EncryptedDatabase::new(db, StaticKeyProvider::new([8u8; 32]));
+    assert!(matches!(
+        other.get_user_data(&alice, None).await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert!(other.batch_get_all_direct().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_database_paged_reads() -> Result<(), StorageError> {
+    let (_, encrypted) = populated_database().await?;
+    crate::storage::tests::run_test_cases_for_storage_util_impl(&encrypted).await;
+
+    // the cursor of a page is the plaintext key of its last record
+    let first = encrypted
+        .batch_get_type_page_direct::<ValueState>(None, 1)
+        .await?;
+    let rest = encrypted
+        .batch_get_type_page_direct::<ValueState>(Some(&first[0].get_full_binary_id()), 100)
+        .await?;
+    assert!(!rest.is_empty());
+    assert!(!rest.contains(&first[0]));
+    Ok(())
+}
@@ -0,0 +1,333 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A fault-injecting [Database] wrapper, for testing the resilience of the directory and of
+//! storage layers against a misbehaving backend.
+//!
+//! The [FaultyDatabase] wraps any [Database], and according to its [FaultConfig] delays
+//! operations, fails them, times them out, or applies only part of a `batch_set` before
+//! failing. All faults are drawn from an RNG seeded upon construction, so a failing test
+//! can be reproduced from its seed (given the same sequence of operations).
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, KeyData, StorageType, TreeId, ValueState, ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// The operations which faults are injected into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FaultTarget {
+    /// Both reads and writes
+    #[default]
+    All,
+    /// Only reads (`get`, `batch_get`, the user state retrievals and [StorageUtil] reads)
+    Reads,
+    /// Only writes (`set` and `batch_set`)
+    Writes,
+}
+
+/// The faults injected by a [FaultyDatabase]. The default configuration injects no faults.
+///
+/// For each targeted operation a single random draw decides the fault: an operation times
+/// out with probability `timeout_rate`, otherwise fails with probability `error_rate`,
+/// otherwise (for a `batch_set` only) is partially applied with probability
+/// `partial_write_rate`. The rates should therefore sum to at most 1.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// The operations faults are injected into
+    pub target: FaultTarget,
+    /// The probability of an operation failing with a [StorageError::Connection] error
+    /// without being applied
+    pub error_rate: f64,
+    /// The probability of a `batch_set` applying a random prefix of its records before
+    /// failing with a [StorageError::Connection] error
+    pub partial_write_rate: f64,
+    /// The probability of an operation stalling for `timeout` before failing with a
+    /// [StorageError::Connection] error, without being applied
+    pub timeout_rate: f64,
+    /// How long a timed out operation stalls for
+    pub timeout: Duration,
+    /// The minimum latency added to every operation (including those which are not targeted)
+    pub min_latency: Duration,
+    /// The maximum latency added to every operation
+    pub max_latency: Duration,
+}
+
+impl FaultConfig {
+    /// A configuration failing the targeted operations at the given rate
+    pub fn errors(target: FaultTarget, error_rate: f64) -> Self {
+        Self {
+            target,
+            error_rate,
+            ..Default::default()
+        }
+    }
+}
+
+enum Fault {
+    None,
+    Error,
+    PartialWrite(f64),
+    Timeout,
+}
+
+/// A [Database] which injects faults into the operations of the wrapped database. See the
+/// [module documentation](self).
+///
+/// Clones share the RNG, configuration and fault counter.
+#[derive(Clone)]
+pub struct FaultyDatabase<Db: Database> {
+    db: Db,
+    rng: Arc<Mutex<StdRng>>,
+    config: Arc<RwLock<FaultConfig>>,
+    num_faults: Arc<AtomicU64>,
+}
+
+impl<Db: Database> FaultyDatabase<Db> {
+    /// Wrap the database, drawing faults from an RNG with the given seed
+    pub fn new(db: Db, seed: u64, config: FaultConfig) -> Self {
+        Self {
+            db,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+            config: Arc::new(RwLock::new(config)),
+            num_faults: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Retrieve a reference to the underlying database
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    /// Replace the fault configuration, i.e. to inject faults only once a test has been set up
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Stop injecting faults and latency
+    pub fn disable(&self) {
+        self.set_config(FaultConfig::default());
+    }
+
+    /// The number of faults injected so far (excluding latency)
+    pub fn num_injected_faults(&self) -> u64 {
+        self.num_faults.load(Ordering::Relaxed)
+    }
+
+    /// Draw the latency and fault of an operation, without holding the locks across an await
+    fn draw(&self, is_write: bool, is_batch_write: bool) -> (Duration, Fault, Duration) {
+        let config = self.config.read().unwrap().clone();
+        let mut rng = self.rng.lock().unwrap();
+
+        let latency = if config.max_latency > config.min_latency {
+            rng.gen_range(config.min_latency..=config.max_latency)
+        } else {
+            config.min_latency
+        };
+
+        let targeted = match config.target {
+            FaultTarget::All => true,
+            FaultTarget::Reads => !is_write,
+            FaultTarget::Writes => is_write,
+        };
+        if !targeted {
+            return (latency, Fault::None, config.timeout);
+        }
+
+        let roll = rng.gen::<f64>();
+        let fault = if roll < config.timeout_rate {
+            Fault::Timeout
+        } else if roll < config.timeout_rate + config.error_rate {
+            Fault::Error
+        } else if is_batch_write
+            && roll < config.timeout_rate + config.error_rate + config.partial_write_rate
+        {
+            Fault::PartialWrite(rng.gen::<f64>())
+        } else {
+            Fault::None
+        };
+        (latency, fault, config.timeout)
+    }
+
+    /// Apply the latency and fault of an operation, returning the fraction of a batch
+    /// to write on a partial write
+    async fn inject(
+        &self,
+        operation: &str,
+        is_write: bool,
+        is_batch_write: bool,
+    ) -> Result<Option<f64>, StorageError> {
+        let (latency, fault, timeout) = self.draw(is_write, is_batch_write);
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        match fault {
+            Fault::None => Ok(None),
+            Fault::Error => {
+                self.num_faults.fetch_add(1, Ordering::Relaxed);
+                Err(StorageError::Connection(format!(
+                    "Injected failure of {operation}"
+                )))
+            }
+            Fault::Timeout => {
+                self.num_faults.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(timeout).await;
+                Err(StorageError::Connection(format!(
+                    "Injected timeout of {operation} after {timeout:?}"
+                )))
+            }
+            Fault::PartialWrite(fraction) => {
+                self.num_faults.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(fraction))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Db: Database> Database for FaultyDatabase<Db> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.inject("set", true, false).await?;
+        self.db.set(record).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        match self.inject("batch_set", true, true).await? {
+            None => self.db.batch_set(records, state).await,
+            Some(fraction) => {
+                let num_applied = (records.len() as f64 * fraction) as usize;
+                let total = records.len();
+                let applied = records.into_iter().take(num_applied).collect::<Vec<_>>();
+                if !applied.is_empty() {
+                    self.db.batch_set(applied, state).await?;
+                }
+                Err(StorageError::Connection(format!(
+                    "Injected failure of batch_set after applying {num_applied} of {total} records"
+                )))
+            }
+        }
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        self.inject("get", false, false).await?;
+        self.db.get::<St>(id).await
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.inject("batch_get", false, false).await?;
+        self.db.batch_get::<St>(ids).await
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        self.inject("get_user_data", false, false).await?;
+        self.db.get_user_data(username, tree).await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        self.inject("get_user_state", false, false).await?;
+        self.db.get_user_state(username, flag, tree).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.inject("get_user_state_versions", false, false).await?;
+        self.db.get_user_state_versions(usernames, flag, tree).await
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        self.inject("get_user_states", false, false).await?;
+        self.db.get_user_states(usernames, flag, tree).await
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        self.db.computes_record_macs()
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil> StorageUtil for FaultyDatabase<Db> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.inject("batch_get_type_direct", false, false).await?;
+        self.db.batch_get_type_direct::<St>().await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.inject("batch_get_all_direct", false, false).await?;
+        self.db.batch_get_all_direct().await
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.inject("batch_get_type_page_direct", false, false)
+            .await?;
+        self.db.batch_get_type_page_direct::<St>(after, limit).await
+    }
+
+    async fn batch_get_stored_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, DbRecord)>, StorageError> {
+        self.inject("batch_get_stored_page_direct", false, false)
+            .await?;
+        self.db
+            .batch_get_stored_page_direct(storage_type, after, limit)
+            .await
+    }
+
+    async fn batch_delete_direct(&self, stored_ids: &[Vec<u8>]) -> Result<(), StorageError> {
+        self.inject("batch_delete_direct", true, false).await?;
+        self.db.batch_delete_direct(stored_ids).await
+    }
+}
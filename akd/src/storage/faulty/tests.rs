@@ -0,0 +1,147 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Fault injection tests
+
+use super::*;
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::Azks;
+use std::time::Instant;
+
+fn user_states(count: u64) -> Vec<DbRecord> {
+    (1..=count)
+        .map(|i| {
+            DbRecord::ValueState(DbRecord::build_user_state(
+                format!("user{i}").into_bytes(),
+                b"value".to_vec(),
+                1,
+                256,
+                [i as u8; 32],
+                1,
+            ))
+        })
+        .collect()
+}
+
+async fn outcomes(seed: u64) -> Vec<bool> {
+    let db = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        seed,
+        FaultConfig::errors(FaultTarget::All, 0.5),
+    );
+    let mut outcomes = vec![];
+    for _ in 0..32 {
+        outcomes.push(matches!(
+            db.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+            Err(StorageError::Connection(_))
+        ));
+    }
+    outcomes
+}
+
+#[tokio::test]
+async fn test_faults_are_reproducible() {
+    let first = outcomes(42).await;
+    assert_eq!(first, outcomes(42).await);
+    assert_ne!(first, outcomes(43).await);
+}
+
+#[tokio::test]
+async fn test_injected_errors() -> Result<(), StorageError> {
+    let db = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        1,
+        FaultConfig::errors(FaultTarget::Writes, 1.0),
+    );
+    let azks = DbRecord::Azks(DbRecord::build_azks(1, 1));
+
+    // failed writes are not applied
+    assert!(matches!(
+        db.set(azks.clone()).await,
+        Err(StorageError::Connection(_))
+    ));
+    assert!(matches!(
+        db.batch_set(user_states(4), DbSetState::General).await,
+        Err(StorageError::Connection(_))
+    ));
+    assert!(db.inner().batch_get_all_direct().await?.is_empty());
+    assert_eq!(2, db.num_injected_faults());
+
+    // reads are not targeted
+    db.inner().set(azks.clone()).await?;
+    assert_eq!(azks, db.get::<Azks>(&DEFAULT_AZKS_KEY).await?);
+
+    db.set_config(FaultConfig::errors(FaultTarget::Reads, 1.0));
+    assert!(db.get::<Azks>(&DEFAULT_AZKS_KEY).await.is_err());
+    assert!(db.batch_get_all_direct().await.is_err());
+    db.set(azks.clone()).await?;
+
+    db.disable();
+    assert_eq!(azks, db.get::<Azks>(&DEFAULT_AZKS_KEY).await?);
+    assert_eq!(4, db.num_injected_faults());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_partial_writes() -> Result<(), StorageError> {
+    let db = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        7,
+        FaultConfig {
+            partial_write_rate: 1.0,
+            ..Default::default()
+        },
+    );
+    let records = user_states(64);
+    assert!(db
+        .batch_set(records.clone(), DbSetState::General)
+        .await
+        .is_err());
+
+    // a prefix of the batch was applied
+    let written = db.inner().batch_get_all_direct().await?;
+    assert!(written.len() < records.len());
+    for record in written.iter() {
+        assert!(records[..written.len()].contains(record));
+    }
+
+    // single writes are never partially applied
+    db.set(DbRecord::Azks(DbRecord::build_azks(1, 1))).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_latency_and_timeouts() {
+    let db = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        3,
+        FaultConfig {
+            min_latency: Duration::from_millis(5),
+            max_latency: Duration::from_millis(10),
+            ..Default::default()
+        },
+    );
+    let start = Instant::now();
+    assert!(db.get::<Azks>(&DEFAULT_AZKS_KEY).await.is_err());
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert_eq!(0, db.num_injected_faults());
+
+    db.set_config(FaultConfig {
+        timeout_rate: 1.0,
+        timeout: Duration::from_millis(20),
+        ..Default::default()
+    });
+    let start = Instant::now();
+    assert!(matches!(
+        db.set(DbRecord::Azks(DbRecord::build_azks(1, 1))).await,
+        Err(StorageError::Connection(_))
+    ));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(db.inner().batch_get_all_direct().await.unwrap().is_empty());
+    assert_eq!(1, db.num_injected_faults());
+}
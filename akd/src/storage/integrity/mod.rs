@@ -0,0 +1,380 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Tamper-evidence for stored records.
+//!
+//! The [IntegrityDatabase] wraps a [Database] and, for every record written, stores a
+//! [RecordMac] holding a keyed BLAKE3 MAC over the record's full binary key
+//! ([Storable::get_full_binary_key_id]) and its contents. Records are checked against their
+//! MAC when read, and a record which is missing its MAC or does not match it is reported as a
+//! [StorageError::IntegrityViolation].
+//!
+//! Records are written in the same [Database::batch_set] as their MACs, so they are written
+//! as atomically as the underlying database writes a batch.
+//!
+//! MACs are computed under the key served by a [MacKeyProvider], which is independent of any
+//! encryption key (see [crate::storage::encryption]). Changing the MAC key invalidates every
+//! stored MAC, so they must be recomputed with [IntegrityDatabase::backfill_macs] afterwards.
+//!
+//! The MACs detect records which are modified, or replaced with records from elsewhere in
+//! the database. They do not detect records which are deleted along with their MACs. Notably,
+//! deleting the latest [ValueState] of a user (and its MAC) rolls the user back to its prior
+//! state without detection by this layer. Such a rollback is detected by clients instead, as
+//! the lookup proofs for the prior state do not verify against the root hash of the tree.
+
+use crate::errors::StorageError;
+use crate::storage::codec::encode_record;
+use crate::storage::types::{
+    DbRecord, KeyData, RecordMac, RecordMacKey, StorageType, TreeId, ValueState,
+    ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+/// The size, in bytes, of a MAC key
+pub const MAC_KEY_BYTES: usize = 32;
+
+const MAC_KEY_CONTEXT: &str = "akd storage integrity mac key";
+
+/// The types of the records which are protected by a MAC
+const PROTECTED_TYPES: [StorageType; 6] = [
+    StorageType::Azks,
+    StorageType::TreeNode,
+    StorageType::ValueState,
+    StorageType::EpochChangeset,
+    StorageType::SchemaVersion,
+    StorageType::PendingEpoch,
+];
+
+/// Provides the key which record MACs are computed under. Implementations which retrieve the
+/// key from an external service should cache it, as it is retrieved for every read and write.
+#[async_trait]
+pub trait MacKeyProvider: Send + Sync {
+    /// Retrieve the MAC key
+    async fn retrieve(&self) -> Result<[u8; MAC_KEY_BYTES], StorageError>;
+}
+
+/// A [MacKeyProvider] which serves a fixed key
+#[derive(Clone)]
+pub struct StaticMacKeyProvider([u8; MAC_KEY_BYTES]);
+
+impl StaticMacKeyProvider {
+    /// Create a provider which serves the given key
+    pub fn new(key: [u8; MAC_KEY_BYTES]) -> Self {
+        Self(key)
+    }
+}
+
+#[async_trait]
+impl MacKeyProvider for StaticMacKeyProvider {
+    async fn retrieve(&self) -> Result<[u8; MAC_KEY_BYTES], StorageError> {
+        Ok(self.0)
+    }
+}
+
+/// A [Database] which attaches a keyed MAC to every stored record and checks it upon read.
+/// See the [module documentation](self).
+pub struct IntegrityDatabase<Db: Database, K: MacKeyProvider> {
+    db: Db,
+    keys: K,
+}
+
+impl<Db: Database, K: MacKeyProvider> IntegrityDatabase<Db, K> {
+    /// Wrap the database, computing MACs under the key served by the provider
+    pub fn new(db: Db, keys: K) -> Self {
+        Self { db, keys }
+    }
+
+    /// Retrieve a reference to the underlying database
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    async fn mac_key(&self) -> Result<[u8; 32], StorageError> {
+        Ok(blake3::derive_key(
+            MAC_KEY_CONTEXT,
+            &self.keys.retrieve().await?,
+        ))
+    }
+
+    fn compute_mac(mac_key: &[u8; 32], record_key: &[u8], record: &DbRecord) -> blake3::Hash {
+        let mut contents = vec![];
+        encode_record(&mut contents, record);
+        blake3::Hasher::new_keyed(mac_key)
+            .update(&(record_key.len() as u64).to_be_bytes())
+            .update(record_key)
+            .update(&contents)
+            .finalize()
+    }
+
+    fn check(
+        mac_key: &[u8; 32],
+        record: &DbRecord,
+        macs: &HashMap<Vec<u8>, RecordMac>,
+    ) -> Result<(), StorageError> {
+        let record_key = record.get_full_binary_id();
+        let stored = macs.get(&record_key).ok_or_else(|| {
+            StorageError::IntegrityViolation(format!(
+                "{:?} record {:?} has no MAC",
+                record.storage_type(),
+                record_key
+            ))
+        })?;
+        // comparison of hashes is constant-time
+        let mac = Self::compute_mac(mac_key, &record_key, record);
+        if mac != blake3::Hash::from(stored.mac) {
+            return Err(StorageError::IntegrityViolation(format!(
+                "{:?} record {:?} does not match its MAC",
+                record.storage_type(),
+                record_key
+            )));
+        }
+        Ok(())
+    }
+
+    async fn get_macs(
+        &self,
+        records: &[DbRecord],
+    ) -> Result<HashMap<Vec<u8>, RecordMac>, StorageError> {
+        let keys = records
+            .iter()
+            .map(|record| RecordMacKey(record.get_full_binary_id()))
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+        Ok(self
+            .db
+            .batch_get::<RecordMac>(&keys)
+            .await?
+            .into_iter()
+            .filter_map(|record| match record {
+                DbRecord::RecordMac(mac) => Some((mac.record_key.clone(), mac)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Check the records against their stored MACs, [RecordMac] records themselves are not
+    /// checked
+    async fn verify(&self, records: &[DbRecord]) -> Result<(), StorageError> {
+        let records = records
+            .iter()
+            .filter(|record| !matches!(record, DbRecord::RecordMac(_)))
+            .cloned()
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mac_key = self.mac_key().await?;
+        let macs = self.get_macs(&records).await?;
+        for record in records.iter() {
+            Self::check(&mac_key, record, &macs)?;
+        }
+        Ok(())
+    }
+
+    async fn verify_states(&self, states: &[ValueState]) -> Result<(), StorageError> {
+        let records = states
+            .iter()
+            .cloned()
+            .map(DbRecord::ValueState)
+            .collect::<Vec<_>>();
+        self.verify(&records).await
+    }
+}
+
+impl<Db: StorageUtil, K: MacKeyProvider> IntegrityDatabase<Db, K> {
+    /// Compute and store the MAC of every record held in the database, reading `page_size`
+    /// records at a time, and returning the number of records. This must be run when adding
+    /// the integrity layer to a database which already holds records, and after changing
+    /// the MAC key. It must not run concurrently with writes.
+    pub async fn backfill_macs(&self, page_size: usize) -> Result<usize, StorageError> {
+        let mac_key = self.mac_key().await?;
+        let mut count = 0;
+        for storage_type in PROTECTED_TYPES.into_iter() {
+            let mut after = None;
+            loop {
+                let page = self
+                    .db
+                    .batch_get_storage_type_page_direct(storage_type, after.as_deref(), page_size)
+                    .await?;
+                let last = match page.last() {
+                    Some(last) => last.get_full_binary_id(),
+                    None => break,
+                };
+                let macs = page
+                    .iter()
+                    .map(|record| {
+                        let record_key = record.get_full_binary_id();
+                        let mac = Self::compute_mac(&mac_key, &record_key, record);
+                        DbRecord::RecordMac(DbRecord::build_record_mac(record_key, *mac.as_bytes()))
+                    })
+                    .collect::<Vec<_>>();
+                count += macs.len();
+                self.db.batch_set(macs, DbSetState::General).await?;
+                after = Some(last);
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[async_trait]
+impl<Db: Database, K: MacKeyProvider> Database for IntegrityDatabase<Db, K> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.batch_set(vec![record], DbSetState::General).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let mac_key = self.mac_key().await?;
+        let mut macs = HashMap::new();
+        for record in records.iter() {
+            if let DbRecord::RecordMac(_) = record {
+                continue;
+            }
+            let record_key = record.get_full_binary_id();
+            let mac = Self::compute_mac(&mac_key, &record_key, record);
+            // a later write of the same record in the batch supersedes an earlier one
+            macs.insert(record_key, *mac.as_bytes());
+        }
+
+        // the MACs are written in the same batch as the records they cover
+        let mut records = records;
+        records.extend(macs.into_iter().map(|(record_key, mac)| {
+            DbRecord::RecordMac(DbRecord::build_record_mac(record_key, mac))
+        }));
+        self.db.batch_set(records, state).await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        let record = self.db.get::<St>(id).await?;
+        self.verify(std::slice::from_ref(&record)).await?;
+        Ok(record)
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get::<St>(ids).await?;
+        self.verify(&records).await?;
+        Ok(records)
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        let data = self.db.get_user_data(username, tree).await?;
+        self.verify_states(&data.states).await?;
+        Ok(data)
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        let state = self.db.get_user_state(username, flag, tree).await?;
+        self.verify_states(std::slice::from_ref(&state)).await?;
+        Ok(state)
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        // the versions alone cannot be checked against the MACs of the full states, so the
+        // full states are retrieved and checked
+        Ok(self
+            .get_user_states(usernames, flag, tree)
+            .await?
+            .into_iter()
+            .map(|(username, state)| (username, (state.version, state.value)))
+            .collect())
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        let states = self.db.get_user_states(usernames, flag, tree).await?;
+        let records = states
+            .values()
+            .cloned()
+            .map(DbRecord::ValueState)
+            .collect::<Vec<_>>();
+        self.verify(&records).await?;
+        Ok(states)
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        true
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil, K: MacKeyProvider> StorageUtil for IntegrityDatabase<Db, K> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get_type_direct::<St>().await?;
+        self.verify(&records).await?;
+        Ok(records)
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.db.batch_get_all_direct().await?;
+        let mac_key = self.mac_key().await?;
+        let macs = records
+            .iter()
+            .filter_map(|record| match record {
+                DbRecord::RecordMac(mac) => Some((mac.record_key.clone(), mac.clone())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        for record in records.iter() {
+            if !matches!(record, DbRecord::RecordMac(_)) {
+                Self::check(&mac_key, record, &macs)?;
+            }
+        }
+        Ok(records)
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self
+            .db
+            .batch_get_type_page_direct::<St>(after, limit)
+            .await?;
+        self.verify(&records).await?;
+        Ok(records)
+    }
+}
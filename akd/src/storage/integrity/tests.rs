@@ -0,0 +1,195 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Storage integrity tests
+
+use super::*;
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::tests::{populate_database, user_state};
+use crate::storage::types::ValueStateKey;
+use crate::Azks;
+
+async fn populated_database() -> Result<
+    (
+        AsyncInMemoryDatabase,
+        IntegrityDatabase<AsyncInMemoryDatabase, StaticMacKeyProvider>,
+    ),
+    StorageError,
+> {
+    let db = AsyncInMemoryDatabase::new();
+    let protected = IntegrityDatabase::new(db.clone(), StaticMacKeyProvider::new([7u8; 32]));
+    populate_database(&protected).await?;
+    Ok((db, protected))
+}
+
+#[tokio::test]
+async fn test_integrity_database_reads() -> Result<(), StorageError> {
+    let (db, protected) = populated_database().await?;
+    let alice = AkdLabel::from("alice");
+    let bob = AkdLabel::from("bob");
+
+    // every record has a MAC
+    let macs = db.batch_get_type_direct::<RecordMac>().await?;
+    assert_eq!(4, macs.len());
+
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(2, 3)),
+        protected.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    let records = protected
+        .batch_get::<ValueState>(&[
+            ValueStateKey::new(alice.to_vec(), 1),
+            ValueStateKey::new(bob.to_vec(), 2),
+        ])
+        .await?;
+    assert_eq!(2, records.len());
+    assert_eq!(2, protected.get_user_data(&alice, None).await?.states.len());
+    assert_eq!(
+        user_state("alice", "secret key 2", 2, 2),
+        protected
+            .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+            .await?
+    );
+    let versions = protected
+        .get_user_state_versions(
+            &[alice.clone(), bob.clone(), AkdLabel::from("carol")],
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
+        .await?;
+    assert_eq!(2, versions.len());
+    assert_eq!(
+        Some(&(2, AkdValue::from("secret key 2"))),
+        versions.get(&alice)
+    );
+    let states = protected
+        .get_user_states(
+            &[alice.clone(), bob.clone()],
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        Some(&user_state("bob", "secret key 3", 1, 2)),
+        states.get(&bob)
+    );
+    assert_eq!(
+        3,
+        protected.batch_get_type_direct::<ValueState>().await?.len()
+    );
+    assert_eq!(8, protected.batch_get_all_direct().await?.len());
+
+    // overwriting a record replaces its MAC
+    protected
+        .set(DbRecord::Azks(DbRecord::build_azks(3, 5)))
+        .await?;
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 5)),
+        protected.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_integrity_database_detects_tampering() -> Result<(), StorageError> {
+    let (db, protected) = populated_database().await?;
+    let alice = AkdLabel::from("alice");
+
+    // modify a record directly in the underlying database
+    db.set(DbRecord::ValueState(user_state("alice", "forged", 2, 2)))
+        .await?;
+    assert!(matches!(
+        protected
+            .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+            .await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+    assert!(matches!(
+        protected.get_user_data(&alice, None).await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+    assert!(matches!(
+        protected
+            .get_user_state_versions(
+                std::slice::from_ref(&alice),
+                ValueStateRetrievalFlag::MaxEpoch,
+                None
+            )
+            .await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+    assert!(matches!(
+        protected.batch_get_all_direct().await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+
+    // records which are untouched are still readable
+    protected
+        .get_user_state(&alice, ValueStateRetrievalFlag::SpecificVersion(1), None)
+        .await?;
+
+    // a MAC computed under a different key is rejected
+    let other = IntegrityDatabase::new(db, StaticMacKeyProvider::new([8u8; 32]));
+    assert!(matches!(
+        other.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_integrity_database_missing_mac() -> Result<(), StorageError> {
+    let (db, protected) = populated_database().await?;
+
+    // a record inserted without a MAC is rejected
+    db.set(DbRecord::ValueState(user_state(
+        "carol",
+        "secret key 4",
+        1,
+        3,
+    )))
+    .await?;
+    assert!(matches!(
+        protected
+            .get_user_state(
+                &AkdLabel::from("carol"),
+                ValueStateRetrievalFlag::MaxEpoch,
+                None
+            )
+            .await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+
+    // until the MACs are backfilled, a page at a time
+    assert_eq!(5, protected.backfill_macs(2).await?);
+    protected
+        .get_user_state(
+            &AkdLabel::from("carol"),
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
+        .await?;
+    protected.batch_get_all_direct().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_integrity_database_mac_key_rotation() -> Result<(), StorageError> {
+    let (db, _) = populated_database().await?;
+
+    // the MACs computed under the previous key are rejected, until they are recomputed
+    let rotated = IntegrityDatabase::new(db, StaticMacKeyProvider::new([8u8; 32]));
+    assert!(matches!(
+        rotated.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+    assert_eq!(4, rotated.backfill_macs(100).await?);
+    rotated.get::<Azks>(&DEFAULT_AZKS_KEY).await?;
+    assert_eq!(8, rotated.batch_get_all_direct().await?.len());
+    Ok(())
+}
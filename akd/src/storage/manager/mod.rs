@@ -9,30 +9,42 @@
 //! to manage interactions with the data layer to optimize things like caching and
 //! transaction management
 
-use crate::storage::cache::TimedCache;
+use crate::append_only_zks::{Azks, DEFAULT_AZKS_KEY};
+use crate::metrics::{
+    storage_type_label, MetricsRecorder, CACHE_HIT_RATIO, CACHE_REQUESTS_TOTAL,
+    STORAGE_OPERATIONS_TOTAL, STORAGE_OPERATION_DURATION_SECONDS, STORAGE_RECORDS_WRITTEN_TOTAL,
+};
+use crate::storage::cache::{CacheMetrics, ObjectCache, TimedCache};
 use crate::storage::transaction::Transaction;
 use crate::storage::types::DbRecord;
+use crate::storage::types::EpochChangeset;
+use crate::storage::types::EpochChangesetKey;
 use crate::storage::types::KeyData;
+use crate::storage::types::PendingEpoch;
+use crate::storage::types::StorageType;
 use crate::storage::types::ValueState;
+use crate::storage::types::EPOCH_CHANGESET_PART_LABELS;
 use crate::storage::Database;
 use crate::storage::DbSetState;
 use crate::storage::Storable;
 use crate::storage::StorageError;
+use crate::utils::in_span;
 use crate::AkdLabel;
 use crate::AkdValue;
 
-use log::debug;
+use futures::stream::BoxStream;
+use log::{debug, warn};
 #[cfg(feature = "runtime_metrics")]
-use log::{error, info, warn};
+use log::{error, info};
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::sync::atomic::AtomicU64;
-#[cfg(feature = "runtime_metrics")]
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use super::types::ValueStateRetrievalFlag;
+use pinned::PinnedNodes;
+use replicas::ReadReplicas;
 
 type Metric = usize;
 
@@ -46,21 +58,43 @@ const METRIC_TOMBSTONE: Metric = 6;
 const METRIC_GET_USER_STATE: Metric = 7;
 const METRIC_GET_USER_DATA: Metric = 8;
 const METRIC_GET_USER_STATE_VERSIONS: Metric = 9;
+const METRIC_REPLICA_READ: Metric = 10;
 
-const NUM_METRICS: usize = 10;
+const NUM_METRICS: usize = 11;
 
+mod pinned;
+mod replicas;
 #[cfg(test)]
 mod tests;
 
 /// Represents the manager of the storage mediums, including caching
 /// and transactional operations (creating the transaction, committing it, etc)
+///
+/// Optionally, reads may be routed to a set of read replicas of the underlying
+/// database (see [StorageManager::with_read_replicas]). All writes, transaction
+/// commits, and reads made while a transaction is active go to the primary database.
+///
+/// The top levels of the tree may also be pinned in memory (see
+/// [StorageManager::with_pinned_levels]), outside of the reach of cache eviction.
 pub struct StorageManager<Db: Database> {
-    cache: Option<TimedCache>,
+    cache: Option<Arc<dyn ObjectCache>>,
     transaction: Transaction,
     /// The underlying database managed by this storage manager
     db: Arc<Db>,
+    /// Optional read replicas of the underlying database
+    replicas: Option<Arc<ReadReplicas<Db>>>,
+    /// The latest epoch observed from the primary database's AZKS record
+    latest_epoch: Arc<AtomicU64>,
+    /// Optional pinned nodes of the top levels of the tree
+    pinned: Option<Arc<PinnedNodes>>,
+    /// Whether an [EpochChangeset] is recorded with each committed epoch
+    record_changesets: bool,
 
     metrics: [Arc<AtomicU64>; NUM_METRICS],
+    /// Optional recorder of structured metrics
+    metrics_recorder: Option<Arc<dyn MetricsRecorder>>,
+    /// The cache hits and misses, for the hit ratio reported to the metrics recorder
+    cache_lookups: Arc<(AtomicU64, AtomicU64)>,
 }
 
 impl<Db: Database> Clone for StorageManager<Db> {
@@ -69,7 +103,13 @@ impl<Db: Database> Clone for StorageManager<Db> {
             cache: self.cache.clone(),
             transaction: self.transaction.clone(),
             db: self.db.clone(),
+            replicas: self.replicas.clone(),
+            latest_epoch: self.latest_epoch.clone(),
+            pinned: self.pinned.clone(),
+            record_changesets: self.record_changesets,
             metrics: self.metrics.clone(),
+            metrics_recorder: self.metrics_recorder.clone(),
+            cache_lookups: self.cache_lookups.clone(),
         }
     }
 }
@@ -84,7 +124,13 @@ impl<Db: Database> StorageManager<Db> {
             cache: None,
             transaction: Transaction::new(),
             db: Arc::new(db),
+            replicas: None,
+            latest_epoch: Arc::new(AtomicU64::new(0)),
+            pinned: None,
+            record_changesets: false,
             metrics: [0; NUM_METRICS].map(|_| Arc::new(AtomicU64::new(0))),
+            metrics_recorder: None,
+            cache_lookups: Arc::new((AtomicU64::new(0), AtomicU64::new(0))),
         }
     }
 
@@ -96,14 +142,132 @@ impl<Db: Database> StorageManager<Db> {
         cache_clean_frequency: Option<Duration>,
     ) -> Self {
         Self {
-            cache: Some(TimedCache::new(
+            cache: Some(Arc::new(TimedCache::new(
                 cache_item_lifetime,
                 cache_limit_bytes,
                 cache_clean_frequency,
-            )),
+            ))),
+            transaction: Transaction::new(),
+            db: Arc::new(db),
+            replicas: None,
+            latest_epoch: Arc::new(AtomicU64::new(0)),
+            pinned: None,
+            record_changesets: false,
+            metrics: [0; NUM_METRICS].map(|_| Arc::new(AtomicU64::new(0))),
+            metrics_recorder: None,
+            cache_lookups: Arc::new((AtomicU64::new(0), AtomicU64::new(0))),
+        }
+    }
+
+    /// Create a new storage manager with the provided cache policy (for example an
+    /// [crate::storage::cache::LruCache])
+    pub fn new_with_cache<C: ObjectCache + 'static>(db: Db, cache: C) -> Self {
+        Self {
+            cache: Some(Arc::new(cache)),
             transaction: Transaction::new(),
             db: Arc::new(db),
+            replicas: None,
+            latest_epoch: Arc::new(AtomicU64::new(0)),
+            pinned: None,
+            record_changesets: false,
             metrics: [0; NUM_METRICS].map(|_| Arc::new(AtomicU64::new(0))),
+            metrics_recorder: None,
+            cache_lookups: Arc::new((AtomicU64::new(0), AtomicU64::new(0))),
+        }
+    }
+
+    /// Route reads to the provided read replicas of the primary database. The primary
+    /// continues to serve all writes, transaction commits, and reads of the AZKS record.
+    ///
+    /// A replica only serves a read once it has caught up to the latest epoch observed
+    /// by this storage manager, otherwise the read falls back to the primary. Replicas
+    /// which are ahead of that epoch are safe to read from, since tree nodes retain
+    /// their previous value (see [crate::tree_node::TreeNodeWithPreviousValue]).
+    ///
+    /// * `replicas`: The read replicas of the primary database
+    /// * `epoch_check_frequency`: How often each replica's epoch is re-checked (default 1s)
+    pub fn with_read_replicas(
+        mut self,
+        replicas: Vec<Db>,
+        epoch_check_frequency: Option<Duration>,
+    ) -> Self {
+        self.replicas = if replicas.is_empty() {
+            None
+        } else {
+            Some(Arc::new(ReadReplicas::new(replicas, epoch_check_frequency)))
+        };
+        self
+    }
+
+    /// Subscribe to the feed of epochs published to the primary database, if supported
+    /// by the database implementation (see [Database::subscribe_epoch_changes])
+    pub fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    /// Returns the number of read replicas configured for this storage manager
+    pub fn num_read_replicas(&self) -> usize {
+        self.replicas.as_ref().map_or(0, |replicas| replicas.len())
+    }
+
+    /// Pin the nodes of the top `levels` levels of the tree (starting at the root) in
+    /// memory. Pinned nodes are never evicted from the cache, and are only reloaded when
+    /// the epoch changes. Note that `levels` levels hold up to 2^`levels` - 1 nodes.
+    ///
+    /// The pinned nodes are loaded by [StorageManager::warm_pinned_nodes], which is
+    /// called when constructing a [crate::directory::Directory].
+    pub fn with_pinned_levels(mut self, levels: u8) -> Self {
+        self.pinned = if levels == 0 {
+            None
+        } else {
+            Some(Arc::new(PinnedNodes::new(levels)))
+        };
+        self
+    }
+
+    /// Load the pinned tree nodes (see [StorageManager::with_pinned_levels]) for the
+    /// latest epoch in the database. This is a no-op if no levels are pinned, the nodes
+    /// are already loaded for the latest epoch, or there is no AZKS in storage yet.
+    pub async fn warm_pinned_nodes(&self) -> Result<(), StorageError> {
+        let pinned = match &self.pinned {
+            Some(pinned) => pinned,
+            None => return Ok(()),
+        };
+        let epoch = match self.db.get::<Azks>(&DEFAULT_AZKS_KEY).await {
+            Ok(DbRecord::Azks(azks)) => azks.get_latest_epoch(),
+            Ok(_) | Err(StorageError::NotFound(_)) => return Ok(()),
+            Err(other) => return Err(other),
+        };
+        self.latest_epoch.fetch_max(epoch, Ordering::Relaxed);
+        pinned.load(&*self.db, epoch, None).await
+    }
+
+    /// Record an [EpochChangeset] with each committed epoch, holding the labels of the tree
+    /// nodes modified in the epoch. Read-only instances use these to evict only the modified
+    /// nodes from their caches upon an epoch change (see [StorageManager::invalidate_cache_since]).
+    pub fn with_epoch_changesets(mut self) -> Self {
+        self.record_changesets = true;
+        self
+    }
+
+    /// Emit structured metrics of the storage operations (and of the operations of a
+    /// [crate::directory::Directory] over this storage manager) to the provided recorder.
+    /// See [crate::metrics] for the metrics emitted.
+    pub fn with_metrics_recorder(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics_recorder = Some(recorder);
+        self
+    }
+
+    /// Retrieve the metrics recorder, if present
+    pub(crate) fn metrics_recorder(&self) -> Option<&dyn MetricsRecorder> {
+        self.metrics_recorder.as_deref()
+    }
+
+    /// Returns the number of tree nodes currently pinned in memory
+    pub async fn num_pinned_nodes(&self) -> usize {
+        match &self.pinned {
+            Some(pinned) => pinned.len().await,
+            None => 0,
         }
     }
 
@@ -113,11 +277,22 @@ impl<Db: Database> StorageManager<Db> {
         self.db.clone()
     }
 
+    /// Retrieve a reference to the database implementation, bypassing any caching or
+    /// transaction pending
+    pub(crate) fn database(&self) -> &Db {
+        &self.db
+    }
+
     /// Returns whether the storage manager has a cache
     pub fn has_cache(&self) -> bool {
         self.cache.is_some()
     }
 
+    /// Retrieve the access counters and size information of the cache (if present)
+    pub fn cache_metrics(&self) -> Option<CacheMetrics> {
+        self.cache.as_ref().map(|cache| cache.metrics())
+    }
+
     /// Log metrics from the storage manager (cache, transaction, and storage hit rates etc)
     pub async fn log_metrics(&self, level: log::Level) {
         if let Some(cache) = &self.cache {
@@ -147,6 +322,7 @@ impl<Db: Database> StorageManager<Db> {
     GET USER STATE {}
     GET USER DATA {}
     GET USER STATE VERSIONS {}
+    REPLICA READS {}
 ===================================================
 ============ Database operation timing ============
 ===================================================
@@ -160,6 +336,7 @@ impl<Db: Database> StorageManager<Db> {
                 snapshot[METRIC_GET_USER_STATE],
                 snapshot[METRIC_GET_USER_DATA],
                 snapshot[METRIC_GET_USER_STATE_VERSIONS],
+                snapshot[METRIC_REPLICA_READ],
                 snapshot[METRIC_READ_TIME],
                 snapshot[METRIC_WRITE_TIME]
             );
@@ -190,10 +367,20 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Commit a transaction in the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(records = tracing::field::Empty, epoch = tracing::field::Empty)
+        )
+    )]
     pub async fn commit_transaction(&self) -> Result<u64, StorageError> {
         // this retrieves all the trans operations, and "de-activates" the transaction flag
-        let records = self.transaction.commit_transaction()?;
+        let mut records = self.transaction.commit_transaction()?;
         let num_records = records.len();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("records", num_records);
 
         // The transaction is now complete (or reverted) and therefore we can re-enable
         // the cache cleaning status
@@ -206,12 +393,40 @@ impl<Db: Database> StorageManager<Db> {
             return Ok(0);
         }
 
-        let _epoch = match records.last() {
+        let epoch = match records.last() {
             Some(DbRecord::Azks(azks)) => Ok(azks.latest_epoch),
             other => Err(StorageError::Transaction(format!(
                 "The last record in the transaction log is NOT an Azks record {other:?}"
             ))),
         }?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("epoch", epoch);
+
+        if self.record_changesets {
+            let node_labels = records
+                .iter()
+                .filter_map(|record| match record {
+                    DbRecord::TreeNode(node) => Some(node.label),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            // the AZKS must remain the last record written
+            let azks = records.pop();
+            records.extend(
+                EpochChangeset::split(epoch, node_labels)
+                    .into_iter()
+                    .map(DbRecord::EpochChangeset),
+            );
+            records.extend(azks);
+        }
+
+        let committed_nodes = self.pinned.as_ref().map(|_| {
+            records
+                .iter()
+                .filter(|record| matches!(record, DbRecord::TreeNode(_)))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
 
         // update the cache
         if let Some(cache) = &self.cache {
@@ -219,15 +434,118 @@ impl<Db: Database> StorageManager<Db> {
         }
 
         // Write to the database
-        self.tic_toc(
-            METRIC_WRITE_TIME,
-            self.db.batch_set(records, DbSetState::TransactionCommit),
-        )
-        .await?;
+        self.record_writes(&records);
+        let result = self
+            .tic_toc(
+                METRIC_WRITE_TIME,
+                "batch_set",
+                batch_storage_type_label(&records),
+                self.db.batch_set(records, DbSetState::TransactionCommit),
+            )
+            .await;
+        if let Some(pinned) = &self.pinned {
+            match (&result, committed_nodes) {
+                // move the pinned nodes to the new epoch once it has been committed. Nodes
+                // which weren't modified in this commit are retained from the previous epoch.
+                (Ok(_), Some(committed_nodes)) => {
+                    if let Err(err) = pinned.load(&*self.db, epoch, Some(&committed_nodes)).await {
+                        warn!("Failed to reload the pinned tree nodes: {}", err);
+                        pinned.clear().await;
+                    }
+                }
+                // the epoch may have been partially written, so the pinned nodes are
+                // reloaded from the database on the next read of the AZKS
+                _ => pinned.clear().await,
+            }
+        }
+        result?;
         self.increment_metric(METRIC_BATCH_SET);
+        self.latest_epoch.fetch_max(epoch, Ordering::Relaxed);
         Ok(num_records as u64)
     }
 
+    /// Write the records of the active transaction directly to the database, as one part of
+    /// the epoch marked by `pending` which is written in several parts rather than committed
+    /// at once (see [crate::directory::Directory::publish_from_iter]). The transaction must
+    /// not hold an [Azks], which is written by [StorageManager::complete_pending_epoch] once
+    /// every part has been.
+    ///
+    /// When changesets are recorded (see [StorageManager::with_epoch_changesets]), the labels
+    /// of the tree nodes written are recorded in [EpochChangeset] parts numbered from
+    /// `pending.next_part`. The marker is advanced past them, and written in the same batch.
+    ///
+    /// Should the write fail part way, the records written before the failure remain. So the
+    /// marker and changeset parts are written ahead of the tree nodes whose labels they record,
+    /// and the tree nodes deepest first, so that no node written refers to a child of the
+    /// epoch which was not. A part which is not written leaves the changeset incomplete, and
+    /// readers flush their caches upon reaching the epoch.
+    pub async fn write_pending_transaction(
+        &self,
+        pending: &mut PendingEpoch,
+    ) -> Result<u64, StorageError> {
+        let transaction_records = self.transaction.commit_transaction()?;
+        if let Some(cache) = &self.cache {
+            cache.enable_clean();
+        }
+        if transaction_records
+            .iter()
+            .any(|record| matches!(record, DbRecord::Azks(_)))
+        {
+            return Err(StorageError::Transaction(
+                "A part of a pending epoch cannot hold an Azks record".to_string(),
+            ));
+        }
+
+        let mut changesets = vec![];
+        if self.record_changesets {
+            let node_labels = transaction_records
+                .iter()
+                .filter_map(|record| match record {
+                    DbRecord::TreeNode(node) => Some(node.label),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for node_labels in node_labels.chunks(EPOCH_CHANGESET_PART_LABELS) {
+                changesets.push(DbRecord::EpochChangeset(EpochChangeset {
+                    epoch: pending.epoch,
+                    part: pending.next_part,
+                    num_parts: 0,
+                    node_labels: node_labels.to_vec(),
+                    tree: None,
+                }));
+                pending.next_part += 1;
+            }
+        }
+        let mut records = vec![DbRecord::PendingEpoch(pending.clone())];
+        records.extend(changesets);
+        records.extend(transaction_records);
+        let num_records = records.len() as u64;
+        self.batch_set(records).await?;
+        Ok(num_records)
+    }
+
+    /// Complete the epoch marked by `pending` by writing its [Azks], preceded by the first
+    /// part of its [EpochChangeset] (if recorded), which holds the number of its parts
+    pub async fn complete_pending_epoch(
+        &self,
+        pending: &PendingEpoch,
+        azks: Azks,
+    ) -> Result<(), StorageError> {
+        let mut records = vec![];
+        if self.record_changesets {
+            records.push(DbRecord::EpochChangeset(EpochChangeset {
+                epoch: pending.epoch,
+                part: 0,
+                num_parts: pending.next_part,
+                node_labels: vec![],
+                tree: None,
+            }));
+        }
+        // the AZKS must remain the last record written
+        records.push(DbRecord::Azks(azks));
+        self.batch_set(records).await
+    }
+
     /// Rollback a transaction
     pub fn rollback_transaction(&self) -> Result<(), StorageError> {
         self.transaction.rollback_transaction()?;
@@ -259,6 +577,14 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Store a record in the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(storage_type = storage_type_label(record.storage_type()))
+        )
+    )]
     pub async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
         // we're in a transaction, set the item in the transaction
         if self.is_transaction_active() {
@@ -270,14 +596,28 @@ impl<Db: Database> StorageManager<Db> {
         if let Some(cache) = &self.cache {
             cache.put(&record).await;
         }
+        if let Some(pinned) = &self.pinned {
+            pinned.update(std::slice::from_ref(&record)).await;
+        }
 
         // write to the database
-        self.tic_toc(METRIC_WRITE_TIME, self.db.set(record)).await?;
+        self.record_writes(std::slice::from_ref(&record));
+        let storage_type = storage_type_label(record.storage_type());
+        self.tic_toc(METRIC_WRITE_TIME, "set", storage_type, self.db.set(record))
+            .await?;
         self.increment_metric(METRIC_SET);
         Ok(())
     }
 
     /// Set a batch of records in the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(records = records.len())
+        )
+    )]
     pub async fn batch_set(&self, records: Vec<DbRecord>) -> Result<(), StorageError> {
         if records.is_empty() {
             // nothing to do, save the cycles
@@ -294,10 +634,16 @@ impl<Db: Database> StorageManager<Db> {
         if let Some(cache) = &self.cache {
             cache.batch_put(&records).await;
         }
+        if let Some(pinned) = &self.pinned {
+            pinned.update(&records).await;
+        }
 
         // Write to the database
+        self.record_writes(&records);
         self.tic_toc(
             METRIC_WRITE_TIME,
+            "batch_set",
+            batch_storage_type_label(&records),
             self.db.batch_set(records, DbSetState::General),
         )
         .await?;
@@ -306,13 +652,26 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Retrieve a stored record directly from the data layer, ignoring any caching or transaction processes
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(storage_type = storage_type_label(St::data_type()))
+        )
+    )]
     pub async fn get_direct<St: Storable>(
         &self,
         id: &St::StorageKey,
     ) -> Result<DbRecord, StorageError> {
         // cache miss, read direct from db
         let record = self
-            .tic_toc(METRIC_READ_TIME, self.db.get::<St>(id))
+            .tic_toc(
+                METRIC_READ_TIME,
+                "get",
+                storage_type_label(St::data_type()),
+                self.db.get::<St>(id),
+            )
             .await?;
         self.increment_metric(METRIC_GET);
         Ok(record)
@@ -329,28 +688,58 @@ impl<Db: Database> StorageManager<Db> {
             }
         }
 
-        // check for a cache hit
+        // check the pinned nodes, and then for a cache hit
+        let full_key = St::get_full_binary_key_id(id);
+        if let Some(result) = self.get_pinned::<St>(&full_key).await {
+            self.record_cache_lookup(St::data_type(), true);
+            return Some(result);
+        }
         if let Some(cache) = &self.cache {
-            if let Some(result) = cache.hit_test::<St>(id).await {
-                return Some(result);
-            }
+            let result = cache.get(&full_key).await;
+            self.record_cache_lookup(St::data_type(), result.is_some());
+            return result;
         }
 
         None
     }
 
     /// Retrieve a stored record from the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(storage_type = storage_type_label(St::data_type()))
+        )
+    )]
     pub async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
         if let Some(result) = self.get_from_cache_only::<St>(id).await {
+            self.observe_epoch(&result);
+            self.refresh_pinned_nodes(&result).await;
             return Ok(result);
         }
 
         // cache miss, read direct from db
         self.increment_metric(METRIC_GET);
 
+        // the AZKS determines the epoch being served, and therefore always comes from the primary
+        let replica = if St::data_type() == StorageType::Azks {
+            None
+        } else {
+            self.select_replica(self.latest_epoch.load(Ordering::Relaxed))
+                .await
+        };
+        let db = replica.unwrap_or_else(|| self.db.clone());
         let record = self
-            .tic_toc(METRIC_READ_TIME, self.db.get::<St>(id))
+            .tic_toc(
+                METRIC_READ_TIME,
+                "get",
+                storage_type_label(St::data_type()),
+                db.get::<St>(id),
+            )
             .await?;
+        self.observe_epoch(&record);
+        self.refresh_pinned_nodes(&record).await;
         if let Some(cache) = &self.cache {
             // cache the result
             cache.put(&record).await;
@@ -359,6 +748,18 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Retrieve a batch of records by id from the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                storage_type = storage_type_label(St::data_type()),
+                ids = ids.len(),
+                uncached = tracing::field::Empty
+            )
+        )
+    )]
     pub async fn batch_get<St: Storable>(
         &self,
         ids: &[St::StorageKey],
@@ -385,9 +786,18 @@ impl<Db: Database> StorageManager<Db> {
                 }
             }
 
-            // check if item is cached
+            // check if item is pinned or cached
+            let full_key = St::get_full_binary_key_id(id);
+            if let Some(result) = self.get_pinned::<St>(&full_key).await {
+                self.record_cache_lookup(St::data_type(), true);
+                records.push(result);
+                key_set.remove(id);
+                continue;
+            }
             if let Some(cache) = &self.cache {
-                if let Some(result) = cache.hit_test::<St>(id).await {
+                let result = cache.get(&full_key).await;
+                self.record_cache_lookup(St::data_type(), result.is_some());
+                if let Some(result) = result {
                     records.push(result);
                     key_set.remove(id);
                     continue;
@@ -398,8 +808,19 @@ impl<Db: Database> StorageManager<Db> {
         if !key_set.is_empty() {
             // these are items to be retrieved from the backing database (not in pending transaction or in the object cache)
             let keys = key_set.into_iter().collect::<Vec<_>>();
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("uncached", keys.len());
+            let db = self
+                .select_replica(self.latest_epoch.load(Ordering::Relaxed))
+                .await
+                .unwrap_or_else(|| self.db.clone());
             let mut results = self
-                .tic_toc(METRIC_READ_TIME, self.db.batch_get::<St>(&keys))
+                .tic_toc(
+                    METRIC_READ_TIME,
+                    "batch_get",
+                    storage_type_label(St::data_type()),
+                    db.batch_get::<St>(&keys),
+                )
                 .await?;
 
             // cache the db returned results
@@ -413,14 +834,116 @@ impl<Db: Database> StorageManager<Db> {
         Ok(records)
     }
 
-    /// Flush the caching of objects (if present)
+    /// Flush the caching of objects (if present). The epochs of any read replicas
+    /// will also be re-checked on their next access, and any pinned tree nodes are
+    /// reloaded on the next read of the AZKS.
     pub async fn flush_cache(&self) {
         if let Some(cache) = &self.cache {
             cache.flush().await;
         }
+        if let Some(pinned) = &self.pinned {
+            pinned.clear().await;
+        }
+        if let Some(replicas) = &self.replicas {
+            replicas.invalidate().await;
+        }
+    }
+
+    /// Invalidate the cached records which were modified in the epochs following
+    /// `previous_epoch` up to and including `epoch`, as recorded by their [EpochChangeset]s
+    /// (see [StorageManager::with_epoch_changesets]). The AZKS is always evicted. If any of
+    /// the changesets are unavailable, the cache is flushed in its entirety instead.
+    ///
+    /// Changesets only hold the tree nodes of an epoch. Value states rewritten by
+    /// [StorageManager::tombstone_value_states] are not part of any epoch, so a cache
+    /// holding them must be flushed (see [StorageManager::flush_cache]) after tombstoning.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn invalidate_cache_since(&self, previous_epoch: u64, epoch: u64) {
+        if let Some(replicas) = &self.replicas {
+            replicas.invalidate().await;
+        }
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return,
+        };
+
+        let changesets = match self.get_epoch_changesets(previous_epoch, epoch).await {
+            Some(changesets) => changesets,
+            None => {
+                debug!(
+                    "Epoch changesets unavailable for epochs {}..={}, flushing the cache",
+                    previous_epoch + 1,
+                    epoch
+                );
+                cache.flush().await;
+                return;
+            }
+        };
+
+        let mut keys = vec![Azks::get_full_binary_key_id(&DEFAULT_AZKS_KEY)];
+        for changeset in changesets.into_iter() {
+            keys.extend(changeset.node_labels.into_iter().map(|label| {
+                crate::tree_node::TreeNodeWithPreviousValue::get_full_binary_key_id(
+                    &crate::tree_node::NodeKey::new(label),
+                )
+            }));
+        }
+        debug!("Evicting {} modified records from the cache", keys.len());
+        cache.evict(&keys).await;
     }
 
-    /// Tombstones all value states for a given AkdLabel, up to and including a given epoch
+    /// Read every part of the changesets of the epochs following `previous_epoch` up to and
+    /// including `epoch`, or `None` if any of them are unavailable
+    async fn get_epoch_changesets(
+        &self,
+        previous_epoch: u64,
+        epoch: u64,
+    ) -> Option<Vec<EpochChangeset>> {
+        let first_parts = ((previous_epoch + 1)..=epoch)
+            .map(|epoch| EpochChangesetKey::new(epoch, 0))
+            .collect::<Vec<_>>();
+        let mut changesets = self.batch_get_epoch_changesets(&first_parts).await?;
+        let remaining_parts = changesets
+            .iter()
+            .flat_map(|changeset| {
+                (1..changeset.num_parts).map(|part| EpochChangesetKey::new(changeset.epoch, part))
+            })
+            .collect::<Vec<_>>();
+        if !remaining_parts.is_empty() {
+            changesets.extend(self.batch_get_epoch_changesets(&remaining_parts).await?);
+        }
+        Some(changesets)
+    }
+
+    async fn batch_get_epoch_changesets(
+        &self,
+        keys: &[EpochChangesetKey],
+    ) -> Option<Vec<EpochChangeset>> {
+        let records = self
+            .tic_toc(
+                METRIC_READ_TIME,
+                "batch_get",
+                storage_type_label(StorageType::EpochChangeset),
+                self.db.batch_get::<EpochChangeset>(keys),
+            )
+            .await
+            .ok()?;
+        self.increment_metric(METRIC_BATCH_GET);
+        let changesets = records
+            .into_iter()
+            .filter_map(|record| match record {
+                DbRecord::EpochChangeset(changeset) => Some(changeset),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (changesets.len() == keys.len()).then_some(changesets)
+    }
+
+    /// Tombstones all value states for a given AkdLabel, up to and including a given epoch.
+    ///
+    /// Only the cache of this storage manager is updated. Other instances over the same
+    /// storage (i.e. read-only directories) keep serving the cached value states until their
+    /// caches are flushed, as tombstones are not recorded in [EpochChangeset]s.
     pub async fn tombstone_value_states(
         &self,
         username: &AkdLabel,
@@ -436,6 +959,7 @@ impl<Db: Database> StorageManager<Db> {
                     value: crate::AkdValue(crate::TOMBSTONE.to_vec()),
                     username: value_state.username,
                     version: value_state.version,
+                    tree: value_state.tree,
                 }));
             }
         }
@@ -449,13 +973,23 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Retrieve the specified user state object based on the retrieval flag from the database
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, username))
+    )]
     pub async fn get_user_state(
         &self,
         username: &AkdLabel,
         flag: ValueStateRetrievalFlag,
     ) -> Result<ValueState, StorageError> {
+        let db = self.user_state_read_db(Some(flag)).await;
         let maybe_db_state = match self
-            .tic_toc(METRIC_READ_TIME, self.db.get_user_state(username, flag))
+            .tic_toc(
+                METRIC_READ_TIME,
+                "get_user_state",
+                storage_type_label(StorageType::ValueState),
+                db.get_user_state(username, flag, None),
+            )
             .await
         {
             Err(StorageError::NotFound(_)) => Ok(None),
@@ -497,9 +1031,16 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Retrieve all values states for a given user
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
+        let db = self.user_state_read_db(None).await;
         let maybe_db_data = match self
-            .tic_toc(METRIC_READ_TIME, self.db.get_user_data(username))
+            .tic_toc(
+                METRIC_READ_TIME,
+                "get_user_data",
+                storage_type_label(StorageType::ValueState),
+                db.get_user_data(username, None),
+            )
             .await
         {
             Err(StorageError::NotFound(_)) => Ok(None),
@@ -543,15 +1084,26 @@ impl<Db: Database> StorageManager<Db> {
     }
 
     /// Retrieve the user -> state version mapping in bulk. This is the same as get_user_state in a loop, but with less data retrieved from the storage layer
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(labels = usernames.len(), ?flag)
+        )
+    )]
     pub async fn get_user_state_versions(
         &self,
         usernames: &[AkdLabel],
         flag: ValueStateRetrievalFlag,
     ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        let db = self.user_state_read_db(Some(flag)).await;
         let mut data = self
             .tic_toc(
                 METRIC_READ_TIME,
-                self.db.get_user_state_versions(usernames, flag),
+                "get_user_state_versions",
+                storage_type_label(StorageType::ValueState),
+                db.get_user_state_versions(usernames, flag, None),
             )
             .await?;
         self.increment_metric(METRIC_GET_USER_STATE_VERSIONS);
@@ -580,6 +1132,53 @@ impl<Db: Database> StorageManager<Db> {
         Ok(data)
     }
 
+    /// Retrieve the specified user state objects of a set of users in bulk. This is the same as
+    /// get_user_state in a loop, omitting the users which are not found
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(labels = usernames.len(), ?flag)
+        )
+    )]
+    pub async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        let db = self.user_state_read_db(Some(flag)).await;
+        let mut data = self
+            .tic_toc(
+                METRIC_READ_TIME,
+                "get_user_states",
+                storage_type_label(StorageType::ValueState),
+                db.get_user_states(usernames, flag, None),
+            )
+            .await?;
+        self.increment_metric(METRIC_GET_USER_STATE);
+
+        // in the event we are in a transaction, there may be an updated object in the
+        // transactional storage. Therefore we should update the db retrieved value if
+        // we can with what's in the transaction log
+        if self.is_transaction_active() {
+            let transaction_records = self.transaction.get_users_states(usernames, flag);
+            for (label, value_state) in transaction_records.into_iter() {
+                let record = match data.get(&label) {
+                    Some(db_state) => {
+                        Self::compare_db_and_transaction_records(db_state.epoch, value_state, flag)
+                    }
+                    None => Some(value_state),
+                };
+                if let Some(record) = record {
+                    data.insert(label, record);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
     fn compare_db_and_transaction_records(
         state_epoch: u64,
         transaction_value: ValueState,
@@ -617,6 +1216,67 @@ impl<Db: Database> StorageManager<Db> {
         None
     }
 
+    /// Select a read replica which has reached `min_epoch`, if one is available. Reads made
+    /// while a transaction is active are always served by the primary, since the writer
+    /// must observe the latest committed state.
+    async fn select_replica(&self, min_epoch: u64) -> Option<Arc<Db>> {
+        let replicas = self.replicas.as_ref()?;
+        if self.is_transaction_active() {
+            return None;
+        }
+        let replica = replicas.select(min_epoch).await;
+        if replica.is_some() {
+            self.increment_metric(METRIC_REPLICA_READ);
+        }
+        replica
+    }
+
+    /// Select the database to serve a user state read from. A lagging replica could return
+    /// an older (but present) state for a user, so replicas must have caught up to the
+    /// epoch of interest.
+    async fn user_state_read_db(&self, flag: Option<ValueStateRetrievalFlag>) -> Arc<Db> {
+        let min_epoch = match flag {
+            Some(ValueStateRetrievalFlag::LeqEpoch(epoch))
+            | Some(ValueStateRetrievalFlag::SpecificEpoch(epoch)) => epoch,
+            _ => self.latest_epoch.load(Ordering::Relaxed),
+        };
+        self.select_replica(min_epoch)
+            .await
+            .unwrap_or_else(|| self.db.clone())
+    }
+
+    /// Retrieve a tree node by its full binary id from the pinned nodes, if any are pinned
+    async fn get_pinned<St: Storable>(&self, full_key: &[u8]) -> Option<DbRecord> {
+        match &self.pinned {
+            Some(pinned) if St::data_type() == StorageType::TreeNode => pinned.get(full_key).await,
+            _ => None,
+        }
+    }
+
+    /// Reload the pinned nodes if the epoch of the provided AZKS record differs from the
+    /// epoch they were loaded for. During a transaction the pinned nodes are instead moved
+    /// to the new epoch upon commit.
+    async fn refresh_pinned_nodes(&self, record: &DbRecord) {
+        if let (Some(pinned), DbRecord::Azks(azks)) = (&self.pinned, record) {
+            let epoch = azks.get_latest_epoch();
+            if self.is_transaction_active() || pinned.epoch().await == Some(epoch) {
+                return;
+            }
+            if let Err(err) = pinned.load(&*self.db, epoch, None).await {
+                warn!("Failed to reload the pinned tree nodes: {}", err);
+                pinned.clear().await;
+            }
+        }
+    }
+
+    /// Track the latest epoch from any AZKS record read by this storage manager
+    fn observe_epoch(&self, record: &DbRecord) {
+        if let DbRecord::Azks(azks) = record {
+            self.latest_epoch
+                .fetch_max(azks.get_latest_epoch(), Ordering::Relaxed);
+        }
+    }
+
     fn increment_metric(&self, _metric: Metric) {
         #[cfg(feature = "runtime_metrics")]
         {
@@ -624,20 +1284,88 @@ impl<Db: Database> StorageManager<Db> {
         }
     }
 
-    async fn tic_toc<T>(&self, _metric: Metric, f: impl std::future::Future<Output = T>) -> T {
+    async fn tic_toc<T>(
+        &self,
+        _metric: Metric,
+        operation: &'static str,
+        storage_type: &'static str,
+        f: impl std::future::Future<Output = T>,
+    ) -> T {
+        let f = in_span!(DEBUG, f, "database", operation, storage_type);
+        let recorder = self.metrics_recorder.as_deref();
+        #[cfg(not(feature = "runtime_metrics"))]
+        if recorder.is_none() {
+            return f.await;
+        }
+
+        let tic = std::time::Instant::now();
+        let out = f.await;
+        let delta = std::time::Instant::now().duration_since(tic);
+
         #[cfg(feature = "runtime_metrics")]
-        {
-            let tic = std::time::Instant::now();
-            let out = f.await;
-            let delta = std::time::Instant::now().duration_since(tic);
+        self.metrics[_metric].fetch_add(delta.as_millis() as u64, Ordering::Relaxed);
+
+        if let Some(recorder) = recorder {
+            let labels = [("operation", operation), ("storage_type", storage_type)];
+            recorder.increment_counter(STORAGE_OPERATIONS_TOTAL, &labels, 1);
+            recorder.record_histogram(
+                STORAGE_OPERATION_DURATION_SECONDS,
+                &labels,
+                delta.as_secs_f64(),
+            );
+        }
+        out
+    }
 
-            self.metrics[_metric].fetch_add(delta.as_millis() as u64, Ordering::Relaxed);
+    /// Report the number of records written, by type, to the metrics recorder (if present)
+    fn record_writes(&self, records: &[DbRecord]) {
+        if let Some(recorder) = &self.metrics_recorder {
+            let mut counts = HashMap::new();
+            for record in records.iter() {
+                *counts.entry(record.storage_type()).or_insert(0u64) += 1;
+            }
+            for (storage_type, count) in counts.into_iter() {
+                recorder.increment_counter(
+                    STORAGE_RECORDS_WRITTEN_TOTAL,
+                    &[("storage_type", storage_type_label(storage_type))],
+                    count,
+                );
+            }
+        }
+    }
 
-            out
+    /// Report a lookup of a record in the cache (or pinned nodes) to the metrics recorder
+    /// (if present)
+    fn record_cache_lookup(&self, storage_type: StorageType, hit: bool) {
+        if let Some(recorder) = &self.metrics_recorder {
+            let (hits, misses) = &*self.cache_lookups;
+            if hit {
+                hits.fetch_add(1, Ordering::Relaxed);
+            } else {
+                misses.fetch_add(1, Ordering::Relaxed);
+            }
+            recorder.increment_counter(
+                CACHE_REQUESTS_TOTAL,
+                &[
+                    ("result", if hit { "hit" } else { "miss" }),
+                    ("storage_type", storage_type_label(storage_type)),
+                ],
+                1,
+            );
+            let hits = hits.load(Ordering::Relaxed);
+            let total = hits + misses.load(Ordering::Relaxed);
+            recorder.set_gauge(CACHE_HIT_RATIO, &[], hits as f64 / total as f64);
         }
-        #[cfg(not(feature = "runtime_metrics"))]
-        {
-            f.await
+    }
+}
+
+/// The `storage_type` metric label of a batch of records, which is `mixed` if the batch
+/// holds records of several types
+fn batch_storage_type_label(records: &[DbRecord]) -> &'static str {
+    match records.first().map(|record| record.storage_type()) {
+        Some(first) if records.iter().all(|record| record.storage_type() == first) => {
+            storage_type_label(first)
         }
+        _ => "mixed",
     }
 }
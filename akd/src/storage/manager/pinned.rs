@@ -0,0 +1,160 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Pinning of the upper levels of the tree in memory.
+//!
+//! Every lookup and history proof walks through the same few levels below the root,
+//! so these nodes are held outside of the (evictable) object cache. Pinned nodes are
+//! never expired, and are only reloaded once the epoch changes.
+
+use crate::storage::types::DbRecord;
+use crate::storage::{Database, Storable, StorageError};
+use crate::tree_node::{NodeKey, TreeNodeWithPreviousValue};
+use crate::NodeLabel;
+
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::RwLock;
+
+#[derive(Default)]
+struct PinnedState {
+    /// The epoch the pinned nodes were loaded for, [None] if they have not been loaded
+    epoch: Option<u64>,
+    nodes: HashMap<Vec<u8>, DbRecord>,
+}
+
+/// The nodes of the top `levels` levels of the tree, starting at the root
+pub(crate) struct PinnedNodes {
+    levels: u8,
+    state: RwLock<PinnedState>,
+}
+
+impl PinnedNodes {
+    pub(crate) fn new(levels: u8) -> Self {
+        Self {
+            levels,
+            state: RwLock::new(PinnedState::default()),
+        }
+    }
+
+    /// Retrieve a pinned node by its full binary id
+    pub(crate) async fn get(&self, full_key: &[u8]) -> Option<DbRecord> {
+        self.state.read().await.nodes.get(full_key).cloned()
+    }
+
+    /// The epoch the pinned nodes were last loaded for
+    pub(crate) async fn epoch(&self) -> Option<u64> {
+        self.state.read().await.epoch
+    }
+
+    pub(crate) async fn len(&self) -> usize {
+        self.state.read().await.nodes.len()
+    }
+
+    /// Replace any pinned nodes with the provided records, leaving the set of pinned
+    /// nodes unchanged. Used for writes which happen outside of a publish.
+    pub(crate) async fn update(&self, records: &[DbRecord]) {
+        let mut state = self.state.write().await;
+        if state.nodes.is_empty() {
+            return;
+        }
+        for record in records.iter() {
+            if let DbRecord::TreeNode(_) = record {
+                let key = record.get_full_binary_id();
+                if let Some(node) = state.nodes.get_mut(&key) {
+                    *node = record.clone();
+                }
+            }
+        }
+    }
+
+    /// Drop all of the pinned nodes, they will be reloaded on the next epoch change
+    pub(crate) async fn clear(&self) {
+        *(self.state.write().await) = PinnedState::default();
+    }
+
+    /// Reload the pinned nodes for the given epoch, unless they are already loaded for it.
+    ///
+    /// If the nodes `committed` in the transaction which produced this epoch are provided,
+    /// the pinned nodes are resolved from them together with the currently pinned nodes
+    /// (which were not modified), and are otherwise read from the database.
+    pub(crate) async fn load<Db: Database>(
+        &self,
+        db: &Db,
+        epoch: u64,
+        committed: Option<&[DbRecord]>,
+    ) -> Result<(), StorageError> {
+        // the write lock is held for the whole load, so that concurrent readers
+        // observing the same epoch change don't all re-load the nodes
+        let mut state = self.state.write().await;
+        if state.epoch == Some(epoch) {
+            return Ok(());
+        }
+
+        let mut known = HashMap::new();
+        if let Some(committed) = committed {
+            known = std::mem::take(&mut state.nodes);
+            for record in committed.iter() {
+                known.insert(record.get_full_binary_id(), record.clone());
+            }
+        }
+
+        let mut nodes = HashMap::new();
+        let mut level = vec![NodeLabel::root()];
+        for _ in 0..self.levels {
+            if level.is_empty() {
+                break;
+            }
+
+            let mut records = vec![];
+            let mut missing = vec![];
+            for label in level.into_iter() {
+                let key = NodeKey::new(label);
+                match known.get(&TreeNodeWithPreviousValue::get_full_binary_key_id(&key)) {
+                    Some(record) => records.push(record.clone()),
+                    None => missing.push(key),
+                }
+            }
+            if !missing.is_empty() {
+                records.append(&mut db.batch_get::<TreeNodeWithPreviousValue>(&missing).await?);
+            }
+
+            // children of both the latest and previous node are pinned, since readers
+            // which are an epoch behind will traverse the previous node's children
+            let mut next_level = HashSet::new();
+            for record in records.into_iter() {
+                if let DbRecord::TreeNode(node) = &record {
+                    for tree_node in std::iter::once(&node.latest_node).chain(&node.previous_node) {
+                        next_level.extend(tree_node.left_child);
+                        next_level.extend(tree_node.right_child);
+                    }
+                    nodes.insert(record.get_full_binary_id(), record);
+                }
+            }
+            level = next_level
+                .into_iter()
+                .filter(|label| {
+                    !nodes.contains_key(&TreeNodeWithPreviousValue::get_full_binary_key_id(
+                        &NodeKey::new(*label),
+                    ))
+                })
+                .collect();
+        }
+
+        debug!(
+            "Pinned {} tree nodes in the top {} levels for epoch {}",
+            nodes.len(),
+            self.levels,
+            epoch
+        );
+        *state = PinnedState {
+            epoch: Some(epoch),
+            nodes,
+        };
+        Ok(())
+    }
+}
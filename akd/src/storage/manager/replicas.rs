@@ -0,0 +1,135 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Routing of read operations to a set of read replicas of the primary database.
+//!
+//! Replicas are assumed to be asynchronous copies of the primary, and therefore
+//! may lag behind it. Each replica's progress is tracked by periodically reading
+//! the [Azks] record stored in it, since the [Azks] is always the last record
+//! written in a publish. A replica is only selected for a read if it has caught up
+//! to the latest epoch known to the storage manager. Replicas which are _ahead_ of
+//! the epoch being served are safe to read from, as tree nodes are stored as
+//! [crate::tree_node::TreeNodeWithPreviousValue] records.
+
+use crate::append_only_zks::{Azks, DEFAULT_AZKS_KEY};
+use crate::storage::types::DbRecord;
+use crate::storage::Database;
+
+use log::{debug, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// check the epoch of each replica every 1s by default
+pub(crate) const DEFAULT_REPLICA_EPOCH_CHECK_FREQUENCY_MS: u64 = 1000;
+
+struct Replica<Db: Database> {
+    db: Arc<Db>,
+    /// The latest epoch observed in the replica's [Azks] record
+    epoch: AtomicU64,
+    /// Whether the replica's epoch has been observed at least once
+    observed: AtomicBool,
+    last_check: RwLock<Option<Instant>>,
+}
+
+/// A set of read replicas of the primary database, with lag-aware selection
+pub(crate) struct ReadReplicas<Db: Database> {
+    replicas: Vec<Replica<Db>>,
+    next: AtomicUsize,
+    check_frequency: Duration,
+}
+
+impl<Db: Database> ReadReplicas<Db> {
+    pub(crate) fn new(replicas: Vec<Db>, o_check_frequency: Option<Duration>) -> Self {
+        let check_frequency = match o_check_frequency {
+            Some(frequency) if frequency > Duration::from_millis(1) => frequency,
+            _ => Duration::from_millis(DEFAULT_REPLICA_EPOCH_CHECK_FREQUENCY_MS),
+        };
+        Self {
+            replicas: replicas
+                .into_iter()
+                .map(|db| Replica {
+                    db: Arc::new(db),
+                    epoch: AtomicU64::new(0),
+                    observed: AtomicBool::new(false),
+                    last_check: RwLock::new(None),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            check_frequency,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Select a replica which has reached at least `min_epoch`. Replicas are visited
+    /// round-robin so that reads are spread across them. Returns [None] if no replica
+    /// is sufficiently up-to-date, in which case the caller should fall back to the primary.
+    pub(crate) async fn select(&self, min_epoch: u64) -> Option<Arc<Db>> {
+        let num_replicas = self.replicas.len();
+        if num_replicas == 0 {
+            return None;
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..num_replicas {
+            let replica = &self.replicas[(start + offset) % num_replicas];
+            self.refresh_epoch(replica).await;
+            if replica.observed.load(Ordering::Relaxed)
+                && replica.epoch.load(Ordering::Relaxed) >= min_epoch
+            {
+                return Some(replica.db.clone());
+            }
+        }
+
+        debug!(
+            "No read replica has reached epoch {}, falling back to the primary",
+            min_epoch
+        );
+        None
+    }
+
+    /// Force a re-check of the epoch of every replica on the next selection
+    pub(crate) async fn invalidate(&self) {
+        for replica in self.replicas.iter() {
+            *(replica.last_check.write().await) = None;
+        }
+    }
+
+    async fn refresh_epoch(&self, replica: &Replica<Db>) {
+        let do_check = {
+            // we need the {} brackets in order to release the read lock, since we _may_ acquire a write lock shortly later
+            match *(replica.last_check.read().await) {
+                Some(last_check) => last_check + self.check_frequency < Instant::now(),
+                None => true,
+            }
+        };
+        if !do_check {
+            return;
+        }
+
+        let mut last_check = replica.last_check.write().await;
+        match replica.db.get::<Azks>(&DEFAULT_AZKS_KEY).await {
+            Ok(DbRecord::Azks(azks)) => {
+                replica.epoch.store(azks.latest_epoch, Ordering::Relaxed);
+                replica.observed.store(true, Ordering::Relaxed);
+            }
+            Ok(_) => {
+                warn!("Read replica returned a non-AZKS record for the AZKS key");
+                replica.observed.store(false, Ordering::Relaxed);
+            }
+            Err(err) => {
+                debug!("Failed to retrieve the AZKS from a read replica: {}", err);
+                replica.observed.store(false, Ordering::Relaxed);
+            }
+        }
+        *last_check = Some(Instant::now());
+    }
+}
@@ -57,6 +57,7 @@ async fn test_storage_manager_transaction() {
     records.push(DbRecord::Azks(Azks {
         latest_epoch: 0,
         num_nodes: 0,
+        tree: None,
     }));
 
     storage_manager
@@ -75,7 +76,7 @@ async fn test_storage_manager_transaction() {
     assert_eq!(11, storage_manager.transaction.count());
 
     // test a retrieval doesn't go to the database. Since we know the db is empty, it should be retrieved from the transaction log
-    let key = NodeKey(NodeLabel {
+    let key = NodeKey::new(NodeLabel {
         label_len: 2,
         label_val: [2u8; 32],
     });
@@ -86,7 +87,7 @@ async fn test_storage_manager_transaction() {
 
     let keys = vec![
         key,
-        NodeKey(NodeLabel {
+        NodeKey::new(NodeLabel {
             label_len: 3,
             label_val: [3u8; 32],
         }),
@@ -151,6 +152,7 @@ async fn test_storage_manager_cache_populated_by_batch_set() {
     records.push(DbRecord::Azks(Azks {
         latest_epoch: 0,
         num_nodes: 0,
+        tree: None,
     }));
 
     // write straight to the db, populating the cache
@@ -163,7 +165,7 @@ async fn test_storage_manager_cache_populated_by_batch_set() {
     storage_manager.db.clear();
 
     // test a retrieval still gets data (from the cache)
-    let key = NodeKey(NodeLabel {
+    let key = NodeKey::new(NodeLabel {
         label_len: 2,
         label_val: [2u8; 32],
     });
@@ -174,7 +176,7 @@ async fn test_storage_manager_cache_populated_by_batch_set() {
 
     let keys = vec![
         key,
-        NodeKey(NodeLabel {
+        NodeKey::new(NodeLabel {
             label_len: 3,
             label_val: [3u8; 32],
         }),
@@ -206,7 +208,7 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
                 label_len: i,
                 label_val: [i as u8; 32],
             };
-            keys.push(NodeKey(label));
+            keys.push(NodeKey::new(label));
             DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
                 label.label_val,
                 label.label_len,
@@ -233,6 +235,7 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
     records.push(DbRecord::Azks(Azks {
         latest_epoch: 0,
         num_nodes: 0,
+        tree: None,
     }));
 
     // write straight to the db
@@ -262,7 +265,7 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
     storage_manager.db.clear();
 
     // test a retrieval still gets data (from the cache)
-    let key = NodeKey(NodeLabel {
+    let key = NodeKey::new(NodeLabel {
         label_len: 2,
         label_val: [2u8; 32],
     });
@@ -273,7 +276,7 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
 
     let keys = vec![
         key,
-        NodeKey(NodeLabel {
+        NodeKey::new(NodeLabel {
             label_len: 3,
             label_val: [3u8; 32],
         }),
@@ -294,3 +297,364 @@ async fn test_storage_manager_cache_populated_by_batch_get() {
             .await
     );
 }
+
+#[tokio::test]
+async fn test_storage_manager_read_replica_routing() {
+    let primary = AsyncInMemoryDatabase::new();
+    let replica = AsyncInMemoryDatabase::new();
+    let storage_manager = StorageManager::new_no_cache(primary.clone())
+        .with_read_replicas(vec![replica.clone()], None);
+    assert_eq!(1, storage_manager.num_read_replicas());
+
+    let label = NodeLabel {
+        label_len: 1,
+        label_val: [1u8; 32],
+    };
+    let node_at_epoch = |epoch: u64| {
+        DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
+            label.label_val,
+            label.label_len,
+            epoch,
+            epoch,
+            [0u8; 32],
+            0,
+            0,
+            None,
+            None,
+            EMPTY_DIGEST,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ))
+    };
+    let azks_at_epoch = |epoch: u64| {
+        DbRecord::Azks(Azks {
+            latest_epoch: epoch,
+            num_nodes: 1,
+            tree: None,
+        })
+    };
+
+    // the primary is at epoch 2, while the replica lags behind at epoch 1
+    primary
+        .batch_set(
+            vec![node_at_epoch(2), azks_at_epoch(2)],
+            DbSetState::General,
+        )
+        .await
+        .unwrap();
+    replica
+        .batch_set(
+            vec![node_at_epoch(1), azks_at_epoch(1)],
+            DbSetState::General,
+        )
+        .await
+        .unwrap();
+
+    // the AZKS is always read from the primary
+    let azks = storage_manager
+        .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await
+        .unwrap();
+    assert_eq!(azks_at_epoch(2), azks);
+
+    // the replica is lagging, so the node should come from the primary
+    let got = storage_manager
+        .get::<TreeNodeWithPreviousValue>(&NodeKey::new(label))
+        .await
+        .unwrap();
+    assert_eq!(node_at_epoch(2), got);
+
+    // once the replica has caught up (and is re-checked), reads are served from it
+    replica
+        .batch_set(vec![azks_at_epoch(3)], DbSetState::General)
+        .await
+        .unwrap();
+    storage_manager.flush_cache().await;
+    let got = storage_manager
+        .batch_get::<TreeNodeWithPreviousValue>(&[NodeKey::new(label)])
+        .await
+        .unwrap();
+    assert_eq!(vec![node_at_epoch(1)], got);
+
+    // reads within a transaction always go to the primary
+    assert!(storage_manager.begin_transaction());
+    let got = storage_manager
+        .get::<TreeNodeWithPreviousValue>(&NodeKey::new(label))
+        .await
+        .unwrap();
+    assert_eq!(node_at_epoch(2), got);
+    storage_manager.rollback_transaction().unwrap();
+}
+
+#[tokio::test]
+async fn test_storage_manager_pinned_nodes() {
+    let db = AsyncInMemoryDatabase::new();
+    let storage_manager = StorageManager::new_no_cache(db.clone()).with_pinned_levels(2);
+
+    let root_at_epoch = |epoch: u64| {
+        DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
+            NodeLabel::root().label_val,
+            NodeLabel::root().label_len,
+            epoch,
+            epoch,
+            NodeLabel::root().label_val,
+            1,
+            0,
+            None,
+            None,
+            EMPTY_DIGEST,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ))
+    };
+    let azks_at_epoch = |epoch: u64| {
+        DbRecord::Azks(Azks {
+            latest_epoch: epoch,
+            num_nodes: 1,
+            tree: None,
+        })
+    };
+
+    // nothing to pin prior to the AZKS existing
+    storage_manager.warm_pinned_nodes().await.unwrap();
+    assert_eq!(0, storage_manager.num_pinned_nodes().await);
+
+    db.batch_set(
+        vec![root_at_epoch(1), azks_at_epoch(1)],
+        DbSetState::General,
+    )
+    .await
+    .unwrap();
+    storage_manager.warm_pinned_nodes().await.unwrap();
+    assert_eq!(1, storage_manager.num_pinned_nodes().await);
+
+    // the root is served from the pinned nodes, even though it has changed underneath
+    db.set(root_at_epoch(2)).await.unwrap();
+    let got = storage_manager
+        .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
+        .await
+        .unwrap();
+    assert_eq!(root_at_epoch(1), got);
+
+    // until the epoch changes
+    db.set(azks_at_epoch(2)).await.unwrap();
+    storage_manager
+        .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await
+        .unwrap();
+    let got = storage_manager
+        .batch_get::<TreeNodeWithPreviousValue>(&[NodeKey::new(NodeLabel::root())])
+        .await
+        .unwrap();
+    assert_eq!(vec![root_at_epoch(2)], got);
+}
+
+#[tokio::test]
+async fn test_storage_manager_epoch_changesets() {
+    let db = AsyncInMemoryDatabase::new();
+    let writer = StorageManager::new_no_cache(db.clone()).with_epoch_changesets();
+    let reader = StorageManager::new(db.clone(), None, None, None);
+
+    let node = |i: u32, epoch: u64| {
+        DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
+            [i as u8; 32],
+            i,
+            epoch,
+            epoch,
+            [0u8; 32],
+            0,
+            0,
+            None,
+            None,
+            EMPTY_DIGEST,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ))
+    };
+    let azks_at_epoch = |epoch: u64| {
+        DbRecord::Azks(Azks {
+            latest_epoch: epoch,
+            num_nodes: 4,
+            tree: None,
+        })
+    };
+    let keys = (0..4)
+        .map(|i| NodeKey::new(NodeLabel::new([i as u8; 32], i)))
+        .collect::<Vec<_>>();
+
+    // epoch 1 writes all the nodes
+    writer.begin_transaction();
+    writer
+        .batch_set(
+            (0..4)
+                .map(|i| node(i, 1))
+                .chain([azks_at_epoch(1)])
+                .collect(),
+        )
+        .await
+        .unwrap();
+    writer.commit_transaction().await.unwrap();
+
+    // the reader caches everything
+    reader
+        .batch_get::<TreeNodeWithPreviousValue>(&keys)
+        .await
+        .unwrap();
+    assert_eq!(4, reader.cache_metrics().unwrap().num_items);
+
+    // epoch 2 modifies only the first node
+    writer.begin_transaction();
+    writer
+        .batch_set(vec![node(0, 2), azks_at_epoch(2)])
+        .await
+        .unwrap();
+    writer.commit_transaction().await.unwrap();
+    let changeset = db
+        .get::<EpochChangeset>(&EpochChangesetKey::new(2, 0))
+        .await
+        .unwrap();
+    assert_eq!(
+        DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(2, 0, 1, vec![keys[0].0])),
+        changeset
+    );
+
+    // only the modified node is evicted
+    reader.invalidate_cache_since(1, 2).await;
+    assert_eq!(3, reader.cache_metrics().unwrap().num_items);
+    let got = reader
+        .get::<TreeNodeWithPreviousValue>(&keys[0])
+        .await
+        .unwrap();
+    assert_eq!(node(0, 2), got);
+
+    // a missing changeset flushes the cache in its entirety
+    reader.invalidate_cache_since(2, 3).await;
+    assert_eq!(0, reader.cache_metrics().unwrap().num_items);
+}
+
+#[tokio::test]
+async fn test_storage_manager_epoch_changeset_parts() {
+    let labels = (0..(EPOCH_CHANGESET_PART_LABELS as u64 + 1))
+        .map(|i| NodeLabel::new(crate::utils::byte_arr_from_u64(i), 64))
+        .collect::<Vec<_>>();
+    let parts = EpochChangeset::split(2, labels.clone());
+    assert_eq!(2, parts.len());
+    assert!(parts.iter().all(|part| part.num_parts == 2));
+    assert_eq!(EPOCH_CHANGESET_PART_LABELS, parts[0].node_labels.len());
+    assert_eq!(
+        vec![labels[EPOCH_CHANGESET_PART_LABELS]],
+        parts[1].node_labels
+    );
+    assert_eq!(1, EpochChangeset::split(2, vec![]).len());
+
+    let db = AsyncInMemoryDatabase::new();
+    let reader = StorageManager::new(db.clone(), None, None, None);
+    let node = |i: u32| {
+        DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
+            [i as u8; 32],
+            i,
+            1,
+            1,
+            [0u8; 32],
+            0,
+            0,
+            None,
+            None,
+            EMPTY_DIGEST,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        ))
+    };
+    let keys = (0..3)
+        .map(|i| NodeKey::new(NodeLabel::new([i as u8; 32], i)))
+        .collect::<Vec<_>>();
+    db.batch_set((0..3).map(node).collect(), DbSetState::General)
+        .await
+        .unwrap();
+    reader
+        .batch_get::<TreeNodeWithPreviousValue>(&keys)
+        .await
+        .unwrap();
+    assert_eq!(3, reader.cache_metrics().unwrap().num_items);
+
+    // the nodes of every part of the changeset are evicted
+    db.batch_set(
+        vec![
+            DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(2, 0, 2, vec![keys[0].0])),
+            DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(2, 1, 2, vec![keys[1].0])),
+        ],
+        DbSetState::General,
+    )
+    .await
+    .unwrap();
+    reader.invalidate_cache_since(1, 2).await;
+    assert_eq!(1, reader.cache_metrics().unwrap().num_items);
+
+    // a missing part flushes the cache in its entirety
+    reader
+        .batch_get::<TreeNodeWithPreviousValue>(&keys)
+        .await
+        .unwrap();
+    db.set(DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(
+        3,
+        0,
+        2,
+        vec![keys[0].0],
+    )))
+    .await
+    .unwrap();
+    reader.invalidate_cache_since(2, 3).await;
+    assert_eq!(0, reader.cache_metrics().unwrap().num_items);
+}
+
+#[tokio::test]
+async fn test_storage_manager_epoch_change_feed() {
+    use futures::StreamExt;
+
+    let db = AsyncInMemoryDatabase::new();
+    let storage_manager = StorageManager::new_no_cache(db);
+    let mut changes = storage_manager
+        .subscribe_epoch_changes()
+        .expect("The in-memory database supports change notification");
+
+    for epoch in 1..=3 {
+        storage_manager.begin_transaction();
+        storage_manager
+            .set(DbRecord::Azks(Azks {
+                latest_epoch: epoch,
+                num_nodes: 1,
+                tree: None,
+            }))
+            .await
+            .unwrap();
+        storage_manager.commit_transaction().await.unwrap();
+    }
+
+    for epoch in 1..=3 {
+        assert_eq!(Some(epoch), changes.next().await);
+    }
+}
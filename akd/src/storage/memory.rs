@@ -11,17 +11,35 @@
 
 use crate::errors::StorageError;
 use crate::storage::types::{
-    DbRecord, KeyData, StorageType, ValueState, ValueStateKey, ValueStateRetrievalFlag,
+    DbRecord, KeyData, StorageType, TreeId, ValueState, ValueStateKey, ValueStateRetrievalFlag,
 };
 use crate::storage::{Database, Storable, StorageUtil};
 use crate::{AkdLabel, AkdValue};
 use async_trait::async_trait;
 use dashmap::DashMap;
-use std::collections::HashMap;
+use futures::stream::BoxStream;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
 use std::sync::Arc;
+use tokio::sync::broadcast;
 
 type Epoch = u64;
 type UserValueMap = HashMap<Epoch, ValueState>;
+/// The tree and username the value states of a [UserValueMap] belong to
+type UserKey = (Option<TreeId>, Vec<u8>);
+
+/// the number of epoch changes buffered for slow subscribers, older changes are dropped
+const EPOCH_CHANGE_CAPACITY: usize = 16;
+
+/// Broadcasts the epoch of each written [crate::Azks] record to the subscribers
+#[derive(Clone, Debug)]
+struct EpochChangeFeed(broadcast::Sender<u64>);
+
+impl Default for EpochChangeFeed {
+    fn default() -> Self {
+        Self(broadcast::channel(EPOCH_CHANGE_CAPACITY).0)
+    }
+}
 
 // ===== Basic In-Memory database ==== //
 
@@ -29,7 +47,10 @@ type UserValueMap = HashMap<Epoch, ValueState>;
 #[derive(Default, Clone, Debug)]
 pub struct AsyncInMemoryDatabase {
     db: Arc<DashMap<Vec<u8>, DbRecord>>,
-    user_info: Arc<DashMap<Vec<u8>, UserValueMap>>,
+    user_info: Arc<DashMap<UserKey, UserValueMap>>,
+    /// The ordered full binary ids of the records of each type, which pages are read from
+    index: Arc<DashMap<StorageType, BTreeSet<Vec<u8>>>>,
+    epoch_changes: EpochChangeFeed,
 }
 
 unsafe impl Send for AsyncInMemoryDatabase {}
@@ -45,6 +66,7 @@ impl AsyncInMemoryDatabase {
     pub fn clear(&self) {
         self.db.clear();
         self.user_info.clear();
+        self.index.clear();
     }
 
     async fn get_internal<St: Storable>(
@@ -54,8 +76,10 @@ impl AsyncInMemoryDatabase {
         let bin_id = St::get_full_binary_key_id(id);
         // if the request is for a value state, look in the value state set
         if St::data_type() == StorageType::ValueState {
-            if let Ok(ValueStateKey(username, epoch)) = ValueState::key_from_full_binary(&bin_id) {
-                if let Some(state) = self.user_info.get(&username) {
+            if let Ok(ValueStateKey(username, epoch, tree)) =
+                ValueState::key_from_full_binary(&bin_id)
+            {
+                if let Some(state) = self.user_info.get(&(tree, username)) {
                     if let Some(found) = state.get(&epoch) {
                         return Ok(DbRecord::ValueState(found.clone()));
                     }
@@ -88,9 +112,16 @@ impl Database for AsyncInMemoryDatabase {
         records: Vec<DbRecord>,
         _state: crate::storage::DbSetState,
     ) -> Result<(), StorageError> {
+        let mut new_epoch = None;
         for record in records.into_iter() {
+            if let DbRecord::Azks(azks) = &record {
+                new_epoch = Some(azks.latest_epoch);
+            }
+            // the record is indexed once written, so that pages only refer to stored records
+            let storage_type = record.storage_type();
+            let full_key = record.get_full_binary_id();
             if let DbRecord::ValueState(value_state) = record {
-                let username = value_state.username.to_vec();
+                let username = (value_state.tree, value_state.username.to_vec());
                 match self.user_info.get_mut(&username) {
                     Some(mut states) => {
                         states.insert(value_state.epoch, value_state);
@@ -102,8 +133,13 @@ impl Database for AsyncInMemoryDatabase {
                     }
                 }
             } else {
-                self.db.insert(record.get_full_binary_id(), record);
+                self.db.insert(full_key.clone(), record);
             }
+            self.index.entry(storage_type).or_default().insert(full_key);
+        }
+        if let Some(epoch) = new_epoch {
+            // sending only fails if there are no subscribers
+            let _ = self.epoch_changes.0.send(epoch);
         }
         Ok(())
     }
@@ -135,8 +171,12 @@ impl Database for AsyncInMemoryDatabase {
     }
 
     /// Retrieve the user data for a given user
-    async fn get_user_data(&self, username: &AkdLabel) -> Result<KeyData, StorageError> {
-        if let Some(result) = self.user_info.get(&username.0) {
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        if let Some(result) = self.user_info.get(&(tree, username.to_vec())) {
             let mut results: Vec<ValueState> = result.values().cloned().collect::<Vec<_>>();
             // return ordered by epoch (from smallest -> largest)
             results.sort_by(|a, b| a.epoch.cmp(&b.epoch));
@@ -152,8 +192,9 @@ impl Database for AsyncInMemoryDatabase {
         &self,
         username: &AkdLabel,
         flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
     ) -> Result<ValueState, StorageError> {
-        let intermediate = self.get_user_data(username).await?.states;
+        let intermediate = self.get_user_data(username, tree).await?.states;
         match flag {
             ValueStateRetrievalFlag::MaxEpoch =>
             // retrieve by max epoch
@@ -217,10 +258,11 @@ impl Database for AsyncInMemoryDatabase {
         &self,
         keys: &[AkdLabel],
         flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
     ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
         let mut map = HashMap::new();
         for username in keys.iter() {
-            if let Ok(result) = self.get_user_state(username, flag).await {
+            if let Ok(result) = self.get_user_state(username, flag, tree).await {
                 map.insert(
                     AkdLabel(result.username.to_vec()),
                     (result.version, AkdValue(result.value.to_vec())),
@@ -229,6 +271,21 @@ impl Database for AsyncInMemoryDatabase {
         }
         Ok(map)
     }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        let receiver = self.epoch_changes.0.subscribe();
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(epoch) => return Some((epoch, receiver)),
+                    // the subscriber fell behind, the next received epoch is the latest
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Some(Box::pin(stream))
+    }
 }
 
 #[async_trait]
@@ -238,11 +295,7 @@ impl StorageUtil for AsyncInMemoryDatabase {
             .batch_get_all_direct()
             .await?
             .into_iter()
-            .filter(|record| match record {
-                DbRecord::Azks(_) => St::data_type() == StorageType::Azks,
-                DbRecord::TreeNode(_) => St::data_type() == StorageType::TreeNode,
-                DbRecord::ValueState(_) => St::data_type() == StorageType::ValueState,
-            })
+            .filter(|record| record.storage_type() == St::data_type())
             .collect();
 
         Ok(records)
@@ -266,4 +319,33 @@ impl StorageUtil for AsyncInMemoryDatabase {
 
         Ok(records)
     }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        // the page is selected by key, so that only the records of the page are cloned
+        let keys = match self.index.get(&St::data_type()) {
+            Some(index) => {
+                let start = match after {
+                    Some(after) => Bound::Excluded(after),
+                    None => Bound::Unbounded,
+                };
+                index
+                    .range::<[u8], _>((start, Bound::Unbounded))
+                    .take(limit)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            }
+            None => vec![],
+        };
+
+        let mut records = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            let id = St::key_from_full_binary(key).map_err(StorageError::Other)?;
+            records.push(self.get_internal::<St>(&id).await?);
+        }
+        Ok(records)
+    }
 }
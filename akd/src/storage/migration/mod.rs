@@ -0,0 +1,342 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Versioning of the storage schema, and migrations between schema versions.
+//!
+//! The version of the encoding of the stored records is held in a [SchemaVersion] record.
+//! Directories created prior to the introduction of schema versioning have no such
+//! record, and are treated as being at version 0.
+//!
+//! A [Migration] upgrades the records of a set of [StorageType]s from one schema version
+//! to the next. The [MigrationRegistry] holds the available migrations, and applies them in
+//! order to bring a storage backend up to [CURRENT_SCHEMA_VERSION]. Records are read a page
+//! at a time with [StorageUtil::batch_get_stored_page_direct], bypassing any caching, and each
+//! page of migrated records is written before the next is read. The stored schema version
+//! is updated after each migration completes, so that an interrupted upgrade resumes from
+//! the last completed migration.
+//!
+//! Storage of a schema version older than [MIN_COMPATIBLE_SCHEMA_VERSION] must be migrated
+//! before a directory can be opened over it. Since version 2, the full binary key of a
+//! [ValueState](crate::storage::types::ValueState) holds the username ahead of the epoch. The
+//! migration to it writes each value state stored under its previous key again under its new
+//! key, and deletes the previous key with [StorageUtil::batch_delete_direct]. As records are
+//! read along with the keys they are stored under, this only affects backends which store
+//! records under their full binary keys, while backends which store records by their fields
+//! have nothing to re-key. The records of an
+//! [IntegrityDatabase](crate::storage::integrity::IntegrityDatabase) are authenticated along
+//! with their keys, so the database it wraps should be migrated, and the MACs then recomputed
+//! with [IntegrityDatabase::backfill_macs](crate::storage::integrity::IntegrityDatabase::backfill_macs).
+
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, SchemaVersion, StorageType, ValueStateKey, DEFAULT_SCHEMA_VERSION_KEY,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::Azks;
+
+use log::info;
+use std::collections::{BTreeMap, HashSet};
+
+#[cfg(test)]
+mod tests;
+
+/// The schema version of the records written by this version of the library
+pub const CURRENT_SCHEMA_VERSION: u64 = 2;
+
+/// The oldest schema version whose records can be read by this version of the library
+/// without migrating. This is raised whenever a migration changes a record encoding or key.
+pub const MIN_COMPATIBLE_SCHEMA_VERSION: u64 = 2;
+
+/// The number of records read (and written) at a time by a migration, unless configured
+/// with [MigrationRegistry::with_page_size]
+pub const DEFAULT_MIGRATION_PAGE_SIZE: usize = 1000;
+
+/// Check that records of the `stored` schema version can be used by this version of the
+/// library, i.e. that they are neither newer than [CURRENT_SCHEMA_VERSION] nor older than
+/// [MIN_COMPATIBLE_SCHEMA_VERSION]. [None] denotes a directory created prior to schema
+/// versioning (version 0).
+pub fn check_schema_version(stored: Option<u64>) -> Result<(), StorageError> {
+    let stored = stored.unwrap_or_default();
+    if stored > CURRENT_SCHEMA_VERSION {
+        return Err(StorageError::Other(format!(
+            "Storage schema version {stored} is newer than the latest supported version {CURRENT_SCHEMA_VERSION}"
+        )));
+    }
+    if stored < MIN_COMPATIBLE_SCHEMA_VERSION {
+        return Err(StorageError::Other(format!(
+            "Storage schema version {stored} must be migrated to version {CURRENT_SCHEMA_VERSION}, see akd::storage::migration::MigrationRegistry"
+        )));
+    }
+    Ok(())
+}
+
+/// Retrieve the schema version of the records held in the database, [None] if the database
+/// has no schema version record
+pub async fn get_schema_version<Db: Database>(db: &Db) -> Result<Option<u64>, StorageError> {
+    match db.get::<SchemaVersion>(&DEFAULT_SCHEMA_VERSION_KEY).await {
+        Ok(DbRecord::SchemaVersion(schema)) => Ok(Some(schema.version)),
+        Ok(_) => Err(StorageError::Other(
+            "Unexpected record type for the schema version".to_string(),
+        )),
+        Err(StorageError::NotFound(_)) => Ok(None),
+        Err(other) => Err(other),
+    }
+}
+
+/// A migration of stored records from one schema version to the next
+pub trait Migration: Send + Sync {
+    /// The schema version this migration upgrades from. It upgrades to the following version.
+    fn source_version(&self) -> u64;
+
+    /// A short description of the changes made by the migration
+    fn description(&self) -> String;
+
+    /// The types of records which are migrated
+    fn storage_types(&self) -> Vec<StorageType>;
+
+    /// Upgrade a single record, returning [None] if it is unchanged
+    fn migrate(&self, record: &DbRecord) -> Result<Option<DbRecord>, StorageError>;
+
+    /// Whether a record stored under `stored_id`, which differs from its full binary id, is
+    /// stored under the key format this migration upgrades from. Such a record is written
+    /// again under its full binary id, and the `stored_id` deleted. Records stored under any
+    /// other key fail the migration.
+    fn is_source_id(&self, _stored_id: &[u8], _record: &DbRecord) -> bool {
+        false
+    }
+}
+
+/// The baseline migration, adopting schema versioning for directories created prior to it.
+/// The record encodings are unchanged, so this only records the schema version.
+struct AdoptSchemaVersioning;
+
+impl Migration for AdoptSchemaVersioning {
+    fn source_version(&self) -> u64 {
+        0
+    }
+
+    fn description(&self) -> String {
+        "Adopt storage schema versioning".to_string()
+    }
+
+    fn storage_types(&self) -> Vec<StorageType> {
+        vec![]
+    }
+
+    fn migrate(&self, _record: &DbRecord) -> Result<Option<DbRecord>, StorageError> {
+        Ok(None)
+    }
+}
+
+/// The migration to the keys of records of several trees, which hold the tree of the record.
+/// The full binary key of a [ValueState](crate::storage::types::ValueState) now holds the
+/// username ahead of the epoch, so each value state stored under its previous key
+/// ([legacy_value_state_key]) is moved to its new key. The full binary key of the [Azks] of a
+/// single tree is unchanged.
+struct RekeyRecordsByTree;
+
+/// Decodes the full binary key of a [ValueState](crate::storage::types::ValueState) of schema
+/// version 1, which holds the epoch ahead of the username
+fn legacy_value_state_key(bin: &[u8]) -> Result<ValueStateKey, String> {
+    match bin.split_first() {
+        Some((&first, rest)) if first == StorageType::ValueState as u8 => {
+            if rest.len() < 9 {
+                return Err("Not enough bytes to form a proper key".to_string());
+            }
+            let (epoch_bytes, username) = rest.split_at(8);
+            let epoch_bytes: [u8; 8] = epoch_bytes.try_into().expect("Slice with incorrect length");
+            Ok(ValueStateKey::new(
+                username.to_vec(),
+                u64::from_be_bytes(epoch_bytes),
+            ))
+        }
+        _ => Err("Not a value state key".to_string()),
+    }
+}
+
+impl Migration for RekeyRecordsByTree {
+    fn source_version(&self) -> u64 {
+        1
+    }
+
+    fn description(&self) -> String {
+        "Re-key the value states and AZKS by tree".to_string()
+    }
+
+    fn storage_types(&self) -> Vec<StorageType> {
+        vec![StorageType::ValueState]
+    }
+
+    fn migrate(&self, _record: &DbRecord) -> Result<Option<DbRecord>, StorageError> {
+        Ok(None)
+    }
+
+    fn is_source_id(&self, stored_id: &[u8], record: &DbRecord) -> bool {
+        match record {
+            DbRecord::ValueState(state) => {
+                legacy_value_state_key(stored_id).is_ok_and(|key| key == state.get_id())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The result of applying the migrations of a [MigrationRegistry]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The schema version prior to migrating, [None] if the storage held no schema version
+    pub from_version: Option<u64>,
+    /// The schema version after migrating
+    pub to_version: u64,
+    /// The number of records rewritten by the migrations
+    pub num_records_migrated: usize,
+}
+
+/// A registry of the [Migration]s between schema versions
+pub struct MigrationRegistry {
+    migrations: BTreeMap<u64, Box<dyn Migration>>,
+    page_size: usize,
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MigrationRegistry {
+    /// Create a registry with the migrations of this version of the library
+    pub fn new() -> Self {
+        let mut migrations: BTreeMap<u64, Box<dyn Migration>> = BTreeMap::new();
+        migrations.insert(0, Box::new(AdoptSchemaVersioning));
+        migrations.insert(1, Box::new(RekeyRecordsByTree));
+        Self {
+            migrations,
+            page_size: DEFAULT_MIGRATION_PAGE_SIZE,
+        }
+    }
+
+    /// Read (and write) records `page_size` at a time when migrating
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Register an additional migration, replacing any migration from the same version
+    pub fn with_migration<M: Migration + 'static>(mut self, migration: M) -> Self {
+        self.migrations
+            .insert(migration.source_version(), Box::new(migration));
+        self
+    }
+
+    /// Migrate the records of the database up to [CURRENT_SCHEMA_VERSION]. An empty
+    /// database (i.e. one without an [Azks]) is simply stamped with the current version.
+    ///
+    /// Migrations must not run concurrently with a publish, and any caches over the database
+    /// should be flushed afterwards.
+    pub async fn migrate<Db: StorageUtil>(&self, db: &Db) -> Result<MigrationReport, StorageError> {
+        let target = CURRENT_SCHEMA_VERSION;
+        let from_version = get_schema_version(db).await?;
+        let mut report = MigrationReport {
+            from_version,
+            to_version: from_version.unwrap_or_default(),
+            num_records_migrated: 0,
+        };
+
+        if from_version.is_none() {
+            if let Err(StorageError::NotFound(_)) = db.get::<Azks>(&DEFAULT_AZKS_KEY).await {
+                info!("Storage is empty, setting the schema version to {target}");
+                Self::set_version(db, target).await?;
+                report.to_version = target;
+                return Ok(report);
+            }
+        }
+
+        if report.to_version > target {
+            return Err(StorageError::Other(format!(
+                "Storage schema version {} is newer than the latest supported version {target}",
+                report.to_version
+            )));
+        }
+
+        while report.to_version < target {
+            let migration = self.migrations.get(&report.to_version).ok_or_else(|| {
+                StorageError::Other(format!(
+                    "No migration is registered from schema version {}",
+                    report.to_version
+                ))
+            })?;
+            info!(
+                "Migrating storage from schema version {} to {}: {}",
+                report.to_version,
+                report.to_version + 1,
+                migration.description()
+            );
+
+            for storage_type in migration.storage_types().into_iter() {
+                let mut after = None;
+                loop {
+                    let page = db
+                        .batch_get_stored_page_direct(
+                            storage_type,
+                            after.as_deref(),
+                            self.page_size,
+                        )
+                        .await?;
+                    let last = match page.last() {
+                        Some((stored_id, _)) => stored_id.clone(),
+                        None => break,
+                    };
+                    let mut migrated = vec![];
+                    let mut moved = vec![];
+                    for (stored_id, record) in page.into_iter() {
+                        let is_moved = stored_id != record.get_full_binary_id();
+                        if is_moved && !migration.is_source_id(&stored_id, &record) {
+                            return Err(StorageError::Other(format!(
+                                "Unexpected stored key of a {storage_type:?} record for schema version {}",
+                                report.to_version
+                            )));
+                        }
+                        match migration.migrate(&record)? {
+                            Some(upgraded) => migrated.push(upgraded),
+                            None if is_moved => migrated.push(record),
+                            None => {}
+                        }
+                        if is_moved {
+                            moved.push(stored_id);
+                        }
+                    }
+                    report.num_records_migrated += migrated.len();
+                    if !migrated.is_empty() {
+                        // a previous key is not deleted if it is also the new key of a record
+                        let written = migrated
+                            .iter()
+                            .map(DbRecord::get_full_binary_id)
+                            .collect::<HashSet<_>>();
+                        moved.retain(|stored_id| !written.contains(stored_id));
+                        db.batch_set(migrated, DbSetState::General).await?;
+                        db.batch_delete_direct(&moved).await?;
+                    }
+                    after = Some(last);
+                }
+            }
+
+            report.to_version += 1;
+            Self::set_version(db, report.to_version).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn set_version<Db: Database>(db: &Db, version: u64) -> Result<(), StorageError> {
+        db.set(DbRecord::SchemaVersion(DbRecord::build_schema_version(
+            version,
+        )))
+        .await
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Schema migration tests
+
+use super::*;
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::types::{
+    split_tree_scoped_key, KeyData, TreeId, ValueState, ValueStateKey, ValueStateRetrievalFlag,
+};
+use crate::{AkdLabel, AkdValue};
+
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::Mutex;
+
+/// A test migration which upper-cases the values of all value states
+struct UppercaseValues;
+
+impl Migration for UppercaseValues {
+    fn source_version(&self) -> u64 {
+        0
+    }
+
+    fn description(&self) -> String {
+        "Upper-case values".to_string()
+    }
+
+    fn storage_types(&self) -> Vec<StorageType> {
+        vec![StorageType::ValueState]
+    }
+
+    fn migrate(&self, record: &DbRecord) -> Result<Option<DbRecord>, StorageError> {
+        match record {
+            DbRecord::ValueState(state) if state.value.0.iter().any(u8::is_ascii_lowercase) => {
+                let mut state = state.clone();
+                state.value = AkdValue(state.value.0.to_ascii_uppercase());
+                Ok(Some(DbRecord::ValueState(state)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A backend which stores records under their full binary keys, as a key-value store does
+#[derive(Default)]
+struct KeyValueDatabase {
+    records: Mutex<BTreeMap<Vec<u8>, DbRecord>>,
+}
+
+impl KeyValueDatabase {
+    fn insert(&self, key: Vec<u8>, record: DbRecord) {
+        self.records.lock().unwrap().insert(key, record);
+    }
+
+    fn stored_keys(&self, storage_type: StorageType) -> Vec<Vec<u8>> {
+        self.records
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| split_tree_scoped_key(storage_type, key).is_ok())
+            .cloned()
+            .collect()
+    }
+
+    fn unsupported<T>() -> Result<T, StorageError> {
+        Err(StorageError::Other(
+            "User data is not retrieved by migrations".to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl Database for KeyValueDatabase {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.insert(record.get_full_binary_id(), record);
+        Ok(())
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        _state: DbSetState,
+    ) -> Result<(), StorageError> {
+        for record in records.into_iter() {
+            self.insert(record.get_full_binary_id(), record);
+        }
+        Ok(())
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        self.records
+            .lock()
+            .unwrap()
+            .get(&St::get_full_binary_key_id(id))
+            .cloned()
+            .ok_or_else(|| StorageError::NotFound(format!("{:?}", St::data_type())))
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let records = self.records.lock().unwrap();
+        Ok(ids
+            .iter()
+            .filter_map(|id| records.get(&St::get_full_binary_key_id(id)).cloned())
+            .collect())
+    }
+
+    async fn get_user_data(
+        &self,
+        _username: &AkdLabel,
+        _tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        Self::unsupported()
+    }
+
+    async fn get_user_state(
+        &self,
+        _username: &AkdLabel,
+        _flag: ValueStateRetrievalFlag,
+        _tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        Self::unsupported()
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        _usernames: &[AkdLabel],
+        _flag: ValueStateRetrievalFlag,
+        _tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        Self::unsupported()
+    }
+}
+
+#[async_trait]
+impl StorageUtil for KeyValueDatabase {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.batch_get_type_page_direct::<St>(None, usize::MAX)
+            .await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        Ok(self.records.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let page = self
+            .batch_get_stored_page_direct(St::data_type(), after, limit)
+            .await?;
+        Ok(page.into_iter().map(|(_, record)| record).collect())
+    }
+
+    async fn batch_get_stored_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, DbRecord)>, StorageError> {
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        Ok(self
+            .records
+            .lock()
+            .unwrap()
+            .range::<[u8], _>((start, Bound::Unbounded))
+            .filter(|(key, _)| split_tree_scoped_key(storage_type, key).is_ok())
+            .take(limit)
+            .map(|(key, record)| (key.clone(), record.clone()))
+            .collect())
+    }
+
+    async fn batch_delete_direct(&self, stored_ids: &[Vec<u8>]) -> Result<(), StorageError> {
+        let mut records = self.records.lock().unwrap();
+        for stored_id in stored_ids.iter() {
+            records.remove(stored_id);
+        }
+        Ok(())
+    }
+}
+
+/// The full binary key of a value state of schema version 1, which holds the epoch ahead of
+/// the username
+fn legacy_key(username: &[u8], epoch: u64) -> Vec<u8> {
+    let mut key = vec![StorageType::ValueState as u8];
+    key.extend_from_slice(&epoch.to_be_bytes());
+    key.extend_from_slice(username);
+    key
+}
+
+/// A key-value backend holding value states of schema version 1 under their previous keys
+fn legacy_key_value_database() -> KeyValueDatabase {
+    let db = KeyValueDatabase::default();
+    for (username, epoch) in [(b"user1", 1), (b"user2", 1), (b"user1", 2)] {
+        let state = DbRecord::build_user_state(
+            username.to_vec(),
+            b"value".to_vec(),
+            epoch,
+            1,
+            [epoch as u8; 32],
+            epoch,
+        );
+        db.insert(legacy_key(username, epoch), DbRecord::ValueState(state));
+    }
+    let azks = DbRecord::Azks(DbRecord::build_azks(1, 3));
+    db.insert(azks.get_full_binary_id(), azks);
+    let schema = DbRecord::SchemaVersion(DbRecord::build_schema_version(1));
+    db.insert(schema.get_full_binary_id(), schema);
+    db
+}
+
+async fn legacy_database() -> Result<AsyncInMemoryDatabase, StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let records = vec![
+        DbRecord::ValueState(DbRecord::build_user_state(
+            b"user1".to_vec(),
+            b"value".to_vec(),
+            1,
+            1,
+            [1u8; 32],
+            1,
+        )),
+        DbRecord::ValueState(DbRecord::build_user_state(
+            b"user2".to_vec(),
+            b"VALUE".to_vec(),
+            1,
+            1,
+            [2u8; 32],
+            1,
+        )),
+        DbRecord::Azks(DbRecord::build_azks(1, 3)),
+    ];
+    db.batch_set(records, DbSetState::General).await?;
+    Ok(db)
+}
+
+#[test]
+fn test_check_schema_version() {
+    assert!(check_schema_version(None).is_err());
+    assert!(check_schema_version(Some(0)).is_err());
+    assert!(check_schema_version(Some(1)).is_err());
+    assert!(check_schema_version(Some(CURRENT_SCHEMA_VERSION)).is_ok());
+    assert!(check_schema_version(Some(CURRENT_SCHEMA_VERSION + 1)).is_err());
+}
+
+#[tokio::test]
+async fn test_migrate_empty_database() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    assert_eq!(None, get_schema_version(&db).await?);
+
+    let report = MigrationRegistry::new().migrate(&db).await?;
+    assert_eq!(
+        MigrationReport {
+            from_version: None,
+            to_version: CURRENT_SCHEMA_VERSION,
+            num_records_migrated: 0,
+        },
+        report
+    );
+    assert_eq!(Some(CURRENT_SCHEMA_VERSION), get_schema_version(&db).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_database() -> Result<(), StorageError> {
+    let db = legacy_database().await?;
+    let report = MigrationRegistry::new().migrate(&db).await?;
+    assert_eq!(None, report.from_version);
+    assert_eq!(CURRENT_SCHEMA_VERSION, report.to_version);
+    // the in-memory database stores value states by their fields, so none are re-keyed
+    assert_eq!(0, report.num_records_migrated);
+    assert_eq!(Some(CURRENT_SCHEMA_VERSION), get_schema_version(&db).await?);
+
+    // migrating again has nothing to do
+    let report = MigrationRegistry::new().migrate(&db).await?;
+    assert_eq!(Some(CURRENT_SCHEMA_VERSION), report.from_version);
+    assert_eq!(CURRENT_SCHEMA_VERSION, report.to_version);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_rewrites_records() -> Result<(), StorageError> {
+    let db = legacy_database().await?;
+    // a page at a time, so that the migrated records are written between pages
+    let registry = MigrationRegistry::new()
+        .with_migration(UppercaseValues)
+        .with_page_size(1);
+    let report = registry.migrate(&db).await?;
+    // only the lower-case value is rewritten
+    assert_eq!(1, report.num_records_migrated);
+
+    for username in [b"user1".to_vec(), b"user2".to_vec()] {
+        let record = db
+            .get::<ValueState>(&ValueStateKey::new(username, 1))
+            .await?;
+        match record {
+            DbRecord::ValueState(state) => assert_eq!(AkdValue(b"VALUE".to_vec()), state.value),
+            _ => panic!("Unexpected record type"),
+        }
+    }
+
+    // the migration is not applied a second time
+    let report = registry.migrate(&db).await?;
+    assert_eq!(0, report.num_records_migrated);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_from_version_1() -> Result<(), StorageError> {
+    let db = legacy_database().await?;
+    db.set(DbRecord::SchemaVersion(DbRecord::build_schema_version(1)))
+        .await?;
+    let report = MigrationRegistry::new()
+        .with_page_size(1)
+        .migrate(&db)
+        .await?;
+    assert_eq!(
+        MigrationReport {
+            from_version: Some(1),
+            to_version: 2,
+            num_records_migrated: 0,
+        },
+        report
+    );
+    assert_eq!(Some(2), get_schema_version(&db).await?);
+    check_schema_version(get_schema_version(&db).await?)?;
+
+    let records = db
+        .batch_get::<ValueState>(&[
+            ValueStateKey::new(b"user1".to_vec(), 1),
+            ValueStateKey::new(b"user2".to_vec(), 1),
+        ])
+        .await?;
+    assert_eq!(2, records.len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_newer_version_fails() -> Result<(), StorageError> {
+    let db = legacy_database().await?;
+    db.set(DbRecord::SchemaVersion(DbRecord::build_schema_version(
+        CURRENT_SCHEMA_VERSION + 1,
+    )))
+    .await?;
+    assert!(MigrationRegistry::new().migrate(&db).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_rekeys_stored_value_states() -> Result<(), StorageError> {
+    let db = legacy_key_value_database();
+    // a page at a time, so that the pages are read while the value states are re-keyed
+    let report = MigrationRegistry::new()
+        .with_page_size(1)
+        .migrate(&db)
+        .await?;
+    assert_eq!(
+        MigrationReport {
+            from_version: Some(1),
+            to_version: 2,
+            num_records_migrated: 3,
+        },
+        report
+    );
+
+    // the value states are stored under their new keys only
+    let keys = [
+        ValueStateKey::new(b"user1".to_vec(), 1),
+        ValueStateKey::new(b"user1".to_vec(), 2),
+        ValueStateKey::new(b"user2".to_vec(), 1),
+    ];
+    assert_eq!(
+        keys.iter()
+            .map(ValueState::get_full_binary_key_id)
+            .collect::<Vec<_>>(),
+        db.stored_keys(StorageType::ValueState)
+    );
+    for key in keys.iter() {
+        match db.get::<ValueState>(key).await? {
+            DbRecord::ValueState(state) => assert_eq!(*key, state.get_id()),
+            _ => panic!("Unexpected record type"),
+        }
+    }
+    assert_eq!(1, db.stored_keys(StorageType::Azks).len());
+    assert_eq!(Some(2), get_schema_version(&db).await?);
+
+    // migrating again has nothing to do
+    let report = MigrationRegistry::new().migrate(&db).await?;
+    assert_eq!(0, report.num_records_migrated);
+    assert_eq!(3, db.stored_keys(StorageType::ValueState).len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_unexpected_stored_key_fails() -> Result<(), StorageError> {
+    let db = legacy_key_value_database();
+    // a value state stored under the previous key of another value state
+    let state =
+        DbRecord::build_user_state(b"user3".to_vec(), b"value".to_vec(), 1, 1, [3u8; 32], 1);
+    db.insert(legacy_key(b"user4", 1), DbRecord::ValueState(state));
+
+    assert!(MigrationRegistry::new().migrate(&db).await.is_err());
+    // the schema version is not updated
+    assert_eq!(Some(1), get_schema_version(&db).await?);
+    Ok(())
+}
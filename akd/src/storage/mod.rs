@@ -9,9 +9,11 @@
 
 use crate::errors::StorageError;
 use crate::storage::types::{DbRecord, StorageType};
+use crate::tree_node::TreeNodeWithPreviousValue;
 use crate::{AkdLabel, AkdValue};
 
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 #[cfg(feature = "serde_serialization")]
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashMap;
@@ -19,14 +21,25 @@ use std::hash::Hash;
 use std::marker::{Send, Sync};
 
 pub mod cache;
+pub(crate) mod codec;
+pub mod encryption;
+#[cfg(any(test, feature = "public_tests"))]
+pub mod faulty;
+pub mod integrity;
+pub mod retry;
+pub(crate) mod spill;
 pub mod transaction;
+pub mod transfer;
+pub mod tree_scope;
 pub mod types;
+pub mod wal;
 
 /*
 Various implementations supported by the library are imported here and usable at various checkpoints
 */
 pub mod manager;
 pub mod memory;
+pub mod migration;
 
 pub use manager::StorageManager;
 
@@ -34,6 +47,7 @@ pub use manager::StorageManager;
 pub mod tests;
 
 /// Denotes the "state" when a batch_set is being called in the data layer
+#[derive(Clone, Copy)]
 pub enum DbSetState {
     /// Being called as part of a transaction commit operation
     TransactionCommit,
@@ -115,14 +129,22 @@ pub trait Database: Send + Sync {
 
     /* User data searching */
 
+    // The user data retrieved is that of the given tree of a storage backend holding several
+    // trees (see tree_scope), and the tree is None for a backend holding a single tree
+
     /// Retrieve the user data for a given user
-    async fn get_user_data(&self, username: &AkdLabel) -> Result<types::KeyData, StorageError>;
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<types::TreeId>,
+    ) -> Result<types::KeyData, StorageError>;
 
     /// Retrieve a specific state for a given user
     async fn get_user_state(
         &self,
         username: &AkdLabel,
         flag: types::ValueStateRetrievalFlag,
+        tree: Option<types::TreeId>,
     ) -> Result<types::ValueState, StorageError>;
 
     /// Retrieve the user -> state version mapping in bulk. This is the same as get_user_states but with less data retrieved from the storage layer
@@ -130,7 +152,56 @@ pub trait Database: Send + Sync {
         &self,
         usernames: &[AkdLabel],
         flag: types::ValueStateRetrievalFlag,
+        tree: Option<types::TreeId>,
     ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError>;
+
+    /// Retrieve the states of a set of users in bulk, as selected by the flag. Users with no
+    /// such state are omitted. The default implementation retrieves the state of each user
+    /// with [Database::get_user_state], and should be overridden by implementations which can
+    /// retrieve them in a single query.
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: types::ValueStateRetrievalFlag,
+        tree: Option<types::TreeId>,
+    ) -> Result<HashMap<AkdLabel, types::ValueState>, StorageError> {
+        let states = futures::future::join_all(
+            usernames
+                .iter()
+                .map(|username| self.get_user_state(username, flag, tree)),
+        )
+        .await;
+        let mut results = HashMap::new();
+        for state in states.into_iter() {
+            match state {
+                Ok(state) => {
+                    results.insert(state.username.clone(), state);
+                }
+                Err(StorageError::NotFound(_)) => {}
+                Err(other) => return Err(other),
+            }
+        }
+        Ok(results)
+    }
+
+    /* Change notification */
+
+    /// Subscribe to a feed of the epochs published to the database, for implementations
+    /// which support push-based change notification. Each time an [crate::Azks] record is
+    /// written, its epoch is yielded by the stream. Returns [None] if unsupported (the default),
+    /// in which case readers fall back to polling the [crate::Azks] record for changes.
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        None
+    }
+
+    /* Integrity */
+
+    /// Whether the database computes and stores the [types::RecordMac] of every record
+    /// written to it (see [integrity::IntegrityDatabase]), in which case MACs computed
+    /// elsewhere must not be written over them. Returns false by default.
+    fn computes_record_macs(&self) -> bool {
+        false
+    }
 }
 
 /// Optional storage layer utility functions for debug and test purposes
@@ -141,4 +212,151 @@ pub trait StorageUtil: Database {
 
     /// Retrieves all stored records from the data layer, ignoring any caching or transaction pending
     async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError>;
+
+    /// Retrieves a page of at most `limit` stored records of a given type from the data layer,
+    /// ignoring any caching or transaction pending. Records are paged through in an order
+    /// determined by the implementation, starting after the record whose full binary id
+    /// ([Storable::get_full_binary_id]) is `after`, or from the first record if [None]. An
+    /// empty page is returned once there are no further records.
+    ///
+    /// Implementations must seek to the cursor rather than read and filter every record of the
+    /// type (e.g. with an indexed range scan), as streaming a type reads one page per `limit`
+    /// records: a page which costs O(N) makes the stream O(N²).
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError>;
+
+    /// Retrieves a page of the stored records of the given [StorageType], as
+    /// [StorageUtil::batch_get_type_page_direct] does for the corresponding [Storable]
+    async fn batch_get_storage_type_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError>
+    where
+        Self: Sized,
+    {
+        match storage_type {
+            StorageType::Azks => {
+                self.batch_get_type_page_direct::<crate::Azks>(after, limit)
+                    .await
+            }
+            StorageType::TreeNode => {
+                self.batch_get_type_page_direct::<TreeNodeWithPreviousValue>(after, limit)
+                    .await
+            }
+            StorageType::ValueState => {
+                self.batch_get_type_page_direct::<types::ValueState>(after, limit)
+                    .await
+            }
+            StorageType::EpochChangeset => {
+                self.batch_get_type_page_direct::<types::EpochChangeset>(after, limit)
+                    .await
+            }
+            StorageType::SchemaVersion => {
+                self.batch_get_type_page_direct::<types::SchemaVersion>(after, limit)
+                    .await
+            }
+            StorageType::RecordMac => {
+                self.batch_get_type_page_direct::<types::RecordMac>(after, limit)
+                    .await
+            }
+            StorageType::PendingEpoch => {
+                self.batch_get_type_page_direct::<types::PendingEpoch>(after, limit)
+                    .await
+            }
+        }
+    }
+
+    /// Retrieves a page of the stored records of the given [StorageType] along with the key
+    /// each is stored under, as [StorageUtil::batch_get_storage_type_page_direct] does, with
+    /// `after` being the stored key of the last record of the previous page.
+    ///
+    /// Backends which store records by their fields (e.g. the columns of a table) store each
+    /// record under its full binary id, which the default implementation returns. Backends
+    /// which store records under their full binary keys should return the key a record is held
+    /// under, which differs from its full binary id for records written with an earlier key
+    /// format (see [migration]).
+    async fn batch_get_stored_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, DbRecord)>, StorageError>
+    where
+        Self: Sized,
+    {
+        let page = self
+            .batch_get_storage_type_page_direct(storage_type, after, limit)
+            .await?;
+        Ok(page
+            .into_iter()
+            .map(|record| (record.get_full_binary_id(), record))
+            .collect())
+    }
+
+    /// Deletes the records stored under the given keys, as retrieved with
+    /// [StorageUtil::batch_get_stored_page_direct], bypassing any caching. This is used by
+    /// [migration]s to remove records written under an earlier key format, and the default
+    /// implementation, for backends which store every record under its full binary id, only
+    /// supports deleting nothing.
+    async fn batch_delete_direct(&self, stored_ids: &[Vec<u8>]) -> Result<(), StorageError> {
+        if stored_ids.is_empty() {
+            Ok(())
+        } else {
+            Err(StorageError::Other(
+                "Deleting stored records is not supported by this storage backend".to_string(),
+            ))
+        }
+    }
+
+    /// Streams all stored records of a given type from the data layer, retrieving them in pages
+    /// of `page_size` records with [StorageUtil::batch_get_type_page_direct]
+    fn stream_type_direct<St: Storable>(
+        &self,
+        page_size: usize,
+    ) -> BoxStream<'_, Result<DbRecord, StorageError>>
+    where
+        Self: Sized,
+    {
+        // the state is the cursor of the next page, or None once the last page is retrieved
+        stream::try_unfold(
+            Some(None),
+            move |cursor: Option<Option<Vec<u8>>>| async move {
+                let after = match cursor {
+                    Some(after) => after,
+                    None => return Ok(None),
+                };
+                let page = self
+                    .batch_get_type_page_direct::<St>(after.as_deref(), page_size)
+                    .await?;
+                let next = page.last().map(|record| Some(record.get_full_binary_id()));
+                Ok(Some((stream::iter(page.into_iter().map(Ok)), next)))
+            },
+        )
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Streams all stored records from the data layer, type by type, retrieving them in pages of
+    /// `page_size` records with [StorageUtil::batch_get_type_page_direct]
+    fn stream_all_direct(&self, page_size: usize) -> BoxStream<'_, Result<DbRecord, StorageError>>
+    where
+        Self: Sized,
+    {
+        stream::iter([
+            self.stream_type_direct::<crate::Azks>(page_size),
+            self.stream_type_direct::<TreeNodeWithPreviousValue>(page_size),
+            self.stream_type_direct::<types::ValueState>(page_size),
+            self.stream_type_direct::<types::EpochChangeset>(page_size),
+            self.stream_type_direct::<types::SchemaVersion>(page_size),
+            self.stream_type_direct::<types::RecordMac>(page_size),
+            self.stream_type_direct::<types::PendingEpoch>(page_size),
+        ])
+        .flatten()
+        .boxed()
+    }
 }
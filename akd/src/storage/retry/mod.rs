@@ -0,0 +1,290 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Retries of storage operations which fail with a transient error.
+//!
+//! The [RetryingDatabase] wraps a [Database], and retries operations which fail with a
+//! retryable error (see [StorageError::is_retryable]) with exponential backoff, according
+//! to its [RetryPolicy].
+//!
+//! Reads are always safe to retry. Writes are retried by re-issuing the entire batch, which
+//! is safe as long as the wrapped database applies writes as upserts keyed by the record's
+//! id, as all the storage layers of this crate do: a retried batch overwrites any records
+//! which were applied by a failed attempt with the same contents. Writes to a database which
+//! does not (i.e. one which appends records) must not be retried, see
+//! [RetryPolicy::retry_writes].
+//!
+//! Since the wrapped database takes ownership of a batch, a copy of the batch is kept for
+//! every attempt which could still be retried. The final attempt, and every write when
+//! writes are not retried, is handed the caller's batch without copying it.
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, KeyData, StorageType, TreeId, ValueState, ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use log::warn;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// How the operations of a [RetryingDatabase] are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts of an operation, including the first
+    pub max_attempts: u32,
+    /// The delay before the first retry
+    pub initial_backoff: Duration,
+    /// The factor the delay is multiplied by after each retry
+    pub multiplier: u32,
+    /// The maximum delay between retries
+    pub max_backoff: Duration,
+    /// Whether failed writes are retried. This must only be enabled when the wrapped database
+    /// applies writes as idempotent upserts.
+    pub retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(50),
+            multiplier: 2,
+            max_backoff: Duration::from_secs(2),
+            retry_writes: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry (numbered from 1)
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .checked_pow(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// A [Database] which retries operations failing with a transient error. See the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct RetryingDatabase<Db: Database> {
+    db: Db,
+    policy: RetryPolicy,
+}
+
+impl<Db: Database> RetryingDatabase<Db> {
+    /// Wrap the database, retrying operations according to the policy
+    pub fn new(db: Db, policy: RetryPolicy) -> Self {
+        Self { db, policy }
+    }
+
+    /// Retrieve a reference to the underlying database
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    /// Retrieve the retry policy
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// The value to issue an attempt with, which is moved into the final attempt rather than
+    /// copied
+    fn take_or_clone<T: Clone + Default>(value: &mut Option<T>, last_attempt: bool) -> T {
+        if last_attempt {
+            value.take().unwrap_or_default()
+        } else {
+            value.clone().unwrap_or_default()
+        }
+    }
+
+    async fn retry<T, F, Fut>(
+        &self,
+        operation: &str,
+        is_write: bool,
+        mut attempt: F,
+    ) -> Result<T, StorageError>
+    where
+        F: FnMut(bool) -> Fut,
+        Fut: Future<Output = Result<T, StorageError>>,
+    {
+        let max_attempts = if is_write && !self.policy.retry_writes {
+            1
+        } else {
+            self.policy.max_attempts.max(1)
+        };
+        let mut retry = 0;
+        loop {
+            match attempt(retry + 1 >= max_attempts).await {
+                Err(err) if err.is_retryable() && retry + 1 < max_attempts => {
+                    retry += 1;
+                    let backoff = self.policy.backoff(retry);
+                    warn!(
+                        "Storage operation {} failed ({}), retry {} of {} in {:?}",
+                        operation,
+                        err,
+                        retry,
+                        max_attempts - 1,
+                        backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Db: Database> Database for RetryingDatabase<Db> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.retry("set", true, |_| self.db.set(record.clone()))
+            .await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        // the batch is only copied while a failed attempt could still be retried, the final
+        // attempt is handed the caller's batch
+        let mut records = Some(records);
+        self.retry("batch_set", true, |last_attempt| {
+            self.db
+                .batch_set(Self::take_or_clone(&mut records, last_attempt), state)
+        })
+        .await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        self.retry("get", false, |_| self.db.get::<St>(id)).await
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.retry("batch_get", false, |_| self.db.batch_get::<St>(ids))
+            .await
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        self.retry("get_user_data", false, |_| {
+            self.db.get_user_data(username, tree)
+        })
+        .await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        self.retry("get_user_state", false, |_| {
+            self.db.get_user_state(username, flag, tree)
+        })
+        .await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.retry("get_user_state_versions", false, |_| {
+            self.db.get_user_state_versions(usernames, flag, tree)
+        })
+        .await
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        self.retry("get_user_states", false, |_| {
+            self.db.get_user_states(usernames, flag, tree)
+        })
+        .await
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        self.db.computes_record_macs()
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil> StorageUtil for RetryingDatabase<Db> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.retry("batch_get_type_direct", false, |_| {
+            self.db.batch_get_type_direct::<St>()
+        })
+        .await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.retry("batch_get_all_direct", false, |_| {
+            self.db.batch_get_all_direct()
+        })
+        .await
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.retry("batch_get_type_page_direct", false, |_| {
+            self.db.batch_get_type_page_direct::<St>(after, limit)
+        })
+        .await
+    }
+
+    async fn batch_get_stored_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, DbRecord)>, StorageError> {
+        self.retry("batch_get_stored_page_direct", false, |_| {
+            self.db
+                .batch_get_stored_page_direct(storage_type, after, limit)
+        })
+        .await
+    }
+
+    async fn batch_delete_direct(&self, stored_ids: &[Vec<u8>]) -> Result<(), StorageError> {
+        self.retry("batch_delete_direct", true, |_| {
+            self.db.batch_delete_direct(stored_ids)
+        })
+        .await
+    }
+}
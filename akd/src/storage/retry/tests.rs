@@ -0,0 +1,131 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Storage retry tests
+
+use super::*;
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::storage::faulty::{FaultConfig, FaultTarget, FaultyDatabase};
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::Azks;
+
+fn quick_policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(4),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_storage_error_retryability() {
+    assert!(StorageError::Connection("timeout".to_string()).is_retryable());
+    assert!(!StorageError::NotFound("azks".to_string()).is_retryable());
+    assert!(!StorageError::Transaction("active".to_string()).is_retryable());
+    assert!(!StorageError::Other("bad row".to_string()).is_retryable());
+    assert!(!StorageError::IntegrityViolation("mac".to_string()).is_retryable());
+}
+
+#[test]
+fn test_exponential_backoff() {
+    let policy = RetryPolicy {
+        initial_backoff: Duration::from_millis(10),
+        multiplier: 3,
+        max_backoff: Duration::from_millis(100),
+        ..Default::default()
+    };
+    assert_eq!(Duration::from_millis(10), policy.backoff(1));
+    assert_eq!(Duration::from_millis(30), policy.backoff(2));
+    assert_eq!(Duration::from_millis(90), policy.backoff(3));
+    assert_eq!(Duration::from_millis(100), policy.backoff(4));
+    assert_eq!(Duration::from_millis(100), policy.backoff(64));
+}
+
+#[tokio::test]
+async fn test_transient_errors_are_retried() -> Result<(), StorageError> {
+    let faulty = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        11,
+        FaultConfig::errors(FaultTarget::All, 0.5),
+    );
+    let db = RetryingDatabase::new(faulty.clone(), quick_policy(32));
+    for epoch in 1..=16 {
+        db.set(DbRecord::Azks(DbRecord::build_azks(epoch, epoch)))
+            .await?;
+        assert_eq!(
+            DbRecord::Azks(DbRecord::build_azks(epoch, epoch)),
+            db.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+        );
+    }
+    assert!(faulty.num_injected_faults() > 0);
+
+    // a partially applied batch is completed by the retry
+    faulty.set_config(FaultConfig {
+        partial_write_rate: 0.5,
+        ..Default::default()
+    });
+    let records = (1..=32u64)
+        .map(|i| {
+            DbRecord::ValueState(DbRecord::build_user_state(
+                format!("user{i}").into_bytes(),
+                b"value".to_vec(),
+                1,
+                256,
+                [i as u8; 32],
+                1,
+            ))
+        })
+        .collect::<Vec<_>>();
+    db.batch_set(records, DbSetState::General).await?;
+    assert_eq!(
+        32,
+        faulty
+            .inner()
+            .batch_get_type_direct::<ValueState>()
+            .await?
+            .len()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retries_are_bounded() {
+    let faulty = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        5,
+        FaultConfig::errors(FaultTarget::All, 1.0),
+    );
+    let db = RetryingDatabase::new(faulty.clone(), quick_policy(3));
+    assert!(matches!(
+        db.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::Connection(_))
+    ));
+    assert_eq!(3, faulty.num_injected_faults());
+
+    // permanent errors are not retried
+    faulty.disable();
+    assert!(matches!(
+        db.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::NotFound(_))
+    ));
+
+    // nor are writes, when disabled
+    faulty.set_config(FaultConfig::errors(FaultTarget::All, 1.0));
+    let db = RetryingDatabase::new(
+        faulty.clone(),
+        RetryPolicy {
+            retry_writes: false,
+            ..quick_policy(3)
+        },
+    );
+    assert!(db
+        .set(DbRecord::Azks(DbRecord::build_azks(1, 1)))
+        .await
+        .is_err());
+    assert_eq!(4, faulty.num_injected_faults());
+}
@@ -0,0 +1,257 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! An external sort of value states by their node labels, for sets of states which do not fit
+//! in memory.
+//!
+//! States are buffered up to a run size, at which the buffer is sorted and spilled to a
+//! temporary file in the spill directory as a run of length-prefixed records. Once every
+//! state is added, runs are merged in passes of at most [MERGE_FAN_IN] runs until no more than
+//! [MERGE_FAN_IN] remain, so that the number of files open at once is bounded. The sorted
+//! states are then read back by merging the remaining runs, which holds a single state of
+//! each run in memory. A set of states which fits in a single run is never spilled.
+//!
+//! The runs hold the states in plaintext. They're created with exclusive access for the
+//! current user, under unpredictable names, and are removed when the [SortedValueStates] is
+//! dropped.
+
+use crate::errors::StorageError;
+use crate::storage::codec::{encode_record, Reader};
+use crate::storage::types::{DbRecord, ValueState};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+#[cfg(test)]
+mod tests;
+
+/// The maximum number of runs merged at once, and so of run files open at once
+pub(crate) const MERGE_FAN_IN: usize = 64;
+
+/// Value states sorted by their node labels, spilled to temporary files once they exceed the
+/// run size. See the [module documentation](self).
+pub(crate) struct SortedValueStates {
+    run_size: usize,
+    fan_in: usize,
+    spill_dir: PathBuf,
+    buffer: Vec<ValueState>,
+    runs: Vec<NamedTempFile>,
+    len: u64,
+}
+
+impl SortedValueStates {
+    /// An empty set of states, holding at most `run_size` states in memory at a time and
+    /// spilling runs of them to `spill_dir`
+    pub(crate) fn new(run_size: usize, spill_dir: PathBuf) -> Self {
+        Self {
+            run_size: run_size.max(1),
+            fan_in: MERGE_FAN_IN,
+            spill_dir,
+            buffer: vec![],
+            runs: vec![],
+            len: 0,
+        }
+    }
+
+    /// The number of states added
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Adds a state, spilling the buffered states as a run once the run size is reached
+    pub(crate) fn push(&mut self, state: ValueState) -> Result<(), StorageError> {
+        self.buffer.push(state);
+        self.len += 1;
+        if self.buffer.len() >= self.run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Completes the states added, after which they can be read back in order
+    pub(crate) fn finish(&mut self) -> Result<(), StorageError> {
+        if self.runs.is_empty() {
+            self.buffer.sort_unstable_by_key(|state| state.label);
+            return Ok(());
+        }
+        self.spill()?;
+
+        // merge the runs until they can be read back with a single merge
+        while self.runs.len() > self.fan_in {
+            let mut merged = Vec::with_capacity(self.runs.len().div_ceil(self.fan_in));
+            for group in self.runs.chunks(self.fan_in) {
+                merged.push(self.write_run(merge_runs(group)?)?);
+            }
+            // the merged runs are removed as they're dropped
+            self.runs = merged;
+        }
+        Ok(())
+    }
+
+    /// Reads back the states in the order of their node labels. Must be called after
+    /// [SortedValueStates::finish].
+    pub(crate) fn iter(
+        &self,
+    ) -> Result<Box<dyn Iterator<Item = Result<ValueState, StorageError>> + Send + '_>, StorageError>
+    {
+        if self.runs.is_empty() {
+            return Ok(Box::new(self.buffer.iter().cloned().map(Ok)));
+        }
+        Ok(Box::new(merge_runs(&self.runs)?))
+    }
+
+    fn spill(&mut self) -> Result<(), StorageError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.buffer.sort_unstable_by_key(|state| state.label);
+        let states = std::mem::take(&mut self.buffer);
+        let run = self.write_run(states.into_iter().map(Ok))?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// Writes the sorted states to a new run in the spill directory
+    fn write_run(
+        &self,
+        states: impl Iterator<Item = Result<ValueState, StorageError>>,
+    ) -> Result<NamedTempFile, StorageError> {
+        // the run is removed on drop, even if it's only partially written
+        let run = tempfile::Builder::new()
+            .prefix("akd_sorted_states_")
+            .suffix(".run")
+            .tempfile_in(&self.spill_dir)
+            .map_err(|err| io_error(&self.spill_dir, err))?;
+        let mut writer = BufWriter::new(run.as_file());
+        let mut encoded = vec![];
+        for state in states {
+            encoded.clear();
+            encode_record(&mut encoded, &DbRecord::ValueState(state?));
+            writer
+                .write_all(&(encoded.len() as u32).to_be_bytes())
+                .and_then(|_| writer.write_all(&encoded))
+                .map_err(|err| io_error(run.path(), err))?;
+        }
+        writer.flush().map_err(|err| io_error(run.path(), err))?;
+        drop(writer);
+        Ok(run)
+    }
+}
+
+/// Opens the runs and merges them, in the order of the states' node labels
+fn merge_runs(runs: &[NamedTempFile]) -> Result<MergedRuns, StorageError> {
+    let mut readers = runs
+        .iter()
+        .map(RunReader::open)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut heads = BinaryHeap::new();
+    for (index, run) in readers.iter_mut().enumerate() {
+        if let Some(state) = run.next_state()? {
+            heads.push(Reverse(Head { state, index }));
+        }
+    }
+    Ok(MergedRuns {
+        runs: readers,
+        heads,
+    })
+}
+
+/// The next state of a run, ordered by its node label
+struct Head {
+    state: ValueState,
+    index: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.state.label == other.state.label && self.index == other.index
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.state
+            .label
+            .cmp(&other.state.label)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+struct RunReader {
+    path: PathBuf,
+    reader: BufReader<File>,
+}
+
+impl RunReader {
+    fn open(run: &NamedTempFile) -> Result<Self, StorageError> {
+        // each reader has its own handle, and so its own position in the run
+        let file = run.reopen().map_err(|err| io_error(run.path(), err))?;
+        Ok(Self {
+            path: run.path().to_path_buf(),
+            reader: BufReader::new(file),
+        })
+    }
+
+    fn next_state(&mut self) -> Result<Option<ValueState>, StorageError> {
+        let mut len = [0u8; 4];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(io_error(&self.path, err)),
+        }
+        let mut encoded = vec![0u8; u32::from_be_bytes(len) as usize];
+        self.reader
+            .read_exact(&mut encoded)
+            .map_err(|err| io_error(&self.path, err))?;
+        match Reader::new(&encoded).record()? {
+            DbRecord::ValueState(state) => Ok(Some(state)),
+            _ => Err(StorageError::Other(format!(
+                "Unexpected record type in the sorted run {:?}",
+                self.path
+            ))),
+        }
+    }
+}
+
+struct MergedRuns {
+    runs: Vec<RunReader>,
+    heads: BinaryHeap<Reverse<Head>>,
+}
+
+impl Iterator for MergedRuns {
+    type Item = Result<ValueState, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse(Head { state, index }) = self.heads.pop()?;
+        match self.runs[index].next_state() {
+            Ok(Some(next)) => self.heads.push(Reverse(Head { state: next, index })),
+            Ok(None) => {}
+            Err(err) => {
+                // the merge cannot continue without the run
+                self.heads.clear();
+                return Some(Err(err));
+            }
+        }
+        Some(Ok(state))
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> StorageError {
+    StorageError::Other(format!("Sorted run {path:?} I/O error: {err}"))
+}
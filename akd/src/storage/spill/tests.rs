@@ -0,0 +1,102 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Tests of the external sort of value states
+
+use super::*;
+use crate::{AkdLabel, AkdValue, NodeLabel};
+
+fn states(count: u8) -> Vec<ValueState> {
+    (0..count)
+        .map(|i| {
+            // labels which are not added in order
+            let label_byte = i.wrapping_mul(157);
+            ValueState::new(
+                AkdLabel(vec![i]),
+                AkdValue(vec![i]),
+                1,
+                NodeLabel::new([label_byte; 32], 256),
+                1,
+            )
+        })
+        .collect()
+}
+
+fn sorted(mut states: Vec<ValueState>) -> Vec<ValueState> {
+    states.sort_by_key(|state| state.label);
+    states
+}
+
+fn num_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir).unwrap().count()
+}
+
+#[test]
+fn test_sorted_value_states_in_memory() -> Result<(), StorageError> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut sorter = SortedValueStates::new(100, dir.path().to_path_buf());
+    for state in states(20) {
+        sorter.push(state)?;
+    }
+    sorter.finish()?;
+
+    // nothing is spilled
+    assert_eq!(0, num_files(dir.path()));
+    assert_eq!(20, sorter.len());
+    assert_eq!(
+        sorted(states(20)),
+        sorter.iter()?.collect::<Result<Vec<_>, _>>()?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sorted_value_states_merge_passes() -> Result<(), StorageError> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut sorter = SortedValueStates::new(1, dir.path().to_path_buf());
+    sorter.fan_in = 3;
+    for state in states(20) {
+        sorter.push(state)?;
+    }
+    assert_eq!(20, num_files(dir.path()));
+
+    // the runs are merged until a single merge of at most the fan-in remains
+    sorter.finish()?;
+    assert!(sorter.runs.len() <= 3);
+    assert_eq!(sorter.runs.len(), num_files(dir.path()));
+    for _ in 0..2 {
+        assert_eq!(
+            sorted(states(20)),
+            sorter.iter()?.collect::<Result<Vec<_>, _>>()?
+        );
+    }
+
+    drop(sorter);
+    assert_eq!(0, num_files(dir.path()));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sorted_value_states_run_permissions() -> Result<(), StorageError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut sorter = SortedValueStates::new(1, dir.path().to_path_buf());
+    for state in states(2) {
+        sorter.push(state)?;
+    }
+    for run in sorter.runs.iter() {
+        let mode = run.as_file().metadata().unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    // a spill directory which does not exist fails the sort
+    let mut sorter = SortedValueStates::new(1, dir.path().join("missing"));
+    assert!(sorter.push(states(1).remove(0)).is_err());
+    Ok(())
+}
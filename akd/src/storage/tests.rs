@@ -11,6 +11,7 @@ use crate::errors::StorageError;
 use crate::storage::types::*;
 use crate::storage::Database;
 use crate::storage::StorageManager;
+use crate::storage::{Storable, StorageUtil};
 use crate::tree_node::*;
 use crate::utils::byte_arr_from_u64;
 use crate::NodeLabel;
@@ -18,6 +19,7 @@ use crate::{AkdLabel, AkdValue};
 
 use akd_core::hash::EMPTY_DIGEST;
 use akd_core::AzksValue;
+use futures::TryStreamExt;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::time::{Duration, Instant};
@@ -37,10 +39,45 @@ mod memory_storage_tests {
     #[serial]
     async fn test_in_memory_db() {
         let db = AsyncInMemoryDatabase::new();
-        crate::storage::tests::run_test_cases_for_storage_impl(db).await;
+        crate::storage::tests::run_test_cases_for_storage_impl(db.clone()).await;
+        crate::storage::tests::run_test_cases_for_storage_util_impl(&db).await;
     }
 }
 
+// *** Fixtures shared by the tests of the storage wrappers *** //
+
+/// A value state whose node label is derived from its version
+#[cfg(test)]
+pub(crate) fn user_state(username: &str, value: &str, version: u64, epoch: u64) -> ValueState {
+    DbRecord::build_user_state(
+        username.as_bytes().to_vec(),
+        value.as_bytes().to_vec(),
+        version,
+        256,
+        [version as u8; 32],
+        epoch,
+    )
+}
+
+/// The records written by [populate_database]: two states of "alice", one state of "bob"
+/// and an azks
+#[cfg(test)]
+pub(crate) fn populated_records() -> Vec<DbRecord> {
+    vec![
+        DbRecord::ValueState(user_state("alice", "secret key 1", 1, 1)),
+        DbRecord::ValueState(user_state("alice", "secret key 2", 2, 2)),
+        DbRecord::ValueState(user_state("bob", "secret key 3", 1, 2)),
+        DbRecord::Azks(DbRecord::build_azks(2, 3)),
+    ]
+}
+
+/// Writes the [populated_records] through the given database in a single batch
+#[cfg(test)]
+pub(crate) async fn populate_database<S: Database>(db: &S) -> Result<(), StorageError> {
+    db.batch_set(populated_records(), crate::storage::DbSetState::General)
+        .await
+}
+
 // *** Run the test cases for a given data-layer impl *** //
 /// Run the storage-layer test suite for a given storage implementation.
 /// This is public because it can be used by other implemented storage layers
@@ -56,12 +93,21 @@ pub async fn run_test_cases_for_storage_impl<S: Database>(db: S) -> StorageManag
     manager
 }
 
+/// Run the test suite of the [StorageUtil] bulk reads for a given storage implementation,
+/// checking that paging through the records retrieves the same records as the bulk reads.
+/// This is public because it can be used by other implemented storage layers for
+/// consistency checks (e.g. mysql, memcached, etc)
+pub async fn run_test_cases_for_storage_util_impl<S: StorageUtil>(db: &S) {
+    test_paged_reads(db).await;
+}
+
 // *** New Test Helper Functions *** //
 async fn test_get_and_set_item<Ns: Database>(storage: &Ns) {
     // === Azks storage === //
     let azks = Azks {
         latest_epoch: 34,
         num_nodes: 10,
+        tree: None,
     };
 
     let set_result = storage.set(DbRecord::Azks(azks.clone())).await;
@@ -92,8 +138,8 @@ async fn test_get_and_set_item<Ns: Database>(storage: &Ns) {
     let mut node2 = node.clone();
     node2.label = NodeLabel::new(byte_arr_from_u64(16), 4);
 
-    let key = NodeKey(NodeLabel::new(byte_arr_from_u64(13), 4));
-    let key2 = NodeKey(NodeLabel::new(byte_arr_from_u64(16), 4));
+    let key = NodeKey::new(NodeLabel::new(byte_arr_from_u64(13), 4));
+    let key2 = NodeKey::new(NodeLabel::new(byte_arr_from_u64(16), 4));
 
     let set_result = storage
         .set(DbRecord::TreeNode(PvTreeNode::from_tree_node(node.clone())))
@@ -123,9 +169,10 @@ async fn test_get_and_set_item<Ns: Database>(storage: &Ns) {
     }
 
     // === ValueState storage === //
-    let key = ValueStateKey("test".as_bytes().to_vec(), 1);
+    let key = ValueStateKey::new("test".as_bytes().to_vec(), 1);
     let value = ValueState {
         username: AkdLabel::from("test"),
+        tree: None,
         epoch: 1,
         label: NodeLabel::new(byte_arr_from_u64(1), 1),
         version: 1,
@@ -171,6 +218,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
                 },
                 epoch,
                 username: AkdLabel(user.clone()),
+                tree: None,
             }));
         }
         epoch += 1;
@@ -186,7 +234,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
     let toc: Duration = Instant::now() - tic;
     println!("Storage batch op: {} ms", toc.as_millis());
     let got = storage
-        .get::<ValueState>(&ValueStateKey(rand_users[0].clone(), 10))
+        .get::<ValueState>(&ValueStateKey::new(rand_users[0].clone(), 10))
         .await;
     if got.is_err() {
         panic!("Failed to retrieve a user after batch insert");
@@ -194,7 +242,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
 
     let keys: Vec<ValueStateKey> = rand_users
         .iter()
-        .map(|user| ValueStateKey(user.clone(), 1))
+        .map(|user| ValueStateKey::new(user.clone(), 1))
         .collect();
     let got_all = storage.batch_get::<ValueState>(&keys).await;
     match got_all {
@@ -233,7 +281,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
         .map(|user| AkdLabel(user.clone()))
         .collect();
     let got_all_min_states = storage
-        .get_user_state_versions(&user_keys, ValueStateRetrievalFlag::MinEpoch)
+        .get_user_state_versions(&user_keys, ValueStateRetrievalFlag::MinEpoch, None)
         .await;
     // should be the same thing as the previous get
     match got_all_min_states {
@@ -274,7 +322,7 @@ async fn test_batch_get_items<Ns: Database>(storage: &Ns) {
     }
 
     let got_all_max_states = storage
-        .get_user_state_versions(&user_keys, ValueStateRetrievalFlag::MaxEpoch)
+        .get_user_state_versions(&user_keys, ValueStateRetrievalFlag::MaxEpoch, None)
         .await;
     // should be the same thing as the previous get
     match got_all_max_states {
@@ -339,6 +387,7 @@ async fn test_transactions<S: Database>(storage: &StorageManager<S>) {
                 },
                 epoch,
                 username: AkdLabel(user.clone()),
+                tree: None,
             }));
         }
         epoch += 1;
@@ -347,6 +396,7 @@ async fn test_transactions<S: Database>(storage: &StorageManager<S>) {
     data.push(DbRecord::Azks(Azks {
         latest_epoch: 1,
         num_nodes: 34,
+        tree: None,
     }));
 
     let new_data = data
@@ -362,6 +412,7 @@ async fn test_transactions<S: Database>(storage: &StorageManager<S>) {
                 DbRecord::Azks(azks) => DbRecord::Azks(Azks {
                     latest_epoch: azks.latest_epoch + 10000,
                     num_nodes: azks.num_nodes,
+                    tree: None,
                 }),
                 _ => new_item,
             }
@@ -373,7 +424,7 @@ async fn test_transactions<S: Database>(storage: &StorageManager<S>) {
     let toc: Duration = Instant::now() - tic;
     println!("Storage batch op: {} ms", toc.as_millis());
     let got = storage
-        .get::<ValueState>(&ValueStateKey(rand_users[0].clone(), 10))
+        .get::<ValueState>(&ValueStateKey::new(rand_users[0].clone(), 10))
         .await;
     if got.is_err() {
         panic!("Failed to retrieve a user after batch insert");
@@ -387,7 +438,7 @@ async fn test_transactions<S: Database>(storage: &StorageManager<S>) {
     println!("Transactional storage batch op: {} ms", toc.as_millis());
 
     let got = storage
-        .get::<ValueState>(&ValueStateKey(rand_users[0].clone(), 10 + 10000))
+        .get::<ValueState>(&ValueStateKey::new(rand_users[0].clone(), 10 + 10000))
         .await;
     if got.is_err() {
         panic!("Failed to retrieve a user after batch insert");
@@ -418,6 +469,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         },
         epoch: 1u64,
         username: AkdLabel(rand_user),
+        tree: None,
     };
     let mut sample_state_2 = sample_state.clone();
     sample_state_2.username = AkdLabel::from("test_user");
@@ -441,7 +493,10 @@ async fn test_user_data<S: Database>(storage: &S) {
         .await;
     assert_eq!(Ok(()), result);
 
-    let data = storage.get_user_data(&sample_state.username).await.unwrap();
+    let data = storage
+        .get_user_data(&sample_state.username, None)
+        .await
+        .unwrap();
     assert_eq!(3, data.states.len());
 
     let versions = data
@@ -482,6 +537,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         .get_user_state(
             &sample_state.username,
             ValueStateRetrievalFlag::SpecificVersion(2),
+            None,
         )
         .await;
     assert_eq!(
@@ -491,12 +547,13 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             value: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            tree: None,
         }),
         specific_result
     );
 
     let specifc_result = storage
-        .get::<ValueState>(&ValueStateKey(sample_state.username.to_vec(), 123))
+        .get::<ValueState>(&ValueStateKey::new(sample_state.username.to_vec(), 123))
         .await;
     if let Ok(DbRecord::ValueState(state)) = specifc_result {
         assert_eq!(
@@ -506,6 +563,7 @@ async fn test_user_data<S: Database>(storage: &S) {
                 label: NodeLabel::new(byte_arr_from_u64(1), 1),
                 value: AkdValue(rand_value.clone()),
                 username: sample_state.username.clone(),
+                tree: None,
             },
             state
         );
@@ -517,6 +575,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         .get_user_state(
             &sample_state.username,
             ValueStateRetrievalFlag::SpecificVersion(100),
+            None,
         )
         .await;
     assert!(matches!(missing_result, Err(StorageError::NotFound(_)),));
@@ -525,6 +584,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         .get_user_state(
             &sample_state.username,
             ValueStateRetrievalFlag::SpecificEpoch(123),
+            None,
         )
         .await;
     assert_eq!(
@@ -534,12 +594,17 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             value: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            tree: None,
         }),
         specific_result
     );
 
     let specific_result = storage
-        .get_user_state(&sample_state.username, ValueStateRetrievalFlag::MinEpoch)
+        .get_user_state(
+            &sample_state.username,
+            ValueStateRetrievalFlag::MinEpoch,
+            None,
+        )
         .await;
     assert_eq!(
         Ok(ValueState {
@@ -548,12 +613,17 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             value: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            tree: None,
         }),
         specific_result
     );
 
     let specific_result = storage
-        .get_user_state(&sample_state.username, ValueStateRetrievalFlag::MaxEpoch)
+        .get_user_state(
+            &sample_state.username,
+            ValueStateRetrievalFlag::MaxEpoch,
+            None,
+        )
         .await;
     assert_eq!(
         Ok(ValueState {
@@ -562,6 +632,7 @@ async fn test_user_data<S: Database>(storage: &S) {
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             value: AkdValue(rand_value.clone()),
             username: sample_state.username.clone(),
+            tree: None,
         }),
         specific_result
     );
@@ -589,7 +660,7 @@ async fn test_user_data<S: Database>(storage: &S) {
         .await;
     assert_eq!(Ok(()), result);
 
-    let data = storage.get_user_data(&sample_state_2.username).await;
+    let data = storage.get_user_data(&sample_state_2.username, None).await;
     assert_eq!(4, data.unwrap().states.len());
 }
 
@@ -614,6 +685,7 @@ async fn test_tombstoning_data<S: Database>(
         },
         epoch: 1u64,
         username: AkdLabel(rand_user.clone()),
+        tree: None,
     };
     let mut sample_state2 = sample_state.clone();
     sample_state2.username = AkdLabel::from("tombstone_test_user");
@@ -688,3 +760,93 @@ async fn test_tombstoning_data<S: Database>(
 
     Ok(())
 }
+
+async fn test_paged_reads<S: StorageUtil>(storage: &S) {
+    let mut records = vec![DbRecord::Azks(DbRecord::build_azks(3, 20))];
+    for i in 0..20u64 {
+        records.push(DbRecord::TreeNode(
+            DbRecord::build_tree_node_with_previous_value(
+                byte_arr_from_u64(i << 56),
+                8,
+                1,
+                1,
+                [0u8; 32],
+                0,
+                1,
+                None,
+                None,
+                EMPTY_DIGEST,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        ));
+    }
+    for epoch in 1..=3 {
+        records.push(DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(
+            epoch,
+            0,
+            1,
+            vec![NodeLabel::new(byte_arr_from_u64(epoch << 56), 8)],
+        )));
+    }
+    storage
+        .batch_set(records, crate::storage::DbSetState::General)
+        .await
+        .unwrap();
+
+    async fn check_type<St: Storable, S: StorageUtil>(storage: &S) {
+        let mut expected = storage.batch_get_type_direct::<St>().await.unwrap();
+        expected.sort();
+        for page_size in [1, 7, 1000] {
+            let mut after = None;
+            let mut paged = vec![];
+            loop {
+                let page = storage
+                    .batch_get_type_page_direct::<St>(after.as_deref(), page_size)
+                    .await
+                    .unwrap();
+                assert!(page.len() <= page_size);
+                match page.last() {
+                    Some(last) => after = Some(last.get_full_binary_id()),
+                    None => break,
+                }
+                paged.extend(page);
+            }
+            paged.sort();
+            assert_eq!(
+                expected,
+                paged,
+                "{:?} pages of {page_size}",
+                St::data_type()
+            );
+
+            let mut streamed = storage
+                .stream_type_direct::<St>(page_size)
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            streamed.sort();
+            assert_eq!(expected, streamed);
+        }
+    }
+    check_type::<Azks, _>(storage).await;
+    check_type::<PvTreeNode, _>(storage).await;
+    check_type::<ValueState, _>(storage).await;
+    check_type::<EpochChangeset, _>(storage).await;
+
+    let mut all = storage.batch_get_all_direct().await.unwrap();
+    all.sort();
+    let mut streamed = storage
+        .stream_all_direct(7)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    streamed.sort();
+    assert_eq!(all, streamed);
+}
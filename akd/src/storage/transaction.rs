@@ -110,8 +110,15 @@ impl Transaction {
             .map(|p| p.value().clone())
             .collect::<Vec<_>>();
 
-        // sort according to transaction priority
-        records.sort_by_key(|r| r.transaction_priority());
+        // sort according to transaction priority, and the tree nodes deepest first so that a
+        // commit which fails part way leaves no node referring to a child which was not written
+        records.sort_by_key(|r| {
+            let depth = match r {
+                DbRecord::TreeNode(node) => node.label.label_len,
+                _ => 0,
+            };
+            (r.transaction_priority(), std::cmp::Reverse(depth))
+        });
 
         // flush the trans log
         self.mods.clear();
@@ -298,6 +305,7 @@ mod tests {
         let azks = DbRecord::Azks(Azks {
             num_nodes: 0,
             latest_epoch: 0,
+            tree: None,
         });
         let node1 = DbRecord::TreeNode(TreeNodeWithPreviousValue::from_tree_node(TreeNode {
             label: NodeLabel::new(byte_arr_from_u64(0), 0),
@@ -321,6 +329,7 @@ mod tests {
         }));
         let value1 = DbRecord::ValueState(ValueState {
             username: AkdLabel::from("test"),
+            tree: None,
             epoch: 1,
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             version: 1,
@@ -328,6 +337,7 @@ mod tests {
         });
         let value2 = DbRecord::ValueState(ValueState {
             username: AkdLabel::from("test"),
+            tree: None,
             epoch: 2,
             label: NodeLabel::new(byte_arr_from_u64(1), 1),
             version: 2,
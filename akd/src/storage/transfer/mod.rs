@@ -0,0 +1,310 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Copying of a directory between storage backends.
+//!
+//! [copy_directory] copies every record held by a source [StorageUtil] to a target
+//! [Database](crate::storage::Database), reading the source a page at a time with
+//! [StorageUtil::batch_get_type_page_direct] so that directories larger than memory can be
+//! copied. Records are copied type by type, and the [Azks] is written last so that the target
+//! does not hold a directory until the copy is complete and verified.
+//!
+//! As records are copied, a [RecordDigest] of each type is accumulated: the number of records
+//! and an order-independent digest of their contents. Once every other record has been
+//! written, the records of the target are streamed back and their digests are checked against
+//! those of the copied records, which detects records that were lost or altered by the target
+//! (or between the attempts of a resumed copy). The target is therefore expected to be empty
+//! when the copy is started. The [Azks] is then written, and the root hash of the target is
+//! checked against that of the source.
+//!
+//! [RecordMac](crate::storage::types::RecordMac) records are only copied when the target
+//! does not compute its own MACs (see [crate::storage::Database::computes_record_macs]), as
+//! the MACs of the source are computed under its own key and would be written over those of
+//! the target.
+//!
+//! The progress of a copy is reported as a [CopyCheckpoint] after each page is written, and
+//! an interrupted copy is resumed from the last checkpoint. Since writes are upserts, any
+//! records written after that checkpoint are simply copied again. The source must not be
+//! published to while it is copied, which is detected when resuming.
+
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::errors::{AkdError, StorageError};
+use crate::storage::codec::{encode_record, Reader};
+use crate::storage::manager::StorageManager;
+use crate::storage::types::{DbRecord, StorageType};
+use crate::storage::{DbSetState, StorageUtil};
+use crate::{Azks, Digest};
+
+use akd_core::configuration::Configuration;
+use log::info;
+
+#[cfg(test)]
+mod tests;
+
+/// The types of records copied, in order. The [Azks] is copied after these.
+const COPIED_TYPES: [StorageType; 6] = [
+    StorageType::TreeNode,
+    StorageType::ValueState,
+    StorageType::EpochChangeset,
+    StorageType::PendingEpoch,
+    StorageType::RecordMac,
+    StorageType::SchemaVersion,
+];
+
+/// A summary of a set of records, which does not depend on the order the records are read in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordDigest {
+    /// The number of records
+    pub num_records: u64,
+    /// The sum modulo 2^256 of the hashes of the encoded records, as big-endian integers.
+    /// Unlike an XOR of the hashes, duplicated records do not cancel out.
+    pub digest: [u8; 32],
+}
+
+impl RecordDigest {
+    fn add(&mut self, record: &DbRecord) {
+        let mut encoded = vec![];
+        encode_record(&mut encoded, record);
+        let hash = blake3::hash(&encoded);
+        let mut carry = 0u16;
+        for (byte, hash_byte) in self.digest.iter_mut().zip(hash.as_bytes()).rev() {
+            let sum = *byte as u16 + *hash_byte as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+        self.num_records += 1;
+    }
+}
+
+/// The progress of a [copy_directory], from which an interrupted copy can be resumed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyCheckpoint {
+    /// The epoch of the source directory being copied
+    pub epoch: u64,
+    /// The type of the records being copied
+    pub storage_type: StorageType,
+    /// The full binary id of the last record copied of the type, [None] if none have been
+    pub after: Option<Vec<u8>>,
+    /// The number of records copied
+    pub num_records_copied: u64,
+    /// The digests of the records copied of each type, in the order they are copied
+    pub digests: [RecordDigest; COPIED_TYPES.len()],
+}
+
+impl CopyCheckpoint {
+    fn new(epoch: u64) -> Self {
+        Self {
+            epoch,
+            storage_type: COPIED_TYPES[0],
+            after: None,
+            num_records_copied: 0,
+            digests: Default::default(),
+        }
+    }
+
+    /// Encode the checkpoint, to be persisted between attempts of a copy
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.epoch.to_be_bytes().to_vec();
+        out.push(self.storage_type as u8);
+        out.extend_from_slice(&self.num_records_copied.to_be_bytes());
+        for digest in self.digests.iter() {
+            out.extend_from_slice(&digest.num_records.to_be_bytes());
+            out.extend_from_slice(&digest.digest);
+        }
+        if let Some(after) = &self.after {
+            out.extend_from_slice(after);
+        }
+        out
+    }
+
+    /// Decode a checkpoint encoded with [CopyCheckpoint::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        let mut reader = Reader::new(bytes);
+        let epoch = reader.u64()?;
+        let storage_type = reader.u8()?;
+        let storage_type = COPIED_TYPES
+            .into_iter()
+            .find(|t| *t as u8 == storage_type)
+            .ok_or_else(|| {
+                StorageError::Other(format!(
+                    "Unknown storage type {storage_type} in the copy checkpoint"
+                ))
+            })?;
+        let num_records_copied = reader.u64()?;
+        let mut digests = [RecordDigest::default(); COPIED_TYPES.len()];
+        for digest in digests.iter_mut() {
+            digest.num_records = reader.u64()?;
+            digest.digest.copy_from_slice(reader.bytes(32)?);
+        }
+        let after = match reader.remaining() {
+            [] => None,
+            after => Some(after.to_vec()),
+        };
+        Ok(Self {
+            epoch,
+            storage_type,
+            after,
+            num_records_copied,
+            digests,
+        })
+    }
+}
+
+/// The result of a completed [copy_directory]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyReport {
+    /// The epoch of the copied directory
+    pub epoch: u64,
+    /// The root hash of the copied directory
+    pub root_hash: Digest,
+    /// The number of records copied, including those copied prior to resuming
+    pub num_records_copied: u64,
+}
+
+/// Copy the directory held by the `source` storage to the `target`, reading `page_size`
+/// records at a time. The copy resumes from the checkpoint `resume_from` if provided, and
+/// `on_progress` is called with the checkpoint of the copy after each page is written. See
+/// the [module documentation](self).
+///
+/// Returns an error if the source holds no directory, if it has been published to since the
+/// checkpoint, or if the records read back from the target do not match those copied.
+pub async fn copy_directory<TC: Configuration, Src: StorageUtil, Dst: StorageUtil>(
+    source: &StorageManager<Src>,
+    target: &StorageManager<Dst>,
+    page_size: usize,
+    resume_from: Option<CopyCheckpoint>,
+    mut on_progress: impl FnMut(&CopyCheckpoint) + Send,
+) -> Result<CopyReport, AkdError> {
+    let azks = match source.get_direct::<Azks>(&DEFAULT_AZKS_KEY).await? {
+        DbRecord::Azks(azks) => azks,
+        _ => {
+            return Err(AkdError::Storage(StorageError::Other(
+                "Unexpected record type for the AZKS".to_string(),
+            )))
+        }
+    };
+    let epoch = azks.get_latest_epoch();
+    let root_hash = azks.get_root_hash::<TC, _>(source).await?;
+
+    let mut checkpoint = match resume_from {
+        Some(checkpoint) if checkpoint.epoch != epoch => {
+            return Err(AkdError::Storage(StorageError::Other(format!(
+                "The copy being resumed is of epoch {}, but the source directory is at epoch {epoch}",
+                checkpoint.epoch
+            ))));
+        }
+        Some(checkpoint) => {
+            info!(
+                "Resuming the copy of epoch {epoch} after {} records",
+                checkpoint.num_records_copied
+            );
+            checkpoint
+        }
+        None => CopyCheckpoint::new(epoch),
+    };
+
+    let start = COPIED_TYPES
+        .iter()
+        .position(|storage_type| *storage_type == checkpoint.storage_type)
+        .unwrap_or_default();
+    let source_db = source.database();
+    let target_db = target.database();
+    let is_copied = |storage_type: &StorageType| {
+        *storage_type != StorageType::RecordMac || !target_db.computes_record_macs()
+    };
+    for (index, storage_type) in COPIED_TYPES.iter().copied().enumerate().skip(start) {
+        if !is_copied(&storage_type) {
+            continue;
+        }
+        if checkpoint.storage_type != storage_type {
+            checkpoint.storage_type = storage_type;
+            checkpoint.after = None;
+        }
+        info!("Copying the {storage_type:?} records");
+        loop {
+            let page = source_db
+                .batch_get_storage_type_page_direct(
+                    storage_type,
+                    checkpoint.after.as_deref(),
+                    page_size,
+                )
+                .await?;
+            let last = match page.last() {
+                Some(last) => last.get_full_binary_id(),
+                None => break,
+            };
+            checkpoint.num_records_copied += page.len() as u64;
+            for record in page.iter() {
+                checkpoint.digests[index].add(record);
+            }
+            target_db.batch_set(page, DbSetState::General).await?;
+            checkpoint.after = Some(last);
+            on_progress(&checkpoint);
+        }
+    }
+
+    info!(
+        "Copied {} records, checking the records of epoch {epoch} in the target",
+        checkpoint.num_records_copied
+    );
+
+    // the records were written directly to the target, bypassing any cache
+    target.flush_cache().await;
+    for (storage_type, copied) in COPIED_TYPES
+        .iter()
+        .copied()
+        .zip(checkpoint.digests.iter())
+        .filter(|(storage_type, _)| is_copied(storage_type))
+    {
+        let mut written = RecordDigest::default();
+        let mut after = None;
+        loop {
+            let page = target_db
+                .batch_get_storage_type_page_direct(storage_type, after.as_deref(), page_size)
+                .await?;
+            let last = match page.last() {
+                Some(last) => last.get_full_binary_id(),
+                None => break,
+            };
+            for record in page.iter() {
+                written.add(record);
+            }
+            after = Some(last);
+        }
+        if written != *copied {
+            return Err(AkdError::Storage(StorageError::IntegrityViolation(
+                format!(
+                    "The {storage_type:?} records of the copy of epoch {epoch} do not match the \
+                    source: {} records were copied, and {} differing records were read back",
+                    copied.num_records, written.num_records
+                ),
+            )));
+        }
+    }
+
+    target_db.set(DbRecord::Azks(azks.clone())).await?;
+    checkpoint.num_records_copied += 1;
+    let target_azks = match target_db.get::<Azks>(&DEFAULT_AZKS_KEY).await? {
+        DbRecord::Azks(target_azks) if target_azks == azks => target_azks,
+        _ => {
+            return Err(AkdError::Storage(StorageError::IntegrityViolation(
+                format!("The AZKS of the copy of epoch {epoch} does not match the source"),
+            )))
+        }
+    };
+    if target_azks.get_root_hash::<TC, _>(target).await? != root_hash {
+        return Err(AkdError::Storage(StorageError::IntegrityViolation(
+            format!("The root hash of the copy of epoch {epoch} does not match the source"),
+        )));
+    }
+
+    Ok(CopyReport {
+        epoch,
+        root_hash,
+        num_records_copied: checkpoint.num_records_copied,
+    })
+}
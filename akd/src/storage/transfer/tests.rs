@@ -0,0 +1,244 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Storage transfer tests
+
+use super::*;
+use crate::ecvrf::HardCodedAkdVRF;
+use crate::storage::faulty::{FaultConfig, FaultTarget, FaultyDatabase};
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::Database;
+use crate::tree_node::TreeNodeWithPreviousValue;
+use crate::{test_config, AkdLabel, AkdValue, Directory, HistoryParams};
+
+async fn source_directory<TC: Configuration>(
+) -> Result<StorageManager<AsyncInMemoryDatabase>, AkdError> {
+    let storage = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let akd = Directory::<TC, _, _>::new(storage.clone(), HardCodedAkdVRF {}).await?;
+    for epoch in 1..=3 {
+        akd.publish(
+            (0..10)
+                .map(|user| {
+                    (
+                        AkdLabel(format!("user{user}").into_bytes()),
+                        AkdValue(format!("value{epoch}").into_bytes()),
+                    )
+                })
+                .collect(),
+        )
+        .await?;
+    }
+    Ok(storage)
+}
+
+#[test]
+fn test_copy_checkpoint_encoding() -> Result<(), StorageError> {
+    let mut checkpoint = CopyCheckpoint::new(12);
+    assert_eq!(
+        checkpoint,
+        CopyCheckpoint::from_bytes(&checkpoint.to_bytes())?
+    );
+
+    checkpoint.storage_type = StorageType::ValueState;
+    checkpoint.after = Some(vec![4, 1, 2, 3]);
+    checkpoint.num_records_copied = 1234;
+    checkpoint.digests[0].add(&DbRecord::Azks(DbRecord::build_azks(3, 4)));
+    assert_eq!(
+        checkpoint,
+        CopyCheckpoint::from_bytes(&checkpoint.to_bytes())?
+    );
+
+    assert!(CopyCheckpoint::from_bytes(&[0u8; 12]).is_err());
+    assert!(CopyCheckpoint::from_bytes(&checkpoint.to_bytes()[..40]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_record_digest() {
+    let azks = DbRecord::Azks(DbRecord::build_azks(3, 4));
+    let other = DbRecord::Azks(DbRecord::build_azks(3, 5));
+    let digest = |records: &[&DbRecord]| {
+        let mut digest = RecordDigest::default();
+        for record in records.iter() {
+            digest.add(record);
+        }
+        digest.digest
+    };
+
+    // the digest does not depend on the order of the records
+    assert_eq!(digest(&[&azks, &other]), digest(&[&other, &azks]));
+    // and duplicated records do not cancel out
+    assert_ne!(digest(&[]), digest(&[&azks, &azks]));
+    assert_ne!(digest(&[&other]), digest(&[&azks, &azks, &other]));
+}
+
+test_config!(test_copy_directory);
+async fn test_copy_directory<TC: Configuration>() -> Result<(), AkdError> {
+    let source = source_directory::<TC>().await?;
+    let target = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+
+    let mut checkpoints = vec![];
+    let report = copy_directory::<TC, _, _>(&source, &target, 4, None, |checkpoint| {
+        checkpoints.push(checkpoint.clone())
+    })
+    .await?;
+
+    let num_records = source.database().batch_get_all_direct().await?.len() as u64;
+    assert_eq!(num_records, report.num_records_copied);
+    assert_eq!(3, report.epoch);
+    assert!(checkpoints
+        .windows(2)
+        .all(|pair| pair[0].num_records_copied < pair[1].num_records_copied));
+
+    // the copy serves the same directory
+    let akd = Directory::<TC, _, _>::new(source, HardCodedAkdVRF {}).await?;
+    let copy = Directory::<TC, _, _>::new(target, HardCodedAkdVRF {}).await?;
+    let epoch_hash = akd.get_epoch_hash().await?;
+    assert_eq!(epoch_hash, copy.get_epoch_hash().await?);
+    assert_eq!(report.root_hash, epoch_hash.1);
+    let label = AkdLabel::from("user3");
+    assert_eq!(
+        akd.key_history(&label, HistoryParams::default()).await?,
+        copy.key_history(&label, HistoryParams::default()).await?
+    );
+    Ok(())
+}
+
+test_config!(test_copy_directory_keeps_target_macs);
+async fn test_copy_directory_keeps_target_macs<TC: Configuration>() -> Result<(), AkdError> {
+    use crate::storage::integrity::{IntegrityDatabase, StaticMacKeyProvider};
+
+    let source = StorageManager::new_no_cache(IntegrityDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        StaticMacKeyProvider::new([7u8; 32]),
+    ));
+    let akd = Directory::<TC, _, _>::new(source.clone(), HardCodedAkdVRF {}).await?;
+    akd.publish(vec![(AkdLabel::from("user"), AkdValue::from("value"))])
+        .await?;
+
+    // the target computes its MACs under its own key, which the MACs of the source would
+    // otherwise be written over
+    let target = StorageManager::new_no_cache(IntegrityDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        StaticMacKeyProvider::new([8u8; 32]),
+    ));
+    copy_directory::<TC, _, _>(&source, &target, 4, None, |_| {}).await?;
+
+    let copy = Directory::<TC, _, _>::new(target, HardCodedAkdVRF {}).await?;
+    assert_eq!(akd.get_epoch_hash().await?, copy.get_epoch_hash().await?);
+    let label = AkdLabel::from("user");
+    assert_eq!(
+        akd.key_history(&label, HistoryParams::default()).await?,
+        copy.key_history(&label, HistoryParams::default()).await?
+    );
+    Ok(())
+}
+
+test_config!(test_copy_directory_resumes);
+async fn test_copy_directory_resumes<TC: Configuration>() -> Result<(), AkdError> {
+    let source = source_directory::<TC>().await?;
+    let faulty = FaultyDatabase::new(
+        AsyncInMemoryDatabase::new(),
+        5,
+        FaultConfig::errors(FaultTarget::Writes, 0.2),
+    );
+    let target = StorageManager::new_no_cache(faulty.clone());
+
+    let mut checkpoint = None;
+    let mut attempts = 0;
+    let report = loop {
+        attempts += 1;
+        assert!(attempts < 100, "the copy failed to complete");
+        let mut latest = checkpoint.clone();
+        let result = copy_directory::<TC, _, _>(&source, &target, 3, checkpoint, |progress| {
+            latest = Some(progress.clone())
+        })
+        .await;
+        match result {
+            Ok(report) => break report,
+            Err(AkdError::Storage(StorageError::Connection(_))) => checkpoint = latest,
+            Err(other) => return Err(other),
+        }
+    };
+    assert!(attempts > 1);
+    assert!(faulty.num_injected_faults() > 0);
+
+    let akd = Directory::<TC, _, _>::new(source, HardCodedAkdVRF {}).await?;
+    assert_eq!(akd.get_epoch_hash().await?.1, report.root_hash);
+    Ok(())
+}
+
+test_config!(test_copy_directory_detects_altered_records);
+async fn test_copy_directory_detects_altered_records<TC: Configuration>() -> Result<(), AkdError> {
+    let source = source_directory::<TC>().await?;
+    let db = AsyncInMemoryDatabase::new();
+    let target = StorageManager::new_no_cache(db.clone());
+
+    // a copy interrupted once the tree nodes were copied
+    let faulty = FaultyDatabase::new(db.clone(), 3, FaultConfig::default());
+    let interrupted = StorageManager::new_no_cache(faulty.clone());
+    let mut checkpoint = None;
+    let result = copy_directory::<TC, _, _>(&source, &interrupted, 1000, None, |progress| {
+        if progress.storage_type == StorageType::TreeNode {
+            checkpoint = Some(progress.clone());
+            faulty.set_config(FaultConfig::errors(FaultTarget::Writes, 1.0));
+        }
+    })
+    .await;
+    assert!(result.is_err());
+    let checkpoint = checkpoint.expect("The tree nodes should have been copied");
+
+    // one of the copied records is altered in the target before the copy is resumed
+    let node = db
+        .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+        .await?
+        .into_iter()
+        .find_map(|record| match record {
+            DbRecord::TreeNode(node) => Some(node),
+            _ => None,
+        })
+        .expect("The target should hold tree nodes");
+    let mut altered = node.clone();
+    altered.latest_node.last_epoch += 1;
+    db.set(DbRecord::TreeNode(altered)).await?;
+
+    let result = copy_directory::<TC, _, _>(&source, &target, 1000, Some(checkpoint), |_| {}).await;
+    assert!(matches!(
+        result,
+        Err(AkdError::Storage(StorageError::IntegrityViolation(_)))
+    ));
+    // the target holds no directory, as the AZKS is only written once the copy is verified
+    assert!(matches!(
+        db.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::NotFound(_))
+    ));
+    Ok(())
+}
+
+test_config!(test_copy_directory_rejects_changed_source);
+async fn test_copy_directory_rejects_changed_source<TC: Configuration>() -> Result<(), AkdError> {
+    let source = source_directory::<TC>().await?;
+    let target = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+
+    // the checkpoint of a copy started prior to the last publish
+    let mut checkpoint = CopyCheckpoint::new(2);
+    checkpoint.num_records_copied = 10;
+    let result = copy_directory::<TC, _, _>(&source, &target, 4, Some(checkpoint), |_| {}).await;
+    assert!(matches!(
+        result,
+        Err(AkdError::Storage(StorageError::Other(_)))
+    ));
+
+    // an empty source holds no directory to copy
+    let empty = StorageManager::new_no_cache(AsyncInMemoryDatabase::new());
+    let result = copy_directory::<TC, _, _>(&empty, &target, 4, None, |_| {}).await;
+    assert!(matches!(
+        result,
+        Err(AkdError::Storage(StorageError::NotFound(_)))
+    ));
+    Ok(())
+}
@@ -0,0 +1,507 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Several independent trees held in a single storage backend.
+//!
+//! The [TreeScopedDatabase] wraps a [Database] shared by several directories, and presents
+//! one of its trees, identified by a [TreeId], as if it were the only tree of the backend.
+//! Each directory is then created over its own [TreeScopedDatabase] of the shared backend,
+//! i.e. one per customer, without the need for separate schemas.
+//!
+//! Records are placed in the tree as they are written, and the keys of the records read are
+//! placed in the tree in the same way:
+//! * The [crate::Azks], tree nodes, [ValueState]s, [EpochChangeset]s and [PendingEpoch] of
+//!   the tree carry its identifier, which is part of their keys
+//! * The user data of the tree is retrieved by passing its identifier to the user data
+//!   queries of the [Database]
+//! * The [RecordMac]s of an [crate::storage::integrity::IntegrityDatabase] wrapping the
+//!   [TreeScopedDatabase] are keyed by the keys of the records in the tree
+//! * The [SchemaVersion] is shared by the trees of the backend
+//!
+//! The records read from the backend are returned without the tree identifier, so that the
+//! layers above are unaware of the other trees of the backend.
+//!
+//! The keys of the records of a tree begin with a prefix of the tree, so that they're adjacent
+//! in a backend paging through records in the order of their keys. A page of the tree
+//! ([StorageUtil::batch_get_type_page_direct]) is read by seeking to the first key of the
+//! tree, and ends at the first record of the backend outside of it, rather than reading the
+//! records of the other trees. Every record of the tree
+//! ([StorageUtil::batch_get_type_direct]) is read in pages in the same way.
+//!
+//! [SchemaVersion]: crate::storage::types::SchemaVersion
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    tree_scoped_key_prefix, DbRecord, EpochChangeset, EpochChangesetKey, KeyData, PendingEpoch,
+    RecordMac, RecordMacKey, SchemaVersion, StorageType, TreeId, ValueState, ValueStateKey,
+    ValueStateRetrievalFlag, DEFAULT_SCHEMA_VERSION_KEY, TREE_SCOPED_KEY_FLAG,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::tree_node::{NodeKey, TreeNodeWithPreviousValue};
+use crate::{AkdLabel, AkdValue, Azks, NodeLabel};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+/// The number of records read at a time when reading every record of the tree
+const DIRECT_PAGE_SIZE: usize = 1000;
+
+/// A range of full binary keys in the backend, which start with the prefix
+struct KeyRange {
+    /// The first key which may be in the range
+    first: Vec<u8>,
+    prefix: Vec<u8>,
+}
+
+/// A [Database] presenting one of the trees held in a storage backend shared by several
+/// trees. See the [module documentation](self).
+#[derive(Clone)]
+pub struct TreeScopedDatabase<Db: Database> {
+    db: Db,
+    tree: TreeId,
+}
+
+impl<Db: Database> TreeScopedDatabase<Db> {
+    /// Wrap the database, presenting the tree with the given identifier
+    pub fn new(db: Db, tree: TreeId) -> Self {
+        Self { db, tree }
+    }
+
+    /// Retrieve a reference to the underlying database, which holds the records of every tree
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    /// The identifier of the tree presented
+    pub fn tree(&self) -> TreeId {
+        self.tree
+    }
+
+    /// The full binary key in the backend of the record with the given full binary key in the tree
+    fn scope_binary_key(&self, bin: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let storage_type = match bin.first().copied().and_then(StorageType::from_u8) {
+            Some(storage_type) => storage_type,
+            None => {
+                return Err(StorageError::Other(format!(
+                    "Not a record key of a tree {bin:?}"
+                )))
+            }
+        };
+        let scoped = match storage_type {
+            StorageType::Azks => Azks::get_full_binary_key_id(&Some(self.tree)),
+            StorageType::TreeNode => {
+                let key = TreeNodeWithPreviousValue::key_from_full_binary(bin)
+                    .map_err(StorageError::Other)?;
+                TreeNodeWithPreviousValue::get_full_binary_key_id(&NodeKey(key.0, Some(self.tree)))
+            }
+            StorageType::ValueState => {
+                let key = ValueState::key_from_full_binary(bin).map_err(StorageError::Other)?;
+                ValueState::get_full_binary_key_id(&key.with_tree(Some(self.tree)))
+            }
+            StorageType::EpochChangeset => {
+                let key = EpochChangeset::key_from_full_binary(bin).map_err(StorageError::Other)?;
+                EpochChangeset::get_full_binary_key_id(&EpochChangesetKey(
+                    key.0,
+                    key.1,
+                    Some(self.tree),
+                ))
+            }
+            StorageType::RecordMac => {
+                let key = RecordMac::key_from_full_binary(bin).map_err(StorageError::Other)?;
+                RecordMac::get_full_binary_key_id(&RecordMacKey(self.scope_binary_key(&key.0)?))
+            }
+            StorageType::PendingEpoch => PendingEpoch::get_full_binary_key_id(&Some(self.tree)),
+            StorageType::SchemaVersion => bin.to_vec(),
+        };
+        Ok(scoped)
+    }
+
+    /// The full binary key in the tree of the record with the given full binary key in the
+    /// backend, or [None] if the record is not held in the tree
+    fn unscope_binary_key(&self, bin: &[u8]) -> Option<Vec<u8>> {
+        let in_tree = |tree: Option<TreeId>| tree == Some(self.tree);
+        let storage_type = StorageType::from_u8(bin.first()? & !TREE_SCOPED_KEY_FLAG)?;
+        let unscoped = match storage_type {
+            StorageType::Azks => {
+                let tree = Azks::key_from_full_binary(bin).ok()?;
+                in_tree(tree).then(|| Azks::get_full_binary_key_id(&None))?
+            }
+            StorageType::TreeNode => {
+                let key = TreeNodeWithPreviousValue::key_from_full_binary(bin).ok()?;
+                in_tree(key.1).then(|| {
+                    TreeNodeWithPreviousValue::get_full_binary_key_id(&NodeKey::new(key.0))
+                })?
+            }
+            StorageType::ValueState => {
+                let key = ValueState::key_from_full_binary(bin).ok()?;
+                in_tree(key.tree())
+                    .then(|| ValueState::get_full_binary_key_id(&key.with_tree(None)))?
+            }
+            StorageType::EpochChangeset => {
+                let key = EpochChangeset::key_from_full_binary(bin).ok()?;
+                in_tree(key.2).then(|| {
+                    EpochChangeset::get_full_binary_key_id(&EpochChangesetKey(key.0, key.1, None))
+                })?
+            }
+            StorageType::RecordMac => {
+                let key = RecordMac::key_from_full_binary(bin).ok()?;
+                RecordMac::get_full_binary_key_id(&RecordMacKey(self.unscope_binary_key(&key.0)?))
+            }
+            StorageType::PendingEpoch => {
+                let tree = PendingEpoch::key_from_full_binary(bin).ok()?;
+                in_tree(tree).then(|| PendingEpoch::get_full_binary_key_id(&None))?
+            }
+            StorageType::SchemaVersion => bin.to_vec(),
+        };
+        Some(unscoped)
+    }
+
+    /// The ranges of the full binary keys in the backend of the records of the type held in
+    /// the tree. The records of a type are adjacent in the backend, except for [RecordMac]s,
+    /// whose keys follow those of the records they cover.
+    fn key_ranges(&self, storage_type: StorageType) -> Vec<KeyRange> {
+        let tree = Some(self.tree);
+        match storage_type {
+            StorageType::Azks => {
+                let key = Azks::get_full_binary_key_id(&tree);
+                vec![KeyRange {
+                    first: key.clone(),
+                    prefix: key,
+                }]
+            }
+            StorageType::TreeNode => vec![KeyRange {
+                first: TreeNodeWithPreviousValue::get_full_binary_key_id(&NodeKey(
+                    NodeLabel::new([0u8; 32], 0),
+                    tree,
+                )),
+                prefix: tree_scoped_key_prefix(StorageType::TreeNode, tree),
+            }],
+            StorageType::ValueState => vec![KeyRange {
+                // the username of a value state is not empty
+                first: ValueState::get_full_binary_key_id(&ValueStateKey(vec![0u8], 0, tree)),
+                prefix: tree_scoped_key_prefix(StorageType::ValueState, tree),
+            }],
+            StorageType::EpochChangeset => vec![KeyRange {
+                first: EpochChangeset::get_full_binary_key_id(&EpochChangesetKey(0, 0, tree)),
+                prefix: tree_scoped_key_prefix(StorageType::EpochChangeset, tree),
+            }],
+            StorageType::PendingEpoch => {
+                let key = PendingEpoch::get_full_binary_key_id(&tree);
+                vec![KeyRange {
+                    first: key.clone(),
+                    prefix: key,
+                }]
+            }
+            StorageType::SchemaVersion => {
+                let key = SchemaVersion::get_full_binary_key_id(&DEFAULT_SCHEMA_VERSION_KEY);
+                vec![KeyRange {
+                    first: key.clone(),
+                    prefix: key,
+                }]
+            }
+            StorageType::RecordMac => [
+                StorageType::Azks,
+                StorageType::TreeNode,
+                StorageType::ValueState,
+                StorageType::EpochChangeset,
+                StorageType::SchemaVersion,
+                StorageType::PendingEpoch,
+            ]
+            .into_iter()
+            .flat_map(|storage_type| self.key_ranges(storage_type))
+            .map(|range| KeyRange {
+                first: RecordMac::get_full_binary_key_id(&RecordMacKey(range.first)),
+                prefix: RecordMac::get_full_binary_key_id(&RecordMacKey(range.prefix)),
+            })
+            .collect(),
+        }
+    }
+
+    fn scope_key<St: Storable>(
+        &self,
+        key: &St::StorageKey,
+    ) -> Result<St::StorageKey, StorageError> {
+        St::key_from_full_binary(&self.scope_binary_key(&St::get_full_binary_key_id(key))?)
+            .map_err(StorageError::Other)
+    }
+
+    fn scope_record(&self, record: DbRecord) -> Result<DbRecord, StorageError> {
+        let scoped = match record {
+            DbRecord::Azks(azks) => DbRecord::Azks(Azks {
+                tree: Some(self.tree),
+                ..azks
+            }),
+            DbRecord::TreeNode(node) => DbRecord::TreeNode(TreeNodeWithPreviousValue {
+                tree: Some(self.tree),
+                ..node
+            }),
+            DbRecord::ValueState(state) => DbRecord::ValueState(ValueState {
+                tree: Some(self.tree),
+                ..state
+            }),
+            DbRecord::EpochChangeset(changeset) => DbRecord::EpochChangeset(EpochChangeset {
+                tree: Some(self.tree),
+                ..changeset
+            }),
+            DbRecord::RecordMac(mac) => DbRecord::RecordMac(RecordMac {
+                record_key: self.scope_binary_key(&mac.record_key)?,
+                ..mac
+            }),
+            DbRecord::PendingEpoch(pending) => DbRecord::PendingEpoch(PendingEpoch {
+                tree: Some(self.tree),
+                ..pending
+            }),
+            DbRecord::SchemaVersion(version) => DbRecord::SchemaVersion(version),
+        };
+        Ok(scoped)
+    }
+
+    /// The record as held in the tree, or [None] if the record is not held in the tree
+    fn unscope_record(&self, record: DbRecord) -> Option<DbRecord> {
+        let in_tree = record.tree() == Some(self.tree);
+        let unscoped = match record {
+            DbRecord::Azks(azks) if in_tree => DbRecord::Azks(Azks { tree: None, ..azks }),
+            DbRecord::TreeNode(node) if in_tree => {
+                DbRecord::TreeNode(TreeNodeWithPreviousValue { tree: None, ..node })
+            }
+            DbRecord::EpochChangeset(changeset) if in_tree => {
+                DbRecord::EpochChangeset(EpochChangeset {
+                    tree: None,
+                    ..changeset
+                })
+            }
+            DbRecord::PendingEpoch(pending) if in_tree => DbRecord::PendingEpoch(PendingEpoch {
+                tree: None,
+                ..pending
+            }),
+            DbRecord::ValueState(state) if in_tree => DbRecord::ValueState(ValueState {
+                tree: None,
+                ..state
+            }),
+            DbRecord::RecordMac(mac) => DbRecord::RecordMac(RecordMac {
+                record_key: self.unscope_binary_key(&mac.record_key)?,
+                ..mac
+            }),
+            DbRecord::SchemaVersion(version) => DbRecord::SchemaVersion(version),
+            // the record is held in another tree, or in a backend holding a single tree
+            _ => return None,
+        };
+        Some(unscoped)
+    }
+
+    fn unscope_state(&self, state: ValueState) -> ValueState {
+        ValueState {
+            tree: None,
+            ..state
+        }
+    }
+
+    fn unscope_records(&self, records: Vec<DbRecord>) -> Vec<DbRecord> {
+        records
+            .into_iter()
+            .filter_map(|record| self.unscope_record(record))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<Db: Database> Database for TreeScopedDatabase<Db> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.db.set(self.scope_record(record)?).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        let records = records
+            .into_iter()
+            .map(|record| self.scope_record(record))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.db.batch_set(records, state).await
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        let record = self.db.get::<St>(&self.scope_key::<St>(id)?).await?;
+        self.unscope_record(record).ok_or_else(|| {
+            StorageError::NotFound(format!("{:?} {:?} in {:?}", St::data_type(), id, self.tree))
+        })
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        let keys = ids
+            .iter()
+            .map(|id| self.scope_key::<St>(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.unscope_records(self.db.batch_get::<St>(&keys).await?))
+    }
+
+    // the user data is that of the tree presented, which is the only tree seen through the
+    // TreeScopedDatabase
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        _tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        let data = self.db.get_user_data(username, Some(self.tree)).await?;
+        Ok(KeyData {
+            states: data
+                .states
+                .into_iter()
+                .map(|state| self.unscope_state(state))
+                .collect(),
+        })
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        _tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        let state = self
+            .db
+            .get_user_state(username, flag, Some(self.tree))
+            .await?;
+        Ok(self.unscope_state(state))
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        _tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.db
+            .get_user_state_versions(usernames, flag, Some(self.tree))
+            .await
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        _tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        Ok(self
+            .db
+            .get_user_states(usernames, flag, Some(self.tree))
+            .await?
+            .into_iter()
+            .map(|(username, state)| (username, self.unscope_state(state)))
+            .collect())
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        // the feed of the backend carries the epochs of every tree, so readers of the tree
+        // poll its azks for changes instead
+        None
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        self.db.computes_record_macs()
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil> StorageUtil for TreeScopedDatabase<Db> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        // the records are read from the key ranges of the tree, rather than reading those of
+        // every tree of the backend
+        self.stream_type_direct::<St>(DIRECT_PAGE_SIZE)
+            .try_collect()
+            .await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.stream_all_direct(DIRECT_PAGE_SIZE).try_collect().await
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        if St::data_type() == StorageType::SchemaVersion {
+            return self.db.batch_get_type_page_direct::<St>(after, limit).await;
+        }
+
+        // the ranges of the tree are paged through in turn, resuming in the range of the cursor
+        let ranges = self.key_ranges(St::data_type());
+        let (mut index, mut cursor) = match after {
+            None => (0, None),
+            Some(after) => {
+                let scoped = self.scope_binary_key(after)?;
+                match ranges
+                    .iter()
+                    .position(|range| scoped.starts_with(&range.prefix))
+                {
+                    Some(index) => (index, Some(scoped)),
+                    None => {
+                        return Err(StorageError::Other(format!(
+                            "The cursor {after:?} is not a key of {:?} in {:?}",
+                            St::data_type(),
+                            self.tree
+                        )))
+                    }
+                }
+            }
+        };
+
+        let mut records = Vec::new();
+        while index < ranges.len() && records.len() < limit {
+            let range = &ranges[index];
+            let cursor = match cursor.take() {
+                Some(cursor) => cursor,
+                None => {
+                    // the cursor is exclusive, so the range starts with a read of its first key
+                    let first =
+                        St::key_from_full_binary(&range.first).map_err(StorageError::Other)?;
+                    match self.db.get::<St>(&first).await {
+                        Ok(record) => records.extend(self.unscope_record(record)),
+                        Err(StorageError::NotFound(_)) => {}
+                        Err(err) => return Err(err),
+                    }
+                    range.first.clone()
+                }
+            };
+            let requested = limit - records.len();
+            if requested == 0 {
+                break;
+            }
+
+            let page = self
+                .db
+                .batch_get_type_page_direct::<St>(Some(&cursor), requested)
+                .await?;
+            let mut exhausted = page.len() < requested;
+            for record in page {
+                // the records of the range are adjacent, so it ends at the first record outside it
+                if !record.get_full_binary_id().starts_with(&range.prefix) {
+                    exhausted = true;
+                    break;
+                }
+                records.extend(self.unscope_record(record));
+            }
+            if !exhausted {
+                break;
+            }
+            index += 1;
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Tests of several trees held in a single storage backend
+
+use super::*;
+use crate::append_only_zks::DEFAULT_AZKS_KEY;
+use crate::storage::integrity::{IntegrityDatabase, StaticMacKeyProvider};
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::tests::{populate_database, populated_records, user_state};
+use crate::NodeLabel;
+
+use futures::TryStreamExt;
+
+fn tree_node(label: NodeLabel, last_epoch: u64) -> TreeNodeWithPreviousValue {
+    DbRecord::build_tree_node_with_previous_value(
+        label.label_val,
+        label.label_len,
+        last_epoch,
+        last_epoch,
+        [0u8; 32],
+        0,
+        1,
+        None,
+        None,
+        crate::hash::EMPTY_DIGEST,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+}
+
+/// Two trees of a backend, each populated with the [populated_records]
+async fn populated_trees() -> Result<
+    (
+        AsyncInMemoryDatabase,
+        TreeScopedDatabase<AsyncInMemoryDatabase>,
+        TreeScopedDatabase<AsyncInMemoryDatabase>,
+    ),
+    StorageError,
+> {
+    let db = AsyncInMemoryDatabase::new();
+    let first = TreeScopedDatabase::new(db.clone(), TreeId(1));
+    let second = TreeScopedDatabase::new(db.clone(), TreeId(2));
+    populate_database(&first).await?;
+    populate_database(&second).await?;
+    Ok((db, first, second))
+}
+
+#[test]
+fn test_tree_scoped_keys() {
+    let label = NodeLabel::new([3u8; 32], 256);
+    // the keys of a backend holding a single tree are unchanged
+    assert_eq!(
+        vec![1u8, 1u8],
+        Azks::get_full_binary_key_id(&DEFAULT_AZKS_KEY)
+    );
+    assert_eq!(
+        37,
+        TreeNodeWithPreviousValue::get_full_binary_key_id(&NodeKey::new(label)).len()
+    );
+    assert_eq!(
+        [&[4u8][..], b"alice", &1u64.to_be_bytes()].concat(),
+        ValueState::get_full_binary_key_id(&ValueStateKey::new(b"alice".to_vec(), 1))
+    );
+
+    for tree in [None, Some(TreeId(0)), Some(TreeId(u64::MAX))] {
+        let bin = Azks::get_full_binary_key_id(&tree);
+        assert_eq!(Ok(tree), Azks::key_from_full_binary(&bin));
+        let key = NodeKey(label, tree);
+        let bin = TreeNodeWithPreviousValue::get_full_binary_key_id(&key);
+        assert_eq!(
+            Ok(key),
+            TreeNodeWithPreviousValue::key_from_full_binary(&bin)
+        );
+        let key = ValueStateKey::new(b"alice".to_vec(), 5).with_tree(tree);
+        let bin = ValueState::get_full_binary_key_id(&key);
+        assert_eq!(Ok(key), ValueState::key_from_full_binary(&bin));
+        let key = EpochChangesetKey(7, 3, tree);
+        let bin = EpochChangeset::get_full_binary_key_id(&key);
+        assert_eq!(Ok(key), EpochChangeset::key_from_full_binary(&bin));
+        let bin = PendingEpoch::get_full_binary_key_id(&tree);
+        assert_eq!(Ok(tree), PendingEpoch::key_from_full_binary(&bin));
+    }
+    assert_ne!(
+        Azks::get_full_binary_key_id(&None),
+        Azks::get_full_binary_key_id(&Some(TreeId(1)))
+    );
+    assert!(Azks::key_from_full_binary(&[StorageType::TreeNode as u8]).is_err());
+}
+
+#[tokio::test]
+async fn test_trees_are_independent() -> Result<(), StorageError> {
+    let (db, first, second) = populated_trees().await?;
+    let alice = AkdLabel::from("alice");
+
+    // each tree holds the records written to it, under the same keys
+    let mut expected = populated_records();
+    expected.sort();
+    for tree in [&first, &second] {
+        let mut records = tree.batch_get_all_direct().await?;
+        records.sort();
+        assert_eq!(expected, records);
+    }
+    // which are held separately in the backend
+    assert_eq!(2 * expected.len(), db.batch_get_all_direct().await?.len());
+
+    second
+        .set(DbRecord::ValueState(user_state("alice", "other key", 3, 3)))
+        .await?;
+    second
+        .set(DbRecord::Azks(DbRecord::build_azks(3, 5)))
+        .await?;
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(2, 3)),
+        first.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 5)),
+        second.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    assert_eq!(
+        user_state("alice", "secret key 2", 2, 2),
+        first
+            .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+            .await?
+    );
+    assert_eq!(
+        user_state("alice", "other key", 3, 3),
+        second
+            .get_user_state(&alice, ValueStateRetrievalFlag::MaxEpoch, None)
+            .await?
+    );
+    assert_eq!(2, first.get_user_data(&alice, None).await?.states.len());
+    assert_eq!(3, second.get_user_data(&alice, None).await?.states.len());
+    assert_eq!(
+        HashMap::from([(alice.clone(), (3, AkdValue::from("other key")))]),
+        second
+            .get_user_state_versions(
+                &[alice.clone(), AkdLabel::from("carol")],
+                ValueStateRetrievalFlag::MaxEpoch,
+                None
+            )
+            .await?
+    );
+
+    // the states are held under the keys of their tree in the backend
+    let states = db.get_user_data(&alice, Some(TreeId(2))).await?.states;
+    assert_eq!(3, states.len());
+    assert!(states.iter().all(|state| state.tree() == Some(TreeId(2))));
+    assert_eq!(
+        DbRecord::ValueState(user_state("alice", "other key", 3, 3).with_tree(Some(TreeId(2)))),
+        db.get::<ValueState>(&ValueStateKey::new(alice.to_vec(), 3).with_tree(Some(TreeId(2))))
+            .await?
+    );
+    assert!(db.get_user_data(&alice, None).await.is_err());
+
+    // the records of another tree are not visible, nor are those without a tree
+    let third = TreeScopedDatabase::new(db.clone(), TreeId(3));
+    assert!(matches!(
+        third.get::<Azks>(&DEFAULT_AZKS_KEY).await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert!(third.get_user_data(&alice, None).await.is_err());
+    assert!(third.batch_get_all_direct().await?.is_empty());
+    db.set(DbRecord::Azks(DbRecord::build_azks(1, 1))).await?;
+    db.set(DbRecord::ValueState(user_state("alice", "key", 1, 1)))
+        .await?;
+    assert!(third.batch_get_all_direct().await?.is_empty());
+    assert!(third.get_user_data(&alice, None).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tree_node_keys() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let first = TreeScopedDatabase::new(db.clone(), TreeId(1));
+    let second = TreeScopedDatabase::new(db.clone(), TreeId(2));
+    let label = NodeLabel::new([1u8; 32], 256);
+    first.set(DbRecord::TreeNode(tree_node(label, 1))).await?;
+    second.set(DbRecord::TreeNode(tree_node(label, 2))).await?;
+
+    assert_eq!(
+        DbRecord::TreeNode(tree_node(label, 1)),
+        first
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(label))
+            .await?
+    );
+    assert_eq!(
+        vec![DbRecord::TreeNode(tree_node(label, 2))],
+        second
+            .batch_get::<TreeNodeWithPreviousValue>(&[NodeKey::new(label)])
+            .await?
+    );
+    // the node is held under the key of its tree in the backend
+    assert_eq!(
+        DbRecord::TreeNode(TreeNodeWithPreviousValue {
+            tree: Some(TreeId(2)),
+            ..tree_node(label, 2)
+        }),
+        db.get::<TreeNodeWithPreviousValue>(&NodeKey(label, Some(TreeId(2))))
+            .await?
+    );
+    assert!(db
+        .get::<TreeNodeWithPreviousValue>(&NodeKey::new(label))
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tree_scoped_storage() {
+    let db = AsyncInMemoryDatabase::new();
+    populate_database(&TreeScopedDatabase::new(db.clone(), TreeId(1)))
+        .await
+        .unwrap();
+    let tree = TreeScopedDatabase::new(db, TreeId(2));
+    crate::storage::tests::run_test_cases_for_storage_impl(tree.clone()).await;
+    crate::storage::tests::run_test_cases_for_storage_util_impl(&tree).await;
+}
+
+#[tokio::test]
+async fn test_tree_scoped_paged_reads() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let keys = StaticMacKeyProvider::new([7u8; 32]);
+    let trees = [TreeId(0), TreeId(1), TreeId(u64::MAX)]
+        .into_iter()
+        .map(|tree| IntegrityDatabase::new(TreeScopedDatabase::new(db.clone(), tree), keys.clone()))
+        .collect::<Vec<_>>();
+    for tree in trees.iter() {
+        populate_database(tree).await?;
+    }
+    trees[1]
+        .set(DbRecord::ValueState(user_state(
+            "carol",
+            "secret key 4",
+            1,
+            3,
+        )))
+        .await?;
+
+    // pages of a tree hold only its records, whichever trees come before and after it in the
+    // backend, and the MACs of its records
+    for tree in trees.iter() {
+        let mut expected = tree.batch_get_all_direct().await?;
+        expected.sort();
+        for page_size in [1, 2, 100] {
+            let mut records = tree
+                .stream_all_direct(page_size)
+                .try_collect::<Vec<_>>()
+                .await?;
+            records.sort();
+            assert_eq!(expected, records);
+        }
+    }
+
+    // a cursor outside of the tree is rejected
+    let other = ValueState::get_full_binary_key_id(&ValueStateKey::new(b"alice".to_vec(), 1));
+    assert!(trees[1]
+        .batch_get_type_page_direct::<TreeNodeWithPreviousValue>(Some(&other), 1)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tree_scoped_integrity() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let keys = StaticMacKeyProvider::new([7u8; 32]);
+    let first =
+        IntegrityDatabase::new(TreeScopedDatabase::new(db.clone(), TreeId(1)), keys.clone());
+    let second = IntegrityDatabase::new(TreeScopedDatabase::new(db.clone(), TreeId(2)), keys);
+    populate_database(&first).await?;
+    populate_database(&second).await?;
+    second
+        .set(DbRecord::Azks(DbRecord::build_azks(3, 5)))
+        .await?;
+
+    // the MACs of each tree are held separately, and cover the records of their tree
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(2, 3)),
+        first.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 5)),
+        second.get::<Azks>(&DEFAULT_AZKS_KEY).await?
+    );
+    let records = first
+        .batch_get_all_direct()
+        .await?
+        .into_iter()
+        .filter(|record| !matches!(record, DbRecord::RecordMac(_)))
+        .count();
+    assert_eq!(populated_records().len(), records);
+    Ok(())
+}
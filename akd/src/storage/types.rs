@@ -26,15 +26,105 @@ pub enum StorageType {
     /// Better to keep ValueState = 4 as is?
     /// ValueState
     ValueState = 4,
+    /// EpochChangeset
+    EpochChangeset = 5,
+    /// SchemaVersion
+    SchemaVersion = 6,
+    /// RecordMac
+    RecordMac = 7,
+    /// PendingEpoch
+    PendingEpoch = 8,
 }
 
-/// State for a value at a given version for that key
+impl StorageType {
+    /// The storage type with the given code, which begins the full binary key of its records
+    pub(crate) fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::Azks),
+            2 => Some(Self::TreeNode),
+            4 => Some(Self::ValueState),
+            5 => Some(Self::EpochChangeset),
+            6 => Some(Self::SchemaVersion),
+            7 => Some(Self::RecordMac),
+            8 => Some(Self::PendingEpoch),
+            _ => None,
+        }
+    }
+}
+
+/// The flag set on the [StorageType] byte of the full binary key of a record held in one of
+/// several trees of a storage backend, which is followed by the [TreeId]
+pub(crate) const TREE_SCOPED_KEY_FLAG: u8 = 0x80;
+
+/// The identifier of one of several independent trees held in a single storage backend, see
+/// [crate::storage::tree_scope]. The records of a backend holding a single tree have no tree
+/// identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct TreeId(pub u64);
+
+/// The start of the full binary key of a record of the given type held in the given tree,
+/// which is the [StorageType] byte alone for a record of a backend holding a single tree
+pub(crate) fn tree_scoped_key_prefix(storage_type: StorageType, tree: Option<TreeId>) -> Vec<u8> {
+    match tree {
+        None => vec![storage_type as u8],
+        Some(tree) => {
+            let mut result = vec![storage_type as u8 | TREE_SCOPED_KEY_FLAG];
+            result.extend_from_slice(&tree.0.to_be_bytes());
+            result
+        }
+    }
+}
+
+/// Splits the full binary key of a record of the given type into its tree and the remainder
+/// of the key, following [tree_scoped_key_prefix]
+pub(crate) fn split_tree_scoped_key(
+    storage_type: StorageType,
+    bin: &[u8],
+) -> Result<(Option<TreeId>, &[u8]), String> {
+    match bin.split_first() {
+        Some((&first, rest)) if first == storage_type as u8 => Ok((None, rest)),
+        Some((&first, rest)) if first == storage_type as u8 | TREE_SCOPED_KEY_FLAG => {
+            if rest.len() < 8 {
+                return Err("Not enough bytes to form a proper key".to_string());
+            }
+            let (tree, rest) = rest.split_at(8);
+            let tree: [u8; 8] = tree.try_into().expect("Slice with incorrect length");
+            Ok((Some(TreeId(u64::from_be_bytes(tree))), rest))
+        }
+        _ => Err(format!("Not a {storage_type:?} key")),
+    }
+}
+
+/// The key of a [ValueState], which is its username, epoch and tree
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 #[cfg_attr(
     feature = "serde_serialization",
     derive(serde::Deserialize, serde::Serialize)
 )]
-pub struct ValueStateKey(pub Vec<u8>, pub u64);
+pub struct ValueStateKey(pub Vec<u8>, pub u64, pub(crate) Option<TreeId>);
+
+impl ValueStateKey {
+    /// The key of the state of the username published in the epoch in a storage backend
+    /// holding a single tree
+    pub fn new(username: Vec<u8>, epoch: u64) -> Self {
+        Self(username, epoch, None)
+    }
+
+    /// The tree the state belongs to, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.2
+    }
+
+    /// The key of the state of the username published in the epoch in the given tree of a
+    /// storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self(self.0, self.1, tree)
+    }
+}
 
 /// The state of the value for a given key, starting at a particular epoch.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -54,6 +144,12 @@ pub struct ValueState {
     pub epoch: u64,
     /// The username associated to this value state (username + epoch is the record key)
     pub username: AkdLabel,
+    /// The tree the value state belongs to, in a storage backend holding several trees
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) tree: Option<TreeId>,
 }
 
 impl akd_core::SizeOf for ValueState {
@@ -63,6 +159,7 @@ impl akd_core::SizeOf for ValueState {
             + self.label.size_of()
             + std::mem::size_of::<u64>()
             + self.username.size_of()
+            + std::mem::size_of::<Option<TreeId>>()
     }
 }
 
@@ -74,29 +171,28 @@ impl crate::storage::Storable for ValueState {
     }
 
     fn get_id(&self) -> ValueStateKey {
-        ValueStateKey(self.username.to_vec(), self.epoch)
+        ValueStateKey(self.username.to_vec(), self.epoch, self.tree)
     }
 
     fn get_full_binary_key_id(key: &ValueStateKey) -> Vec<u8> {
-        let mut result = vec![StorageType::ValueState as u8];
-        result.extend_from_slice(&key.1.to_be_bytes());
+        let mut result = tree_scoped_key_prefix(StorageType::ValueState, key.2);
         result.extend_from_slice(&key.0);
+        result.extend_from_slice(&key.1.to_be_bytes());
 
         result
     }
 
     fn key_from_full_binary(bin: &[u8]) -> Result<ValueStateKey, String> {
-        if bin.len() < 10 {
+        let (tree, rest) = split_tree_scoped_key(StorageType::ValueState, bin)
+            .map_err(|_| "Not a value state key".to_string())?;
+        if rest.len() < 9 {
             return Err("Not enough bytes to form a proper key".to_string());
         }
 
-        if bin[0] != StorageType::ValueState as u8 {
-            return Err("Not a value state key".to_string());
-        }
-
-        let epoch_bytes: [u8; 8] = bin[1..=8].try_into().expect("Slice with incorrect length");
+        let (username, epoch_bytes) = rest.split_at(rest.len() - 8);
+        let epoch_bytes: [u8; 8] = epoch_bytes.try_into().expect("Slice with incorrect length");
         let epoch = u64::from_be_bytes(epoch_bytes);
-        Ok(ValueStateKey(bin[9..].to_vec(), epoch))
+        Ok(ValueStateKey(username.to_vec(), epoch, tree))
     }
 }
 
@@ -114,7 +210,356 @@ impl ValueState {
             label,
             epoch,
             username,
+            tree: None,
+        }
+    }
+
+    /// The tree the value state belongs to, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    /// The value state, of the given tree of a storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self { tree, ..self }
+    }
+}
+
+/// The maximum number of node labels held in a single part of an [EpochChangeset]
+pub const EPOCH_CHANGESET_PART_LABELS: usize = 10_000;
+
+/// The set of tree nodes which were modified in publishing a given epoch. When recorded
+/// (see [crate::storage::manager::StorageManager::with_epoch_changesets]), read-only
+/// instances are able to evict only the modified nodes from their caches upon an epoch
+/// change, rather than flushing them entirely.
+///
+/// The labels of an epoch are split into parts of at most [EPOCH_CHANGESET_PART_LABELS]
+/// labels, stored as separate records, so that no single record grows with the size of the
+/// publish.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct EpochChangeset {
+    /// The epoch which was published
+    pub epoch: u64,
+    /// The index of this part of the changeset of the epoch
+    pub part: u32,
+    /// The number of parts the changeset of the epoch is split into, as recorded by its first
+    /// part. The later parts of an epoch written in several batches (see [PendingEpoch])
+    /// record 0, as the number is not yet known when they're written.
+    pub num_parts: u32,
+    /// The labels of the tree nodes modified in the epoch, held by this part
+    pub node_labels: Vec<NodeLabel>,
+    /// The tree the changeset belongs to, in a storage backend holding several trees
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) tree: Option<TreeId>,
+}
+
+impl EpochChangeset {
+    /// The tree the changeset belongs to, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    /// The changeset, of the given tree of a storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self { tree, ..self }
+    }
+
+    /// Split the labels of the tree nodes modified in the epoch into the parts of its
+    /// changeset. There is always at least one part, even when no nodes were modified.
+    pub fn split(epoch: u64, node_labels: Vec<NodeLabel>) -> Vec<Self> {
+        let mut chunks = node_labels
+            .chunks(EPOCH_CHANGESET_PART_LABELS)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<_>>();
+        if chunks.is_empty() {
+            chunks.push(vec![]);
+        }
+        let num_parts = chunks.len() as u32;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(part, node_labels)| Self {
+                epoch,
+                part: part as u32,
+                num_parts,
+                node_labels,
+                tree: None,
+            })
+            .collect()
+    }
+}
+
+/// The key of a part of an [EpochChangeset], which is its epoch, part index and tree
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct EpochChangesetKey(pub u64, pub u32, pub(crate) Option<TreeId>);
+
+impl EpochChangesetKey {
+    /// The key of a part of the changeset of the epoch in a storage backend holding a
+    /// single tree
+    pub fn new(epoch: u64, part: u32) -> Self {
+        Self(epoch, part, None)
+    }
+
+    /// The tree the changeset belongs to, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.2
+    }
+
+    /// The key of a part of the changeset of the epoch in the given tree of a storage
+    /// backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self(self.0, self.1, tree)
+    }
+}
+
+impl akd_core::SizeOf for EpochChangeset {
+    fn size_of(&self) -> usize {
+        std::mem::size_of::<u64>()
+            + 2 * std::mem::size_of::<u32>()
+            + std::mem::size_of::<Option<TreeId>>()
+            + self
+                .node_labels
+                .iter()
+                .map(|label| label.size_of())
+                .sum::<usize>()
+    }
+}
+
+impl crate::storage::Storable for EpochChangeset {
+    type StorageKey = EpochChangesetKey;
+
+    fn data_type() -> StorageType {
+        StorageType::EpochChangeset
+    }
+
+    fn get_id(&self) -> EpochChangesetKey {
+        EpochChangesetKey(self.epoch, self.part, self.tree)
+    }
+
+    fn get_full_binary_key_id(key: &EpochChangesetKey) -> Vec<u8> {
+        let mut result = tree_scoped_key_prefix(StorageType::EpochChangeset, key.2);
+        result.extend_from_slice(&key.0.to_be_bytes());
+        result.extend_from_slice(&key.1.to_be_bytes());
+        result
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<EpochChangesetKey, String> {
+        let (tree, bin) = split_tree_scoped_key(StorageType::EpochChangeset, bin)?;
+        if bin.len() < 12 {
+            return Err("Not enough bytes to form a proper key".to_string());
+        }
+
+        let epoch_bytes: [u8; 8] = bin[..8].try_into().expect("Slice with incorrect length");
+        let part_bytes: [u8; 4] = bin[8..12].try_into().expect("Slice with incorrect length");
+        Ok(EpochChangesetKey(
+            u64::from_be_bytes(epoch_bytes),
+            u32::from_be_bytes(part_bytes),
+            tree,
+        ))
+    }
+}
+
+/// The key of the single [SchemaVersion] record of a storage backend
+pub const DEFAULT_SCHEMA_VERSION_KEY: u8 = 1u8;
+
+/// The version of the encoding of the records held in storage. This is written when a
+/// directory is first created and updated as migrations are applied, see
+/// [crate::storage::migration].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct SchemaVersion {
+    /// The schema version of the stored records
+    pub version: u64,
+}
+
+impl akd_core::SizeOf for SchemaVersion {
+    fn size_of(&self) -> usize {
+        std::mem::size_of::<u64>()
+    }
+}
+
+impl crate::storage::Storable for SchemaVersion {
+    type StorageKey = u8;
+
+    fn data_type() -> StorageType {
+        StorageType::SchemaVersion
+    }
+
+    fn get_id(&self) -> u8 {
+        DEFAULT_SCHEMA_VERSION_KEY
+    }
+
+    fn get_full_binary_key_id(key: &u8) -> Vec<u8> {
+        vec![StorageType::SchemaVersion as u8, *key]
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<u8, String> {
+        if bin.is_empty() || bin[0] != StorageType::SchemaVersion as u8 {
+            return Err("Not a schema version key".to_string());
+        }
+        Ok(DEFAULT_SCHEMA_VERSION_KEY)
+    }
+}
+
+/// The operation writing a [PendingEpoch] directly to storage, rather than in a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub enum PendingOperation {
+    /// [crate::directory::Directory::publish_from_iter]
+    PublishFromIter = 1,
+    /// [crate::directory::Directory::bootstrap]
+    Bootstrap = 2,
+}
+
+impl PendingOperation {
+    /// The operation with the given code
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::PublishFromIter),
+            2 => Some(Self::Bootstrap),
+            _ => None,
+        }
+    }
+}
+
+/// Marks an epoch whose records are being written directly to storage in several batches,
+/// rather than committed in a single transaction. The marker is written before the first
+/// record of the epoch, and the epoch is pending for as long as it is later than that of the
+/// [Azks], so writing the [Azks] of the epoch completes it. Until then, only the operation
+/// which started the epoch may write to the tree, to complete it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct PendingEpoch {
+    /// The epoch being written
+    pub epoch: u64,
+    /// The operation writing the epoch
+    pub operation: PendingOperation,
+    /// The index of the next part of the [EpochChangeset] of the epoch to be written. The
+    /// first part is written along with the [Azks], so this starts at 1.
+    pub next_part: u32,
+    /// The tree the epoch belongs to, in a storage backend holding several trees
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) tree: Option<TreeId>,
+}
+
+impl PendingEpoch {
+    /// The tree the epoch belongs to, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    /// The marker, of the given tree of a storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self { tree, ..self }
+    }
+}
+
+/// The key of the single [PendingEpoch] of a storage backend holding a single tree
+pub const DEFAULT_PENDING_EPOCH_KEY: Option<TreeId> = None;
+
+impl akd_core::SizeOf for PendingEpoch {
+    fn size_of(&self) -> usize {
+        std::mem::size_of::<u64>()
+            + std::mem::size_of::<PendingOperation>()
+            + std::mem::size_of::<u32>()
+            + std::mem::size_of::<Option<TreeId>>()
+    }
+}
+
+impl crate::storage::Storable for PendingEpoch {
+    type StorageKey = Option<TreeId>;
+
+    fn data_type() -> StorageType {
+        StorageType::PendingEpoch
+    }
+
+    fn get_id(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    fn get_full_binary_key_id(key: &Option<TreeId>) -> Vec<u8> {
+        tree_scoped_key_prefix(StorageType::PendingEpoch, *key)
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<Option<TreeId>, String> {
+        let (tree, _) = split_tree_scoped_key(StorageType::PendingEpoch, bin)?;
+        Ok(tree)
+    }
+}
+
+/// The key of a [RecordMac], which is the full binary key of the record it protects
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct RecordMacKey(pub Vec<u8>);
+
+/// A keyed MAC over the key and contents of a stored record, see
+/// [crate::storage::integrity].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde_serialization",
+    derive(serde::Deserialize, serde::Serialize)
+)]
+pub struct RecordMac {
+    /// The full binary key of the protected record
+    pub record_key: Vec<u8>,
+    /// The MAC of the record
+    pub mac: [u8; 32],
+}
+
+impl akd_core::SizeOf for RecordMac {
+    fn size_of(&self) -> usize {
+        self.record_key.len() + 32
+    }
+}
+
+impl crate::storage::Storable for RecordMac {
+    type StorageKey = RecordMacKey;
+
+    fn data_type() -> StorageType {
+        StorageType::RecordMac
+    }
+
+    fn get_id(&self) -> RecordMacKey {
+        RecordMacKey(self.record_key.clone())
+    }
+
+    fn get_full_binary_key_id(key: &RecordMacKey) -> Vec<u8> {
+        let mut result = vec![StorageType::RecordMac as u8];
+        result.extend_from_slice(&key.0);
+        result
+    }
+
+    fn key_from_full_binary(bin: &[u8]) -> Result<RecordMacKey, String> {
+        if bin.is_empty() || bin[0] != StorageType::RecordMac as u8 {
+            return Err("Not a record MAC key".to_string());
         }
+        Ok(RecordMacKey(bin[1..].to_vec()))
     }
 }
 
@@ -162,6 +607,14 @@ pub enum DbRecord {
     TreeNode(TreeNodeWithPreviousValue),
     /// The state of the value for a particular key.
     ValueState(ValueState),
+    /// The set of tree nodes modified in an epoch
+    EpochChangeset(EpochChangeset),
+    /// The schema version of the stored records
+    SchemaVersion(SchemaVersion),
+    /// The MAC of a stored record
+    RecordMac(RecordMac),
+    /// The marker of an epoch being written directly to storage
+    PendingEpoch(PendingEpoch),
 }
 
 impl akd_core::SizeOf for DbRecord {
@@ -170,6 +623,10 @@ impl akd_core::SizeOf for DbRecord {
             DbRecord::Azks(azks) => azks.size_of(),
             DbRecord::TreeNode(node) => node.size_of(),
             DbRecord::ValueState(state) => state.size_of(),
+            DbRecord::EpochChangeset(changeset) => changeset.size_of(),
+            DbRecord::SchemaVersion(version) => version.size_of(),
+            DbRecord::RecordMac(mac) => mac.size_of(),
+            DbRecord::PendingEpoch(pending) => pending.size_of(),
         }
     }
 }
@@ -180,6 +637,10 @@ impl Clone for DbRecord {
             DbRecord::Azks(azks) => DbRecord::Azks(azks.clone()),
             DbRecord::TreeNode(node) => DbRecord::TreeNode(node.clone()),
             DbRecord::ValueState(state) => DbRecord::ValueState(state.clone()),
+            DbRecord::EpochChangeset(changeset) => DbRecord::EpochChangeset(changeset.clone()),
+            DbRecord::SchemaVersion(version) => DbRecord::SchemaVersion(*version),
+            DbRecord::RecordMac(mac) => DbRecord::RecordMac(mac.clone()),
+            DbRecord::PendingEpoch(pending) => DbRecord::PendingEpoch(pending.clone()),
         }
     }
 }
@@ -192,6 +653,35 @@ impl DbRecord {
             DbRecord::Azks(azks) => azks.get_full_binary_id(),
             DbRecord::TreeNode(node) => node.get_full_binary_id(),
             DbRecord::ValueState(state) => state.get_full_binary_id(),
+            DbRecord::EpochChangeset(changeset) => changeset.get_full_binary_id(),
+            DbRecord::SchemaVersion(version) => version.get_full_binary_id(),
+            DbRecord::RecordMac(mac) => mac.get_full_binary_id(),
+            DbRecord::PendingEpoch(pending) => pending.get_full_binary_id(),
+        }
+    }
+
+    /// Returns the [StorageType] of the record
+    pub fn storage_type(&self) -> StorageType {
+        match &self {
+            DbRecord::Azks(_) => StorageType::Azks,
+            DbRecord::TreeNode(_) => StorageType::TreeNode,
+            DbRecord::ValueState(_) => StorageType::ValueState,
+            DbRecord::EpochChangeset(_) => StorageType::EpochChangeset,
+            DbRecord::SchemaVersion(_) => StorageType::SchemaVersion,
+            DbRecord::RecordMac(_) => StorageType::RecordMac,
+            DbRecord::PendingEpoch(_) => StorageType::PendingEpoch,
+        }
+    }
+
+    /// Returns the tree the record belongs to in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        match &self {
+            DbRecord::Azks(azks) => azks.tree,
+            DbRecord::TreeNode(node) => node.tree,
+            DbRecord::EpochChangeset(changeset) => changeset.tree,
+            DbRecord::PendingEpoch(pending) => pending.tree,
+            DbRecord::ValueState(state) => state.tree,
+            DbRecord::SchemaVersion(_) | DbRecord::RecordMac(_) => None,
         }
     }
 
@@ -214,6 +704,7 @@ impl DbRecord {
         Azks {
             latest_epoch,
             num_nodes,
+            tree: None,
         }
     }
 
@@ -262,6 +753,7 @@ impl DbRecord {
         };
         TreeNodeWithPreviousValue {
             label,
+            tree: None,
             latest_node: TreeNode {
                 label,
                 last_epoch,
@@ -276,6 +768,46 @@ impl DbRecord {
         }
     }
 
+    /// Build a part of an epoch changeset from the properties
+    pub fn build_epoch_changeset(
+        epoch: u64,
+        part: u32,
+        num_parts: u32,
+        node_labels: Vec<NodeLabel>,
+    ) -> EpochChangeset {
+        EpochChangeset {
+            epoch,
+            part,
+            num_parts,
+            node_labels,
+            tree: None,
+        }
+    }
+
+    /// Build a pending epoch marker from the properties
+    pub fn build_pending_epoch(
+        epoch: u64,
+        operation: PendingOperation,
+        next_part: u32,
+    ) -> PendingEpoch {
+        PendingEpoch {
+            epoch,
+            operation,
+            next_part,
+            tree: None,
+        }
+    }
+
+    /// Build a schema version from the properties
+    pub fn build_schema_version(version: u64) -> SchemaVersion {
+        SchemaVersion { version }
+    }
+
+    /// Build a record MAC from the properties
+    pub fn build_record_mac(record_key: Vec<u8>, mac: [u8; 32]) -> RecordMac {
+        RecordMac { record_key, mac }
+    }
+
     /// Build a user state from the properties
     pub fn build_user_state(
         username: Vec<u8>,
@@ -291,6 +823,7 @@ impl DbRecord {
             label: NodeLabel::new(label_val, label_len),
             epoch,
             username: AkdLabel(username),
+            tree: None,
         }
     }
 }
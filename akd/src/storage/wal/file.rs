@@ -0,0 +1,196 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A file-backed [WriteAheadLog].
+//!
+//! Entries are appended to the file as length-prefixed frames carrying a checksum of their
+//! payload, and the file is synced after every append. A frame which was only partially
+//! written (i.e. due to a crash) is ignored when reading the log back, while a complete
+//! frame whose checksum does not match is reported as corrupt. File operations are run on tokio's blocking
+//! thread pool, so that a commit does not stall the runtime while the log is synced.
+
+use super::{WalEntry, WriteAheadLog};
+use crate::errors::StorageError;
+use crate::storage::codec::{encode_record, Reader};
+use async_trait::async_trait;
+use log::warn;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const ENTRY_BATCH: u8 = 1;
+const ENTRY_COMMIT: u8 = 2;
+
+/// A [WriteAheadLog] stored in a file on the local filesystem
+pub struct FileWriteAheadLog {
+    path: Arc<PathBuf>,
+    file: Arc<Mutex<File>>,
+}
+
+impl FileWriteAheadLog {
+    /// Open (or create) the write-ahead log at the given path
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| io_error(&path, err))?;
+        Ok(Self {
+            path: Arc::new(path),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// The path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run a blocking operation on the (locked) log file on the blocking thread pool
+    async fn with_file<T, F>(&self, op: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut File, &Path) -> std::io::Result<T> + Send + 'static,
+    {
+        let file = self.file.clone();
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            op(&mut file, &path).map_err(|err| io_error(&path, err))
+        })
+        .await
+        .map_err(|err| {
+            StorageError::Other(format!(
+                "Write-ahead log {:?} task failed: {err}",
+                self.path
+            ))
+        })?
+    }
+}
+
+#[async_trait]
+impl WriteAheadLog for FileWriteAheadLog {
+    async fn append(&self, entry: WalEntry) -> Result<(), StorageError> {
+        let payload = encode_entry(&entry)?;
+        let len = u32::try_from(payload.len()).map_err(|_| {
+            StorageError::Other(format!(
+                "Write-ahead log entry of {} bytes is too large to be framed",
+                payload.len()
+            ))
+        })?;
+        let mut frame = Vec::with_capacity(4 + blake3::OUT_LEN + payload.len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(blake3::hash(&payload).as_bytes());
+        frame.extend_from_slice(&payload);
+
+        self.with_file(move |file, _| {
+            file.write_all(&frame)?;
+            file.sync_data()
+        })
+        .await
+    }
+
+    async fn entries(&self) -> Result<Vec<WalEntry>, StorageError> {
+        // the lock is held while reading, so that a concurrent append is not torn
+        let bytes = self
+            .with_file(|_, path| {
+                let mut bytes = vec![];
+                File::open(path)?.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            })
+            .await?;
+
+        let mut entries = vec![];
+        let mut reader = Reader::new(&bytes);
+        while !reader.is_empty() {
+            let frame = reader.u32().and_then(|len| {
+                let checksum = reader.array::<{ blake3::OUT_LEN }>()?;
+                Ok((checksum, reader.bytes(len as usize)?))
+            });
+            let payload = match frame {
+                Ok((checksum, payload)) => {
+                    if blake3::hash(payload) != blake3::Hash::from(checksum) {
+                        return Err(StorageError::IntegrityViolation(format!(
+                            "Corrupt entry in the write-ahead log {:?}",
+                            self.path
+                        )));
+                    }
+                    payload
+                }
+                Err(_) => {
+                    warn!(
+                        "Ignoring a partially written entry at the end of the write-ahead log {:?}",
+                        self.path
+                    );
+                    break;
+                }
+            };
+            entries.push(decode_entry(payload)?);
+        }
+        Ok(entries)
+    }
+
+    async fn truncate(&self) -> Result<(), StorageError> {
+        self.with_file(|file, _| {
+            file.set_len(0)?;
+            file.sync_all()
+        })
+        .await
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> StorageError {
+    StorageError::Other(format!("Write-ahead log {path:?} I/O error: {err}"))
+}
+
+fn encode_entry(entry: &WalEntry) -> Result<Vec<u8>, StorageError> {
+    let mut out = vec![];
+    match entry {
+        WalEntry::Batch { epoch, records } => {
+            let num_records = u32::try_from(records.len()).map_err(|_| {
+                StorageError::Other(format!(
+                    "Write-ahead log batch of {} records is too large to be encoded",
+                    records.len()
+                ))
+            })?;
+            out.push(ENTRY_BATCH);
+            out.extend_from_slice(&epoch.to_be_bytes());
+            out.extend_from_slice(&num_records.to_be_bytes());
+            for record in records.iter() {
+                encode_record(&mut out, record);
+            }
+        }
+        WalEntry::Commit { epoch } => {
+            out.push(ENTRY_COMMIT);
+            out.extend_from_slice(&epoch.to_be_bytes());
+        }
+    }
+    Ok(out)
+}
+
+fn decode_entry(payload: &[u8]) -> Result<WalEntry, StorageError> {
+    let mut reader = Reader::new(payload);
+    match reader.u8()? {
+        ENTRY_BATCH => {
+            let epoch = reader.u64()?;
+            let num_records = reader.u32()?;
+            let records = (0..num_records)
+                .map(|_| reader.record())
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(WalEntry::Batch { epoch, records })
+        }
+        ENTRY_COMMIT => Ok(WalEntry::Commit {
+            epoch: reader.u64()?,
+        }),
+        other => Err(StorageError::Other(format!(
+            "Unknown entry type {other} in the write-ahead log"
+        ))),
+    }
+}
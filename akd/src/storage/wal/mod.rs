@@ -0,0 +1,377 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! A write-ahead log for transaction commits, which provides crash-consistent epochs on
+//! top of backends which cannot apply a batch of records atomically.
+//!
+//! The [WriteAheadLogDatabase] wraps a [Database] and, when a transaction is committed,
+//! first durably records the batch of records followed by a commit marker in a
+//! [WriteAheadLog]. Only then is the batch applied to the underlying database, after which
+//! the log is truncated. Upon startup, a batch which was committed to the log but may not
+//! have been fully applied is replayed, while a batch without a commit marker is discarded
+//! (it was never applied). Since records are written as upserts, replaying a batch which
+//! was partially (or fully) applied is safe. A batch whose epoch is not newer than the
+//! stored AZKS was already applied (the AZKS is written last) or superseded, and is
+//! discarded rather than replayed.
+//!
+//! Should applying a batch fail, the commit fails and the wrapper refuses any further
+//! commit until [WriteAheadLogDatabase::recover] is called. The caller has been told that
+//! the epoch failed, so the batch is neither silently replayed beneath a later commit nor
+//! dropped while it may have been partially applied.
+
+use crate::errors::StorageError;
+use crate::storage::types::{
+    DbRecord, KeyData, StorageType, TreeId, ValueState, ValueStateRetrievalFlag,
+};
+use crate::storage::{Database, DbSetState, Storable, StorageUtil};
+use crate::{AkdLabel, AkdValue, Azks};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+mod file;
+#[cfg(test)]
+mod tests;
+
+pub use file::FileWriteAheadLog;
+
+/// An entry of the [WriteAheadLog]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalEntry {
+    /// The batch of records to be committed for an epoch
+    Batch {
+        /// The epoch being committed
+        epoch: u64,
+        /// The records of the commit
+        records: Vec<DbRecord>,
+    },
+    /// Marks the batch of the given epoch as committed, meaning it will be applied
+    Commit {
+        /// The epoch which was committed
+        epoch: u64,
+    },
+}
+
+/// The outcome of recovering from a [WriteAheadLog] on startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalRecovery {
+    /// The log was empty, there was nothing to recover
+    Clean,
+    /// A committed batch for the epoch was re-applied to the database
+    Replayed(u64),
+    /// An uncommitted batch for the epoch was discarded
+    Discarded(u64),
+    /// A committed batch for the epoch was discarded, as the stored AZKS is already at
+    /// (or beyond) the epoch
+    Stale(u64),
+}
+
+/// A durable, append-only log. Implementations must guarantee that an entry is durable
+/// once [WriteAheadLog::append] returns, and that a partially written entry is not
+/// returned by [WriteAheadLog::entries].
+#[async_trait]
+pub trait WriteAheadLog: Send + Sync {
+    /// Durably append an entry to the log
+    async fn append(&self, entry: WalEntry) -> Result<(), StorageError>;
+
+    /// Retrieve all of the complete entries of the log, in the order they were appended
+    async fn entries(&self) -> Result<Vec<WalEntry>, StorageError>;
+
+    /// Remove all of the entries from the log
+    async fn truncate(&self) -> Result<(), StorageError>;
+}
+
+/// A [WriteAheadLog] which is held in memory. This is only crash-consistent so long as
+/// the log outlives the database wrapper (i.e. for testing)
+#[derive(Debug, Clone, Default)]
+pub struct MemoryWriteAheadLog {
+    entries: Arc<std::sync::Mutex<Vec<WalEntry>>>,
+}
+
+impl MemoryWriteAheadLog {
+    /// Create a new, empty in-memory log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<WalEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl WriteAheadLog for MemoryWriteAheadLog {
+    async fn append(&self, entry: WalEntry) -> Result<(), StorageError> {
+        self.lock().push(entry);
+        Ok(())
+    }
+
+    async fn entries(&self) -> Result<Vec<WalEntry>, StorageError> {
+        Ok(self.lock().clone())
+    }
+
+    async fn truncate(&self) -> Result<(), StorageError> {
+        self.lock().clear();
+        Ok(())
+    }
+}
+
+/// A [Database] which records transaction commits in a [WriteAheadLog] prior to applying
+/// them to the underlying database. Writes which are not part of a transaction commit
+/// are passed through as-is.
+pub struct WriteAheadLogDatabase<Db: Database, W: WriteAheadLog> {
+    db: Db,
+    wal: W,
+    /// Serializes commits, as the log holds a single batch at a time
+    commit_lock: Mutex<()>,
+    /// Whether the log may hold a batch, i.e. a commit failed and the log must be
+    /// recovered prior to the next commit
+    needs_recovery: AtomicBool,
+}
+
+impl<Db: Database, W: WriteAheadLog> WriteAheadLogDatabase<Db, W> {
+    /// Wrap the database with the write-ahead log, recovering any batch left in the log
+    /// by an interrupted commit. See [WriteAheadLogDatabase::recover].
+    pub async fn new(db: Db, wal: W) -> Result<Self, StorageError> {
+        let wal_db = Self {
+            db,
+            wal,
+            commit_lock: Mutex::new(()),
+            needs_recovery: AtomicBool::new(true),
+        };
+        wal_db.recover().await?;
+        Ok(wal_db)
+    }
+
+    /// Replay a committed batch from the log, or discard an uncommitted (or stale) one,
+    /// and then truncate the log. This is called upon construction, and must be called
+    /// after a failed commit before any further commit is accepted.
+    pub async fn recover(&self) -> Result<WalRecovery, StorageError> {
+        let _guard = self.commit_lock.lock().await;
+        self.recover_locked().await
+    }
+
+    async fn recover_locked(&self) -> Result<WalRecovery, StorageError> {
+        let mut batch = None;
+        let mut committed = false;
+        for entry in self.wal.entries().await?.into_iter() {
+            match entry {
+                WalEntry::Batch { epoch, records } => {
+                    batch = Some((epoch, records));
+                    committed = false;
+                }
+                WalEntry::Commit { epoch } => {
+                    committed = matches!(&batch, Some((batch_epoch, _)) if *batch_epoch == epoch);
+                }
+            }
+        }
+
+        let outcome = match batch {
+            None => WalRecovery::Clean,
+            Some((epoch, records)) if committed && self.is_stale(&records).await? => {
+                warn!(
+                    "Discarding the committed batch of epoch {} from the write-ahead log, as the stored AZKS is not older",
+                    epoch
+                );
+                WalRecovery::Stale(epoch)
+            }
+            Some((epoch, records)) if committed => {
+                info!(
+                    "Replaying {} records of the committed epoch {} from the write-ahead log",
+                    records.len(),
+                    epoch
+                );
+                self.db
+                    .batch_set(records, DbSetState::TransactionCommit)
+                    .await?;
+                WalRecovery::Replayed(epoch)
+            }
+            Some((epoch, _)) => {
+                warn!(
+                    "Discarding the uncommitted batch of epoch {} from the write-ahead log",
+                    epoch
+                );
+                WalRecovery::Discarded(epoch)
+            }
+        };
+        self.wal.truncate().await?;
+        self.needs_recovery.store(false, Ordering::Release);
+        Ok(outcome)
+    }
+
+    /// Whether the stored AZKS is at (or beyond) the epoch of the batch's AZKS
+    async fn is_stale(&self, records: &[DbRecord]) -> Result<bool, StorageError> {
+        let azks = match records.iter().find_map(|record| match record {
+            DbRecord::Azks(azks) => Some(azks),
+            _ => None,
+        }) {
+            Some(azks) => azks,
+            None => return Ok(false),
+        };
+        match self.db.get::<Azks>(&azks.get_id()).await {
+            Ok(DbRecord::Azks(stored)) => Ok(stored.latest_epoch >= azks.latest_epoch),
+            Ok(_) | Err(StorageError::NotFound(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Retrieve a reference to the underlying database
+    pub fn inner(&self) -> &Db {
+        &self.db
+    }
+
+    /// Retrieve a reference to the write-ahead log
+    pub fn wal(&self) -> &W {
+        &self.wal
+    }
+}
+
+#[async_trait]
+impl<Db: Database, W: WriteAheadLog> Database for WriteAheadLogDatabase<Db, W> {
+    async fn set(&self, record: DbRecord) -> Result<(), StorageError> {
+        self.db.set(record).await
+    }
+
+    async fn batch_set(
+        &self,
+        records: Vec<DbRecord>,
+        state: DbSetState,
+    ) -> Result<(), StorageError> {
+        if let DbSetState::General = state {
+            return self.db.batch_set(records, state).await;
+        }
+
+        let _guard = self.commit_lock.lock().await;
+        if self.needs_recovery.load(Ordering::Acquire) {
+            return Err(StorageError::Transaction(
+                "A previous commit failed, the write-ahead log must be recovered before committing"
+                    .to_string(),
+            ));
+        }
+
+        let epoch = records
+            .iter()
+            .find_map(|record| match record {
+                DbRecord::Azks(azks) => Some(azks.latest_epoch),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        // the batch must be durable prior to the commit marker, so that a commit marker
+        // is never observed without its batch
+        self.needs_recovery.store(true, Ordering::Release);
+        self.wal
+            .append(WalEntry::Batch {
+                epoch,
+                records: records.clone(),
+            })
+            .await?;
+        self.wal.append(WalEntry::Commit { epoch }).await?;
+
+        // should applying the batch fail, the log is retained so that the batch is
+        // replayed upon recovery, which must happen before the next commit
+        self.db.batch_set(records, state).await?;
+        self.wal.truncate().await?;
+        self.needs_recovery.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    async fn get<St: Storable>(&self, id: &St::StorageKey) -> Result<DbRecord, StorageError> {
+        self.db.get::<St>(id).await
+    }
+
+    async fn batch_get<St: Storable>(
+        &self,
+        ids: &[St::StorageKey],
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.db.batch_get::<St>(ids).await
+    }
+
+    async fn get_user_data(
+        &self,
+        username: &AkdLabel,
+        tree: Option<TreeId>,
+    ) -> Result<KeyData, StorageError> {
+        self.db.get_user_data(username, tree).await
+    }
+
+    async fn get_user_state(
+        &self,
+        username: &AkdLabel,
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<ValueState, StorageError> {
+        self.db.get_user_state(username, flag, tree).await
+    }
+
+    async fn get_user_state_versions(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
+        self.db.get_user_state_versions(usernames, flag, tree).await
+    }
+
+    async fn get_user_states(
+        &self,
+        usernames: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        self.db.get_user_states(usernames, flag, tree).await
+    }
+
+    fn subscribe_epoch_changes(&self) -> Option<BoxStream<'static, u64>> {
+        self.db.subscribe_epoch_changes()
+    }
+
+    fn computes_record_macs(&self) -> bool {
+        self.db.computes_record_macs()
+    }
+}
+
+#[async_trait]
+impl<Db: StorageUtil, W: WriteAheadLog> StorageUtil for WriteAheadLogDatabase<Db, W> {
+    async fn batch_get_type_direct<St: Storable>(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.db.batch_get_type_direct::<St>().await
+    }
+
+    async fn batch_get_all_direct(&self) -> Result<Vec<DbRecord>, StorageError> {
+        self.db.batch_get_all_direct().await
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<DbRecord>, StorageError> {
+        self.db.batch_get_type_page_direct::<St>(after, limit).await
+    }
+
+    async fn batch_get_stored_page_direct(
+        &self,
+        storage_type: StorageType,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, DbRecord)>, StorageError> {
+        self.db
+            .batch_get_stored_page_direct(storage_type, after, limit)
+            .await
+    }
+
+    async fn batch_delete_direct(&self, stored_ids: &[Vec<u8>]) -> Result<(), StorageError> {
+        self.db.batch_delete_direct(stored_ids).await
+    }
+}
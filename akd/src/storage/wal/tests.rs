@@ -0,0 +1,344 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Write-ahead log tests
+
+use super::*;
+use crate::storage::faulty::{FaultConfig, FaultTarget, FaultyDatabase};
+use crate::storage::memory::AsyncInMemoryDatabase;
+use crate::storage::types::{PendingOperation, ValueStateKey};
+use crate::tree_node::{NodeKey, TreeNodeWithPreviousValue};
+use crate::{Azks, NodeLabel};
+use akd_core::hash::EMPTY_DIGEST;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn temp_wal_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("akd_wal_{}_{}.log", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn test_records(epoch: u64) -> Vec<DbRecord> {
+    let mut node = DbRecord::build_tree_node_with_previous_value(
+        [1u8; 32],
+        8,
+        epoch,
+        epoch,
+        [0u8; 32],
+        0,
+        1,
+        Some(NodeLabel::new([2u8; 32], 9)),
+        None,
+        EMPTY_DIGEST,
+        Some(epoch - 1),
+        Some(epoch - 1),
+        Some([0u8; 32]),
+        Some(0),
+        Some(1),
+        None,
+        Some(NodeLabel::new([3u8; 32], 9)),
+        Some([7u8; 32]),
+    );
+    node.latest_node.hash = akd_core::AzksValue([5u8; 32]);
+    vec![
+        DbRecord::TreeNode(node),
+        DbRecord::TreeNode(DbRecord::build_tree_node_with_previous_value(
+            [4u8; 32],
+            256,
+            epoch,
+            epoch,
+            [1u8; 32],
+            8,
+            2,
+            None,
+            None,
+            EMPTY_DIGEST,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )),
+        DbRecord::ValueState(DbRecord::build_user_state(
+            b"user".to_vec(),
+            b"value".to_vec(),
+            epoch,
+            256,
+            [6u8; 32],
+            epoch,
+        )),
+        DbRecord::EpochChangeset(DbRecord::build_epoch_changeset(
+            epoch,
+            0,
+            1,
+            vec![NodeLabel::new([1u8; 32], 8), NodeLabel::new([4u8; 32], 256)],
+        )),
+        DbRecord::SchemaVersion(DbRecord::build_schema_version(1)),
+        DbRecord::PendingEpoch(DbRecord::build_pending_epoch(
+            epoch,
+            PendingOperation::PublishFromIter,
+            2,
+        )),
+        DbRecord::RecordMac(DbRecord::build_record_mac(
+            vec![2, 0, 0, 0, 0, 0, 0, 0, epoch as u8],
+            [6u8; 32],
+        )),
+        DbRecord::Azks(DbRecord::build_azks(epoch, 3)),
+    ]
+}
+
+#[tokio::test]
+async fn test_file_wal_roundtrip() -> Result<(), StorageError> {
+    let path = temp_wal_path("roundtrip");
+    let wal = FileWriteAheadLog::new(&path)?;
+    let entries = vec![
+        WalEntry::Batch {
+            epoch: 2,
+            records: test_records(2),
+        },
+        WalEntry::Commit { epoch: 2 },
+    ];
+    for entry in entries.iter() {
+        wal.append(entry.clone()).await?;
+    }
+    assert_eq!(entries, wal.entries().await?);
+
+    // re-opening the log retains the entries
+    drop(wal);
+    let wal = FileWriteAheadLog::new(&path)?;
+    assert_eq!(entries, wal.entries().await?);
+
+    wal.truncate().await?;
+    assert!(wal.entries().await?.is_empty());
+    wal.append(WalEntry::Commit { epoch: 3 }).await?;
+    assert_eq!(vec![WalEntry::Commit { epoch: 3 }], wal.entries().await?);
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_file_wal_ignores_torn_tail() -> Result<(), StorageError> {
+    let path = temp_wal_path("torn_tail");
+    let wal = FileWriteAheadLog::new(&path)?;
+    let batch = WalEntry::Batch {
+        epoch: 2,
+        records: test_records(2),
+    };
+    wal.append(batch.clone()).await?;
+
+    // simulate a crash part way through writing the commit marker
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&[0, 0, 0, 9, 2, 0]))
+        .unwrap();
+    assert_eq!(vec![batch], wal.entries().await?);
+
+    // the batch is discarded on recovery, as it was never marked committed
+    let db = AsyncInMemoryDatabase::new();
+    let wal_db = WriteAheadLogDatabase::new(db.clone(), wal).await?;
+    assert!(wal_db.wal().entries().await?.is_empty());
+    assert!(db
+        .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await
+        .is_err());
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_file_wal_rejects_corrupt_entry() -> Result<(), StorageError> {
+    let path = temp_wal_path("corrupt_entry");
+    let wal = FileWriteAheadLog::new(&path)?;
+    wal.append(WalEntry::Batch {
+        epoch: 2,
+        records: test_records(2),
+    })
+    .await?;
+    wal.append(WalEntry::Commit { epoch: 2 }).await?;
+
+    // flip a bit in the payload of the (complete) commit marker
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+    assert!(matches!(
+        wal.entries().await,
+        Err(StorageError::IntegrityViolation(_))
+    ));
+
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_replays_committed_batch() -> Result<(), StorageError> {
+    let wal = MemoryWriteAheadLog::new();
+    wal.append(WalEntry::Batch {
+        epoch: 2,
+        records: test_records(2),
+    })
+    .await?;
+    wal.append(WalEntry::Commit { epoch: 2 }).await?;
+
+    let db = AsyncInMemoryDatabase::new();
+    let wal_db = WriteAheadLogDatabase::new(db.clone(), wal.clone()).await?;
+    assert!(wal.entries().await?.is_empty());
+
+    let azks = db
+        .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await?;
+    assert_eq!(DbRecord::Azks(DbRecord::build_azks(2, 3)), azks);
+    let node = wal_db
+        .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::new([1u8; 32], 8)))
+        .await?;
+    assert_eq!(test_records(2)[0], node);
+    wal_db
+        .get::<ValueState>(&ValueStateKey::new(b"user".to_vec(), 2))
+        .await?;
+
+    // a subsequent recovery has nothing to do
+    assert_eq!(WalRecovery::Clean, wal_db.recover().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_discards_uncommitted_batch() -> Result<(), StorageError> {
+    let wal = MemoryWriteAheadLog::new();
+    wal.append(WalEntry::Batch {
+        epoch: 2,
+        records: test_records(2),
+    })
+    .await?;
+
+    // constructed directly, so that the batch is not recovered on construction
+    let db = AsyncInMemoryDatabase::new();
+    let wal_db = WriteAheadLogDatabase {
+        db: db.clone(),
+        wal: wal.clone(),
+        commit_lock: Mutex::new(()),
+        needs_recovery: AtomicBool::new(true),
+    };
+    assert_eq!(WalRecovery::Discarded(2), wal_db.recover().await?);
+    assert!(wal.entries().await?.is_empty());
+    assert!(db
+        .get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_discards_stale_batch() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    db.batch_set(test_records(3), DbSetState::General).await?;
+
+    // a committed batch of an epoch which the stored AZKS has already reached
+    let wal = MemoryWriteAheadLog::new();
+    wal.append(WalEntry::Batch {
+        epoch: 2,
+        records: test_records(2),
+    })
+    .await?;
+    wal.append(WalEntry::Commit { epoch: 2 }).await?;
+
+    let wal_db = WriteAheadLogDatabase {
+        db: db.clone(),
+        wal: wal.clone(),
+        commit_lock: Mutex::new(()),
+        needs_recovery: AtomicBool::new(true),
+    };
+    assert_eq!(WalRecovery::Stale(2), wal_db.recover().await?);
+    assert!(wal.entries().await?.is_empty());
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 3)),
+        db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await?
+    );
+    assert!(db
+        .get::<ValueState>(&ValueStateKey::new(b"user".to_vec(), 2))
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_failed_commit_requires_recovery() -> Result<(), StorageError> {
+    let db = AsyncInMemoryDatabase::new();
+    let faulty = FaultyDatabase::new(db.clone(), 1, FaultConfig::errors(FaultTarget::Writes, 1.0));
+    let wal = MemoryWriteAheadLog::new();
+    let wal_db = WriteAheadLogDatabase::new(faulty.clone(), wal.clone()).await?;
+
+    assert!(wal_db
+        .batch_set(test_records(2), DbSetState::TransactionCommit)
+        .await
+        .is_err());
+    assert_eq!(2, wal.entries().await?.len());
+    faulty.disable();
+
+    // further commits are refused until the failed one is recovered
+    assert!(matches!(
+        wal_db
+            .batch_set(test_records(3), DbSetState::TransactionCommit)
+            .await,
+        Err(StorageError::Transaction(_))
+    ));
+    assert_eq!(2, wal.entries().await?.len());
+    assert_eq!(WalRecovery::Replayed(2), wal_db.recover().await?);
+
+    wal_db
+        .batch_set(test_records(3), DbSetState::TransactionCommit)
+        .await?;
+    assert!(wal.entries().await?.is_empty());
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 3)),
+        db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_commit_and_pass_through() -> Result<(), StorageError> {
+    let wal = MemoryWriteAheadLog::new();
+    let db = AsyncInMemoryDatabase::new();
+    let wal_db = WriteAheadLogDatabase::new(db.clone(), wal.clone()).await?;
+
+    // general writes are not logged
+    wal_db
+        .batch_set(test_records(2)[..1].to_vec(), DbSetState::General)
+        .await?;
+    assert!(wal.entries().await?.is_empty());
+
+    // commits are applied and the log is truncated after
+    wal_db
+        .batch_set(test_records(3), DbSetState::TransactionCommit)
+        .await?;
+    assert!(wal.entries().await?.is_empty());
+    assert_eq!(
+        DbRecord::Azks(DbRecord::build_azks(3, 3)),
+        db.get::<Azks>(&crate::append_only_zks::DEFAULT_AZKS_KEY)
+            .await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wal_paged_reads() -> Result<(), StorageError> {
+    let wal_db =
+        WriteAheadLogDatabase::new(AsyncInMemoryDatabase::new(), MemoryWriteAheadLog::new())
+            .await?;
+    crate::storage::tests::run_test_cases_for_storage_util_impl(&wal_db).await;
+    assert!(wal_db.wal().entries().await?.is_empty());
+    Ok(())
+}
@@ -10,7 +10,9 @@
 use crate::errors::{AkdError, StorageError, TreeNodeError};
 use crate::hash::EMPTY_DIGEST;
 use crate::storage::manager::StorageManager;
-use crate::storage::types::{DbRecord, StorageType};
+use crate::storage::types::{
+    split_tree_scoped_key, tree_scoped_key_prefix, DbRecord, StorageType, TreeId,
+};
 use crate::storage::{Database, Storable};
 use crate::AzksValue;
 use crate::PrefixOrdering;
@@ -81,6 +83,12 @@ impl TreeNodeType {
 pub struct TreeNodeWithPreviousValue {
     /// The label of the node
     pub label: NodeLabel,
+    /// The tree holding the node, in a storage backend holding several trees
+    #[cfg_attr(
+        feature = "serde_serialization",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub(crate) tree: Option<TreeId>,
     /// The "latest" node, either future or current
     pub latest_node: TreeNode,
     /// The "previous" node, either current or past
@@ -90,6 +98,7 @@ pub struct TreeNodeWithPreviousValue {
 impl akd_core::SizeOf for TreeNodeWithPreviousValue {
     fn size_of(&self) -> usize {
         self.label.size_of()
+            + std::mem::size_of::<Option<TreeId>>()
             + self.latest_node.size_of()
             + self.previous_node.as_ref().map_or(8, |v| v.size_of() + 8)
     }
@@ -103,34 +112,42 @@ impl Storable for TreeNodeWithPreviousValue {
     }
 
     fn get_id(&self) -> NodeKey {
-        NodeKey(self.label)
+        NodeKey(self.label, self.tree)
     }
 
     fn get_full_binary_key_id(key: &NodeKey) -> Vec<u8> {
-        let mut result = vec![StorageType::TreeNode as u8];
+        let mut result = tree_scoped_key_prefix(StorageType::TreeNode, key.1);
         result.extend_from_slice(&key.0.label_len.to_be_bytes());
         result.extend_from_slice(&key.0.label_val);
         result
     }
 
     fn key_from_full_binary(bin: &[u8]) -> Result<NodeKey, String> {
-        if bin.len() < 37 {
+        let (tree, bin) = split_tree_scoped_key(StorageType::TreeNode, bin)
+            .map_err(|_| "Not a tree node key".to_string())?;
+        if bin.len() < 36 {
             return Err("Not enough bytes to form a proper key".to_string());
         }
 
-        if bin[0] != StorageType::TreeNode as u8 {
-            return Err("Not a tree node key".to_string());
-        }
-
-        let len_bytes: [u8; 4] = bin[1..=4].try_into().expect("Slice with incorrect length");
-        let val_bytes: [u8; 32] = bin[5..=36].try_into().expect("Slice with incorrect length");
+        let len_bytes: [u8; 4] = bin[..4].try_into().expect("Slice with incorrect length");
+        let val_bytes: [u8; 32] = bin[4..36].try_into().expect("Slice with incorrect length");
         let len = u32::from_be_bytes(len_bytes);
 
-        Ok(NodeKey(NodeLabel::new(val_bytes, len)))
+        Ok(NodeKey(NodeLabel::new(val_bytes, len), tree))
     }
 }
 
 impl TreeNodeWithPreviousValue {
+    /// The tree holding the node, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.tree
+    }
+
+    /// The node, held in the given tree of a storage backend holding several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self { tree, ..self }
+    }
+
     /// Determine which of the previous + latest nodes to retrieve based on the
     /// target epoch. If it should be older than the latest node, and there is no
     /// previous node, it returns Not Found
@@ -148,7 +165,7 @@ impl TreeNodeWithPreviousValue {
                 // no previous, return not found
                 Err(StorageError::NotFound(format!(
                     "TreeNode {:?} at epoch {}",
-                    NodeKey(self.label),
+                    self.get_id(),
                     target_epoch
                 )))
             }
@@ -161,10 +178,10 @@ impl TreeNodeWithPreviousValue {
     /// Construct a TreeNode with "previous" value where the
     /// previous value is None. This is useful for the first
     /// time a node appears in the directory data layer.
-    #[cfg(feature = "public_tests")]
     pub(crate) fn from_tree_node(node: TreeNode) -> Self {
         Self {
             label: node.label,
+            tree: None,
             latest_node: node,
             previous_node: None,
         }
@@ -295,7 +312,7 @@ impl TreeNode {
         } else {
             match TreeNodeWithPreviousValue::get_appropriate_tree_node_from_storage(
                 storage,
-                &NodeKey(self.label),
+                &NodeKey::new(self.label),
                 target_epoch,
             )
             .await
@@ -309,6 +326,7 @@ impl TreeNode {
         // construct the "new" record, shifting the most recent stored value into the "previous" field
         let left_shifted = TreeNodeWithPreviousValue {
             label: self.label,
+            tree: None,
             latest_node: self.clone(),
             previous_node: previous,
         };
@@ -344,13 +362,34 @@ impl TreeNode {
     }
 }
 
-/// Wraps the label with which to find a node in storage.
+/// Wraps the label with which to find a node in storage, along with the tree holding the node
+/// in a storage backend holding several trees.
 #[derive(Clone, PartialEq, Eq, Hash, std::fmt::Debug)]
 #[cfg_attr(
     feature = "serde_serialization",
     derive(serde::Deserialize, serde::Serialize)
 )]
-pub struct NodeKey(pub NodeLabel);
+pub struct NodeKey(pub NodeLabel, pub(crate) Option<TreeId>);
+
+impl NodeKey {
+    /// The key of the node with the label in a storage backend holding a single tree, which is
+    /// also the key of the node within a tree accessed through a
+    /// [crate::storage::tree_scope::TreeScopedDatabase]
+    pub fn new(label: NodeLabel) -> Self {
+        Self(label, None)
+    }
+
+    /// The tree holding the node, in a storage backend holding several trees
+    pub fn tree(&self) -> Option<TreeId> {
+        self.1
+    }
+
+    /// The key of the node with the label in the given tree of a storage backend holding
+    /// several trees
+    pub fn with_tree(self, tree: Option<TreeId>) -> Self {
+        Self(self.0, tree)
+    }
+}
 
 unsafe impl Sync for TreeNode {}
 
@@ -451,7 +490,7 @@ impl TreeNode {
         epoch: u64,
     ) -> Result<Option<TreeNode>, AkdError> {
         if let Some(child_label) = self.get_child_label(direction) {
-            let child_key = NodeKey(child_label);
+            let child_key = NodeKey::new(child_label);
             let get_result = Self::get_from_storage(storage, &child_key, epoch).await;
             match get_result {
                 Ok(node) => Ok(Some(node)),
@@ -612,7 +651,7 @@ mod tests {
         root.write_to_storage(&db, false).await?;
 
         let stored_root = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(NodeLabel::root()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
             .await?;
 
         let root_smallest_descendant_ep = match stored_root {
@@ -621,7 +660,7 @@ mod tests {
         };
 
         let stored_right_child = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(root.right_child.unwrap()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(root.right_child.unwrap()))
             .await?;
 
         let right_child_smallest_descendant_ep = match stored_right_child {
@@ -630,7 +669,7 @@ mod tests {
         };
 
         let stored_left_child = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(root.left_child.unwrap()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(root.left_child.unwrap()))
             .await?;
 
         let left_child_smallest_descendant_ep = match stored_left_child {
@@ -703,7 +742,7 @@ mod tests {
 
         // Get root hash
         let stored_root = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(NodeLabel::root()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
             .await?;
         let root_digest = match stored_root {
             DbRecord::TreeNode(node) => TC::compute_root_hash_from_val(&node.latest_node.hash),
@@ -788,7 +827,7 @@ mod tests {
         );
 
         let stored_root = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(NodeLabel::root()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
             .await?;
         let root_digest = match stored_root {
             DbRecord::TreeNode(node) => TC::compute_root_hash_from_val(&node.latest_node.hash),
@@ -916,7 +955,7 @@ mod tests {
         );
 
         let stored_root = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(NodeLabel::root()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
             .await?;
         let root_digest = match stored_root {
             DbRecord::TreeNode(node) => TC::compute_root_hash_from_val(&node.latest_node.hash),
@@ -1048,7 +1087,7 @@ mod tests {
         root.write_to_storage(&db, false).await?;
 
         let stored_root = db
-            .get::<TreeNodeWithPreviousValue>(&NodeKey(NodeLabel::root()))
+            .get::<TreeNodeWithPreviousValue>(&NodeKey::new(NodeLabel::root()))
             .await?;
         let root_digest = match stored_root {
             DbRecord::TreeNode(node) => TC::compute_root_hash_from_val(&node.latest_node.hash),
@@ -29,6 +29,23 @@ pub(crate) fn random_label(rng: &mut impl rand::Rng) -> crate::NodeLabel {
     }
 }
 
+/// Instruments a future with a `tracing` span of the given level, name and fields when the
+/// `tracing` feature is enabled, and otherwise returns the future unchanged
+macro_rules! in_span {
+    ( $level:ident, $future:expr, $($span:tt)+ ) => {{
+        // the span is created first, so that its fields may borrow from values the future
+        // takes ownership of
+        #[cfg(feature = "tracing")]
+        let span = tracing::span!(tracing::Level::$level, $($span)+);
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument($future, span);
+        #[cfg(not(feature = "tracing"))]
+        let future = $future;
+        future
+    }};
+}
+pub(crate) use in_span;
+
 /// NOTE(new_config): Add a new configuration here
 
 /// Macro used for running tests with different configurations
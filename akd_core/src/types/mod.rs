@@ -31,7 +31,11 @@ use rand::{CryptoRng, Rng};
 #[cfg(not(feature = "nostd"))]
 use std::cmp::{Ord, Ordering, PartialOrd};
 
+pub mod multi_entry;
+pub mod namespace;
 pub mod node_label;
+pub use multi_entry::*;
+pub use namespace::*;
 pub use node_label::*;
 
 // ============================================
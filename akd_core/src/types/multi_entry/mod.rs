@@ -0,0 +1,203 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! This module contains a structured value of several entries, such as the keys of each of
+//! the devices of an account, which are added, removed and replaced individually.
+//!
+//! A [MultiEntryValue] maps entry identifiers to entry data, and has a single canonical
+//! encoding as an [AkdValue], in which the entries are ordered by their identifiers. Values
+//! are only decoded from their canonical encoding, so that equal sets of entries are always
+//! published as equal values.
+
+use crate::AkdValue;
+
+#[cfg(feature = "nostd")]
+use alloc::collections::BTreeMap;
+#[cfg(feature = "nostd")]
+use alloc::format;
+#[cfg(feature = "nostd")]
+use alloc::string::{String, ToString};
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+#[cfg(not(feature = "nostd"))]
+use std::collections::BTreeMap;
+
+#[cfg(test)]
+mod tests;
+
+/// The version of the encoding of a [MultiEntryValue]
+pub const MULTI_ENTRY_VALUE_FORMAT: u8 = 1;
+
+/// An error decoding a [MultiEntryValue], or applying an [EntryOperation] to one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryError {
+    /// The value is not the canonical encoding of a [MultiEntryValue]
+    Malformed(String),
+    /// An entry was added with the identifier of an existing entry
+    EntryExists(Vec<u8>),
+    /// An entry was removed or replaced which does not exist
+    EntryNotFound(Vec<u8>),
+}
+
+impl core::fmt::Display for EntryError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let code = match &self {
+            EntryError::Malformed(msg) => format!("(Malformed) - {msg}"),
+            EntryError::EntryExists(id) => {
+                format!("(Entry exists) - {}", hex::encode(id))
+            }
+            EntryError::EntryNotFound(id) => {
+                format!("(Entry not found) - {}", hex::encode(id))
+            }
+        };
+        write!(f, "Multi-entry value error {code}")
+    }
+}
+
+/// An operation on a single entry of a [MultiEntryValue]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryOperation {
+    /// Adds an entry with the identifier and data, which must not already exist
+    Add(Vec<u8>, Vec<u8>),
+    /// Removes the entry with the identifier, which must exist
+    Remove(Vec<u8>),
+    /// Replaces the data of the entry with the identifier, which must exist
+    Replace(Vec<u8>, Vec<u8>),
+}
+
+/// A value made up of entries, each with an identifier and data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultiEntryValue {
+    /// The data of each entry, by its identifier
+    pub entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// The changes to the entries of a [MultiEntryValue] from one value to the next
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryDiff {
+    /// The entries added, with their data
+    pub added: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The identifiers of the entries removed
+    pub removed: Vec<Vec<u8>>,
+    /// The entries whose data was replaced, with their new data
+    pub replaced: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl EntryDiff {
+    /// Whether the entries are unchanged
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.replaced.is_empty()
+    }
+}
+
+impl MultiEntryValue {
+    /// Applies the operations in order, returning the resulting value. No operation is
+    /// applied if any of them fails.
+    pub fn apply(&self, operations: &[EntryOperation]) -> Result<Self, EntryError> {
+        let mut entries = self.entries.clone();
+        for operation in operations {
+            match operation {
+                EntryOperation::Add(id, data) => {
+                    if entries.contains_key(id) {
+                        return Err(EntryError::EntryExists(id.clone()));
+                    }
+                    entries.insert(id.clone(), data.clone());
+                }
+                EntryOperation::Remove(id) => {
+                    if entries.remove(id).is_none() {
+                        return Err(EntryError::EntryNotFound(id.clone()));
+                    }
+                }
+                EntryOperation::Replace(id, data) => match entries.get_mut(id) {
+                    Some(existing) => *existing = data.clone(),
+                    None => return Err(EntryError::EntryNotFound(id.clone())),
+                },
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// The changes to the entries from this value to the next
+    pub fn diff(&self, next: &Self) -> EntryDiff {
+        let mut diff = EntryDiff::default();
+        for (id, data) in next.entries.iter() {
+            match self.entries.get(id) {
+                None => diff.added.push((id.clone(), data.clone())),
+                Some(previous) if previous != data => {
+                    diff.replaced.push((id.clone(), data.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for id in self.entries.keys() {
+            if !next.entries.contains_key(id) {
+                diff.removed.push(id.clone());
+            }
+        }
+        diff
+    }
+
+    /// Encodes the value as an [AkdValue], with the entries in the order of their
+    /// identifiers, each identifier and data prefixed by its length
+    pub fn to_value(&self) -> AkdValue {
+        let mut bytes = Vec::new();
+        bytes.push(MULTI_ENTRY_VALUE_FORMAT);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for (id, data) in self.entries.iter() {
+            bytes.extend_from_slice(&(id.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(data);
+        }
+        AkdValue(bytes)
+    }
+
+    /// Decodes a value encoded with [MultiEntryValue::to_value], rejecting any encoding
+    /// which is not canonical
+    pub fn from_value(value: &AkdValue) -> Result<Self, EntryError> {
+        let mut reader = &value[..];
+        match take(&mut reader, 1)? {
+            [MULTI_ENTRY_VALUE_FORMAT] => {}
+            _ => return Err(EntryError::Malformed("unknown format".to_string())),
+        }
+        let count = take_u32(&mut reader)?;
+
+        let mut entries = BTreeMap::new();
+        let mut previous_id: Option<&[u8]> = None;
+        for _ in 0..count {
+            let id_len = take_u32(&mut reader)?;
+            let id = take(&mut reader, id_len)?;
+            let data_len = take_u32(&mut reader)?;
+            let data = take(&mut reader, data_len)?;
+            if matches!(previous_id, Some(previous) if previous >= id) {
+                return Err(EntryError::Malformed(
+                    "entries are not in increasing order of their identifiers".to_string(),
+                ));
+            }
+            previous_id = Some(id);
+            entries.insert(id.to_vec(), data.to_vec());
+        }
+        if !reader.is_empty() {
+            return Err(EntryError::Malformed("trailing bytes".to_string()));
+        }
+        Ok(Self { entries })
+    }
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8], EntryError> {
+    if reader.len() < len {
+        return Err(EntryError::Malformed("too short".to_string()));
+    }
+    let (taken, rest) = reader.split_at(len);
+    *reader = rest;
+    Ok(taken)
+}
+
+fn take_u32(reader: &mut &[u8]) -> Result<usize, EntryError> {
+    let bytes = take(reader, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
@@ -0,0 +1,130 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Tests for multi-entry values
+
+use super::*;
+#[cfg(feature = "nostd")]
+use alloc::vec;
+
+// ================= Test helpers ================= //
+
+fn entry(id: &str, data: &str) -> (Vec<u8>, Vec<u8>) {
+    (id.as_bytes().to_vec(), data.as_bytes().to_vec())
+}
+
+fn multi_entry_value(entries: &[(&str, &str)]) -> MultiEntryValue {
+    MultiEntryValue {
+        entries: entries.iter().map(|(id, data)| entry(id, data)).collect(),
+    }
+}
+
+// ================= Tests ================= //
+
+#[test]
+fn test_multi_entry_encoding() {
+    for value in [
+        MultiEntryValue::default(),
+        multi_entry_value(&[("phone", "key1")]),
+        multi_entry_value(&[("phone", "key1"), ("laptop", ""), ("", "key3")]),
+    ] {
+        assert_eq!(
+            Ok(value.clone()),
+            MultiEntryValue::from_value(&value.to_value())
+        );
+    }
+
+    // the encoding is independent of the order in which the entries were added
+    let added = MultiEntryValue::default()
+        .apply(&[
+            EntryOperation::Add(b"phone".to_vec(), b"key1".to_vec()),
+            EntryOperation::Add(b"laptop".to_vec(), b"key2".to_vec()),
+        ])
+        .unwrap();
+    assert_eq!(
+        multi_entry_value(&[("laptop", "key2"), ("phone", "key1")]).to_value(),
+        added.to_value()
+    );
+}
+
+#[test]
+fn test_multi_entry_rejects_non_canonical_encodings() {
+    let encoded = multi_entry_value(&[("a", "key1"), ("b", "key2")]).to_value();
+
+    let mut unknown_format = encoded.clone();
+    unknown_format[0] = MULTI_ENTRY_VALUE_FORMAT + 1;
+    let mut trailing = encoded.clone();
+    trailing.push(0);
+    let mut truncated = encoded.clone();
+    truncated.pop();
+    // the entries swapped, out of the order of their identifiers
+    let mut unordered = encoded.clone();
+    unordered[9] = b'b';
+    unordered[22] = b'a';
+    let mut duplicate = encoded.clone();
+    duplicate[22] = b'a';
+
+    for value in [
+        AkdValue(vec![]),
+        unknown_format,
+        trailing,
+        truncated,
+        unordered,
+        duplicate,
+    ] {
+        assert!(matches!(
+            MultiEntryValue::from_value(&value),
+            Err(EntryError::Malformed(_))
+        ));
+    }
+}
+
+#[test]
+fn test_multi_entry_operations() {
+    let value = multi_entry_value(&[("phone", "key1"), ("laptop", "key2")]);
+
+    let updated = value
+        .apply(&[
+            EntryOperation::Add(b"tablet".to_vec(), b"key3".to_vec()),
+            EntryOperation::Remove(b"laptop".to_vec()),
+            EntryOperation::Replace(b"phone".to_vec(), b"key4".to_vec()),
+        ])
+        .unwrap();
+    assert_eq!(
+        multi_entry_value(&[("phone", "key4"), ("tablet", "key3")]),
+        updated
+    );
+    assert_eq!(
+        EntryDiff {
+            added: vec![entry("tablet", "key3")],
+            removed: vec![b"laptop".to_vec()],
+            replaced: vec![entry("phone", "key4")],
+        },
+        value.diff(&updated)
+    );
+    assert!(value.diff(&value).is_empty());
+
+    // an operation which cannot be applied fails the operations as a whole
+    assert_eq!(
+        Err(EntryError::EntryExists(b"phone".to_vec())),
+        value.apply(&[EntryOperation::Add(b"phone".to_vec(), b"key3".to_vec())])
+    );
+    assert_eq!(
+        Err(EntryError::EntryNotFound(b"tablet".to_vec())),
+        value.apply(&[
+            EntryOperation::Remove(b"laptop".to_vec()),
+            EntryOperation::Replace(b"tablet".to_vec(), b"key3".to_vec()),
+        ])
+    );
+    assert_eq!(
+        Err(EntryError::EntryNotFound(b"laptop".to_vec())),
+        value.apply(&[
+            EntryOperation::Remove(b"laptop".to_vec()),
+            EntryOperation::Remove(b"laptop".to_vec()),
+        ])
+    );
+}
@@ -0,0 +1,57 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! This module contains the namespaces which separate the labels of several logical label
+//! spaces, such as phone numbers and email addresses, held in a single directory.
+//!
+//! A label within a [Namespace] is held in the directory as the namespaced label
+//! I2OSP(len(namespace) as u64, namespace) || label. As this is the label input to
+//! [Configuration::get_hash_from_label_input](crate::Configuration::get_hash_from_label_input),
+//! from which the VRF label is derived, equal labels in different namespaces have unrelated
+//! VRF labels, and a proof for a label in one namespace does not verify for another.
+
+use crate::AkdLabel;
+
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+
+#[cfg(test)]
+mod tests;
+
+/// A namespace of labels within a directory
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Namespace(pub Vec<u8>);
+
+impl core::convert::From<&str> for Namespace {
+    fn from(s: &str) -> Self {
+        Self(s.as_bytes().to_vec())
+    }
+}
+
+impl Namespace {
+    /// The label held in the directory for a label within the namespace
+    pub fn label(&self, akd_label: &AkdLabel) -> AkdLabel {
+        AkdLabel([&crate::utils::i2osp_array(&self.0)[..], &akd_label[..]].concat())
+    }
+
+    /// Splits a label held in the directory into its namespace and the label within the
+    /// namespace, or [None] if it is not a namespaced label
+    pub fn split(namespaced_label: &AkdLabel) -> Option<(Namespace, AkdLabel)> {
+        if namespaced_label.len() < 8 {
+            return None;
+        }
+        let (len, rest) = namespaced_label.split_at(8);
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(len);
+        let len = usize::try_from(u64::from_be_bytes(len_bytes)).ok()?;
+        if rest.len() < len {
+            return None;
+        }
+        let (namespace, akd_label) = rest.split_at(len);
+        Some((Namespace(namespace.to_vec()), AkdLabel(akd_label.to_vec())))
+    }
+}
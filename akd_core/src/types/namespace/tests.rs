@@ -0,0 +1,37 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Tests for namespaces
+
+use super::*;
+#[cfg(feature = "nostd")]
+use alloc::vec;
+
+#[test]
+fn test_namespaced_labels() {
+    let phone = Namespace::from("phone");
+    let email = Namespace::from("email");
+    let label = AkdLabel::from("alice");
+
+    for namespace in [&phone, &email, &Namespace(vec![])] {
+        assert_eq!(
+            Some((namespace.clone(), label.clone())),
+            Namespace::split(&namespace.label(&label))
+        );
+    }
+    assert_ne!(phone.label(&label), email.label(&label));
+    // the length prefix keeps a namespace and label from being split differently
+    assert_ne!(
+        Namespace::from("ab").label(&AkdLabel::from("c")),
+        Namespace::from("a").label(&AkdLabel::from("bc"))
+    );
+
+    assert_eq!(None, Namespace::split(&AkdLabel::from("alice")));
+    let mut truncated = phone.label(&label);
+    truncated.truncate(10);
+    assert_eq!(None, Namespace::split(&truncated));
+}
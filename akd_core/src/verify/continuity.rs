@@ -0,0 +1,318 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Verification of key continuity, in which each value of a label is signed by the key of
+//! the value it replaces.
+//!
+//! A label with key continuity holds values encoded as a [ContinuityValue], which carries
+//! an ed25519 public key, an opaque payload, and the signature which authorizes the value.
+//! The first value of a label is unsigned, and each subsequent value is signed by the key
+//! of the previous value, binding the label and the previous value as well as the new key
+//! and payload. A value may instead be signed by a recovery key known to clients, such as
+//! for a user who has lost their key.
+
+use super::VerificationError;
+use crate::{AkdLabel, AkdValue, HistoryProof};
+
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use ed25519_dalek::{Signer, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+
+#[cfg(feature = "nostd")]
+use alloc::format;
+#[cfg(feature = "nostd")]
+use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+
+/// The version of the encoding of a [ContinuityValue]
+pub const CONTINUITY_VALUE_FORMAT: u8 = 1;
+
+const SIGNING_DOMAIN: &[u8] = b"akd_key_continuity";
+
+const INITIAL: u8 = 0;
+const SIGNED: u8 = 1;
+const RECOVERY: u8 = 2;
+
+/// What authorizes a [ContinuityValue] to replace the previous value of its label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// The first value of the label, which is unsigned
+    Initial,
+    /// A signature by the key of the previous value of the label
+    Signed(Signature),
+    /// A signature by the recovery key, in place of the key of the previous value
+    Recovery(Signature),
+}
+
+impl Authorization {
+    fn kind(&self) -> u8 {
+        match self {
+            Authorization::Initial => INITIAL,
+            Authorization::Signed(_) => SIGNED,
+            Authorization::Recovery(_) => RECOVERY,
+        }
+    }
+}
+
+/// A value of a label with key continuity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinuityValue {
+    /// The key which signs the next value of the label
+    pub public_key: VerifyingKey,
+    /// What authorizes this value
+    pub authorization: Authorization,
+    /// The opaque value held for the label
+    pub payload: Vec<u8>,
+}
+
+impl ContinuityValue {
+    /// Creates the first value of a label
+    pub fn initial(public_key: VerifyingKey, payload: Vec<u8>) -> Self {
+        Self {
+            public_key,
+            authorization: Authorization::Initial,
+            payload,
+        }
+    }
+
+    /// Creates a value replacing the previous value of the label, signed by the key of the
+    /// previous value
+    pub fn signed(
+        akd_label: &AkdLabel,
+        previous: &AkdValue,
+        signing_key: &SigningKey,
+        public_key: VerifyingKey,
+        payload: Vec<u8>,
+    ) -> Self {
+        let message = signing_message(akd_label, Some(previous), SIGNED, &public_key, &payload);
+        Self {
+            public_key,
+            authorization: Authorization::Signed(signing_key.sign(&message)),
+            payload,
+        }
+    }
+
+    /// Creates a value replacing the previous value of the label (if any), signed by the
+    /// recovery key
+    pub fn recovery(
+        akd_label: &AkdLabel,
+        previous: Option<&AkdValue>,
+        recovery_key: &SigningKey,
+        public_key: VerifyingKey,
+        payload: Vec<u8>,
+    ) -> Self {
+        let message = signing_message(akd_label, previous, RECOVERY, &public_key, &payload);
+        Self {
+            public_key,
+            authorization: Authorization::Recovery(recovery_key.sign(&message)),
+            payload,
+        }
+    }
+
+    /// Encodes the value as an [AkdValue]
+    pub fn to_value(&self) -> AkdValue {
+        let mut bytes =
+            Vec::with_capacity(2 + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH + self.payload.len());
+        bytes.push(CONTINUITY_VALUE_FORMAT);
+        bytes.push(self.authorization.kind());
+        bytes.extend_from_slice(self.public_key.as_bytes());
+        match &self.authorization {
+            Authorization::Initial => {}
+            Authorization::Signed(signature) | Authorization::Recovery(signature) => {
+                bytes.extend_from_slice(&signature.to_bytes())
+            }
+        }
+        bytes.extend_from_slice(&self.payload);
+        AkdValue(bytes)
+    }
+
+    /// Decodes a value encoded with [ContinuityValue::to_value]
+    pub fn from_value(value: &AkdValue) -> Result<Self, VerificationError> {
+        let malformed = |reason: &str| {
+            VerificationError::KeyContinuity(format!("Malformed key continuity value: {reason}"))
+        };
+
+        let (format, kind, rest) = match &value[..] {
+            [format, kind, rest @ ..] => (*format, *kind, rest),
+            _ => return Err(malformed("too short")),
+        };
+        if format != CONTINUITY_VALUE_FORMAT {
+            return Err(malformed("unknown format"));
+        }
+        if rest.len() < PUBLIC_KEY_LENGTH {
+            return Err(malformed("too short"));
+        }
+        let (public_key, rest) = rest.split_at(PUBLIC_KEY_LENGTH);
+        let public_key =
+            VerifyingKey::try_from(public_key).map_err(|_| malformed("invalid public key"))?;
+
+        let (authorization, payload) = if kind == INITIAL {
+            (Authorization::Initial, rest)
+        } else {
+            if rest.len() < SIGNATURE_LENGTH {
+                return Err(malformed("too short"));
+            }
+            let (signature, payload) = rest.split_at(SIGNATURE_LENGTH);
+            let signature =
+                Signature::from_slice(signature).map_err(|_| malformed("invalid signature"))?;
+            match kind {
+                SIGNED => (Authorization::Signed(signature), payload),
+                RECOVERY => (Authorization::Recovery(signature), payload),
+                _ => return Err(malformed("unknown authorization")),
+            }
+        };
+
+        Ok(Self {
+            public_key,
+            authorization,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// The message signed to authorize a value, binding the label, the previous value, and the
+/// kind of authorization along with the new key and payload
+fn signing_message(
+    akd_label: &AkdLabel,
+    previous: Option<&AkdValue>,
+    kind: u8,
+    public_key: &VerifyingKey,
+    payload: &[u8],
+) -> Vec<u8> {
+    let previous: &[u8] = previous.map(|value| &value[..]).unwrap_or_default();
+    [
+        SIGNING_DOMAIN,
+        &(akd_label.len() as u64).to_be_bytes(),
+        &akd_label[..],
+        &(previous.len() as u64).to_be_bytes(),
+        previous,
+        &[kind],
+        public_key.as_bytes(),
+        payload,
+    ]
+    .concat()
+}
+
+/// Verifies that a value may replace the previous value of the label (if any), returning
+/// the decoded value. The previous value must itself be a [ContinuityValue], unless the
+/// value is signed by the recovery key.
+pub fn verify_key_continuity(
+    akd_label: &AkdLabel,
+    previous: Option<&AkdValue>,
+    value: &AkdValue,
+    recovery_key: Option<&VerifyingKey>,
+) -> Result<ContinuityValue, VerificationError> {
+    let continuity_value = ContinuityValue::from_value(value)?;
+    let message = signing_message(
+        akd_label,
+        previous,
+        continuity_value.authorization.kind(),
+        &continuity_value.public_key,
+        &continuity_value.payload,
+    );
+
+    match (&continuity_value.authorization, previous) {
+        (Authorization::Initial, None) => {}
+        (Authorization::Initial, Some(_)) => {
+            return Err(VerificationError::KeyContinuity(
+                "An unsigned value cannot replace an existing value".to_string(),
+            ))
+        }
+        (Authorization::Signed(_), None) => {
+            return Err(VerificationError::KeyContinuity(
+                "A signed value has no previous value to be signed by".to_string(),
+            ))
+        }
+        (Authorization::Signed(signature), Some(previous)) => {
+            let previous = ContinuityValue::from_value(previous)?;
+            previous
+                .public_key
+                .verify_strict(&message, signature)
+                .map_err(|_| {
+                    VerificationError::KeyContinuity(
+                        "The value is not signed by the key of the previous value".to_string(),
+                    )
+                })?;
+        }
+        (Authorization::Recovery(signature), _) => {
+            let recovery_key = recovery_key.ok_or_else(|| {
+                VerificationError::KeyContinuity(
+                    "The value is signed by a recovery key, but none was provided".to_string(),
+                )
+            })?;
+            recovery_key
+                .verify_strict(&message, signature)
+                .map_err(|_| {
+                    VerificationError::KeyContinuity(
+                        "The value is not signed by the recovery key".to_string(),
+                    )
+                })?;
+        }
+    }
+
+    Ok(continuity_value)
+}
+
+/// Verifies the signature chain of the values in a key history proof, which should have
+/// been verified with [key_history_verify](super::key_history_verify). Each value must be
+/// authorized to replace the value preceding it in the proof. The oldest value in the
+/// proof is only checked to be authorized if it is the first version of the label, as the
+/// value preceding it is otherwise not included.
+///
+/// Tombstoned values (see [crate::TOMBSTONE]) are no longer known, so the chain is not
+/// checked across them: a value following a tombstoned value is not checked to be
+/// authorized, and a tombstoned value is not checked at all.
+pub fn key_continuity_verify(
+    akd_label: &AkdLabel,
+    proof: &HistoryProof,
+    recovery_key: Option<&VerifyingKey>,
+) -> Result<(), VerificationError> {
+    let is_tombstone = |value: &AkdValue| value.0 == crate::TOMBSTONE;
+
+    // The update proofs are in decreasing order of version
+    for (update_proof, previous_proof) in proof
+        .update_proofs
+        .iter()
+        .zip(proof.update_proofs.iter().skip(1))
+    {
+        if update_proof.version != previous_proof.version + 1 {
+            return Err(VerificationError::KeyContinuity(format!(
+                "Version {} does not follow version {}",
+                update_proof.version, previous_proof.version
+            )));
+        }
+        if is_tombstone(&update_proof.value) {
+            continue;
+        }
+        if is_tombstone(&previous_proof.value) {
+            ContinuityValue::from_value(&update_proof.value)?;
+            continue;
+        }
+        verify_key_continuity(
+            akd_label,
+            Some(&previous_proof.value),
+            &update_proof.value,
+            recovery_key,
+        )?;
+    }
+
+    match proof.update_proofs.last() {
+        Some(oldest) if is_tombstone(&oldest.value) => {}
+        Some(oldest) if oldest.version == 1 => {
+            verify_key_continuity(akd_label, None, &oldest.value, recovery_key)?;
+        }
+        Some(oldest) => {
+            ContinuityValue::from_value(&oldest.value)?;
+        }
+        None => {
+            return Err(VerificationError::KeyContinuity(
+                "No update proofs included in the proof".to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
@@ -12,7 +12,7 @@ use super::VerificationError;
 
 use crate::configuration::Configuration;
 use crate::hash::Digest;
-use crate::{AkdLabel, LookupProof, VerifyResult, VersionFreshness};
+use crate::{AkdLabel, LookupProof, Namespace, VerifyResult, VersionFreshness};
 
 /// Verifies a lookup with respect to the root_hash
 pub fn lookup_verify<TC: Configuration>(
@@ -70,3 +70,23 @@ pub fn lookup_verify<TC: Configuration>(
         value: proof.value,
     })
 }
+
+/// Verifies a lookup of a label within a namespace with respect to the root_hash. The
+/// proof is verified for the namespaced label, so a proof for the label in another
+/// namespace is rejected.
+pub fn namespaced_lookup_verify<TC: Configuration>(
+    vrf_public_key: &[u8],
+    root_hash: Digest,
+    current_epoch: u64,
+    namespace: &Namespace,
+    akd_label: AkdLabel,
+    proof: LookupProof,
+) -> Result<VerifyResult, VerificationError> {
+    lookup_verify::<TC>(
+        vrf_public_key,
+        root_hash,
+        current_epoch,
+        namespace.label(&akd_label),
+        proof,
+    )
+}
@@ -8,8 +8,11 @@
 //! This module contains verification calls for different proofs contained in the AKD crate
 
 pub mod base;
+#[cfg(feature = "vrf")]
+pub mod continuity;
 pub mod history;
 pub mod lookup;
+pub mod multi_entry;
 
 #[cfg(feature = "nostd")]
 use alloc::format;
@@ -29,6 +32,8 @@ pub enum VerificationError {
     LookupProof(String),
     /// Error verifying a history proof
     HistoryProof(String),
+    /// Error verifying the key continuity of a label
+    KeyContinuity(String),
     /// Error verifying a VRF proof
     #[cfg(feature = "vrf")]
     Vrf(crate::ecvrf::VrfError),
@@ -46,6 +51,7 @@ impl core::fmt::Display for VerificationError {
             }
             VerificationError::LookupProof(err) => format!("(Lookup proof) - {err}"),
             VerificationError::HistoryProof(err) => format!("(History proof) - {err}"),
+            VerificationError::KeyContinuity(err) => format!("(Key continuity) - {err}"),
             #[cfg(feature = "vrf")]
             VerificationError::Vrf(vrf) => vrf.to_string(),
             #[cfg(feature = "protobuf")]
@@ -82,5 +88,8 @@ impl From<protobuf::Error> for VerificationError {
 #[cfg(feature = "public_tests")]
 pub use base::{verify_membership_for_tests_only, verify_nonmembership_for_tests_only};
 
+#[cfg(feature = "vrf")]
+pub use continuity::{key_continuity_verify, verify_key_continuity, ContinuityValue};
 pub use history::{key_history_verify, HistoryVerificationParams};
-pub use lookup::lookup_verify;
+pub use lookup::{lookup_verify, namespaced_lookup_verify};
+pub use multi_entry::{history_entry_diffs, VersionDiff};
@@ -0,0 +1,94 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Verification of the changes to the entries of a label holding [MultiEntryValue]s
+
+use super::VerificationError;
+use crate::{EntryDiff, HistoryProof, MultiEntryValue};
+
+#[cfg(feature = "nostd")]
+use alloc::format;
+#[cfg(feature = "nostd")]
+use alloc::string::ToString;
+#[cfg(feature = "nostd")]
+use alloc::vec::Vec;
+
+/// The changes to the entries of a label made by one of its versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionDiff {
+    /// The version of the label
+    pub version: u64,
+    /// The epoch in which the version was published
+    pub epoch: u64,
+    /// The changes from the previous version
+    pub diff: EntryDiff,
+}
+
+/// Computes the changes to the entries made by each version of a label in a key history
+/// proof, which should have been verified with [key_history_verify](super::key_history_verify).
+/// The diffs are in decreasing order of version, as are the update proofs. The first version
+/// of a label is diffed against no entries, and the oldest version in the proof is otherwise
+/// left out, as the value preceding it is not included.
+///
+/// Tombstoned values (see [crate::TOMBSTONE]) are no longer known, so the diffs stop at the
+/// newest tombstoned version: neither it nor the version following it are diffed, nor are
+/// any older versions.
+pub fn history_entry_diffs(proof: &HistoryProof) -> Result<Vec<VersionDiff>, VerificationError> {
+    if proof.update_proofs.is_empty() {
+        return Err(VerificationError::HistoryProof(
+            "No update proofs included in the proof".to_string(),
+        ));
+    }
+    let values = proof
+        .update_proofs
+        .iter()
+        .map(|update_proof| {
+            if update_proof.value.0 == crate::TOMBSTONE {
+                return Ok(None);
+            }
+            MultiEntryValue::from_value(&update_proof.value)
+                .map(Some)
+                .map_err(|err| {
+                    VerificationError::HistoryProof(format!(
+                        "Version {} is not a multi-entry value: {err}",
+                        update_proof.version
+                    ))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let no_entries = MultiEntryValue::default();
+    let mut diffs = Vec::with_capacity(values.len());
+    for (index, update_proof) in proof.update_proofs.iter().enumerate() {
+        let value = match &values[index] {
+            Some(value) => value,
+            None => break,
+        };
+        let previous = match proof.update_proofs.get(index + 1) {
+            Some(previous_proof) if previous_proof.version + 1 == update_proof.version => {
+                match &values[index + 1] {
+                    Some(previous) => previous,
+                    None => break,
+                }
+            }
+            Some(previous_proof) => {
+                return Err(VerificationError::HistoryProof(format!(
+                    "Version {} does not follow version {}",
+                    update_proof.version, previous_proof.version
+                )))
+            }
+            None if update_proof.version == 1 => &no_entries,
+            None => break,
+        };
+        diffs.push(VersionDiff {
+            version: update_proof.version,
+            epoch: update_proof.epoch,
+            diff: previous.diff(value),
+        });
+    }
+    Ok(diffs)
+}
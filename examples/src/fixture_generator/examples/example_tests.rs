@@ -12,7 +12,10 @@ use std::fs::File;
 use akd::{
     directory::Directory,
     ecvrf::HardCodedAkdVRF,
-    storage::{memory::AsyncInMemoryDatabase, Database, StorageManager, StorageUtil},
+    storage::{
+        memory::AsyncInMemoryDatabase, migration::MigrationRegistry, types::DbRecord, Database,
+        StorageManager, StorageUtil,
+    },
     NamedConfiguration,
 };
 
@@ -37,6 +40,8 @@ async fn test_use_fixture<TC: NamedConfiguration>() {
     db.batch_set(initial_state.records, akd::storage::DbSetState::General)
         .await
         .unwrap();
+    // the fixture predates schema versioning, so its records are migrated first
+    MigrationRegistry::new().migrate(&db).await.unwrap();
     let vrf = HardCodedAkdVRF {};
     let storage_manager = StorageManager::new_no_cache(db);
     let akd = Directory::<TC, _, _>::new(storage_manager.clone(), vrf)
@@ -53,7 +58,10 @@ async fn test_use_fixture<TC: NamedConfiguration>() {
         .get_db()
         .batch_get_all_direct()
         .await
-        .unwrap();
+        .unwrap()
+        .into_iter()
+        .filter(|record| !matches!(record, DbRecord::SchemaVersion(_)))
+        .collect::<Vec<_>>();
     assert_eq!(final_state.records.len(), records.len());
     assert!(records.iter().all(|r| final_state.records.contains(r)));
 }
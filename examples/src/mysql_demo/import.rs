@@ -0,0 +1,101 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+//! Streaming readers of label-value dumps, for bootstrapping a directory
+
+use akd::{AkdLabel, AkdValue};
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::BufRead;
+
+/// The formats of label-value dumps
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum ImportFormat {
+    /// A `label,value` row per entry, with an optional `label,value` header. Fields containing
+    /// commas or quotes are quoted, with quotes escaped by doubling them.
+    Csv,
+    /// A `{"label": ..., "value": ...}` object per entry, separated by whitespace
+    Json,
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    label: String,
+    value: String,
+}
+
+/// Reads the entries of a dump one at a time
+pub(crate) fn read_entries<'a, R: BufRead + Send + 'a>(
+    reader: R,
+    format: ImportFormat,
+) -> Box<dyn Iterator<Item = Result<(AkdLabel, AkdValue)>> + Send + 'a> {
+    match format {
+        ImportFormat::Csv => Box::new(
+            reader
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+                .filter(|(index, line)| {
+                    *index != 0 || !matches!(line, Ok(line) if line.trim() == "label,value")
+                })
+                .map(|(index, line)| {
+                    let line = line?;
+                    parse_csv_row(&line)
+                        .map_err(|err| anyhow!("Malformed row on line {}: {err}", index + 1))
+                }),
+        ),
+        ImportFormat::Json => Box::new(
+            serde_json::Deserializer::from_reader(reader)
+                .into_iter::<JsonEntry>()
+                .enumerate()
+                .map(|(index, entry)| {
+                    let entry =
+                        entry.map_err(|err| anyhow!("Malformed entry {}: {err}", index + 1))?;
+                    Ok((AkdLabel::from(&entry.label), AkdValue::from(&entry.value)))
+                }),
+        ),
+    }
+}
+
+fn parse_csv_row(line: &str) -> Result<(AkdLabel, AkdValue)> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => bail!("unterminated quoted field"),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                bail!("unexpected characters after a quoted field");
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            break;
+        }
+    }
+
+    match &fields[..] {
+        [label, value] => Ok((AkdLabel::from(label), AkdValue::from(value))),
+        other => bail!("expected 2 fields, found {}", other.len()),
+    }
+}
@@ -8,6 +8,8 @@
 //! An example tool for running AKD backed by MySQL storage
 
 use akd::ecvrf::HardCodedAkdVRF;
+use akd::errors::StorageError;
+use akd::storage::transfer::{copy_directory, CopyCheckpoint, CopyReport};
 use akd::storage::StorageManager;
 use akd::Directory;
 use clap::{Parser, ValueEnum};
@@ -19,12 +21,14 @@ use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use std::convert::From;
 use std::io::*;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::*;
 use tokio::time::timeout;
 
 mod commands;
 mod directory_host;
+mod import;
 mod logs;
 mod mysql;
 mod mysql_storables;
@@ -32,6 +36,7 @@ mod mysql_storables;
 #[cfg(test)]
 mod tests;
 
+use import::ImportFormat;
 use logs::ConsoleLogger;
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -74,6 +79,26 @@ enum OtherMode {
     Flush,
     #[clap(about = "Drop existing database tables (for schema migration etc.)")]
     Drop,
+    #[clap(about = "Copy the directory to another database")]
+    Copy {
+        /// The MySQL database to copy to, or an in-memory database if omitted
+        #[clap(long = "target")]
+        target: Option<String>,
+        /// The number of records read from the source at a time
+        #[clap(long = "page_size", default_value = "1000")]
+        page_size: usize,
+        /// A file recording the progress of the copy, from which an interrupted copy is resumed
+        #[clap(long = "checkpoint")]
+        checkpoint: Option<PathBuf>,
+    },
+    #[clap(about = "Bootstrap an empty directory from a dump of labels and values")]
+    Import {
+        /// The dump to import
+        path: PathBuf,
+        /// The format of the dump
+        #[clap(value_enum, long = "format", default_value = "csv", ignore_case = true)]
+        format: ImportFormat,
+    },
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -202,9 +227,151 @@ async fn pre_process_input(cli: &CliArgs, db: Option<&AsyncMySqlDatabase>) -> Op
             return Option::from(());
         }
     }
+    if let Some(OtherMode::Copy {
+        target,
+        page_size,
+        checkpoint,
+    }) = &cli.other_mode
+    {
+        println!("======= Copying database ======= ");
+        match db {
+            Some(mysql_db) => {
+                let source = StorageManager::new_no_cache(mysql_db.clone());
+                let result = match target {
+                    Some(target) => {
+                        let target_db = AsyncMySqlDatabase::new(
+                            "localhost",
+                            target,
+                            Option::from("root"),
+                            Option::from("example"),
+                            Option::from(8001),
+                            cli.mysql_insert_depth,
+                        )
+                        .await
+                        .expect("Failed to create async mysql db");
+                        copy_database(
+                            &source,
+                            &StorageManager::new_no_cache(target_db),
+                            *page_size,
+                            checkpoint.as_ref(),
+                        )
+                        .await
+                    }
+                    None => {
+                        let target_db = akd::storage::memory::AsyncInMemoryDatabase::new();
+                        copy_database(
+                            &source,
+                            &StorageManager::new_no_cache(target_db),
+                            *page_size,
+                            checkpoint.as_ref(),
+                        )
+                        .await
+                    }
+                };
+                match result {
+                    Ok(report) => info!(
+                        "Copied {} records of the directory at epoch {} with root hash {}",
+                        report.num_records_copied,
+                        report.epoch,
+                        hex::encode(report.root_hash)
+                    ),
+                    Err(error) => error!("Error copying database: {}", error),
+                }
+            }
+            None => error!("Command available with MySQL db's only"),
+        }
+        return Option::from(());
+    }
+    if let Some(OtherMode::Import { path, format }) = &cli.other_mode {
+        println!("======= Importing database ======= ");
+        match db {
+            Some(mysql_db) => match import_entries(mysql_db, path, *format).await {
+                Ok(epoch_hash) => info!(
+                    "Bootstrapped the directory at epoch {} with root hash {}",
+                    epoch_hash.epoch(),
+                    hex::encode(epoch_hash.hash())
+                ),
+                Err(error) => error!("Error importing database: {}", error),
+            },
+            None => error!("Command available with MySQL db's only"),
+        }
+        return Option::from(());
+    }
     None
 }
 
+/// Bootstraps the directory from the dump. An entry which fails to be read fails the
+/// bootstrap, which reads every entry before writing anything.
+async fn import_entries(
+    mysql_db: &AsyncMySqlDatabase,
+    path: &PathBuf,
+    format: ImportFormat,
+) -> anyhow::Result<akd::EpochHash> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    info!("Importing the entries of {path:?}");
+
+    let storage_manager = StorageManager::new_no_cache(mysql_db.clone());
+    let directory = Directory::<TC, _, _>::new(storage_manager, HardCodedAkdVRF {}).await?;
+    let tic = Instant::now();
+    let mut num_entries = 0u64;
+    let entries = import::read_entries(reader, format).map(|entry| {
+        num_entries += 1;
+        entry.map_err(|err| {
+            akd::errors::AkdError::Storage(StorageError::Other(format!(
+                "Failed to read entry {num_entries} of the dump: {err}"
+            )))
+        })
+    });
+    let epoch_hash = directory.try_bootstrap(entries).await?;
+    info!(
+        "Imported {num_entries} entries in {} s",
+        Instant::now().duration_since(tic).as_secs_f64()
+    );
+    Ok(epoch_hash)
+}
+
+/// Copies the directory, resuming from and recording progress to the checkpoint file if
+/// provided
+async fn copy_database<Dst: akd::storage::StorageUtil>(
+    source: &StorageManager<AsyncMySqlDatabase>,
+    target: &StorageManager<Dst>,
+    page_size: usize,
+    checkpoint_file: Option<&PathBuf>,
+) -> core::result::Result<CopyReport, akd::errors::AkdError> {
+    let resume_from = match checkpoint_file.map(std::fs::read_to_string) {
+        Some(Ok(contents)) => {
+            let bytes = hex::decode(contents.trim())
+                .map_err(|err| StorageError::Other(format!("Malformed checkpoint file: {err}")))?;
+            let checkpoint = CopyCheckpoint::from_bytes(&bytes)?;
+            info!(
+                "Resuming copy after {} records",
+                checkpoint.num_records_copied
+            );
+            Some(checkpoint)
+        }
+        Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => {
+            return Err(
+                StorageError::Other(format!("Failed to read the checkpoint file: {err}")).into(),
+            );
+        }
+        _ => None,
+    };
+    let report = copy_directory::<TC, _, _>(source, target, page_size, resume_from, |progress| {
+        debug!("Copied {} records", progress.num_records_copied);
+        if let Some(path) = checkpoint_file {
+            if let Err(err) = std::fs::write(path, hex::encode(progress.to_bytes())) {
+                warn!("Failed to record the copy checkpoint: {err}");
+            }
+        }
+    })
+    .await?;
+    if let Some(path) = checkpoint_file {
+        // the copy is complete, so there is nothing to resume
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(report)
+}
+
 async fn process_input(
     cli: &CliArgs,
     tx: &Sender<directory_host::Rpc>,
@@ -420,6 +587,9 @@ async fn process_input(
                     }
                 }
             }
+            OtherMode::Copy { .. } | OtherMode::Import { .. } => {
+                // handled prior to the directory being created
+            }
         }
     } else {
         // Traditional REPL processing loop
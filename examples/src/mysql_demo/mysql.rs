@@ -7,13 +7,15 @@
 
 //! This module implements operations for a simple asynchronized mysql database
 
-use crate::mysql_demo::mysql_storables::MySqlStorable;
+use crate::mysql_demo::mysql_storables::{decode_tree, encode_tree, MySqlStorable};
 use akd::errors::StorageError;
 use akd::hash::DIGEST_BYTES;
-use akd::storage::types::{DbRecord, KeyData, StorageType, ValueState, ValueStateRetrievalFlag};
-use akd::storage::{Database, Storable};
+use akd::storage::types::{
+    DbRecord, EpochChangeset, KeyData, PendingEpoch, RecordMac, SchemaVersion, StorageType, TreeId,
+    ValueState, ValueStateRetrievalFlag,
+};
+use akd::storage::{Database, Storable, StorageUtil};
 use akd::tree_node::TreeNodeWithPreviousValue;
-use akd::NodeLabel;
 use akd::{AkdLabel, AkdValue};
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
@@ -32,6 +34,10 @@ type MySqlError = mysql_async::Error;
 const TABLE_AZKS: &str = crate::mysql_demo::mysql_storables::TABLE_AZKS;
 const TABLE_HISTORY_TREE_NODES: &str = crate::mysql_demo::mysql_storables::TABLE_HISTORY_TREE_NODES;
 const TABLE_USER: &str = crate::mysql_demo::mysql_storables::TABLE_USER;
+const TABLE_EPOCH_CHANGESETS: &str = crate::mysql_demo::mysql_storables::TABLE_EPOCH_CHANGESETS;
+const TABLE_SCHEMA_VERSION: &str = crate::mysql_demo::mysql_storables::TABLE_SCHEMA_VERSION;
+const TABLE_RECORD_MACS: &str = crate::mysql_demo::mysql_storables::TABLE_RECORD_MACS;
+const TABLE_PENDING_EPOCHS: &str = crate::mysql_demo::mysql_storables::TABLE_PENDING_EPOCHS;
 const TEMP_IDS_TABLE: &str = crate::mysql_demo::mysql_storables::TEMP_IDS_TABLE;
 
 const MAXIMUM_SQL_TIER_CONNECTION_TIMEOUT_SECS: u64 = 300;
@@ -43,6 +49,28 @@ enum BatchMode {
     None,
 }
 
+// MySQL server error codes of a lock wait timeout, and of a deadlock
+const ER_LOCK_WAIT_TIMEOUT: u16 = 1205;
+const ER_LOCK_DEADLOCK: u16 = 1213;
+
+/// Convert a MySQL error into a [StorageError], reporting transient failures (of the
+/// connection, or of lock contention) as retryable [StorageError::Connection] errors
+fn to_storage_error(error: MySqlError) -> StorageError {
+    let message = format!("MySQL Error {error}");
+    match &error {
+        MySqlError::Io(_)
+        | MySqlError::Driver(
+            mysql_async::DriverError::ConnectionClosed | mysql_async::DriverError::PoolDisconnected,
+        ) => StorageError::Connection(message),
+        MySqlError::Server(server)
+            if server.code == ER_LOCK_WAIT_TIMEOUT || server.code == ER_LOCK_DEADLOCK =>
+        {
+            StorageError::Connection(message)
+        }
+        _ => StorageError::Other(message),
+    }
+}
+
 // MySQL's max supported text size is 65535
 // Of the prepared insert's below in this logic,
 // we have a max-string size of 267 + N(190).
@@ -298,7 +326,8 @@ impl<'a> AsyncMySqlDatabase {
         let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
             + TABLE_AZKS
             + "` (`key` SMALLINT UNSIGNED NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL,"
-            + " `num_nodes` BIGINT UNSIGNED NOT NULL, PRIMARY KEY (`key`))";
+            + " `num_nodes` BIGINT UNSIGNED NOT NULL, `tree` VARBINARY(8) NOT NULL DEFAULT '',"
+            + " PRIMARY KEY (`tree`, `key`))";
         tx.query_drop(command).await?;
 
         // History tree nodes table
@@ -317,16 +346,46 @@ impl<'a> AsyncMySqlDatabase {
             + " `p_node_type` SMALLINT UNSIGNED, `p_left_child_len` INT UNSIGNED, `p_left_child_label_val` VARBINARY(32), "
             + " `p_right_child_len` INT UNSIGNED, `p_right_child_label_val` VARBINARY(32), `p_hash` VARBINARY("
             + &DIGEST_BYTES.to_string()
-            + "),"
-            + " PRIMARY KEY (`label_len`, `label_val`))";
+            + "), `tree` VARBINARY(8) NOT NULL DEFAULT '',"
+            + " PRIMARY KEY (`tree`, `label_len`, `label_val`))";
         tx.query_drop(command).await?;
 
         // User data table
         let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
             + TABLE_USER
-            + "` (`username` VARBINARY(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, `version` BIGINT UNSIGNED NOT NULL,"
+            + "` (`username` VARBINARY(512) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, `version` BIGINT UNSIGNED NOT NULL,"
             + " `node_label_val` VARBINARY(32) NOT NULL, `node_label_len` INT UNSIGNED NOT NULL, `data` VARBINARY(2000),"
-            + " PRIMARY KEY(`username`, `epoch`))";
+            + " `tree` VARBINARY(8) NOT NULL DEFAULT '', PRIMARY KEY(`tree`, `username`, `epoch`))";
+        tx.query_drop(command).await?;
+
+        // Epoch changesets table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + TABLE_EPOCH_CHANGESETS
+            + "` (`epoch` BIGINT UNSIGNED NOT NULL, `part` INT UNSIGNED NOT NULL,"
+            + " `num_parts` INT UNSIGNED NOT NULL, `node_labels` LONGBLOB NOT NULL,"
+            + " `tree` VARBINARY(8) NOT NULL DEFAULT '', PRIMARY KEY(`tree`, `epoch`, `part`))";
+        tx.query_drop(command).await?;
+
+        // Schema version table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + TABLE_SCHEMA_VERSION
+            + "` (`key` SMALLINT UNSIGNED NOT NULL, `version` BIGINT UNSIGNED NOT NULL,"
+            + " PRIMARY KEY (`key`))";
+        tx.query_drop(command).await?;
+
+        // Record MACs table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + TABLE_RECORD_MACS
+            + "` (`record_key` VARBINARY(512) NOT NULL, `mac` BINARY(32) NOT NULL,"
+            + " PRIMARY KEY(`record_key`))";
+        tx.query_drop(command).await?;
+
+        // Pending epochs table
+        let command = "CREATE TABLE IF NOT EXISTS `".to_owned()
+            + TABLE_PENDING_EPOCHS
+            + "` (`epoch` BIGINT UNSIGNED NOT NULL, `operation` TINYINT UNSIGNED NOT NULL,"
+            + " `next_part` INT UNSIGNED NOT NULL, `tree` VARBINARY(8) NOT NULL DEFAULT '',"
+            + " PRIMARY KEY (`tree`))";
         tx.query_drop(command).await?;
 
         // if we got here, we're good to commit. Transaction's will auto-rollback when memory freed if commit wasn't done.
@@ -348,6 +407,18 @@ impl<'a> AsyncMySqlDatabase {
         let command = "DELETE FROM `".to_owned() + TABLE_HISTORY_TREE_NODES + "`";
         tx.query_drop(command).await?;
 
+        let command = "DELETE FROM `".to_owned() + TABLE_EPOCH_CHANGESETS + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DELETE FROM `".to_owned() + TABLE_SCHEMA_VERSION + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DELETE FROM `".to_owned() + TABLE_RECORD_MACS + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DELETE FROM `".to_owned() + TABLE_PENDING_EPOCHS + "`";
+        tx.query_drop(command).await?;
+
         tx.commit().await?;
 
         Ok(())
@@ -367,6 +438,18 @@ impl<'a> AsyncMySqlDatabase {
         let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_HISTORY_TREE_NODES + "`";
         tx.query_drop(command).await?;
 
+        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_EPOCH_CHANGESETS + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_SCHEMA_VERSION + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_RECORD_MACS + "`";
+        tx.query_drop(command).await?;
+
+        let command = "DROP TABLE IF EXISTS `".to_owned() + TABLE_PENDING_EPOCHS + "`";
+        tx.query_drop(command).await?;
+
         tx.commit().await?;
 
         Ok(())
@@ -439,6 +522,18 @@ impl<'a> AsyncMySqlDatabase {
                 DbRecord::ValueState(_) => {
                     DbRecord::set_batch_statement::<akd::storage::types::ValueState>(i)
                 }
+                DbRecord::EpochChangeset(_) => {
+                    DbRecord::set_batch_statement::<akd::storage::types::EpochChangeset>(i)
+                }
+                DbRecord::SchemaVersion(_) => {
+                    DbRecord::set_batch_statement::<akd::storage::types::SchemaVersion>(i)
+                }
+                DbRecord::RecordMac(_) => {
+                    DbRecord::set_batch_statement::<akd::storage::types::RecordMac>(i)
+                }
+                DbRecord::PendingEpoch(_) => {
+                    DbRecord::set_batch_statement::<akd::storage::types::PendingEpoch>(i)
+                }
             }
         };
 
@@ -624,10 +719,49 @@ impl<'a> AsyncMySqlDatabase {
             ))),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
             }
         }
     }
+
+    /// Parse a row of the user table, selected as `username`, `epoch`, `version`,
+    /// `node_label_val`, `node_label_len`, `data`, `tree`
+    fn value_state_from_row(row: &mut Row) -> Option<ValueState> {
+        if let (
+            Some(username),
+            Some(epoch),
+            Some(version),
+            Some(node_label_val),
+            Some(node_label_len),
+            Some(data),
+            Some(tree),
+        ) = (
+            row.take(0),
+            row.take(1),
+            row.take(2),
+            row.take::<Vec<_>, _>(3),
+            row.take(4),
+            row.take(5),
+            row.take::<Vec<u8>, _>(6),
+        ) {
+            // explicitly check the array length for safety
+            let r: core::result::Result<[u8; 32], _> = node_label_val.try_into();
+            if let (Ok(label_val), Some(tree)) = (r, decode_tree(&tree)) {
+                return Some(
+                    DbRecord::build_user_state(
+                        username,
+                        data,
+                        version,
+                        node_label_len,
+                        label_val,
+                        epoch,
+                    )
+                    .with_tree(tree),
+                );
+            }
+        }
+        None
+    }
 }
 
 #[async_trait]
@@ -638,7 +772,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(_) => Ok(()),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -669,6 +803,22 @@ impl Database for AsyncMySqlDatabase {
                     .entry(StorageType::ValueState)
                     .or_insert_with(Vec::new)
                     .push(record),
+                DbRecord::EpochChangeset(_) => groups
+                    .entry(StorageType::EpochChangeset)
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::SchemaVersion(_) => groups
+                    .entry(StorageType::SchemaVersion)
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::RecordMac(_) => groups
+                    .entry(StorageType::RecordMac)
+                    .or_insert_with(Vec::new)
+                    .push(record),
+                DbRecord::PendingEpoch(_) => groups
+                    .entry(StorageType::PendingEpoch)
+                    .or_insert_with(Vec::new)
+                    .push(record),
             }
         }
         // now execute each type'd batch in batch operations
@@ -720,7 +870,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(_) => Ok(()),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -852,7 +1002,7 @@ impl Database for AsyncMySqlDatabase {
             }
             Err(error) => {
                 error!("MySQL error {}", error);
-                return Err(StorageError::Other(format!("MySQL Error {error}")));
+                return Err(to_storage_error(error));
             }
         }
 
@@ -862,6 +1012,7 @@ impl Database for AsyncMySqlDatabase {
     async fn get_user_data(
         &self,
         username: &AkdLabel,
+        tree: Option<TreeId>,
     ) -> core::result::Result<KeyData, StorageError> {
         // This is the same as previous logic under "get_all"
 
@@ -872,47 +1023,18 @@ impl Database for AsyncMySqlDatabase {
         let result = async {
             let mut conn = self.get_connection().await?;
             let statement_text =
-                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data` FROM `"
+                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `tree` FROM `"
                     .to_owned()
                     + TABLE_USER
-                    + "` WHERE `username` = :the_user";
+                    + "` WHERE `tree` = :the_tree AND `username` = :the_user";
             let mut result = conn
-                .exec_iter(statement_text, params! { "the_user" => username.0.clone() })
+                .exec_iter(
+                    statement_text,
+                    params! { "the_tree" => encode_tree(tree), "the_user" => username.0.clone() },
+                )
                 .await?;
             let out = result
-                .map(|mut row| {
-                    if let (
-                        Some(username),
-                        Some(epoch),
-                        Some(version),
-                        Some(node_label_val),
-                        Some(node_label_len),
-                        Some(data),
-                    ) = (
-                        row.take(0),
-                        row.take(1),
-                        row.take(2),
-                        row.take::<Vec<u8>, _>(3),
-                        row.take(4),
-                        row.take(5),
-                    ) {
-                        // explicitly check the array length for safety
-                        let r: core::result::Result<[u8; 32], _> = node_label_val.try_into();
-                        if let Ok(label_val) = r {
-                            return Some(ValueState {
-                                epoch,
-                                version,
-                                label: NodeLabel {
-                                    label_val,
-                                    label_len: node_label_len,
-                                },
-                                value: AkdValue(data),
-                                username: AkdLabel(username),
-                            });
-                        }
-                    }
-                    None
-                })
+                .map(|mut row| Self::value_state_from_row(&mut row))
                 .await
                 .map(|a| a.into_iter().flatten().collect::<Vec<_>>());
 
@@ -926,7 +1048,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(output) => Ok(output),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -935,6 +1057,7 @@ impl Database for AsyncMySqlDatabase {
         &self,
         username: &AkdLabel,
         flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
     ) -> core::result::Result<ValueState, StorageError> {
         self.record_call_stats('r', "get_user_state".to_string(), "".to_string())
             .await;
@@ -942,11 +1065,14 @@ impl Database for AsyncMySqlDatabase {
         let result = async {
             let mut conn = self.get_connection().await?;
             let mut statement_text =
-                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data` FROM `"
+                "SELECT `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `tree` FROM `"
                     .to_owned()
                     + TABLE_USER
-                    + "` WHERE `username` = :the_user";
-            let mut params_map = vec![("the_user", Value::from(&username.0))];
+                    + "` WHERE `tree` = :the_tree AND `username` = :the_user";
+            let mut params_map = vec![
+                ("the_tree", Value::from(encode_tree(tree))),
+                ("the_user", Value::from(&username.0)),
+            ];
             // apply the specific filter
             match flag {
                 ValueStateRetrievalFlag::SpecificVersion(version) => {
@@ -970,39 +1096,7 @@ impl Database for AsyncMySqlDatabase {
             let out = conn
                 .exec_iter(statement_text, mysql_async::Params::from(params_map))
                 .await?
-                .map(|mut row| {
-                    if let (
-                        Some(username),
-                        Some(epoch),
-                        Some(version),
-                        Some(node_label_val),
-                        Some(node_label_len),
-                        Some(data),
-                    ) = (
-                        row.take(0),
-                        row.take(1),
-                        row.take(2),
-                        row.take::<Vec<_>, _>(3),
-                        row.take(4),
-                        row.take(5),
-                    ) {
-                        // explicitly check the array length for safety
-                        let r: core::result::Result<[u8; 32], _> = node_label_val.try_into();
-                        if let Ok(label_val) = r {
-                            return Some(ValueState {
-                                epoch,
-                                version,
-                                label: NodeLabel {
-                                    label_val,
-                                    label_len: node_label_len,
-                                },
-                                value: AkdValue(data),
-                                username: AkdLabel(username),
-                            });
-                        }
-                    }
-                    None
-                })
+                .map(|mut row| Self::value_state_from_row(&mut row))
                 .await
                 .map(|a| a.into_iter().flatten().collect::<Vec<_>>());
 
@@ -1015,7 +1109,7 @@ impl Database for AsyncMySqlDatabase {
             Ok(None) => Err(StorageError::NotFound(format!("ValueState {username:?}"))),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
             }
         }
     }
@@ -1024,8 +1118,23 @@ impl Database for AsyncMySqlDatabase {
         &self,
         keys: &[AkdLabel],
         flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
     ) -> core::result::Result<HashMap<AkdLabel, (u64, AkdValue)>, StorageError> {
-        self.record_call_stats('r', "get_user_state_versions".to_string(), "".to_string())
+        Ok(self
+            .get_user_states(keys, flag, tree)
+            .await?
+            .into_iter()
+            .map(|(username, state)| (username, (state.version, state.value)))
+            .collect())
+    }
+
+    async fn get_user_states(
+        &self,
+        keys: &[AkdLabel],
+        flag: ValueStateRetrievalFlag,
+        tree: Option<TreeId>,
+    ) -> core::result::Result<HashMap<AkdLabel, ValueState>, StorageError> {
+        self.record_call_stats('r', "get_user_states".to_string(), "".to_string())
             .await;
 
         let mut results = HashMap::new();
@@ -1036,7 +1145,7 @@ impl Database for AsyncMySqlDatabase {
             debug!("Creating the temporary search username's table");
             let out = conn
                 .query_drop(
-                    "CREATE TEMPORARY TABLE `search_users`(`username` VARBINARY(256) NOT NULL, PRIMARY KEY (`username`))",
+                    "CREATE TEMPORARY TABLE `search_users`(`username` VARBINARY(512) NOT NULL, PRIMARY KEY (`username`))",
                 )
                 .await;
             self.check_for_infra_error(out)?;
@@ -1120,70 +1229,55 @@ impl Database for AsyncMySqlDatabase {
             tx.commit().await?;
 
             debug!("Querying records with JOIN");
-            // select all records for provided user names
-            let mut params_map = vec![];
+            // select all records of the tree for provided user names
+            let mut params_map = vec![("the_tree", Value::from(encode_tree(tree)))];
             let (filter, epoch_grouping) = {
                 // apply the specific filter
                 match flag {
                     ValueStateRetrievalFlag::SpecificVersion(version) => {
                         params_map.push(("the_version", Value::from(version)));
-                        ("WHERE tmp.`version` = :the_version", "tmp.`epoch`")
+                        ("AND tmp.`version` = :the_version", "tmp.`epoch`")
                     }
                     ValueStateRetrievalFlag::SpecificEpoch(epoch) => {
                         params_map.push(("the_epoch", Value::from(epoch)));
-                        ("WHERE tmp.`epoch` = :the_epoch", "tmp.`epoch`")
+                        ("AND tmp.`epoch` = :the_epoch", "tmp.`epoch`")
                     }
                     ValueStateRetrievalFlag::MaxEpoch => ("", "MAX(tmp.`epoch`)"),
                     ValueStateRetrievalFlag::MinEpoch => ("", "MIN(tmp.`epoch`)"),
                     ValueStateRetrievalFlag::LeqEpoch(epoch) => {
                         params_map.push(("the_epoch", Value::from(epoch)));
-                        (" WHERE tmp.`epoch` <= :the_epoch", "MAX(tmp.`epoch`)")
+                        ("AND tmp.`epoch` <= :the_epoch", "MAX(tmp.`epoch`)")
                     }
                 }
             };
             let select_statement = format!(
-                r"SELECT full.`username`, full.`version`, full.`data`
+                r"SELECT full.`username`, full.`epoch`, full.`version`, full.`node_label_val`,
+                    full.`node_label_len`, full.`data`, full.`tree`
                 FROM {TABLE_USER} full
                 INNER JOIN (
                     SELECT tmp.`username`, {epoch_grouping} AS `epoch`
                     FROM {TABLE_USER} tmp
                     INNER JOIN `search_users` su
                         ON su.`username` = tmp.`username`
-                    {filter}
+                    WHERE tmp.`tree` = :the_tree {filter}
                     GROUP BY tmp.`username`
                 ) epochs
                     ON epochs.`username` = full.`username`
                     AND epochs.`epoch` = full.`epoch`
+                WHERE full.`tree` = :the_tree
                 "
             );
 
-            let out = if params_map.is_empty() {
-                let _t = conn.query_iter(select_statement).await;
-                self.check_for_infra_error(_t)?
-                    .reduce_and_drop(vec![], |mut acc, mut row: mysql_async::Row| {
-                        if let (Some(Ok(username)), Some(Ok(version)), Some(Ok(data))) =
-                            (row.take_opt(0), row.take_opt(1), row.take_opt(2))
-                        {
-                            acc.push((AkdLabel(username), (version, AkdValue(data))))
-                        }
-                        acc
-                    })
-                    .await?
-            } else {
-                let _t = conn
-                    .exec_iter(select_statement, mysql_async::Params::from(params_map))
-                    .await;
-                self.check_for_infra_error(_t)?
-                    .reduce_and_drop(vec![], |mut acc, mut row: mysql_async::Row| {
-                        if let (Some(Ok(username)), Some(Ok(version)), Some(Ok(data))) =
-                            (row.take_opt(0), row.take_opt(1), row.take_opt(2))
-                        {
-                            acc.push((AkdLabel(username), (version, AkdValue(data))))
-                        }
-                        acc
-                    })
-                    .await?
-            };
+            let _t = conn
+                .exec_iter(select_statement, mysql_async::Params::from(params_map))
+                .await;
+            let out = self
+                .check_for_infra_error(_t)?
+                .reduce_and_drop(vec![], |mut acc, mut row: mysql_async::Row| {
+                    acc.extend(Self::value_state_from_row(&mut row));
+                    acc
+                })
+                .await?;
 
             debug!(
                 "Retrieved {} records for {} users in query\nDropping search table...",
@@ -1194,8 +1288,8 @@ impl Database for AsyncMySqlDatabase {
             let nout = conn.query_drop("DROP TEMPORARY TABLE `search_users`").await;
             self.check_for_infra_error(nout)?;
 
-            for item in out.into_iter() {
-                results.insert(item.0, item.1);
+            for state in out.into_iter() {
+                results.insert(state.username.clone(), state);
             }
 
             Ok::<(), MySqlError>(())
@@ -1204,7 +1298,96 @@ impl Database for AsyncMySqlDatabase {
             Ok(()) => Ok(results),
             Err(error) => {
                 error!("MySQL error {}", error);
-                Err(StorageError::Other(format!("MySQL Error {error}")))
+                Err(to_storage_error(error))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StorageUtil for AsyncMySqlDatabase {
+    async fn batch_get_type_direct<St: Storable>(
+        &self,
+    ) -> core::result::Result<Vec<DbRecord>, StorageError> {
+        self.record_call_stats(
+            'r',
+            "batch_get_type_direct".to_string(),
+            format!("{:?}", St::data_type()),
+        )
+        .await;
+
+        let result = async {
+            let mut conn = self.get_connection().await?;
+            let out = conn.query::<Row, _>(DbRecord::get_statement::<St>()).await;
+            let rows = self.check_for_infra_error(out)?;
+            rows.into_iter()
+                .map(|mut row| DbRecord::from_row::<St>(&mut row))
+                .collect::<core::result::Result<Vec<_>, MySqlError>>()
+        };
+
+        match result.await {
+            Ok(records) => Ok(records),
+            Err(error) => {
+                error!("MySQL error {}", error);
+                Err(to_storage_error(error))
+            }
+        }
+    }
+
+    async fn batch_get_all_direct(&self) -> core::result::Result<Vec<DbRecord>, StorageError> {
+        let mut records = self.batch_get_type_direct::<akd::Azks>().await?;
+        records.append(
+            &mut self
+                .batch_get_type_direct::<TreeNodeWithPreviousValue>()
+                .await?,
+        );
+        records.append(&mut self.batch_get_type_direct::<ValueState>().await?);
+        records.append(&mut self.batch_get_type_direct::<EpochChangeset>().await?);
+        records.append(&mut self.batch_get_type_direct::<SchemaVersion>().await?);
+        records.append(&mut self.batch_get_type_direct::<RecordMac>().await?);
+        records.append(&mut self.batch_get_type_direct::<PendingEpoch>().await?);
+        Ok(records)
+    }
+
+    async fn batch_get_type_page_direct<St: Storable>(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> core::result::Result<Vec<DbRecord>, StorageError> {
+        self.record_call_stats(
+            'r',
+            "batch_get_type_page_direct".to_string(),
+            format!("{:?}", St::data_type()),
+        )
+        .await;
+
+        let params = match after {
+            Some(after) => {
+                let key = St::key_from_full_binary(after).map_err(StorageError::Other)?;
+                match DbRecord::get_specific_params::<St>(&key) {
+                    Some(params) => params,
+                    // the table without key parameters holds a single row, so has no next page
+                    None => return Ok(vec![]),
+                }
+            }
+            None => Params::Empty,
+        };
+        let statement = DbRecord::get_page_statement::<St>(after.is_some(), limit);
+
+        let result = async {
+            let mut conn = self.get_connection().await?;
+            let out = conn.exec::<Row, _, _>(statement, params).await;
+            let rows = self.check_for_infra_error(out)?;
+            rows.into_iter()
+                .map(|mut row| DbRecord::from_row::<St>(&mut row))
+                .collect::<core::result::Result<Vec<_>, MySqlError>>()
+        };
+
+        match result.await {
+            Ok(records) => Ok(records),
+            Err(error) => {
+                error!("MySQL error {}", error);
+                Err(to_storage_error(error))
             }
         }
     }
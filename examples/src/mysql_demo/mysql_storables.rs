@@ -9,7 +9,7 @@
 
 use std::convert::TryInto;
 
-use akd::storage::types::{DbRecord, StorageType};
+use akd::storage::types::{DbRecord, PendingOperation, StorageType, TreeId};
 use akd::storage::Storable;
 use akd::tree_node::{NodeKey, TreeNodeWithPreviousValue};
 use akd::NodeLabel;
@@ -21,13 +21,61 @@ type MySqlError = mysql_async::Error;
 pub(crate) const TABLE_AZKS: &str = "azks";
 pub(crate) const TABLE_HISTORY_TREE_NODES: &str = "history";
 pub(crate) const TABLE_USER: &str = "users";
+pub(crate) const TABLE_EPOCH_CHANGESETS: &str = "epoch_changesets";
+pub(crate) const TABLE_SCHEMA_VERSION: &str = "schema_version";
+pub(crate) const TABLE_RECORD_MACS: &str = "record_macs";
+pub(crate) const TABLE_PENDING_EPOCHS: &str = "pending_epochs";
 pub(crate) const TEMP_IDS_TABLE: &str = "temp_ids_table";
 
-const SELECT_AZKS_DATA: &str = "`epoch`, `num_nodes`";
+const SELECT_AZKS_DATA: &str = "`epoch`, `num_nodes`, `tree`";
 const SELECT_HISTORY_TREE_NODE_DATA: &str =
-    "`label_len`, `label_val`, `last_epoch`, `least_descendant_ep`, `parent_label_len`, `parent_label_val`, `node_type`, `left_child_len`, `left_child_label_val`, `right_child_len`, `right_child_label_val`, `hash`, `p_last_epoch`, `p_least_descendant_ep`, `p_parent_label_len`, `p_parent_label_val`, `p_node_type`, `p_left_child_len`, `p_left_child_label_val`, `p_right_child_len`, `p_right_child_label_val`, `p_hash`";
+    "`label_len`, `label_val`, `last_epoch`, `least_descendant_ep`, `parent_label_len`, `parent_label_val`, `node_type`, `left_child_len`, `left_child_label_val`, `right_child_len`, `right_child_label_val`, `hash`, `p_last_epoch`, `p_least_descendant_ep`, `p_parent_label_len`, `p_parent_label_val`, `p_node_type`, `p_left_child_len`, `p_left_child_label_val`, `p_right_child_len`, `p_right_child_label_val`, `p_hash`, `tree`";
 const SELECT_USER_DATA: &str =
-    "`username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`";
+    "`username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `tree`";
+const SELECT_EPOCH_CHANGESET_DATA: &str = "`epoch`, `part`, `num_parts`, `node_labels`, `tree`";
+const SELECT_SCHEMA_VERSION_DATA: &str = "`version`";
+const SELECT_RECORD_MAC_DATA: &str = "`record_key`, `mac`";
+const SELECT_PENDING_EPOCH_DATA: &str = "`epoch`, `operation`, `next_part`, `tree`";
+
+/// The tree of a record is stored as the big-endian bytes of its identifier, or as no bytes for
+/// a record of a backend holding a single tree, so that records are ordered as their keys are
+pub(crate) fn encode_tree(tree: Option<TreeId>) -> Vec<u8> {
+    tree.map_or_else(Vec::new, |tree| tree.0.to_be_bytes().to_vec())
+}
+
+pub(crate) fn decode_tree(bytes: &[u8]) -> Option<Option<TreeId>> {
+    if bytes.is_empty() {
+        return Some(None);
+    }
+    let tree: [u8; 8] = bytes.try_into().ok()?;
+    Some(Some(TreeId(u64::from_be_bytes(tree))))
+}
+
+/// Node labels of a changeset are stored as a single blob of (length, value) pairs
+const ENCODED_NODE_LABEL_BYTES: usize = 4 + 32;
+
+fn encode_node_labels(labels: &[NodeLabel]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(labels.len() * ENCODED_NODE_LABEL_BYTES);
+    for label in labels.iter() {
+        bytes.extend_from_slice(&label.label_len.to_be_bytes());
+        bytes.extend_from_slice(&label.label_val);
+    }
+    bytes
+}
+
+fn decode_node_labels(bytes: &[u8]) -> Option<Vec<NodeLabel>> {
+    let chunks = bytes.chunks_exact(ENCODED_NODE_LABEL_BYTES);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    chunks
+        .map(|chunk| {
+            let len = u32::from_be_bytes(chunk[..4].try_into().ok()?);
+            let val: [u8; 32] = chunk[4..].try_into().ok()?;
+            Some(NodeLabel::new(val, len))
+        })
+        .collect()
+}
 
 pub(crate) trait MySqlStorable {
     fn set_statement(&self) -> String;
@@ -48,6 +96,8 @@ pub(crate) trait MySqlStorable {
 
     fn get_specific_statement<St: Storable>() -> String;
 
+    fn get_page_statement<St: Storable>(after: bool, limit: usize) -> String;
+
     fn get_specific_params<St: Storable>(key: &St::StorageKey) -> Option<mysql_async::Params>;
 
     fn get_multi_row_specific_params<St: Storable>(
@@ -63,7 +113,7 @@ impl MySqlStorable for DbRecord {
     fn set_statement(&self) -> String {
         match &self {
             DbRecord::Azks(_) => format!("INSERT INTO `{TABLE_AZKS}` (`key`, {SELECT_AZKS_DATA})
-            VALUES (:key, :epoch, :num_nodes)
+            VALUES (:key, :epoch, :num_nodes, :tree)
             ON DUPLICATE KEY UPDATE
                 `epoch` = :epoch
                 , `num_nodes` = :num_nodes"),
@@ -89,7 +139,8 @@ impl MySqlStorable for DbRecord {
                 , :p_left_child_label_val
                 , :p_right_child_len
                 , :p_right_child_label_val
-                , :p_hash)
+                , :p_hash
+                , :tree)
             ON DUPLICATE KEY UPDATE
                 `label_len` = :label_len
                 , `label_val` = :label_val
@@ -113,14 +164,30 @@ impl MySqlStorable for DbRecord {
                 , `p_right_child_len` = :p_right_child_len
                 , `p_right_child_label_val` = :p_right_child_label_val
                 , `p_hash` = :p_hash"),
-            DbRecord::ValueState(_) => format!("INSERT INTO `{TABLE_USER}` ({SELECT_USER_DATA}) VALUES (:username, :epoch, :version, :node_label_val, :node_label_len, :data)"),
+            DbRecord::ValueState(_) => format!("INSERT INTO `{TABLE_USER}` ({SELECT_USER_DATA}) VALUES (:username, :epoch, :version, :node_label_val, :node_label_len, :data, :tree)"),
+            DbRecord::EpochChangeset(_) => format!("INSERT INTO `{TABLE_EPOCH_CHANGESETS}` ({SELECT_EPOCH_CHANGESET_DATA}) VALUES (:epoch, :part, :num_parts, :node_labels, :tree)
+            ON DUPLICATE KEY UPDATE
+                `num_parts` = :num_parts
+                , `node_labels` = :node_labels"),
+            DbRecord::SchemaVersion(_) => format!("INSERT INTO `{TABLE_SCHEMA_VERSION}` (`key`, {SELECT_SCHEMA_VERSION_DATA})
+            VALUES (:key, :version)
+            ON DUPLICATE KEY UPDATE
+                `version` = :version"),
+            DbRecord::RecordMac(_) => format!("INSERT INTO `{TABLE_RECORD_MACS}` ({SELECT_RECORD_MAC_DATA}) VALUES (:record_key, :mac)
+            ON DUPLICATE KEY UPDATE
+                `mac` = :mac"),
+            DbRecord::PendingEpoch(_) => format!("INSERT INTO `{TABLE_PENDING_EPOCHS}` ({SELECT_PENDING_EPOCH_DATA}) VALUES (:epoch, :operation, :next_part, :tree)
+            ON DUPLICATE KEY UPDATE
+                `epoch` = :epoch
+                , `operation` = :operation
+                , `next_part` = :next_part"),
         }
     }
 
     fn set_params(&self) -> Option<mysql_async::Params> {
         match &self {
             DbRecord::Azks(azks) => Some(
-                params! { "key" => 1u8, "epoch" => azks.latest_epoch, "num_nodes" => azks.num_nodes },
+                params! { "key" => 1u8, "epoch" => azks.latest_epoch, "num_nodes" => azks.num_nodes, "tree" => encode_tree(azks.tree()) },
             ),
             DbRecord::TreeNode(node) => Some(params! {
                 "label_len" => node.label.label_len,
@@ -147,9 +214,22 @@ impl MySqlStorable for DbRecord {
                 "p_right_child_len" => node.previous_node.clone().and_then(|a| a.right_child.map(|rc| rc.label_len)),
                 "p_right_child_label_val" => node.previous_node.clone().and_then(|a| a.right_child.map(|rc| rc.label_val)),
                 "p_hash" => node.previous_node.clone().map(|a| a.hash.0),
+                "tree" => encode_tree(node.tree()),
             }),
             DbRecord::ValueState(state) => Some(
-                params! { "username" => state.get_id().0, "epoch" => state.epoch, "version" => state.version, "node_label_len" => state.label.label_len, "node_label_val" => state.label.label_val, "data" => state.value.0.clone() },
+                params! { "username" => state.get_id().0, "epoch" => state.epoch, "version" => state.version, "node_label_len" => state.label.label_len, "node_label_val" => state.label.label_val, "data" => state.value.0.clone(), "tree" => encode_tree(state.tree()) },
+            ),
+            DbRecord::EpochChangeset(changeset) => Some(
+                params! { "epoch" => changeset.epoch, "part" => changeset.part, "num_parts" => changeset.num_parts, "node_labels" => encode_node_labels(&changeset.node_labels), "tree" => encode_tree(changeset.tree()) },
+            ),
+            DbRecord::SchemaVersion(schema) => {
+                Some(params! { "key" => 1u8, "version" => schema.version })
+            }
+            DbRecord::RecordMac(mac) => {
+                Some(params! { "record_key" => mac.record_key.clone(), "mac" => mac.mac })
+            }
+            DbRecord::PendingEpoch(pending) => Some(
+                params! { "epoch" => pending.epoch, "operation" => pending.operation as u8, "next_part" => pending.next_part, "tree" => encode_tree(pending.tree()) },
             ),
         }
     }
@@ -181,16 +261,31 @@ impl MySqlStorable for DbRecord {
                             , :p_left_child_label_val{i}
                             , :p_right_child_len{i}
                             , :p_right_child_label_val{i}
-                            , :p_hash{i})"
+                            , :p_hash{i}
+                            , :tree{i})"
                     );
                 }
                 StorageType::ValueState => {
                     parts = format!(
-                        "{parts}(:username{i}, :epoch{i}, :version{i}, :node_label_val{i}, :node_label_len{i}, :data{i})"
+                        "{parts}(:username{i}, :epoch{i}, :version{i}, :node_label_val{i}, :node_label_len{i}, :data{i}, :tree{i})"
                     );
                 }
+                StorageType::EpochChangeset => {
+                    parts = format!(
+                        "{parts}(:epoch{i}, :part{i}, :num_parts{i}, :node_labels{i}, :tree{i})"
+                    );
+                }
+                StorageType::RecordMac => {
+                    parts = format!("{parts}(:record_key{i}, :mac{i})");
+                }
+                StorageType::Azks => {
+                    parts = format!("{parts}(:key{i}, :epoch{i}, :num_nodes{i}, :tree{i})");
+                }
+                StorageType::PendingEpoch => {
+                    parts = format!("{parts}(:epoch{i}, :operation{i}, :next_part{i}, :tree{i})");
+                }
                 _ => {
-                    // azks
+                    // schema version
                 }
             }
 
@@ -202,7 +297,7 @@ impl MySqlStorable for DbRecord {
         match St::data_type() {
             StorageType::Azks => format!(
                 "INSERT INTO `{TABLE_AZKS}` (`key`, {SELECT_AZKS_DATA})
-            VALUES (:key, :epoch, :num_nodes) as new
+            VALUES {parts} as new
             ON DUPLICATE KEY UPDATE `epoch` = new.epoch, `num_nodes` = new.num_nodes"
             ),
             StorageType::TreeNode => format!(
@@ -241,6 +336,32 @@ impl MySqlStorable for DbRecord {
                 , `node_label_len` = new.node_label_len
                 , `version` = new.version"
             ),
+            StorageType::EpochChangeset => format!(
+                "INSERT INTO `{TABLE_EPOCH_CHANGESETS}` ({SELECT_EPOCH_CHANGESET_DATA})
+            VALUES {parts} as new
+            ON DUPLICATE KEY UPDATE
+                `num_parts` = new.num_parts
+                , `node_labels` = new.node_labels"
+            ),
+            StorageType::SchemaVersion => format!(
+                "INSERT INTO `{TABLE_SCHEMA_VERSION}` (`key`, {SELECT_SCHEMA_VERSION_DATA})
+            VALUES (:key, :version) as new
+            ON DUPLICATE KEY UPDATE `version` = new.version"
+            ),
+            StorageType::RecordMac => format!(
+                "INSERT INTO `{TABLE_RECORD_MACS}` ({SELECT_RECORD_MAC_DATA})
+            VALUES {parts} as new
+            ON DUPLICATE KEY UPDATE
+                `mac` = new.mac"
+            ),
+            StorageType::PendingEpoch => format!(
+                "INSERT INTO `{TABLE_PENDING_EPOCHS}` ({SELECT_PENDING_EPOCH_DATA})
+            VALUES {parts} as new
+            ON DUPLICATE KEY UPDATE
+                `epoch` = new.epoch
+                , `operation` = new.operation
+                , `next_part` = new.next_part"
+            ),
         }
     }
 
@@ -250,9 +371,10 @@ impl MySqlStorable for DbRecord {
             .enumerate()
             .map(|(idx, item)| match &item {
                 DbRecord::Azks(azks) => Ok(vec![
-                    ("key".to_string(), Value::from(1u8)),
-                    ("epoch".to_string(), Value::from(azks.latest_epoch)),
-                    ("num_nodes".to_string(), Value::from(azks.num_nodes)),
+                    (format!("key{idx}"), Value::from(1u8)),
+                    (format!("epoch{idx}"), Value::from(azks.latest_epoch)),
+                    (format!("num_nodes{idx}"), Value::from(azks.num_nodes)),
+                    (format!("tree{idx}"), Value::from(encode_tree(azks.tree()))),
                 ]),
                 DbRecord::TreeNode(node) => {
                     let pnode = &node.previous_node;
@@ -352,6 +474,7 @@ impl MySqlStorable for DbRecord {
                             format!("p_hash{idx}"),
                             Value::from(pnode.clone().map(|a| a.hash.0)),
                         ),
+                        (format!("tree{idx}"), Value::from(encode_tree(node.tree()))),
                     ])
                 }
                 DbRecord::ValueState(state) => Ok(vec![
@@ -367,6 +490,43 @@ impl MySqlStorable for DbRecord {
                         Value::from(state.label.label_val),
                     ),
                     (format!("data{idx}"), Value::from(state.value.0.clone())),
+                    (format!("tree{idx}"), Value::from(encode_tree(state.tree()))),
+                ]),
+                DbRecord::EpochChangeset(changeset) => Ok(vec![
+                    (format!("epoch{idx}"), Value::from(changeset.epoch)),
+                    (format!("part{idx}"), Value::from(changeset.part)),
+                    (format!("num_parts{idx}"), Value::from(changeset.num_parts)),
+                    (
+                        format!("node_labels{idx}"),
+                        Value::from(encode_node_labels(&changeset.node_labels)),
+                    ),
+                    (
+                        format!("tree{idx}"),
+                        Value::from(encode_tree(changeset.tree())),
+                    ),
+                ]),
+                DbRecord::SchemaVersion(schema) => Ok(vec![
+                    ("key".to_string(), Value::from(1u8)),
+                    ("version".to_string(), Value::from(schema.version)),
+                ]),
+                DbRecord::RecordMac(mac) => Ok(vec![
+                    (
+                        format!("record_key{idx}"),
+                        Value::from(mac.record_key.clone()),
+                    ),
+                    (format!("mac{idx}"), Value::from(mac.mac)),
+                ]),
+                DbRecord::PendingEpoch(pending) => Ok(vec![
+                    (format!("epoch{idx}"), Value::from(pending.epoch)),
+                    (
+                        format!("operation{idx}"),
+                        Value::from(pending.operation as u8),
+                    ),
+                    (format!("next_part{idx}"), Value::from(pending.next_part)),
+                    (
+                        format!("tree{idx}"),
+                        Value::from(encode_tree(pending.tree())),
+                    ),
                 ]),
             })
             .collect::<Result<Vec<_>>>()?
@@ -383,23 +543,49 @@ impl MySqlStorable for DbRecord {
                 format!("SELECT {SELECT_HISTORY_TREE_NODE_DATA} FROM `{TABLE_HISTORY_TREE_NODES}`")
             }
             StorageType::ValueState => format!("SELECT {SELECT_USER_DATA} FROM `{TABLE_USER}`"),
+            StorageType::EpochChangeset => {
+                format!("SELECT {SELECT_EPOCH_CHANGESET_DATA} FROM `{TABLE_EPOCH_CHANGESETS}`")
+            }
+            StorageType::SchemaVersion => {
+                format!("SELECT {SELECT_SCHEMA_VERSION_DATA} FROM `{TABLE_SCHEMA_VERSION}`")
+            }
+            StorageType::RecordMac => {
+                format!("SELECT {SELECT_RECORD_MAC_DATA} FROM `{TABLE_RECORD_MACS}`")
+            }
+            StorageType::PendingEpoch => {
+                format!("SELECT {SELECT_PENDING_EPOCH_DATA} FROM `{TABLE_PENDING_EPOCHS}`")
+            }
         }
     }
 
     fn get_batch_create_temp_table<St: Storable>() -> Option<String> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::Azks | StorageType::SchemaVersion | StorageType::PendingEpoch => None,
             StorageType::TreeNode => {
                 Some(
                     format!(
-                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`label_len` INT UNSIGNED NOT NULL, `label_val` VARBINARY(32) NOT NULL, PRIMARY KEY(`label_len`, `label_val`))"
+                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`tree` VARBINARY(8) NOT NULL, `label_len` INT UNSIGNED NOT NULL, `label_val` VARBINARY(32) NOT NULL, PRIMARY KEY(`tree`, `label_len`, `label_val`))"
                     )
                 )
             },
             StorageType::ValueState => {
                 Some(
                     format!(
-                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`username` VARCHAR(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, PRIMARY KEY(`username`, `epoch`))"
+                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`tree` VARBINARY(8) NOT NULL, `username` VARCHAR(256) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, PRIMARY KEY(`tree`, `username`, `epoch`))"
+                    )
+                )
+            },
+            StorageType::EpochChangeset => {
+                Some(
+                    format!(
+                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`tree` VARBINARY(8) NOT NULL, `epoch` BIGINT UNSIGNED NOT NULL, `part` INT UNSIGNED NOT NULL, PRIMARY KEY(`tree`, `epoch`, `part`))"
+                    )
+                )
+            },
+            StorageType::RecordMac => {
+                Some(
+                    format!(
+                        "CREATE TEMPORARY TABLE `{TEMP_IDS_TABLE}`(`record_key` VARBINARY(512) NOT NULL, PRIMARY KEY(`record_key`))"
                     )
                 )
             },
@@ -408,23 +594,39 @@ impl MySqlStorable for DbRecord {
 
     fn get_batch_fill_temp_table<St: Storable>(num_items: Option<usize>) -> String {
         let mut statement = match St::data_type() {
-            StorageType::Azks => "".to_string(),
+            StorageType::Azks | StorageType::SchemaVersion | StorageType::PendingEpoch => {
+                "".to_string()
+            }
             StorageType::TreeNode => {
-                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`label_len`, `label_val`) VALUES ")
+                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`tree`, `label_len`, `label_val`) VALUES ")
             }
             StorageType::ValueState => {
-                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`username`, `epoch`) VALUES ")
+                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`tree`, `username`, `epoch`) VALUES ")
+            }
+            StorageType::EpochChangeset => {
+                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`tree`, `epoch`, `part`) VALUES ")
+            }
+            StorageType::RecordMac => {
+                format!("INSERT INTO `{TEMP_IDS_TABLE}` (`record_key`) VALUES ")
             }
         };
         if let Some(item_count) = num_items {
             for i in 0..item_count {
                 let append = match St::data_type() {
-                    StorageType::Azks => String::from(""),
+                    StorageType::Azks | StorageType::SchemaVersion | StorageType::PendingEpoch => {
+                        String::from("")
+                    }
                     StorageType::TreeNode => {
-                        format!("(:label_len{i}, :label_val{i})")
+                        format!("(:tree{i}, :label_len{i}, :label_val{i})")
                     }
                     StorageType::ValueState => {
-                        format!("(:username{i}, :epoch{i})")
+                        format!("(:tree{i}, :username{i}, :epoch{i})")
+                    }
+                    StorageType::EpochChangeset => {
+                        format!("(:tree{i}, :epoch{i}, :part{i})")
+                    }
+                    StorageType::RecordMac => {
+                        format!("(:record_key{i})")
                     }
                 };
                 statement = format!("{statement}{append}");
@@ -436,9 +638,11 @@ impl MySqlStorable for DbRecord {
             }
         } else {
             statement += match St::data_type() {
-                StorageType::Azks => "",
-                StorageType::TreeNode => "(:label_len, :label_val)",
-                StorageType::ValueState => "(:username, :epoch)",
+                StorageType::Azks | StorageType::SchemaVersion | StorageType::PendingEpoch => "",
+                StorageType::TreeNode => "(:tree, :label_len, :label_val)",
+                StorageType::ValueState => "(:tree, :username, :epoch)",
+                StorageType::EpochChangeset => "(:tree, :epoch, :part)",
+                StorageType::RecordMac => "(:record_key)",
             };
         }
         statement
@@ -449,6 +653,12 @@ impl MySqlStorable for DbRecord {
             StorageType::Azks => {
                 format!("SELECT {SELECT_AZKS_DATA} FROM `{TABLE_AZKS}` LIMIT 1")
             }
+            StorageType::PendingEpoch => {
+                format!("SELECT {SELECT_PENDING_EPOCH_DATA} FROM `{TABLE_PENDING_EPOCHS}` LIMIT 1")
+            }
+            StorageType::SchemaVersion => {
+                format!("SELECT {SELECT_SCHEMA_VERSION_DATA} FROM `{TABLE_SCHEMA_VERSION}` LIMIT 1")
+            }
             StorageType::TreeNode => {
                 format!(
                     "SELECT
@@ -473,9 +683,11 @@ impl MySqlStorable for DbRecord {
                         , a.`p_right_child_len`
                         , a.`p_right_child_label_val`
                         , a.`p_hash`
+                        , a.`tree`
                     FROM `{TABLE_HISTORY_TREE_NODES}` a
                     INNER JOIN {TEMP_IDS_TABLE} ids
-                        ON ids.`label_len` = a.`label_len`
+                        ON ids.`tree` = a.`tree`
+                        AND ids.`label_len` = a.`label_len`
                         AND ids.`label_val` = a.`label_val`"
                 )
             }
@@ -488,36 +700,133 @@ impl MySqlStorable for DbRecord {
                         , a.`node_label_val`
                         , a.`node_label_len`
                         , a.`data`
+                        , a.`tree`
                     FROM `{TABLE_USER}` a
                     INNER JOIN {TEMP_IDS_TABLE} ids
-                        ON ids.`username` = a.`username`
+                        ON ids.`tree` = a.`tree`
+                        AND ids.`username` = a.`username`
                         AND ids.`epoch` = a.`epoch`"
                 )
             }
+            StorageType::EpochChangeset => {
+                format!(
+                    "SELECT
+                        a.`epoch`
+                        , a.`part`
+                        , a.`num_parts`
+                        , a.`node_labels`
+                        , a.`tree`
+                    FROM `{TABLE_EPOCH_CHANGESETS}` a
+                    INNER JOIN {TEMP_IDS_TABLE} ids
+                        ON ids.`tree` = a.`tree`
+                        AND ids.`epoch` = a.`epoch`
+                        AND ids.`part` = a.`part`"
+                )
+            }
+            StorageType::RecordMac => {
+                format!(
+                    "SELECT
+                        a.`record_key`
+                        , a.`mac`
+                    FROM `{TABLE_RECORD_MACS}` a
+                    INNER JOIN {TEMP_IDS_TABLE} ids
+                        ON ids.`record_key` = a.`record_key`"
+                )
+            }
         }
     }
 
     fn get_specific_statement<St: Storable>() -> String {
         match St::data_type() {
             StorageType::Azks => {
-                format!("SELECT {SELECT_AZKS_DATA} FROM `{TABLE_AZKS}` LIMIT 1")
+                format!("SELECT {SELECT_AZKS_DATA} FROM `{TABLE_AZKS}` WHERE `tree` = :tree")
+            }
+            StorageType::PendingEpoch => format!(
+                "SELECT {SELECT_PENDING_EPOCH_DATA} FROM `{TABLE_PENDING_EPOCHS}` WHERE `tree` = :tree"
+            ),
+            StorageType::SchemaVersion => {
+                format!("SELECT {SELECT_SCHEMA_VERSION_DATA} FROM `{TABLE_SCHEMA_VERSION}` LIMIT 1")
             }
             StorageType::TreeNode => format!(
-                "SELECT {SELECT_HISTORY_TREE_NODE_DATA} FROM `{TABLE_HISTORY_TREE_NODES}` WHERE `label_len` = :label_len AND `label_val` = :label_val"
+                "SELECT {SELECT_HISTORY_TREE_NODE_DATA} FROM `{TABLE_HISTORY_TREE_NODES}` WHERE `tree` = :tree AND `label_len` = :label_len AND `label_val` = :label_val"
             ),
             StorageType::ValueState => format!(
-                "SELECT {SELECT_USER_DATA} FROM `{TABLE_USER}` WHERE `username` = :username AND `epoch` = :epoch"
+                "SELECT {SELECT_USER_DATA} FROM `{TABLE_USER}` WHERE `tree` = :tree AND `username` = :username AND `epoch` = :epoch"
+            ),
+            StorageType::EpochChangeset => format!(
+                "SELECT {SELECT_EPOCH_CHANGESET_DATA} FROM `{TABLE_EPOCH_CHANGESETS}` WHERE `tree` = :tree AND `epoch` = :epoch AND `part` = :part"
+            ),
+            StorageType::RecordMac => format!(
+                "SELECT {SELECT_RECORD_MAC_DATA} FROM `{TABLE_RECORD_MACS}` WHERE `record_key` = :record_key"
             ),
         }
     }
 
+    fn get_page_statement<St: Storable>(after: bool, limit: usize) -> String {
+        // pages are ordered by primary key, and start after the key bound to the parameters
+        // of get_specific_params (when `after` is set)
+        let (order, condition) = match St::data_type() {
+            // this table holds a single row, so has no cursor
+            StorageType::SchemaVersion => {
+                return format!("{} LIMIT {limit}", Self::get_statement::<St>())
+            }
+            // a single row per tree
+            StorageType::Azks | StorageType::PendingEpoch => ("`tree`", "`tree` > :tree"),
+            StorageType::TreeNode => (
+                "`tree`, `label_len`, `label_val`",
+                "(`tree`, `label_len`, `label_val`) > (:tree, :label_len, :label_val)",
+            ),
+            StorageType::ValueState => (
+                "`tree`, `username`, `epoch`",
+                "(`tree`, `username`, `epoch`) > (:tree, :username, :epoch)",
+            ),
+            StorageType::EpochChangeset => (
+                "`tree`, `epoch`, `part`",
+                "(`tree`, `epoch`, `part`) > (:tree, :epoch, :part)",
+            ),
+            StorageType::RecordMac => ("`record_key`", "`record_key` > :record_key"),
+        };
+        if after {
+            format!(
+                "{} WHERE {condition} ORDER BY {order} LIMIT {limit}",
+                Self::get_statement::<St>()
+            )
+        } else {
+            format!(
+                "{} ORDER BY {order} LIMIT {limit}",
+                Self::get_statement::<St>()
+            )
+        }
+    }
+
     fn get_specific_params<St: Storable>(key: &St::StorageKey) -> Option<mysql_async::Params> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::SchemaVersion => None,
+            StorageType::Azks => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(tree) = akd::append_only_zks::Azks::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "tree" => encode_tree(tree),
+                    })
+                } else {
+                    None
+                }
+            }
+            StorageType::PendingEpoch => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(tree) = akd::storage::types::PendingEpoch::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "tree" => encode_tree(tree),
+                    })
+                } else {
+                    None
+                }
+            }
             StorageType::TreeNode => {
                 let bin = St::get_full_binary_key_id(key);
                 if let Ok(back) = TreeNodeWithPreviousValue::key_from_full_binary(&bin) {
                     Some(params! {
+                        "tree" => encode_tree(back.tree()),
                         "label_len" => back.0.label_len,
                         "label_val" => back.0.label_val,
                     })
@@ -529,6 +838,7 @@ impl MySqlStorable for DbRecord {
                 let bin = St::get_full_binary_key_id(key);
                 if let Ok(back) = akd::storage::types::ValueState::key_from_full_binary(&bin) {
                     Some(params! {
+                        "tree" => encode_tree(back.tree()),
                         "username" => back.0,
                         "epoch" => back.1
                     })
@@ -536,6 +846,28 @@ impl MySqlStorable for DbRecord {
                     None
                 }
             }
+            StorageType::EpochChangeset => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(epoch) = akd::storage::types::EpochChangeset::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "tree" => encode_tree(epoch.tree()),
+                        "epoch" => epoch.0,
+                        "part" => epoch.1
+                    })
+                } else {
+                    None
+                }
+            }
+            StorageType::RecordMac => {
+                let bin = St::get_full_binary_key_id(key);
+                if let Ok(back) = akd::storage::types::RecordMac::key_from_full_binary(&bin) {
+                    Some(params! {
+                        "record_key" => back.0
+                    })
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -543,7 +875,7 @@ impl MySqlStorable for DbRecord {
         keys: &[St::StorageKey],
     ) -> Option<mysql_async::Params> {
         match St::data_type() {
-            StorageType::Azks => None,
+            StorageType::Azks | StorageType::SchemaVersion | StorageType::PendingEpoch => None,
             StorageType::TreeNode => {
                 let pvec = keys
                     .iter()
@@ -555,6 +887,7 @@ impl MySqlStorable for DbRecord {
                         let back: NodeKey =
                             TreeNodeWithPreviousValue::key_from_full_binary(&bin).unwrap();
                         vec![
+                            (format!("tree{idx}"), Value::from(encode_tree(back.tree()))),
                             (format!("label_len{idx}"), Value::from(back.0.label_len)),
                             (format!("label_val{idx}"), Value::from(back.0.label_val)),
                         ]
@@ -573,6 +906,7 @@ impl MySqlStorable for DbRecord {
                         let back: akd::storage::types::ValueStateKey =
                             akd::storage::types::ValueState::key_from_full_binary(&bin).unwrap();
                         vec![
+                            (format!("tree{idx}"), Value::from(encode_tree(back.tree()))),
                             (format!("username{idx}"), Value::from(back.0.clone())),
                             (format!("epoch{idx}"), Value::from(back.1)),
                         ]
@@ -580,6 +914,40 @@ impl MySqlStorable for DbRecord {
                     .collect::<Vec<_>>();
                 Some(mysql_async::Params::from(pvec))
             }
+            StorageType::EpochChangeset => {
+                let pvec = keys
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(idx, key)| {
+                        let bin = St::get_full_binary_key_id(key);
+                        // Since these are constructed from a safe key, they should never fail
+                        // so we'll leave the unwrap to simplify
+                        let epoch = akd::storage::types::EpochChangeset::key_from_full_binary(&bin)
+                            .unwrap();
+                        vec![
+                            (format!("tree{idx}"), Value::from(encode_tree(epoch.tree()))),
+                            (format!("epoch{idx}"), Value::from(epoch.0)),
+                            (format!("part{idx}"), Value::from(epoch.1)),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                Some(mysql_async::Params::from(pvec))
+            }
+            StorageType::RecordMac => {
+                let pvec = keys
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, key)| {
+                        let bin = St::get_full_binary_key_id(key);
+                        // Since these are constructed from a safe key, they should never fail
+                        // so we'll leave the unwrap to simplify
+                        let back =
+                            akd::storage::types::RecordMac::key_from_full_binary(&bin).unwrap();
+                        (format!("record_key{idx}"), Value::from(back.0))
+                    })
+                    .collect::<Vec<_>>();
+                Some(mysql_async::Params::from(pvec))
+            }
         }
     }
 
@@ -630,9 +998,13 @@ impl MySqlStorable for DbRecord {
 
         match St::data_type() {
             StorageType::Azks => {
-                // epoch, num_nodes
-                if let (Some(Ok(epoch)), Some(Ok(num_nodes))) = (row.take_opt(0), row.take_opt(1)) {
-                    let azks = DbRecord::build_azks(epoch, num_nodes);
+                // epoch, num_nodes, tree
+                if let (Some(Ok(epoch)), Some(Ok(num_nodes)), Some(Ok(tree))) =
+                    (row.take_opt(0), row.take_opt(1), row.take_opt(2))
+                {
+                    let tree_vec: Vec<u8> = tree;
+                    let azks = DbRecord::build_azks(epoch, num_nodes)
+                        .with_tree(decode_tree(&tree_vec).ok_or_else(cast_err)?);
                     return Ok(DbRecord::Azks(azks));
                 }
             }
@@ -663,6 +1035,7 @@ impl MySqlStorable for DbRecord {
                     p_right_child_len,
                     p_right_child_label_val,
                     Some(p_hash),
+                    Some(Ok(tree)),
                 ) = (
                     row.take_opt(0),
                     row.take_opt(1),
@@ -686,6 +1059,7 @@ impl MySqlStorable for DbRecord {
                     row.take(19),
                     row.take(20),
                     row.take(21),
+                    row.take_opt(22),
                 ) {
                     let left_child = optional_child_label(left_child_val_res, left_child_len_res)?;
                     let right_child =
@@ -701,6 +1075,7 @@ impl MySqlStorable for DbRecord {
                     let prev_parent_label_val_vec: Option<Vec<u8>> = p_parent_label_val;
                     let hash_vec: Vec<u8> = hash;
                     let prev_hash_vec: Option<Vec<u8>> = p_hash;
+                    let tree_vec: Vec<u8> = tree;
 
                     let massaged_prev_parent_label_val: Option<[u8; 32]> =
                         match prev_parent_label_val_vec {
@@ -736,12 +1111,13 @@ impl MySqlStorable for DbRecord {
                         p_left_child,
                         p_right_child,
                         massaged_prev_hash_vec,
-                    );
+                    )
+                    .with_tree(decode_tree(&tree_vec).ok_or_else(cast_err)?);
                     return Ok(DbRecord::TreeNode(node));
                 }
             }
             StorageType::ValueState => {
-                // `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`
+                // `username`, `epoch`, `version`, `node_label_val`, `node_label_len`, `data`, `tree`
                 if let (
                     Some(Ok(username)),
                     Some(Ok(epoch)),
@@ -749,6 +1125,7 @@ impl MySqlStorable for DbRecord {
                     Some(Ok(node_label_val)),
                     Some(Ok(node_label_len)),
                     Some(Ok(data)),
+                    Some(Ok(tree)),
                 ) = (
                     row.take_opt(0),
                     row.take_opt(1),
@@ -756,8 +1133,10 @@ impl MySqlStorable for DbRecord {
                     row.take_opt(3),
                     row.take_opt(4),
                     row.take_opt(5),
+                    row.take_opt(6),
                 ) {
                     let node_label_val_vec: Vec<u8> = node_label_val;
+                    let tree_vec: Vec<u8> = tree;
                     let state = DbRecord::build_user_state(
                         username,
                         data,
@@ -765,10 +1144,74 @@ impl MySqlStorable for DbRecord {
                         node_label_len,
                         node_label_val_vec.try_into().map_err(|_| cast_err())?,
                         epoch,
-                    );
+                    )
+                    .with_tree(decode_tree(&tree_vec).ok_or_else(cast_err)?);
                     return Ok(DbRecord::ValueState(state));
                 }
             }
+            StorageType::EpochChangeset => {
+                // `epoch`, `part`, `num_parts`, `node_labels`, `tree`
+                if let (
+                    Some(Ok(epoch)),
+                    Some(Ok(part)),
+                    Some(Ok(num_parts)),
+                    Some(Ok(node_labels)),
+                    Some(Ok(tree)),
+                ) = (
+                    row.take_opt(0),
+                    row.take_opt(1),
+                    row.take_opt(2),
+                    row.take_opt(3),
+                    row.take_opt(4),
+                ) {
+                    let node_labels_vec: Vec<u8> = node_labels;
+                    let tree_vec: Vec<u8> = tree;
+                    let changeset = DbRecord::build_epoch_changeset(
+                        epoch,
+                        part,
+                        num_parts,
+                        decode_node_labels(&node_labels_vec).ok_or_else(cast_err)?,
+                    )
+                    .with_tree(decode_tree(&tree_vec).ok_or_else(cast_err)?);
+                    return Ok(DbRecord::EpochChangeset(changeset));
+                }
+            }
+            StorageType::SchemaVersion => {
+                // version
+                if let Some(Ok(version)) = row.take_opt(0) {
+                    let schema = DbRecord::build_schema_version(version);
+                    return Ok(DbRecord::SchemaVersion(schema));
+                }
+            }
+            StorageType::RecordMac => {
+                // `record_key`, `mac`
+                if let (Some(Ok(record_key)), Some(Ok(mac))) = (row.take_opt(0), row.take_opt(1)) {
+                    let mac_vec: Vec<u8> = mac;
+                    let record_mac = DbRecord::build_record_mac(
+                        record_key,
+                        mac_vec.try_into().map_err(|_| cast_err())?,
+                    );
+                    return Ok(DbRecord::RecordMac(record_mac));
+                }
+            }
+            StorageType::PendingEpoch => {
+                // `epoch`, `operation`, `next_part`, `tree`
+                if let (Some(Ok(epoch)), Some(Ok(operation)), Some(Ok(next_part)), Some(Ok(tree))) = (
+                    row.take_opt(0),
+                    row.take_opt(1),
+                    row.take_opt(2),
+                    row.take_opt(3),
+                ) {
+                    let tree_vec: Vec<u8> = tree;
+                    let pending = DbRecord::build_pending_epoch(
+                        epoch,
+                        PendingOperation::from_u8(operation).ok_or_else(cast_err)?,
+                        next_part,
+                    )
+                    .with_tree(decode_tree(&tree_vec).ok_or_else(cast_err)?);
+                    return Ok(DbRecord::PendingEpoch(pending));
+                }
+            }
         }
         // fallback
         let err = MySqlError::Driver(mysql_async::DriverError::FromRow { row: row.clone() });
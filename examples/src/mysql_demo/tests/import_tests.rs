@@ -0,0 +1,64 @@
+// Copyright (c) Meta Platforms, Inc. and affiliates.
+//
+// This source code is dual-licensed under either the MIT license found in the
+// LICENSE-MIT file in the root directory of this source tree or the Apache
+// License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+// of this source tree. You may select, at your option, one of the above-listed licenses.
+
+use crate::mysql_demo::import::{read_entries, ImportFormat};
+use akd::{AkdLabel, AkdValue};
+use std::io::Cursor;
+
+fn entry(label: &str, value: &str) -> (AkdLabel, AkdValue) {
+    (AkdLabel::from(label), AkdValue::from(value))
+}
+
+#[test]
+fn test_read_csv_entries() {
+    let dump = "label,value\nalice,key1\n\n\"bob, jr\",\"key \"\"2\"\"\"\r\ncarol,\n";
+    let entries = read_entries(Cursor::new(dump), ImportFormat::Csv)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .expect("Failed to read the dump");
+    assert_eq!(
+        vec![
+            entry("alice", "key1"),
+            entry("bob, jr", "key \"2\""),
+            entry("carol", ""),
+        ],
+        entries
+    );
+
+    for malformed in [
+        "alice",
+        "alice,key1,extra",
+        "\"alice,key1",
+        "\"alice\"x,key1",
+    ] {
+        let mut entries = read_entries(Cursor::new(malformed), ImportFormat::Csv);
+        assert!(matches!(entries.next(), Some(Err(_))), "{malformed}");
+    }
+}
+
+#[test]
+fn test_read_json_entries() {
+    let dump = r#"{"label": "alice", "value": "key1"}
+        {"label": "bob", "value": "key2"} {"label": "carol", "value": ""}"#;
+    let entries = read_entries(Cursor::new(dump), ImportFormat::Json)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .expect("Failed to read the dump");
+    assert_eq!(
+        vec![
+            entry("alice", "key1"),
+            entry("bob", "key2"),
+            entry("carol", ""),
+        ],
+        entries
+    );
+
+    let mut entries = read_entries(
+        Cursor::new(r#"{"label": "alice", "value": "key1"} {"label": "bob"}"#),
+        ImportFormat::Json,
+    );
+    assert!(matches!(entries.next(), Some(Ok(_))));
+    assert!(matches!(entries.next(), Some(Err(_))));
+}
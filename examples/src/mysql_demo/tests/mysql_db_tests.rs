@@ -7,6 +7,10 @@
 
 use super::test_util::log_init;
 use crate::mysql_demo::mysql::AsyncMySqlDatabase;
+use akd::append_only_zks::{Azks, DEFAULT_AZKS_KEY};
+use akd::storage::tree_scope::TreeScopedDatabase;
+use akd::storage::types::{DbRecord, TreeId};
+use akd::storage::Database;
 
 // *** Tests *** //
 
@@ -42,6 +46,34 @@ async fn test_mysql_db() {
 
         // The test cases
         let manager = akd::storage::tests::run_test_cases_for_storage_impl(mysql_db.clone()).await;
+        akd::storage::tests::run_test_cases_for_storage_util_impl(&mysql_db).await;
+
+        // Several trees held by the same tables, alongside the records written above
+        let azks = mysql_db.get::<Azks>(&DEFAULT_AZKS_KEY).await;
+        for tree in [TreeId(1), TreeId(u64::MAX)] {
+            let tree = TreeScopedDatabase::new(mysql_db.clone(), tree);
+            akd::storage::tests::run_test_cases_for_storage_impl(tree.clone()).await;
+            akd::storage::tests::run_test_cases_for_storage_util_impl(&tree).await;
+        }
+        let first = TreeScopedDatabase::new(mysql_db.clone(), TreeId(1));
+        let second = TreeScopedDatabase::new(mysql_db.clone(), TreeId(u64::MAX));
+        first
+            .set(DbRecord::Azks(DbRecord::build_azks(2, 3)))
+            .await
+            .expect("Failed to set the azks of the first tree");
+        second
+            .set(DbRecord::Azks(DbRecord::build_azks(3, 5)))
+            .await
+            .expect("Failed to set the azks of the second tree");
+        assert_eq!(
+            Ok(DbRecord::Azks(DbRecord::build_azks(2, 3))),
+            first.get::<Azks>(&DEFAULT_AZKS_KEY).await
+        );
+        assert_eq!(
+            Ok(DbRecord::Azks(DbRecord::build_azks(3, 5))),
+            second.get::<Azks>(&DEFAULT_AZKS_KEY).await
+        );
+        assert_eq!(azks, mysql_db.get::<Azks>(&DEFAULT_AZKS_KEY).await);
 
         // clean the test infra
         if let Err(mysql_async::Error::Server(error)) = manager.get_db().drop_tables().await {